@@ -0,0 +1,237 @@
+use crate::config::ApprovalWebhookConfig;
+use anyhow::{bail, Context, Result};
+use hmac::{Hmac, KeyInit, Mac};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+pub const CALLBACK_PATH: &str = "/approval/callback";
+/// `sha256=<hex HMAC-SHA256 of the body>` on webhook requests and on callbacks.
+pub const SIGNATURE_HEADER: &str = "x-ccterm-signature";
+
+const MAX_REQUEST_BYTES: usize = 65_536;
+/// A client that does not finish its request within this is disconnected.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Posted to the approval webhook when Claude asks for a tool permission.
+#[derive(Debug, Clone, Serialize)]
+pub struct PermissionRequest {
+    /// Approval id to send back in the callback.
+    pub id: String,
+    pub session_name: String,
+    pub conversation_id: String,
+    pub thread_id: Option<String>,
+    /// Claude's notification, e.g. "Claude needs your permission to use Bash".
+    pub message: String,
+    /// The prompt as shown in the pane.
+    pub prompt: String,
+    /// RFC 3339. Without a decision by then, the prompt is denied.
+    pub expires_at: String,
+    pub callback_url: String,
+}
+
+/// Body of a signed callback.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApprovalDecision {
+    pub id: String,
+    /// `approve`, `always` or `deny`, like the Slack buttons.
+    pub decision: String,
+    /// Who decided, shown in the thread.
+    #[serde(default)]
+    pub approver: Option<String>,
+}
+
+pub fn callback_url(cfg: &ApprovalWebhookConfig) -> String {
+    cfg.callback_url
+        .clone()
+        .unwrap_or_else(|| format!("http://{}{CALLBACK_PATH}", cfg.listen))
+}
+
+/// Post a signed permission request to `cfg.url`.
+pub async fn notify(cfg: &ApprovalWebhookConfig, request: &PermissionRequest) -> Result<()> {
+    let body = serde_json::to_vec(request).context("failed to encode permission request")?;
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .context("failed to load native TLS roots")?
+        .https_or_http()
+        .enable_http1()
+        .build();
+    let client = Client::builder(TokioExecutor::new()).build::<_, Full<Bytes>>(connector);
+    let req = hyper::Request::post(&cfg.url)
+        .header("content-type", "application/json")
+        .header(SIGNATURE_HEADER, signature(&cfg.secret, &body))
+        .body(Full::new(Bytes::from(body)))
+        .context("failed to build approval webhook request")?;
+    let resp = client
+        .request(req)
+        .await
+        .with_context(|| format!("failed to call approval webhook {}", cfg.url))?;
+    if !resp.status().is_success() {
+        bail!("approval webhook returned {}", resp.status());
+    }
+    Ok(())
+}
+
+/// Start the callback handler on `cfg.listen`. Callbacks with a valid signature are
+/// passed to the coordinator through `tx`.
+pub async fn start(
+    cfg: ApprovalWebhookConfig,
+    tx: mpsc::UnboundedSender<ApprovalDecision>,
+) -> Result<()> {
+    let listener = TcpListener::bind(&cfg.listen)
+        .await
+        .with_context(|| format!("failed to listen for approval callbacks on {}", cfg.listen))?;
+    info!("callbacks at {}", callback_url(&cfg));
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    warn!("approval callback accept error: {err}");
+                    continue;
+                }
+            };
+            let secret = cfg.secret.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                if let Err(err) = handle(stream, &secret, &tx).await {
+                    warn!("approval callback error: {err:#}");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    secret: &str,
+    tx: &mpsc::UnboundedSender<ApprovalDecision>,
+) -> Result<()> {
+    let request = tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream))
+        .await
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out reading the request")));
+    let response = match request {
+        Ok(request) if request.path != CALLBACK_PATH => text_response("404 Not Found", "Not found"),
+        Ok(request)
+            if !request
+                .signature
+                .as_deref()
+                .is_some_and(|sig| verify(secret, &request.body, sig)) =>
+        {
+            warn!("rejected callback with a missing or invalid signature");
+            text_response("401 Unauthorized", "Invalid signature")
+        }
+        Ok(request) => match serde_json::from_slice::<ApprovalDecision>(&request.body) {
+            Ok(decision) => {
+                info!("{} for permission {}", decision.decision, decision.id);
+                let _ = tx.send(decision);
+                text_response("202 Accepted", "Accepted")
+            }
+            Err(err) => text_response("400 Bad Request", &format!("Invalid body: {err}")),
+        },
+        Err(err) => text_response("400 Bad Request", &format!("Invalid request: {err}")),
+    };
+    stream
+        .write_all(response.as_bytes())
+        .await
+        .context("failed to write approval callback response")?;
+    Ok(())
+}
+
+struct CallbackRequest {
+    path: String,
+    signature: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Read a POST request with a `Content-Length` body.
+async fn read_request(stream: &mut TcpStream) -> Result<CallbackRequest> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        if let Some(pos) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if buf.len() > MAX_REQUEST_BYTES {
+            bail!("request head too large");
+        }
+        let read = stream.read(&mut chunk).await.context("failed to read request")?;
+        if read == 0 {
+            bail!("connection closed before the request head ended");
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut lines = head.lines();
+    let mut parts = lines.next().unwrap_or_default().split_whitespace();
+    let path = match (parts.next(), parts.next()) {
+        (Some("POST"), Some(target)) => target.split('?').next().unwrap_or_default().to_string(),
+        _ => bail!("unsupported request: {}", head.lines().next().unwrap_or_default()),
+    };
+    let mut content_length = 0;
+    let mut signature = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = name.trim().to_ascii_lowercase();
+        if name == "content-length" {
+            content_length = value.trim().parse().context("invalid content-length")?;
+        } else if name == SIGNATURE_HEADER {
+            signature = Some(value.trim().to_string());
+        }
+    }
+    if content_length > MAX_REQUEST_BYTES {
+        bail!("body too large");
+    }
+    let mut body = buf[head_end..].to_vec();
+    while body.len() < content_length {
+        let read = stream.read(&mut chunk).await.context("failed to read request body")?;
+        if read == 0 {
+            bail!("connection closed before the body ended");
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    body.truncate(content_length);
+    Ok(CallbackRequest {
+        path,
+        signature,
+        body,
+    })
+}
+
+fn signature(secret: &str, body: &[u8]) -> String {
+    format!("sha256={}", hex::encode(mac(secret, body).finalize().into_bytes()))
+}
+
+/// Constant-time check of a `sha256=<hex>` signature.
+fn verify(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(Ok(expected)) = signature.strip_prefix("sha256=").map(hex::decode) else {
+        return false;
+    };
+    mac(secret, body).verify_slice(&expected).is_ok()
+}
+
+fn mac(secret: &str, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    mac
+}
+
+fn text_response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
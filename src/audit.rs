@@ -0,0 +1,323 @@
+use crate::config::AuditConfig;
+use crate::events::{CoordinatorEvent, EventBus};
+use crate::paths;
+use anyhow::{bail, Context, Result};
+use jiff::civil::Date;
+use jiff::tz::TimeZone;
+use jiff::{SignedDuration, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Audit log in the state directory, one JSON record per line.
+pub const AUDIT_FILE: &str = "audit.jsonl";
+const PURGE_INTERVAL: Duration = Duration::from_secs(6 * 3600);
+const REDACTED: &str = "[redacted]";
+const CSV_COLUMNS: [&str; 8] = [
+    "ts",
+    "event",
+    "conversation_id",
+    "thread_id",
+    "user_id",
+    "user_name",
+    "session_name",
+    "text",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// RFC 3339, UTC.
+    pub ts: String,
+    pub event: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversation_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_name: Option<String>,
+    /// Message text, outcome, or error, depending on the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+impl AuditRecord {
+    fn time(&self) -> Option<Timestamp> {
+        self.ts.parse().ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "csv" => Ok(Self::Csv),
+            "jsonl" => Ok(Self::Jsonl),
+            other => bail!("unknown export format `{other}` (expected csv or jsonl)"),
+        }
+    }
+}
+
+pub fn audit_path(base_cwd: &Path) -> PathBuf {
+    paths::state_dir(base_cwd).join(AUDIT_FILE)
+}
+
+/// Subscriber appending coordinator activity to the audit log, and purging records
+/// older than `retention_days` at startup and every few hours.
+pub fn spawn_writer(bus: &EventBus, path: PathBuf, cfg: AuditConfig) {
+    // The log must not skip events when the writer is slow, so it does not use the
+    // bounded broadcast channel.
+    let mut rx = bus.subscribe_all();
+    tokio::spawn(async move {
+        let mut purge = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = purge.tick() => {
+                    let Some(days) = cfg.retention_days else {
+                        continue;
+                    };
+                    match purge_older_than(&path, days, Timestamp::now()) {
+                        Ok(0) => {}
+                        Ok(removed) => info!("purged {removed} records"),
+                        Err(err) => warn!("audit purge failed: {err:#}"),
+                    }
+                }
+                event = rx.recv() => match event {
+                    Some(event) => {
+                        let record = record_for(&event, Timestamp::now(), cfg.redact_content);
+                        if let Err(err) = append(&path, &record) {
+                            warn!("audit write failed: {err:#}");
+                        }
+                    }
+                    None => return,
+                },
+            }
+        }
+    });
+}
+
+fn record_for(event: &CoordinatorEvent, ts: Timestamp, redact: bool) -> AuditRecord {
+    let mut record = AuditRecord {
+        ts: ts.to_string(),
+        event: String::new(),
+        conversation_id: None,
+        thread_id: None,
+        user_id: None,
+        user_name: None,
+        session_name: None,
+        text: None,
+    };
+    match event {
+        CoordinatorEvent::MessageReceived {
+            conversation_id,
+            thread_id,
+            user_id,
+            user_name,
+            text,
+        } => {
+            record.event = "message".to_string();
+            record.conversation_id = Some(conversation_id.clone());
+            record.thread_id = thread_id.clone();
+            record.user_id = user_id.clone();
+            record.user_name = user_name.clone();
+            record.text = Some(if redact {
+                REDACTED.to_string()
+            } else {
+                text.clone()
+            });
+        }
+        CoordinatorEvent::SessionSpawned {
+            conversation_id,
+            thread_id,
+            session_name,
+            fingerprint,
+            ..
+        } => {
+            record.event = "session_spawned".to_string();
+            record.conversation_id = Some(conversation_id.clone());
+            record.thread_id = thread_id.clone();
+            record.session_name = Some(session_name.clone());
+            record.text = Some(fingerprint.clone());
+        }
+        CoordinatorEvent::TurnStarted {
+            conversation_id,
+            thread_id,
+            session_name,
+        } => {
+            record.event = "turn_started".to_string();
+            record.conversation_id = Some(conversation_id.clone());
+            record.thread_id = thread_id.clone();
+            record.session_name = Some(session_name.clone());
+        }
+        CoordinatorEvent::TurnCompleted {
+            conversation_id,
+            thread_id,
+            session_name,
+            outcome,
+        } => {
+            record.event = "turn_completed".to_string();
+            record.conversation_id = Some(conversation_id.clone());
+            record.thread_id = thread_id.clone();
+            record.session_name = Some(session_name.clone());
+            record.text = Some(format!("{outcome:?}"));
+        }
+        CoordinatorEvent::UserAction {
+            conversation_id,
+            thread_id,
+            user_id,
+            action,
+            target,
+        } => {
+            record.event = format!("action:{action}");
+            record.conversation_id = Some(conversation_id.clone());
+            record.thread_id = thread_id.clone();
+            record.user_id = user_id.clone();
+            record.text = target.clone();
+        }
+        CoordinatorEvent::Error { context, message } => {
+            record.event = format!("error:{context}");
+            // Errors can quote message text, e.g. a failed plugin's stderr.
+            record.text = Some(if redact {
+                REDACTED.to_string()
+            } else {
+                message.clone()
+            });
+        }
+    }
+    record
+}
+
+fn append(path: &Path, record: &AuditRecord) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create dir: {}", parent.display()))?;
+    }
+    let mut line = serde_json::to_string(record).context("failed to encode audit record")?;
+    line.push('\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open audit log: {}", path.display()))?;
+    file.write_all(line.as_bytes())
+        .with_context(|| format!("failed to write audit log: {}", path.display()))
+}
+
+/// Records in the log, oldest first. Lines that do not parse are skipped.
+fn read_records(path: &Path) -> Result<Vec<AuditRecord>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("failed to open audit log: {}", path.display()))?;
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.context("failed to read audit log")?;
+        if let Ok(record) = serde_json::from_str(&line) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+/// Rewrite the log without records older than `days`, returning how many were removed.
+pub fn purge_older_than(path: &Path, days: u64, now: Timestamp) -> Result<usize> {
+    let cutoff = now - SignedDuration::from_hours(24 * days as i64);
+    let records = read_records(path)?;
+    let total = records.len();
+    let kept: Vec<AuditRecord> = records
+        .into_iter()
+        .filter(|record| record.time().is_none_or(|ts| ts >= cutoff))
+        .collect();
+    if kept.len() == total {
+        return Ok(0);
+    }
+    let mut out = String::new();
+    for record in &kept {
+        out.push_str(&serde_json::to_string(record).context("failed to encode audit record")?);
+        out.push('\n');
+    }
+    paths::write_atomic(path, out)
+        .with_context(|| format!("failed to replace audit log: {}", path.display()))?;
+    Ok(total - kept.len())
+}
+
+/// `--since` value: an RFC 3339 timestamp, a `YYYY-MM-DD` date (UTC midnight), or an
+/// age such as `7d` or `12h`.
+pub fn parse_since(value: &str, now: Timestamp) -> Result<Timestamp> {
+    if let Ok(ts) = value.parse::<Timestamp>() {
+        return Ok(ts);
+    }
+    if let Ok(date) = value.parse::<Date>() {
+        return Ok(date.to_zoned(TimeZone::UTC)?.timestamp());
+    }
+    let hours = match (value.strip_suffix('d'), value.strip_suffix('h')) {
+        (Some(days), _) => days.parse::<i64>().ok().map(|days| days * 24),
+        (_, Some(hours)) => hours.parse::<i64>().ok(),
+        _ => None,
+    };
+    let Some(hours) = hours else {
+        bail!("invalid --since `{value}` (expected a timestamp, YYYY-MM-DD, 7d or 12h)");
+    };
+    Ok(now - SignedDuration::from_hours(hours))
+}
+
+/// Write records at or after `since` to `out`, returning how many were written.
+pub fn export(
+    path: &Path,
+    since: Option<Timestamp>,
+    format: ExportFormat,
+    out: &mut dyn Write,
+) -> Result<usize> {
+    let records: Vec<AuditRecord> = read_records(path)?
+        .into_iter()
+        .filter(|record| since.is_none_or(|since| record.time().is_some_and(|ts| ts >= since)))
+        .collect();
+    if format == ExportFormat::Csv {
+        writeln!(out, "{}", CSV_COLUMNS.join(","))?;
+    }
+    for record in &records {
+        match format {
+            ExportFormat::Jsonl => {
+                writeln!(out, "{}", serde_json::to_string(record)?)?;
+            }
+            ExportFormat::Csv => {
+                let fields = [
+                    Some(record.ts.as_str()),
+                    Some(record.event.as_str()),
+                    record.conversation_id.as_deref(),
+                    record.thread_id.as_deref(),
+                    record.user_id.as_deref(),
+                    record.user_name.as_deref(),
+                    record.session_name.as_deref(),
+                    record.text.as_deref(),
+                ];
+                let row: Vec<String> = fields
+                    .iter()
+                    .map(|field| csv_field(field.unwrap_or_default()))
+                    .collect();
+                writeln!(out, "{}", row.join(","))?;
+            }
+        }
+    }
+    Ok(records.len())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
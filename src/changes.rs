@@ -0,0 +1,173 @@
+use crate::git;
+use regex::Regex;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Directories skipped when scanning a directory that is not a git work tree.
+const SKIPPED_DIRS: [&str; 2] = ["target", "node_modules"];
+/// Files checked at most when scanning without git.
+const MAX_SCANNED_FILES: usize = 20_000;
+/// File names listed in the footer before summarizing the rest.
+const MAX_LISTED_FILES: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    /// Relative to the session cwd.
+    pub path: String,
+    pub created: bool,
+}
+
+/// Files under `cwd` created or modified since `since`. When `cwd` is the root of a git
+/// work tree these are untracked and modified files (ignored files excluded); elsewhere,
+/// including thread directories inside the project repository, the directory is
+/// scanned, skipping hidden directories, `target` and `node_modules`.
+pub fn changed_since(cwd: &Path, since: SystemTime) -> Vec<FileChange> {
+    let mut changes = match git_changes(cwd) {
+        Some(changes) => changes,
+        None => scan_changes(cwd, since),
+    };
+    changes.retain(|change| {
+        std::fs::metadata(cwd.join(&change.path))
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| modified >= since)
+    });
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+fn git_changes(cwd: &Path) -> Option<Vec<FileChange>> {
+    let top = git::run(cwd, &["rev-parse", "--show-toplevel"]).ok()?;
+    if Path::new(&top).canonicalize().ok()? != cwd.canonicalize().ok()? {
+        return None;
+    }
+    let others = git::run(cwd, &["ls-files", "--others", "--exclude-standard", "--", "."]).ok()?;
+    let modified = git::run(cwd, &["ls-files", "--modified", "--", "."]).ok()?;
+    let lines = |output: String, created: bool| -> Vec<FileChange> {
+        output
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|path| FileChange {
+                path: path.to_string(),
+                created,
+            })
+            .collect()
+    };
+    let mut changes = lines(others, true);
+    changes.extend(lines(modified, false));
+    Some(changes)
+}
+
+fn scan_changes(cwd: &Path, since: SystemTime) -> Vec<FileChange> {
+    let mut changes = Vec::new();
+    let mut scanned = 0;
+    let mut pending = vec![cwd.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let Ok(metadata) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_ref()) {
+                    pending.push(entry.path());
+                }
+                continue;
+            }
+            scanned += 1;
+            if scanned > MAX_SCANNED_FILES {
+                return changes;
+            }
+            let Ok(relative) = entry.path().strip_prefix(cwd).map(Path::to_path_buf) else {
+                continue;
+            };
+            // Without git, a file is new when it was born after `since`. Filesystems
+            // without birth times report every change as a modification.
+            changes.push(FileChange {
+                path: relative.to_string_lossy().to_string(),
+                created: metadata.created().is_ok_and(|created| created >= since),
+            });
+        }
+    }
+    changes
+}
+
+/// Changes to upload with the reply: those whose path matches one of `globs`, and with
+/// `referenced`, those `reply` mentions by path or by file name in backticks.
+pub fn to_upload<'a>(
+    changes: &'a [FileChange],
+    reply: &str,
+    referenced: bool,
+    globs: &[String],
+) -> Vec<&'a FileChange> {
+    let globs: Vec<Regex> = globs.iter().map(|glob| glob_regex(glob)).collect();
+    changes
+        .iter()
+        .filter(|change| {
+            let name = Path::new(&change.path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let mentioned = reply.contains(&change.path) || reply.contains(&format!("`{name}`"));
+            (referenced && mentioned) || globs.iter().any(|glob| glob.is_match(&change.path))
+        })
+        .collect()
+}
+
+/// Match a relative path against a glob: `*` and `?` stay within a path component and
+/// `**/` spans any number of directories. A glob without `/` matches file names in any
+/// directory.
+fn glob_regex(glob: &str) -> Regex {
+    let glob = if glob.contains('/') {
+        glob.to_string()
+    } else {
+        format!("**/{glob}")
+    };
+    let mut pattern = String::from("^");
+    let mut rest = glob.as_str();
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("**/") {
+            pattern.push_str("(?:.*/)?");
+            rest = after;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("**") {
+            pattern.push_str(".*");
+            rest = after;
+            continue;
+        }
+        match c {
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    pattern.push('$');
+    Regex::new(&pattern).expect("escaped glob is a valid regex")
+}
+
+/// `Files changed (N): `a` (new), `b`` for the reply, or `None` when nothing changed.
+pub fn footer(changes: &[FileChange]) -> Option<String> {
+    if changes.is_empty() {
+        return None;
+    }
+    let mut names: Vec<String> = changes
+        .iter()
+        .take(MAX_LISTED_FILES)
+        .map(|change| {
+            if change.created {
+                format!("`{}` (new)", change.path)
+            } else {
+                format!("`{}`", change.path)
+            }
+        })
+        .collect();
+    if changes.len() > MAX_LISTED_FILES {
+        names.push(format!("and {} more", changes.len() - MAX_LISTED_FILES));
+    }
+    Some(format!("_Files changed ({}):_ {}", changes.len(), names.join(", ")))
+}
@@ -0,0 +1,138 @@
+use crate::types::{
+    ChatIdentity, HomeView, IncomingEvent, OutgoingMessage, ReplyBlock, TranscriptMessage,
+};
+use anyhow::Result;
+use tokio::sync::mpsc;
+
+/// A chat backend the coordinator talks through. Conversations, threads and messages
+/// are identified by the backend's own ids (Slack channel ids and message `ts`).
+///
+/// Button clicks come back as `IncomingEvent::Action` with the action id
+/// `<action_prefix>_<button>` and the `value` given when posting.
+pub trait ChatAdapter {
+    /// Short name of the backend for logs, e.g. `slack`.
+    fn name(&self) -> &'static str;
+
+    /// Messages, reactions, button clicks and shortcuts from users.
+    fn incoming(&mut self) -> &mut mpsc::UnboundedReceiver<IncomingEvent>;
+
+    /// The bot's user id and workspace for `conversation_id`. A backend serving several
+    /// workspaces answers per conversation.
+    async fn identity(&self, conversation_id: &str) -> Result<ChatIdentity>;
+
+    async fn send(&self, message: &OutgoingMessage) -> Result<()>;
+
+    /// Show a message to `user_id` only, in the conversation or thread of `message`.
+    async fn send_ephemeral(&self, user_id: &str, message: &OutgoingMessage) -> Result<()>;
+
+    /// Post a message and return its id.
+    async fn post(&self, message: &OutgoingMessage) -> Result<String>;
+
+    /// Post `blocks`, in several messages when there are too many for one, and return the
+    /// id of the first.
+    async fn post_blocks(
+        &self,
+        conversation_id: &str,
+        thread_id: Option<&str>,
+        blocks: &[ReplyBlock],
+    ) -> Result<String>;
+
+    /// Post a message with `approve` and `reject` buttons.
+    async fn post_approval(
+        &self,
+        message: &OutgoingMessage,
+        action_prefix: &str,
+        value: &str,
+    ) -> Result<String>;
+
+    /// Post a tool permission prompt with `approve`, `always` and `deny` buttons.
+    async fn post_permission_prompt(
+        &self,
+        message: &OutgoingMessage,
+        action_prefix: &str,
+        value: &str,
+    ) -> Result<String>;
+
+    /// Post a plan titled by the message text, with the buttons of `post_approval`.
+    async fn post_plan(
+        &self,
+        message: &OutgoingMessage,
+        sections: &[String],
+        action_prefix: &str,
+        value: &str,
+    ) -> Result<String>;
+
+    /// Show `view` on the user's home screen, with `new`, `stop` and `export` buttons
+    /// per session.
+    async fn publish_home(
+        &self,
+        user_id: &str,
+        view: &HomeView,
+        action_prefix: &str,
+    ) -> Result<()>;
+
+    async fn permalink(&self, channel: &str, ts: &str) -> Result<String>;
+
+    /// Replace a message with plain text, dropping any buttons.
+    async fn update_text(&self, channel: &str, ts: &str, text: &str) -> Result<()>;
+
+    async fn add_reaction(&self, channel: &str, ts: &str, name: &str) -> Result<()>;
+
+    async fn remove_reaction(&self, channel: &str, ts: &str, name: &str) -> Result<()>;
+
+    /// Create a document shared with the channel and return its id.
+    async fn create_canvas(
+        &self,
+        channel: &str,
+        title: &str,
+        markdown: &str,
+    ) -> Result<String>;
+
+    async fn replace_canvas(
+        &self,
+        channel: &str,
+        canvas_id: &str,
+        markdown: &str,
+    ) -> Result<()>;
+
+    async fn canvas_url(&self, channel: &str, canvas_id: &str) -> Result<String>;
+
+    async fn upload_text(
+        &self,
+        conversation_id: &str,
+        thread_id: Option<&str>,
+        filename: &str,
+        content: &str,
+    ) -> Result<()>;
+
+    async fn upload_bytes(
+        &self,
+        conversation_id: &str,
+        thread_id: Option<&str>,
+        filename: &str,
+        content: Vec<u8>,
+        content_type: &str,
+    ) -> Result<()>;
+
+    /// Fetch a file shared in `conversation_id`, failing when it is larger than
+    /// `max_bytes`.
+    async fn download(&self, conversation_id: &str, url: &str, max_bytes: usize)
+        -> Result<Vec<u8>>;
+
+    /// Up to `limit` channel messages posted before `before_ts`, oldest first.
+    async fn channel_history(
+        &self,
+        channel: &str,
+        before_ts: Option<&str>,
+        limit: u16,
+    ) -> Result<Vec<TranscriptMessage>>;
+
+    /// The message `ts` in `channel`, or `None` when it has no text or cannot be found.
+    /// `thread_ts` is the parent of the thread it was posted in, if known.
+    async fn fetch_message(
+        &self,
+        channel: &str,
+        ts: &str,
+        thread_ts: Option<&str>,
+    ) -> Result<Option<TranscriptMessage>>;
+}
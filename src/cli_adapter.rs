@@ -24,17 +24,23 @@ pub fn parse_input(line: &str) -> Result<IncomingMessage> {
             .ok_or_else(|| anyhow::anyhow!("message text is required after thread id"))?;
         return Ok(IncomingMessage {
             text,
+            user_id: None,
+            user_name: None,
             conversation_id: DEFAULT_CONVERSATION_ID.to_string(),
             thread_id: Some(thread_id),
             timestamp: None,
+            attachments: Vec::new(),
         });
     }
 
     Ok(IncomingMessage {
         text: trimmed.to_string(),
+        user_id: None,
+        user_name: None,
         conversation_id: DEFAULT_CONVERSATION_ID.to_string(),
         thread_id: None,
         timestamp: None,
+        attachments: Vec::new(),
     })
 }
 
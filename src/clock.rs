@@ -0,0 +1,34 @@
+use jiff::Timestamp;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Time source for coordinator waits and timeouts.
+///
+/// `TokioClock` reads tokio's clock, so it follows `tokio::time::pause`/`advance` and
+/// turn-timeout or prompt-wait policies can be driven deterministically. Sleeping yields
+/// to the runtime instead of blocking the coordinator thread.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    /// Wall-clock time, for calendar policies such as quiet hours.
+    fn timestamp(&self) -> Timestamp;
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn timestamp(&self) -> Timestamp {
+        Timestamp::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
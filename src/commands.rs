@@ -0,0 +1,193 @@
+use crate::permissions::PermissionAction;
+use anyhow::{anyhow, bail, Result};
+use std::time::Duration;
+
+/// Chat commands starting with `!`, handled by ccterm instead of being sent to Claude.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatCommand {
+    Set { key: String, value: String },
+    Unset { key: String },
+    Settings,
+    Status,
+    Spawn { task: String },
+    /// Deliver the session's last prompt again, after restarting the session with `--fresh`.
+    Retry { fresh: bool },
+    /// Revert the last auto-committed turn.
+    Undo,
+    /// Hold back progress notices for `duration`, or until the next reply when unset.
+    Mute { duration: Option<Duration> },
+    Unmute,
+    /// Show the sender their last `count` prompts in this conversation, only to them.
+    History { count: usize },
+    /// Post the last `lines` lines of the session's tmux pane.
+    Peek { lines: usize },
+    /// Stop the session, lifting a quarantine, so the next message starts a fresh one.
+    Restart,
+    /// Interrupt the running turn with Escape, followed by Ctrl-C when `hard`.
+    Stop { hard: bool },
+}
+
+pub const BUILTIN_COMMANDS: [&str; 15] = [
+    "set", "unset", "settings", "status", "spawn", "retry", "undo", "mute", "unmute", "last",
+    "history", "peek", "restart", "stop", "interrupt",
+];
+
+/// Default and maximum number of prompts listed by `!history`.
+const HISTORY_DEFAULT: usize = 5;
+const HISTORY_MAX: usize = 20;
+
+/// Default and maximum number of pane lines posted by `!peek`.
+const PEEK_DEFAULT: usize = 20;
+pub const PEEK_MAX: usize = 200;
+
+/// Per-turn overrides written as leading `/name value` words of a message, e.g.
+/// `/model opus explain this file`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TurnDirectives {
+    pub model: Option<String>,
+    /// Default action of the permission policy for the turn; rules still apply first.
+    pub permissions: Option<PermissionAction>,
+    pub timeout: Option<Duration>,
+}
+
+/// Split the leading directives off `text`. Parsing stops at the first word that is not
+/// a known directive, so prompts like "/usr/bin is missing" reach Claude unchanged.
+pub fn parse_directives(text: &str) -> Result<(TurnDirectives, &str)> {
+    let mut directives = TurnDirectives::default();
+    let mut rest = text.trim_start();
+    while let Some(word) = rest.strip_prefix('/') {
+        let (name, after) = word.split_once(char::is_whitespace).unwrap_or((word, ""));
+        let after = after.trim_start();
+        let (value, remaining) = after.split_once(char::is_whitespace).unwrap_or((after, ""));
+        match name {
+            "model" if is_model_name(value) => directives.model = Some(value.to_string()),
+            "model" => bail!("usage: /model <name> <prompt>, e.g. /model opus"),
+            "permissions" => {
+                directives.permissions = Some(match value {
+                    "allow" => PermissionAction::Allow,
+                    "ask" => PermissionAction::Ask,
+                    "deny" => PermissionAction::Deny,
+                    _ => bail!("usage: /permissions allow|ask|deny <prompt>"),
+                })
+            }
+            "timeout" => match parse_duration(value) {
+                Some(timeout) => directives.timeout = Some(timeout),
+                None => bail!("usage: /timeout 90s|30m|2h <prompt>"),
+            },
+            _ => break,
+        }
+        rest = remaining.trim_start();
+    }
+    if rest.is_empty() && directives != TurnDirectives::default() {
+        bail!("directives apply to the message they are in; add a prompt after them");
+    }
+    Ok((directives, rest))
+}
+
+/// Model names and aliases such as `opus` or `claude-sonnet-4-5[1m]`. The name is typed
+/// into the session, so anything else is refused.
+fn is_model_name(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '[' | ']'))
+}
+
+/// Split `!name args` into the command name and its trimmed arguments.
+pub fn split(text: &str) -> Option<(&str, &str)> {
+    let rest = text.trim().strip_prefix('!')?;
+    match rest.split_once(char::is_whitespace) {
+        Some((name, args)) => Some((name, args.trim())),
+        None => Some((rest, "")),
+    }
+}
+
+/// Parse a message as a command. Returns `None` for text that is not a known command,
+/// so messages like "!important" still reach Claude.
+pub fn parse(text: &str) -> Option<Result<ChatCommand>> {
+    let (name, args) = split(text)?;
+    let command = match name {
+        "set" => {
+            let Some((key, value)) = args.split_once(char::is_whitespace) else {
+                return Some(Err(anyhow!("usage: !set <key> <value>")));
+            };
+            ChatCommand::Set {
+                key: key.to_string(),
+                value: value.trim().to_string(),
+            }
+        }
+        "unset" => {
+            if args.is_empty() {
+                return Some(Err(anyhow!("usage: !unset <key>")));
+            }
+            ChatCommand::Unset {
+                key: args.to_string(),
+            }
+        }
+        "settings" => ChatCommand::Settings,
+        "status" => ChatCommand::Status,
+        "spawn" => {
+            if args.is_empty() {
+                return Some(Err(anyhow!("usage: !spawn <task>")));
+            }
+            ChatCommand::Spawn {
+                task: args.to_string(),
+            }
+        }
+        "retry" => match args {
+            "" => ChatCommand::Retry { fresh: false },
+            "--fresh" => ChatCommand::Retry { fresh: true },
+            _ => return Some(Err(anyhow!("usage: !retry [--fresh]"))),
+        },
+        "undo" => ChatCommand::Undo,
+        "mute" if args.is_empty() => ChatCommand::Mute { duration: None },
+        "mute" => match parse_duration(args) {
+            Some(duration) => ChatCommand::Mute {
+                duration: Some(duration),
+            },
+            None => return Some(Err(anyhow!("usage: !mute [30m|2h|1d]"))),
+        },
+        "unmute" => ChatCommand::Unmute,
+        "last" if args.is_empty() => ChatCommand::History { count: 1 },
+        "last" => return Some(Err(anyhow!("usage: !last"))),
+        "history" if args.is_empty() => ChatCommand::History {
+            count: HISTORY_DEFAULT,
+        },
+        "history" => match args.parse() {
+            Ok(count) if (1..=HISTORY_MAX).contains(&count) => ChatCommand::History { count },
+            _ => return Some(Err(anyhow!("usage: !history [1-{HISTORY_MAX}]"))),
+        },
+        "peek" if args.is_empty() => ChatCommand::Peek {
+            lines: PEEK_DEFAULT,
+        },
+        "peek" => match args.parse() {
+            Ok(lines) if (1..=PEEK_MAX).contains(&lines) => ChatCommand::Peek { lines },
+            _ => return Some(Err(anyhow!("usage: !peek [1-{PEEK_MAX}]"))),
+        },
+        "restart" if args.is_empty() => ChatCommand::Restart,
+        "restart" => return Some(Err(anyhow!("usage: !restart"))),
+        "stop" | "interrupt" => match args {
+            "" => ChatCommand::Stop { hard: false },
+            "--hard" => ChatCommand::Stop { hard: true },
+            _ => return Some(Err(anyhow!("usage: !{name} [--hard]"))),
+        },
+        _ => return None,
+    };
+    Some(Ok(command))
+}
+
+/// `90s`, `30m`, `2h` or `1d`.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let unit_secs = match value.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86_400,
+        _ => return None,
+    };
+    let count: u64 = value[..value.len() - 1].parse().ok()?;
+    if count == 0 {
+        return None;
+    }
+    Some(Duration::from_secs(count.checked_mul(unit_secs)?))
+}
@@ -1,9 +1,15 @@
+use crate::permissions::PermissionPolicy;
+use crate::plugins::{self, PluginConfig};
+use crate::quiet;
 use anyhow::{bail, Context, Result};
-use serde::Deserialize;
+use jiff::tz::TimeZone;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub slack: SlackConfig,
     #[serde(default)]
@@ -14,40 +20,679 @@ pub struct Config {
     pub hooks: HooksConfig,
     #[serde(default)]
     pub coordinator: CoordinatorConfig,
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+    #[serde(default)]
+    pub permissions: PermissionPolicy,
+    #[serde(default)]
+    pub reactions: ReactionsConfig,
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHoursConfig>,
+    #[serde(default)]
+    pub plugins: Vec<PluginConfig>,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub approval_webhook: Option<ApprovalWebhookConfig>,
+    #[serde(default)]
+    pub url_fetch: UrlFetchConfig,
+    #[serde(default)]
+    pub log: LogConfig,
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct SlackConfig {
+    /// Optional with `oauth`, where each installed workspace has its own bot token.
+    #[serde(default)]
     pub bot_token: String,
     pub app_token: String,
+    #[serde(default)]
+    pub oauth: Option<SlackOAuthConfig>,
+    /// Callback ID of the message shortcut that sends a message to the channel's session.
+    #[serde(default = "default_message_shortcut")]
+    pub message_shortcut: String,
+    /// Treat every message in channels the bot is a member of as a prompt, not only
+    /// mentions. Direct messages are always handled.
+    #[serde(default)]
+    pub respond_without_mention: bool,
+    /// User ids that may use ccterm. Empty allows every member of the workspace.
+    #[serde(default)]
+    pub allowed_users: Vec<String>,
+    /// Channel ids ccterm takes requests in. Empty allows every channel the bot is in;
+    /// direct messages are only limited by `allowed_users`.
+    #[serde(default)]
+    pub allowed_channels: Vec<String>,
 }
 
+/// Serve an install link and OAuth redirect so the app can be added to more workspaces.
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SlackOAuthConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Address of the install/redirect handler, usually behind a TLS-terminating proxy.
+    #[serde(default = "default_oauth_listen")]
+    pub listen: String,
+    #[serde(default = "default_oauth_scopes")]
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct ClaudeConfig {
     #[serde(default = "default_claude_cmd")]
     pub command: String,
     #[serde(default = "default_cwd")]
     pub cwd: PathBuf,
+    #[serde(default)]
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ClaudeProfile>,
+}
+
+/// Account/billing profile applied to the session environment at spawn.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ClaudeProfile {
+    #[serde(default)]
+    pub config_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl ClaudeProfile {
+    pub fn session_env(&self) -> Vec<(String, String)> {
+        let mut env: Vec<(String, String)> = self
+            .env
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        if let Some(dir) = &self.config_dir {
+            env.push((
+                "CLAUDE_CONFIG_DIR".to_string(),
+                dir.to_string_lossy().to_string(),
+            ));
+        }
+        env
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct TmuxConfig {
     #[serde(default = "default_session_prefix")]
     pub session_prefix: String,
+    /// Follow session output through a tmux control-mode client, so prompt checks run
+    /// when the pane changes instead of on a fixed poll.
+    #[serde(default)]
+    pub control_mode: bool,
+    /// Show chat messages for a session in the status line of terminals attached to it.
+    #[serde(default)]
+    pub notify_attached: bool,
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct HooksConfig {
     #[serde(default = "default_hooks_path")]
     pub events_path: PathBuf,
+    /// Keys hook payloads must be signed with; empty accepts unsigned payloads. The first
+    /// is written to the key file hooks sign with, the rest are still accepted during a
+    /// rotation.
+    #[serde(default)]
+    pub secrets: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct CoordinatorConfig {
     #[serde(default = "default_hook_timeout_secs")]
     pub hook_timeout_secs: u64,
     #[serde(default = "default_prompt_timeout_ms")]
     pub prompt_timeout_ms: u64,
+    /// Further attempts after a session fails to spawn or to show its prompt.
+    #[serde(default = "default_spawn_retries")]
+    pub spawn_retries: u32,
+    /// Delay before the first retry, doubled for each further one up to a minute, plus up
+    /// to the same amount of random jitter.
+    #[serde(default = "default_spawn_retry_backoff_ms")]
+    pub spawn_retry_backoff_ms: u64,
+    #[serde(default)]
+    pub main_context_messages: u16,
+    #[serde(default)]
+    pub max_turn_secs: Option<u64>,
+    /// Most sessions (main, thread and `!spawn` task) running at once. Messages that would
+    /// start another thread session wait until one ends.
+    #[serde(default)]
+    pub max_sessions: Option<u64>,
+    /// Stop thread sessions with no turn for this long. Unset keeps them running.
+    #[serde(default)]
+    pub idle_session_secs: Option<u64>,
+    /// How long after an idle stop, or after a session was found gone, a reply resumes
+    /// the old Claude session with `--resume` rather than starting a new one.
+    #[serde(default = "default_idle_resume_secs")]
+    pub idle_resume_secs: u64,
+    /// Probe every session this often (tmux session alive, and at its prompt when idle).
+    /// Unset turns the checks off.
+    #[serde(default)]
+    pub health_check_secs: Option<u64>,
+    /// Consecutive failed probes after which a session is marked unhealthy.
+    #[serde(default = "default_health_failures")]
+    pub health_failures: u32,
+    #[serde(default)]
+    pub unhealthy_action: UnhealthyAction,
+    /// Stop routing messages to a session after this many failed deliveries within
+    /// `quarantine_window_secs`, until `!restart`. Unset turns quarantine off.
+    #[serde(default)]
+    pub quarantine_failures: Option<u32>,
+    #[serde(default = "default_quarantine_window_secs")]
+    pub quarantine_window_secs: u64,
+    /// Check at startup that a disposable session's `Stop` hook reaches ccterm.
+    #[serde(default)]
+    pub self_test: SelfTestMode,
+    #[serde(default = "default_self_test_timeout_secs")]
+    pub self_test_timeout_secs: u64,
+    /// Replies longer than this many characters are truncated; the full text is uploaded
+    /// as a file when someone reacts with :heavy_plus_sign:. 0 disables truncation.
+    #[serde(default)]
+    pub reply_truncate_chars: usize,
+    #[serde(default)]
+    pub bash_results: BashResultsMode,
+    #[serde(default)]
+    pub output: OutputTarget,
+    #[serde(default)]
+    pub reply_format: ReplyFormat,
+    /// Wrap forwarded chat text as untrusted input (see `guard.rs`).
+    #[serde(default)]
+    pub input_guard: bool,
+    #[serde(default)]
+    pub unfurl_permalinks: UnfurlPermalinks,
+    /// Diffs longer than this many lines are uploaded as `.patch` files. 0 keeps them inline.
+    #[serde(default = "default_diff_upload_lines")]
+    pub diff_upload_lines: usize,
+    #[serde(default)]
+    pub patch_approval: PatchApproval,
+    #[serde(default)]
+    pub auto_commit: AutoCommit,
+    #[serde(default)]
+    pub git_diff: GitDiff,
+    /// Remote used by `auto_commit = "push"`.
+    #[serde(default = "default_auto_push_remote")]
+    pub auto_push_remote: String,
+    #[serde(default)]
+    pub thread_workspace: ThreadWorkspace,
+    /// Shell command run in the session cwd after a turn that edited files.
+    #[serde(default)]
+    pub preview_command: Option<String>,
+    #[serde(default = "default_preview_timeout_secs")]
+    pub preview_timeout_secs: u64,
+    /// Send the collected `!spawn` results to the thread's session once all tasks finish.
+    #[serde(default)]
+    pub task_results_to_session: bool,
+    /// Posted in the thread when a message could not be handled. The error itself is
+    /// logged and, with `admin_channel`, posted there.
+    #[serde(default = "default_user_error_message")]
+    pub user_error_message: String,
+    #[serde(default)]
+    pub admin_channel: Option<String>,
+    /// Sessions whose process tree uses more resident memory than this are stopped.
+    #[serde(default)]
+    pub max_session_memory_mb: Option<u64>,
+    /// Thread directories larger than this get a warning in the thread.
+    #[serde(default)]
+    pub disk_soft_limit_mb: Option<u64>,
+    /// Thread directories larger than this pause their session until someone confirms.
+    #[serde(default)]
+    pub disk_hard_limit_mb: Option<u64>,
+    /// Post plans (`ExitPlanMode`) with approval buttons and keep a TODO list message
+    /// (`TodoWrite`) updated, from `PreToolUse` hooks.
+    #[serde(default)]
+    pub render_plans: bool,
+    /// Post Claude's tool permission prompts, reported by `Notification` hooks, with
+    /// Approve / Always allow / Deny buttons that answer the prompt in the pane.
+    #[serde(default)]
+    pub permission_buttons: bool,
+    /// User ids that may answer any permission prompt, patch or pre-flight approval, and
+    /// use the App Home buttons of any session. Everyone else can only answer for turns
+    /// and sessions they took part in.
+    #[serde(default)]
+    pub permission_approvers: Vec<String>,
+    /// Give each turn a `.ccterm/artifacts/<turn>` directory whose files are uploaded to
+    /// the thread when the turn ends.
+    #[serde(default)]
+    pub turn_artifacts: bool,
+    /// Save files shared with a message to `.ccterm/uploads/<turn>` in the session
+    /// directory and list their paths in the prompt.
+    #[serde(default)]
+    pub accept_uploads: bool,
+    /// Prompts estimated at more tokens than this, counting the conversation context a new
+    /// thread session is seeded with, get a warning or wait for confirmation. Unset turns
+    /// the check off.
+    #[serde(default)]
+    pub preflight_max_tokens: Option<u64>,
+    #[serde(default)]
+    pub preflight_action: PreflightAction,
+    /// Post a title taken from the first prompt as the first reply of a new thread
+    /// session, and store it as the tmux session label.
+    #[serde(default)]
+    pub thread_titles: bool,
+    /// Hook-driven updates (TODO list edits, permission denials) are collected per
+    /// session and posted at most once per interval.
+    #[serde(default = "default_progress_interval_ms")]
+    pub progress_interval_ms: u64,
+    /// Report tool calls while a turn runs; needs the `PreToolUse` hook.
+    #[serde(default)]
+    pub progress_updates: ProgressUpdates,
+    /// Append a "Files changed" footer listing files created or modified during the turn.
+    #[serde(default)]
+    pub files_changed: bool,
+    /// Upload files created or modified during the turn that the reply mentions.
+    #[serde(default)]
+    pub upload_referenced_files: bool,
+    /// Files created or modified during the turn whose paths match one of these globs
+    /// are uploaded whether or not the reply mentions them.
+    #[serde(default)]
+    pub upload_globs: Vec<String>,
+    /// Append a short quoted summary of the turn's thinking blocks to each reply.
+    #[serde(default)]
+    pub reasoning_summary: bool,
+    /// Reply to messages while `ccterm ctl maintenance on` is in effect.
+    #[serde(default = "default_maintenance_message")]
+    pub maintenance_message: String,
+    /// IANA zone name for timestamps in thread context and transcript exports; the
+    /// host's zone when unset.
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
+
+impl CoordinatorConfig {
+    pub fn time_zone(&self) -> Result<TimeZone> {
+        match &self.timezone {
+            Some(name) => TimeZone::get(name)
+                .with_context(|| format!("unknown coordinator.timezone: {name}")),
+            None => Ok(TimeZone::system()),
+        }
+    }
+}
+
+/// What happens to file changes left in the session directory at the end of a turn.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoCommit {
+    #[default]
+    Off,
+    /// Commit everything under the session directory.
+    Commit,
+    /// Commit, then push to a `ccterm/<thread>` branch.
+    Push,
+}
+
+/// What is attached to a reply about the session directory's changes against `HEAD`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GitDiff {
+    #[default]
+    Off,
+    /// `git diff --stat` below the reply.
+    Stat,
+    /// The stat, plus the full patch uploaded as a file.
+    Patch,
+}
+
+/// What happens to a prompt estimated over `preflight_max_tokens`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreflightAction {
+    /// Send it and post a notice with the estimate.
+    #[default]
+    Warn,
+    /// Hold it until someone presses Send on the notice.
+    Confirm,
+}
+
+/// Whose messages are quoted into a prompt that links to them.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnfurlPermalinks {
+    #[default]
+    Off,
+    /// Messages in the conversation the prompt was sent in.
+    Conversation,
+    /// Messages in any conversation the bot can read.
+    Workspace,
+}
+
+/// What `serve` does with the startup self-test.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SelfTestMode {
+    #[default]
+    Off,
+    /// Log a failure and keep starting.
+    Warn,
+    /// Refuse to start when it fails.
+    Require,
+}
+
+/// What a thread session's directory under `.ccterm/threads` holds.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThreadWorkspace {
+    /// An empty directory.
+    #[default]
+    Directory,
+    /// A git worktree of the base cwd on a `ccterm/<thread>` branch, removed when the
+    /// session ends.
+    Worktree,
+}
+
+/// What Approve does on a reply that contains a diff.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PatchApproval {
+    /// No buttons.
+    #[default]
+    Off,
+    /// Tell the session to apply and commit its proposed patch.
+    Instruct,
+    /// Run `git apply` in the session directory.
+    Apply,
+}
+
+/// Updates posted while a turn runs, from the tool calls Claude makes.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgressUpdates {
+    /// Only the reply is posted.
+    #[default]
+    Off,
+    /// One "Working…" message per turn, edited with the latest tool calls.
+    Status,
+    /// A message per progress interval listing the tool calls made since the last one.
+    Thread,
+}
+
+/// What happens to a session once its health probes keep failing.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnhealthyAction {
+    /// Log it, publish an error event, and show it in `!status`.
+    #[default]
+    Report,
+    /// Also stop the session; the next message resumes it in a new one.
+    Restart,
+}
+
+/// Where assistant replies are written.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputTarget {
+    #[default]
+    Message,
+    /// One Slack canvas per conversation, updated with each reply and linked in a message.
+    Canvas,
+}
+
+/// How the Markdown of assistant replies is posted.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplyFormat {
+    /// As Claude wrote it.
+    Raw,
+    /// Converted to Slack mrkdwn (see `mrkdwn.rs`).
+    #[default]
+    Mrkdwn,
+    /// Header, divider and mrkdwn section blocks.
+    Blocks,
+}
+
+/// When to append Bash tool output from the turn to the reply.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BashResultsMode {
+    Off,
+    /// Only when the prompt asks to see output.
+    #[default]
+    Requested,
+    Always,
+}
+
+/// Emoji added to the triggering message as a turn progresses. An empty name skips
+/// that state.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ReactionsConfig {
+    /// Off by default, since it needs the `reactions:write` scope.
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_received_emoji")]
+    pub received: String,
+    #[serde(default = "default_working_emoji")]
+    pub working: String,
+    #[serde(default = "default_done_emoji")]
+    pub done: String,
+    #[serde(default = "default_failed_emoji")]
+    pub failed: String,
+    /// Reactions on ccterm replies that run an action, by emoji name. Not affected by
+    /// `enabled`.
+    #[serde(default)]
+    pub actions: BTreeMap<String, ReactionAction>,
+    /// Reactions on people's messages that send the message to the channel's session,
+    /// by emoji name, with the instruction placed before it. Not affected by `enabled`.
+    #[serde(default)]
+    pub prompts: BTreeMap<String, String>,
+}
+
+/// What a reaction on a ccterm reply does.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReactionAction {
+    /// Send the prompt that produced the reply again.
+    Rerun,
+    /// Continue from the reply in a new thread with a copy of the session.
+    Fork,
+    /// Upload the session transcript.
+    Transcript,
+}
+
+/// Append-only record of messages, turns, and errors in the state directory.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AuditConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Records older than this are purged by the coordinator.
+    #[serde(default)]
+    pub retention_days: Option<u64>,
+    /// Store `[redacted]` instead of message and error text.
+    #[serde(default)]
+    pub redact_content: bool,
+}
+
+/// Diagnostic log written to stderr by `ccterm serve`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    /// `tracing` filter directives, e.g. `info` or `info,ccterm::slack_adapter=debug`.
+    /// `serve --log-level` takes precedence.
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    #[serde(default)]
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: default_log_level(),
+            format: LogFormat::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One human-readable line per event.
+    #[default]
+    Text,
+    /// One JSON object per line, with span fields, for log collectors.
+    Json,
+}
+
+/// Send tool permission prompts to an external approval system and answer them from its
+/// signed callback.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ApprovalWebhookConfig {
+    /// Receives each permission request as a signed JSON POST.
+    pub url: String,
+    /// HMAC-SHA256 key for request and callback signatures.
+    pub secret: String,
+    /// Address of the callback handler.
+    #[serde(default = "default_approval_listen")]
+    pub listen: String,
+    /// Callback URL sent with each request, when the handler is reached through a proxy.
+    /// Defaults to `http://<listen>/approval/callback`.
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    /// Prompts without a decision after this long are denied.
+    #[serde(default = "default_approval_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Also post the Slack buttons (with `coordinator.permission_buttons`); the first
+    /// answer wins. Off, so the approval system stays the only way to approve.
+    #[serde(default)]
+    pub slack_buttons: bool,
+}
+
+/// Fetch URLs in prompts and add their content, for hosts on the allowlist.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct UrlFetchConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Hosts whose URLs are fetched; subdomains are included.
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+    /// URLs fetched per message.
+    #[serde(default = "default_url_fetch_max_urls")]
+    pub max_urls: usize,
+    /// Larger responses are not added.
+    #[serde(default = "default_url_fetch_max_bytes")]
+    pub max_bytes: u64,
+    /// Longer documents are truncated.
+    #[serde(default = "default_url_fetch_max_chars")]
+    pub max_chars: usize,
+    #[serde(default = "default_url_fetch_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for UrlFetchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            allowed_domains: Vec::new(),
+            max_urls: default_url_fetch_max_urls(),
+            max_bytes: default_url_fetch_max_bytes(),
+            max_chars: default_url_fetch_max_chars(),
+            timeout_secs: default_url_fetch_timeout_secs(),
+        }
+    }
+}
+
+/// Daily window during which prompts are held and delivered when it ends.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QuietHoursConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// `HH:MM`, inclusive.
+    #[serde(default)]
+    pub start: String,
+    /// `HH:MM`, exclusive. Earlier than `start` means the window spans midnight.
+    #[serde(default)]
+    pub end: String,
+    /// IANA zone name (`Asia/Tokyo`); the host's zone when unset.
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TurnStatus {
+    Received,
+    Working,
+    Done,
+    Failed,
+}
+
+impl ReactionsConfig {
+    pub fn emoji(&self, status: TurnStatus) -> Option<&str> {
+        let name = match status {
+            TurnStatus::Received => &self.received,
+            TurnStatus::Working => &self.working,
+            TurnStatus::Done => &self.done,
+            TurnStatus::Failed => &self.failed,
+        };
+        let name = name.trim().trim_matches(':');
+        if name.is_empty() {
+            None
+        } else {
+            Some(name)
+        }
+    }
+
+    pub fn action(&self, emoji: &str) -> Option<ReactionAction> {
+        self.actions
+            .iter()
+            .find(|(name, _)| name.trim().trim_matches(':') == emoji)
+            .map(|(_, action)| *action)
+    }
+
+    pub fn prompt(&self, emoji: &str) -> Option<&str> {
+        self.prompts
+            .iter()
+            .find(|(name, _)| name.trim().trim_matches(':') == emoji)
+            .map(|(_, instruction)| instruction.as_str())
+    }
+}
+
+/// Per-channel overrides. The first route whose `channel` matches the conversation id
+/// applies; unset fields fall back to the global sections.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    pub channel: String,
+    #[serde(default)]
+    pub max_turn_secs: Option<u64>,
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub reactions: Option<bool>,
+    #[serde(default)]
+    pub output: Option<OutputTarget>,
+    #[serde(default)]
+    pub reply_format: Option<ReplyFormat>,
+    #[serde(default)]
+    pub input_guard: Option<bool>,
+    #[serde(default)]
+    pub patch_approval: Option<PatchApproval>,
+    #[serde(default)]
+    pub auto_commit: Option<AutoCommit>,
+    #[serde(default)]
+    pub git_diff: Option<GitDiff>,
+    #[serde(default)]
+    pub preflight_max_tokens: Option<u64>,
+    #[serde(default)]
+    pub preflight_action: Option<PreflightAction>,
+    #[serde(default)]
+    pub preview_command: Option<String>,
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHoursConfig>,
+    /// Rank of the channel's thread sessions under `max_sessions`; higher wins. Unrouted
+    /// channels are 0.
+    #[serde(default)]
+    pub priority: i32,
 }
 
 impl Default for ClaudeConfig {
@@ -55,6 +700,22 @@ impl Default for ClaudeConfig {
         Self {
             command: default_claude_cmd(),
             cwd: default_cwd(),
+            default_profile: None,
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for ReactionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            received: default_received_emoji(),
+            working: default_working_emoji(),
+            done: default_done_emoji(),
+            failed: default_failed_emoji(),
+            actions: BTreeMap::new(),
+            prompts: BTreeMap::new(),
         }
     }
 }
@@ -63,6 +724,8 @@ impl Default for TmuxConfig {
     fn default() -> Self {
         Self {
             session_prefix: default_session_prefix(),
+            control_mode: false,
+            notify_attached: false,
         }
     }
 }
@@ -71,6 +734,7 @@ impl Default for HooksConfig {
     fn default() -> Self {
         Self {
             events_path: default_hooks_path(),
+            secrets: Vec::new(),
         }
     }
 }
@@ -80,31 +744,528 @@ impl Default for CoordinatorConfig {
         Self {
             hook_timeout_secs: default_hook_timeout_secs(),
             prompt_timeout_ms: default_prompt_timeout_ms(),
+            spawn_retries: default_spawn_retries(),
+            spawn_retry_backoff_ms: default_spawn_retry_backoff_ms(),
+            main_context_messages: 0,
+            max_turn_secs: None,
+            max_sessions: None,
+            idle_session_secs: None,
+            idle_resume_secs: default_idle_resume_secs(),
+            health_check_secs: None,
+            health_failures: default_health_failures(),
+            unhealthy_action: UnhealthyAction::default(),
+            quarantine_failures: None,
+            quarantine_window_secs: default_quarantine_window_secs(),
+            self_test: SelfTestMode::default(),
+            self_test_timeout_secs: default_self_test_timeout_secs(),
+            reply_truncate_chars: 0,
+            bash_results: BashResultsMode::default(),
+            output: OutputTarget::default(),
+            reply_format: ReplyFormat::default(),
+            input_guard: false,
+            unfurl_permalinks: UnfurlPermalinks::default(),
+            diff_upload_lines: default_diff_upload_lines(),
+            patch_approval: PatchApproval::default(),
+            auto_commit: AutoCommit::default(),
+            git_diff: GitDiff::default(),
+            auto_push_remote: default_auto_push_remote(),
+            thread_workspace: ThreadWorkspace::default(),
+            preview_command: None,
+            preview_timeout_secs: default_preview_timeout_secs(),
+            task_results_to_session: false,
+            user_error_message: default_user_error_message(),
+            admin_channel: None,
+            max_session_memory_mb: None,
+            disk_soft_limit_mb: None,
+            disk_hard_limit_mb: None,
+            render_plans: false,
+            permission_buttons: false,
+            permission_approvers: Vec::new(),
+            turn_artifacts: false,
+            accept_uploads: false,
+            preflight_max_tokens: None,
+            preflight_action: PreflightAction::default(),
+            thread_titles: false,
+            progress_interval_ms: default_progress_interval_ms(),
+            progress_updates: ProgressUpdates::default(),
+            files_changed: false,
+            upload_referenced_files: false,
+            upload_globs: Vec::new(),
+            reasoning_summary: false,
+            maintenance_message: default_maintenance_message(),
+            timezone: None,
         }
     }
 }
 
+/// Top-level table of named overlays selected with `--profile`.
+const CONFIG_PROFILES_KEY: &str = "profiles";
+
+/// Overrides applied on top of the config file, in order: the `[profiles.<name>]` table,
+/// then each `key.path=value` from `--set`.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigLayers {
+    pub profile: Option<String>,
+    pub overrides: Vec<String>,
+}
+
 impl Config {
-    pub fn load(path: &Path) -> Result<Self> {
+    pub fn load(path: &Path, layers: &ConfigLayers) -> Result<Self> {
         let content = fs::read_to_string(path)
             .with_context(|| format!("failed to read config: {}", path.display()))?;
-        let cfg: Config = toml::from_str(&content).context("failed to parse config toml")?;
+        let mut root = parse_config_table(path, &content)?;
+        let profiles = root.remove(CONFIG_PROFILES_KEY);
+        if let Some(name) = &layers.profile {
+            let overlay = profiles
+                .as_ref()
+                .and_then(|profiles| profiles.get(name))
+                .and_then(toml::Value::as_table)
+                .with_context(|| format!("unknown config profile: {name}"))?;
+            merge_tables(&mut root, overlay.clone());
+        }
+        for item in &layers.overrides {
+            apply_override(&mut root, item)?;
+        }
+        let cfg: Config = toml::Value::Table(root)
+            .try_into()
+            .context("failed to parse config toml")?;
 
-        if cfg.slack.bot_token.trim().is_empty() || cfg.slack.app_token.trim().is_empty() {
-            bail!("slack.bot_token and slack.app_token are required");
+        if cfg.slack.app_token.trim().is_empty() {
+            bail!("slack.app_token is required");
         }
+        if cfg.slack.bot_token.trim().is_empty() && cfg.slack.oauth.is_none() {
+            bail!("slack.bot_token is required unless slack.oauth is set");
+        }
+        if cfg.hooks.secrets.iter().any(|secret| secret.trim().is_empty()) {
+            bail!("hooks.secrets must not contain empty keys");
+        }
+        let profile_refs = cfg
+            .routes
+            .iter()
+            .filter_map(|route| route.profile.as_ref())
+            .chain(cfg.claude.default_profile.as_ref());
+        for name in profile_refs {
+            if !cfg.claude.profiles.contains_key(name) {
+                bail!("unknown claude profile: {name}");
+            }
+        }
+        cfg.permissions
+            .compile()
+            .context("invalid permissions rule")?;
+        cfg.validate_ranges()?;
+        plugins::validate(&cfg.plugins)?;
+        let quiet_hours = cfg
+            .routes
+            .iter()
+            .filter_map(|route| route.quiet_hours.as_ref())
+            .chain(cfg.quiet_hours.as_ref())
+            .filter(|quiet| quiet.enabled);
+        for quiet in quiet_hours {
+            quiet::validate(quiet)?;
+        }
+        cfg.coordinator.time_zone()?;
         Ok(cfg)
     }
+
+    /// Reject timeouts that are zero or large enough to be a unit mistake.
+    fn validate_ranges(&self) -> Result<()> {
+        let coordinator = &self.coordinator;
+        check_range(
+            "coordinator.hook_timeout_secs",
+            coordinator.hook_timeout_secs,
+            1..=3600,
+        )?;
+        check_range(
+            "coordinator.prompt_timeout_ms",
+            coordinator.prompt_timeout_ms,
+            100..=600_000,
+        )?;
+        check_range(
+            "coordinator.spawn_retries",
+            u64::from(coordinator.spawn_retries),
+            0..=10,
+        )?;
+        check_range(
+            "coordinator.spawn_retry_backoff_ms",
+            coordinator.spawn_retry_backoff_ms,
+            100..=60_000,
+        )?;
+        check_range(
+            "coordinator.progress_interval_ms",
+            coordinator.progress_interval_ms,
+            100..=60_000,
+        )?;
+        check_range(
+            "coordinator.preview_timeout_secs",
+            coordinator.preview_timeout_secs,
+            1..=86_400,
+        )?;
+        if let Some(secs) = coordinator.max_turn_secs {
+            check_range("coordinator.max_turn_secs", secs, 1..=86_400)?;
+        }
+        if let Some(max) = coordinator.max_sessions {
+            check_range("coordinator.max_sessions", max, 1..=1000)?;
+        }
+        if let Some(secs) = coordinator.idle_session_secs {
+            check_range("coordinator.idle_session_secs", secs, 60..=2_592_000)?;
+        }
+        check_range(
+            "coordinator.idle_resume_secs",
+            coordinator.idle_resume_secs,
+            0..=2_592_000,
+        )?;
+        if let Some(secs) = coordinator.health_check_secs {
+            check_range("coordinator.health_check_secs", secs, 10..=3600)?;
+        }
+        check_range(
+            "coordinator.health_failures",
+            u64::from(coordinator.health_failures),
+            1..=100,
+        )?;
+        if let Some(failures) = coordinator.quarantine_failures {
+            check_range("coordinator.quarantine_failures", u64::from(failures), 1..=100)?;
+        }
+        check_range(
+            "coordinator.quarantine_window_secs",
+            coordinator.quarantine_window_secs,
+            60..=86_400,
+        )?;
+        check_range(
+            "coordinator.self_test_timeout_secs",
+            coordinator.self_test_timeout_secs,
+            10..=3600,
+        )?;
+        if let Some(mb) = coordinator.max_session_memory_mb {
+            check_range("coordinator.max_session_memory_mb", mb, 64..=1_048_576)?;
+        }
+        if let Some(mb) = coordinator.disk_soft_limit_mb {
+            check_range("coordinator.disk_soft_limit_mb", mb, 1..=100_000_000)?;
+        }
+        if let Some(mb) = coordinator.disk_hard_limit_mb {
+            check_range("coordinator.disk_hard_limit_mb", mb, 1..=100_000_000)?;
+        }
+        if let (Some(soft), Some(hard)) =
+            (coordinator.disk_soft_limit_mb, coordinator.disk_hard_limit_mb)
+            && soft > hard
+        {
+            bail!("coordinator.disk_soft_limit_mb ({soft}) exceeds disk_hard_limit_mb ({hard})");
+        }
+        if let Some(tokens) = coordinator.preflight_max_tokens {
+            check_range("coordinator.preflight_max_tokens", tokens, 1000..=10_000_000)?;
+        }
+        if let Some(webhook) = &self.approval_webhook {
+            check_range("approval_webhook.timeout_secs", webhook.timeout_secs, 10..=86_400)?;
+        }
+        if let Some(days) = self.audit.retention_days {
+            check_range("audit.retention_days", days, 1..=36_500)?;
+        }
+        let url_fetch = &self.url_fetch;
+        check_range("url_fetch.max_urls", url_fetch.max_urls as u64, 1..=10)?;
+        check_range("url_fetch.max_bytes", url_fetch.max_bytes, 1024..=50_000_000)?;
+        check_range("url_fetch.max_chars", url_fetch.max_chars as u64, 100..=200_000)?;
+        check_range("url_fetch.timeout_secs", url_fetch.timeout_secs, 1..=120)?;
+        if url_fetch.enabled && url_fetch.allowed_domains.is_empty() {
+            bail!("url_fetch.enabled needs at least one entry in url_fetch.allowed_domains");
+        }
+        for (idx, route) in self.routes.iter().enumerate() {
+            if let Some(secs) = route.max_turn_secs {
+                check_range(&format!("routes[{idx}].max_turn_secs"), secs, 1..=86_400)?;
+            }
+            if let Some(tokens) = route.preflight_max_tokens {
+                let key = format!("routes[{idx}].preflight_max_tokens");
+                check_range(&key, tokens, 1000..=10_000_000)?;
+            }
+        }
+        for (idx, plugin) in self.plugins.iter().enumerate() {
+            check_range(
+                &format!("plugins[{idx}].timeout_secs"),
+                plugin.timeout_secs,
+                1..=86_400,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn route(&self, conversation_id: &str) -> Option<&RouteConfig> {
+        self.routes
+            .iter()
+            .find(|route| route.channel == conversation_id)
+    }
+
+    pub fn claude_profile(&self, conversation_id: &str) -> Option<(&str, &ClaudeProfile)> {
+        let name = self
+            .route(conversation_id)
+            .and_then(|route| route.profile.as_deref())
+            .or(self.claude.default_profile.as_deref())?;
+        self.claude
+            .profiles
+            .get_key_value(name)
+            .map(|(name, profile)| (name.as_str(), profile))
+    }
+
+    pub fn reactions_enabled(&self, conversation_id: &str) -> bool {
+        self.route(conversation_id)
+            .and_then(|route| route.reactions)
+            .unwrap_or(self.reactions.enabled)
+    }
+
+    pub fn output(&self, conversation_id: &str) -> OutputTarget {
+        self.route(conversation_id)
+            .and_then(|route| route.output)
+            .unwrap_or(self.coordinator.output)
+    }
+
+    pub fn reply_format(&self, conversation_id: &str) -> ReplyFormat {
+        self.route(conversation_id)
+            .and_then(|route| route.reply_format)
+            .unwrap_or(self.coordinator.reply_format)
+    }
+
+    pub fn input_guard(&self, conversation_id: &str) -> bool {
+        self.route(conversation_id)
+            .and_then(|route| route.input_guard)
+            .unwrap_or(self.coordinator.input_guard)
+    }
+
+    pub fn patch_approval(&self, conversation_id: &str) -> PatchApproval {
+        self.route(conversation_id)
+            .and_then(|route| route.patch_approval)
+            .unwrap_or(self.coordinator.patch_approval)
+    }
+
+    pub fn auto_commit(&self, conversation_id: &str) -> AutoCommit {
+        self.route(conversation_id)
+            .and_then(|route| route.auto_commit)
+            .unwrap_or(self.coordinator.auto_commit)
+    }
+
+    pub fn git_diff(&self, conversation_id: &str) -> GitDiff {
+        self.route(conversation_id)
+            .and_then(|route| route.git_diff)
+            .unwrap_or(self.coordinator.git_diff)
+    }
+
+    /// Token limit and action of the pre-flight check, when one applies.
+    pub fn preflight(&self, conversation_id: &str) -> Option<(u64, PreflightAction)> {
+        let route = self.route(conversation_id);
+        let max_tokens = route
+            .and_then(|route| route.preflight_max_tokens)
+            .or(self.coordinator.preflight_max_tokens)?;
+        let action = route
+            .and_then(|route| route.preflight_action)
+            .unwrap_or(self.coordinator.preflight_action);
+        Some((max_tokens, action))
+    }
+
+    pub fn priority(&self, conversation_id: &str) -> i32 {
+        self.route(conversation_id).map_or(0, |route| route.priority)
+    }
+
+    pub fn preview_command(&self, conversation_id: &str) -> Option<&str> {
+        self.route(conversation_id)
+            .and_then(|route| route.preview_command.as_deref())
+            .or(self.coordinator.preview_command.as_deref())
+            .filter(|command| !command.trim().is_empty())
+    }
+
+    pub fn quiet_hours(&self, conversation_id: &str) -> Option<&QuietHoursConfig> {
+        self.route(conversation_id)
+            .and_then(|route| route.quiet_hours.as_ref())
+            .or(self.quiet_hours.as_ref())
+            .filter(|quiet| quiet.enabled)
+    }
+
+    pub fn max_turn_secs(&self, conversation_id: &str) -> Option<u64> {
+        self.route(conversation_id)
+            .and_then(|route| route.max_turn_secs)
+            .or(self.coordinator.max_turn_secs)
+    }
+}
+
+pub fn check_range(key: &str, value: u64, range: std::ops::RangeInclusive<u64>) -> Result<()> {
+    if !range.contains(&value) {
+        bail!(
+            "{key} must be between {} and {} (got {value})",
+            range.start(),
+            range.end()
+        );
+    }
+    Ok(())
+}
+
+/// Parse the config file into a TOML table, by extension: `.yaml`/`.yml` and `.json`
+/// are accepted alongside TOML. Nulls are dropped since TOML has no null.
+fn parse_config_table(path: &Path, content: &str) -> Result<toml::Table> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    let value: serde_json::Value = match extension.as_deref() {
+        Some("yaml" | "yml") => {
+            serde_yaml_ng::from_str(content).context("failed to parse config yaml")?
+        }
+        Some("json") => serde_json::from_str(content).context("failed to parse config json")?,
+        _ => return toml::from_str(content).context("failed to parse config toml"),
+    };
+    serde_json::from_value(drop_nulls(value)).context("config root must be a table")
+}
+
+fn drop_nulls(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => map
+            .into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| (key, drop_nulls(value)))
+            .collect(),
+        serde_json::Value::Array(items) => items
+            .into_iter()
+            .filter(|item| !item.is_null())
+            .map(drop_nulls)
+            .collect(),
+        other => other,
+    }
+}
+
+/// Merge `overlay` into `base`; nested tables merge, other values (including arrays
+/// such as `routes`) replace.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => {
+                merge_tables(existing, table)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Apply a `--set key.path=value` override. The value is read as a TOML value
+/// (`600`, `true`, `["a"]`) and falls back to a plain string.
+fn apply_override(root: &mut toml::Table, item: &str) -> Result<()> {
+    let (path, raw) = item
+        .split_once('=')
+        .with_context(|| format!("--set expects key=value: {item}"))?;
+    let keys: Vec<&str> = path.trim().split('.').collect();
+    if keys.iter().any(|key| key.is_empty()) {
+        bail!("invalid --set key: {path}");
+    }
+    let value = toml::from_str::<toml::Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()));
+
+    let (last, parents) = keys.split_last().expect("keys are non-empty");
+    let mut table = root;
+    for key in parents {
+        let entry = table
+            .entry(key.to_string())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        table = entry
+            .as_table_mut()
+            .with_context(|| format!("--set {path}: `{key}` is not a table"))?;
+    }
+    table.insert(last.to_string(), value);
+    Ok(())
 }
 
 fn default_claude_cmd() -> String {
     "claude".to_string()
 }
 
+fn default_message_shortcut() -> String {
+    "ask_ccterm".to_string()
+}
+
+fn default_oauth_listen() -> String {
+    "127.0.0.1:3000".to_string()
+}
+
+fn default_approval_listen() -> String {
+    "127.0.0.1:3001".to_string()
+}
+
+fn default_approval_timeout_secs() -> u64 {
+    600
+}
+
+fn default_url_fetch_max_urls() -> usize {
+    3
+}
+
+fn default_url_fetch_max_bytes() -> u64 {
+    1_000_000
+}
+
+fn default_url_fetch_max_chars() -> usize {
+    20_000
+}
+
+fn default_url_fetch_timeout_secs() -> u64 {
+    10
+}
+
+fn default_oauth_scopes() -> Vec<String> {
+    [
+        "app_mentions:read",
+        "channels:history",
+        "chat:write",
+        "files:write",
+        "reactions:read",
+        "reactions:write",
+        "users:read",
+        "canvases:write",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
 fn default_cwd() -> PathBuf {
     std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."))
 }
 
+fn default_diff_upload_lines() -> usize {
+    150
+}
+
+fn default_auto_push_remote() -> String {
+    "origin".to_string()
+}
+
+fn default_preview_timeout_secs() -> u64 {
+    600
+}
+
+fn default_maintenance_message() -> String {
+    "ccterm is down for maintenance and will be back shortly. `!status` still works."
+        .to_string()
+}
+
+fn default_user_error_message() -> String {
+    "Sorry, I couldn't reach the coding session. Please try again.".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_received_emoji() -> String {
+    "eyes".to_string()
+}
+
+fn default_working_emoji() -> String {
+    "hourglass_flowing_sand".to_string()
+}
+
+fn default_done_emoji() -> String {
+    "white_check_mark".to_string()
+}
+
+fn default_failed_emoji() -> String {
+    "x".to_string()
+}
+
 fn default_session_prefix() -> String {
     "ccterm".to_string()
 }
@@ -120,3 +1281,35 @@ fn default_hook_timeout_secs() -> u64 {
 fn default_prompt_timeout_ms() -> u64 {
     10_000
 }
+
+fn default_spawn_retries() -> u32 {
+    2
+}
+
+fn default_spawn_retry_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_progress_interval_ms() -> u64 {
+    3000
+}
+
+fn default_idle_resume_secs() -> u64 {
+    86_400
+}
+
+fn default_health_failures() -> u32 {
+    3
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_quarantine_window_secs() -> u64 {
+    600
+}
+
+fn default_self_test_timeout_secs() -> u64 {
+    120
+}
@@ -1,33 +1,168 @@
-use crate::types::{Role, TranscriptMessage};
+use crate::types::{Role, ThreadMarker, TranscriptMessage};
 use anyhow::{Context, Result};
+use jiff::tz::TimeZone;
+use jiff::Timestamp;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use tracing::{info, warn};
 
-pub fn read_history(path: &Path, cutoff_ts: Option<&str>) -> Result<Vec<TranscriptMessage>> {
-    let file = File::open(path)
-        .with_context(|| format!("failed to open transcript: {}", path.display()))?;
-    let reader = BufReader::new(file);
-    let cutoff = cutoff_ts.and_then(parse_slack_ts_to_nanos);
-    if cutoff_ts.is_some() && cutoff.is_none() {
-        eprintln!("history cutoff ignored due to invalid Slack timestamp");
+/// First line of context files written by ccterm, so they can be safely regenerated.
+pub const GENERATED_CONTEXT_MARKER: &str = "<!-- generated by ccterm -->";
+
+/// Prompts asking to see command output, for `prompt_requests_output`.
+static OUTPUT_REQUEST: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(show|print|paste|post|share|give)\b.{0,40}\b(output|outputs|result|results|log|logs|stdout|stderr)\b",
+    )
+    .expect("valid output request regex")
+});
+
+const BASH_OUTPUT_MAX_CHARS: usize = 2500;
+/// Length of the condensed reasoning appended to a reply.
+const REASONING_MAX_CHARS: usize = 600;
+
+#[derive(Debug, Clone)]
+pub struct BashResult {
+    pub command: String,
+    pub output: String,
+    pub stderr: String,
+    pub exit_code: i32,
+}
+
+/// Tool activity after the most recent user prompt in a transcript.
+#[derive(Debug, Clone, Default)]
+pub struct TurnSummary {
+    pub prompt: Option<String>,
+    pub bash_results: Vec<BashResult>,
+    /// Paths passed to file-editing tools, in first-use order.
+    pub changed_files: Vec<String>,
+    pub usage: TokenUsage,
+    /// `stop_reason` of the last assistant message (`end_turn`, `max_tokens`, ...).
+    pub stop_reason: Option<String>,
+    /// Text of the turn's thinking blocks, in order.
+    pub thinking: Vec<String>,
+}
+
+/// Token counts summed over the assistant messages of a turn.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+}
+
+impl TokenUsage {
+    fn add(&mut self, other: &TokenUsage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
     }
+}
 
-    let mut out = Vec::new();
-    for line in reader.lines() {
-        let line = line.context("failed to read transcript line")?;
-        if line.trim().is_empty() {
-            continue;
+/// Reading position in a session's transcript, so each read parses only the lines
+/// appended since the last one. A transcript that shrank, or a different path, is read
+/// again from the start.
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptCursor {
+    path: Option<PathBuf>,
+    /// Bytes parsed so far, always at the end of a line.
+    offset: u64,
+    history: Vec<TranscriptMessage>,
+    /// Uuid and text of the last assistant message with text.
+    latest_assistant: Option<(String, String)>,
+}
+
+impl TranscriptCursor {
+    /// Parse the lines appended to `path` since the last read. A last line without a
+    /// newline is taken when it parses and otherwise left for the next read, since
+    /// Claude may still be writing it.
+    pub fn read(&mut self, path: &Path) -> Result<()> {
+        let len = std::fs::metadata(path)
+            .with_context(|| format!("failed to open transcript: {}", path.display()))?
+            .len();
+        let same_path = self.path.as_deref() == Some(path);
+        if !same_path || len < self.offset {
+            if same_path {
+                info!("transcript shrank, reading it again: {}", path.display());
+            }
+            *self = Self {
+                path: Some(path.to_path_buf()),
+                ..Self::default()
+            };
         }
-        let value: Value =
-            serde_json::from_str(&line).with_context(|| "failed to parse transcript JSON")?;
-        let msg = parse_transcript_line(&value, cutoff)?;
-        if let Some(msg) = msg {
-            out.push(msg);
+        if len == self.offset {
+            return Ok(());
         }
+        let mut file = File::open(path)
+            .with_context(|| format!("failed to open transcript: {}", path.display()))?;
+        file.seek(SeekFrom::Start(self.offset))
+            .context("failed to seek in transcript")?;
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader
+                .read_line(&mut line)
+                .context("failed to read transcript line")?;
+            if read == 0 {
+                break;
+            }
+            if line.trim().is_empty() {
+                self.offset += read as u64;
+                continue;
+            }
+            let value: Value = match serde_json::from_str(&line) {
+                Ok(value) => value,
+                Err(_) if !line.ends_with('\n') => break,
+                Err(err) => return Err(err).context("failed to parse transcript JSON"),
+            };
+            self.offset += read as u64;
+            if let Some(latest) = assistant_text_uuid(&value) {
+                self.latest_assistant = Some(latest);
+            }
+            if let Some(msg) = parse_transcript_line(&value, None)? {
+                self.history.push(msg);
+            }
+        }
+        Ok(())
+    }
+
+    /// Uuid and text of the last assistant message with text read so far.
+    pub fn latest_assistant(&self) -> Option<&(String, String)> {
+        self.latest_assistant.as_ref()
     }
-    Ok(out)
+
+    /// Messages read so far, without those sent after the Slack timestamp `cutoff_ts`.
+    pub fn history(&self, cutoff_ts: Option<&str>) -> Vec<TranscriptMessage> {
+        let cutoff = cutoff_ts.and_then(parse_slack_ts_to_nanos);
+        if cutoff_ts.is_some() && cutoff.is_none() {
+            warn!("history cutoff ignored due to invalid Slack timestamp");
+        }
+        self.history
+            .iter()
+            .filter(|msg| match (cutoff, msg.timestamp) {
+                (Some(cutoff), Some(ts)) => ts.as_nanosecond() <= cutoff,
+                _ => true,
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+pub fn read_history(path: &Path, cutoff_ts: Option<&str>) -> Result<Vec<TranscriptMessage>> {
+    let mut cursor = TranscriptCursor::default();
+    cursor.read(path)?;
+    Ok(cursor.history(cutoff_ts))
 }
 
 pub fn latest_assistant_text(path: &Path) -> Result<Option<String>> {
@@ -40,12 +175,26 @@ pub fn latest_assistant_text(path: &Path) -> Result<Option<String>> {
     Ok(text)
 }
 
-pub fn latest_assistant_text_uuid(path: &Path) -> Result<Option<(String, String)>> {
+/// Uuid and text of a transcript line holding an assistant message with text.
+fn assistant_text_uuid(value: &Value) -> Option<(String, String)> {
+    if value.get("type").and_then(Value::as_str) != Some("assistant") {
+        return None;
+    }
+    let content = value.pointer("/message/content").unwrap_or(&Value::Null);
+    let text = extract_assistant_text(content).filter(|text| !text.trim().is_empty())?;
+    let uuid = value.get("uuid").and_then(Value::as_str)?;
+    Some((uuid.to_string(), text))
+}
+
+pub fn latest_turn(path: &Path) -> Result<TurnSummary> {
     let file = File::open(path)
         .with_context(|| format!("failed to open transcript: {}", path.display()))?;
     let reader = BufReader::new(file);
 
-    let mut latest: Option<(String, String)> = None;
+    let mut turn = TurnSummary::default();
+    let mut bash_commands: HashMap<String, String> = HashMap::new();
+    // Each content block of a message is its own line repeating the message's usage.
+    let mut usage_by_message: Vec<(String, TokenUsage)> = Vec::new();
     for line in reader.lines() {
         let line = line.context("failed to read transcript line")?;
         if line.trim().is_empty() {
@@ -53,30 +202,234 @@ pub fn latest_assistant_text_uuid(path: &Path) -> Result<Option<(String, String)
         }
         let value: Value =
             serde_json::from_str(&line).with_context(|| "failed to parse transcript JSON")?;
-        let line_type = value
-            .get("type")
-            .and_then(Value::as_str)
-            .unwrap_or_default();
-        if line_type != "assistant" {
+        if value.get("isMeta").and_then(Value::as_bool) == Some(true) {
             continue;
         }
-        let message = value.get("message").unwrap_or(&Value::Null);
-        let content = message.get("content").unwrap_or(&Value::Null);
-        let text = extract_assistant_text(content);
-        let text = match text {
-            Some(text) if !text.trim().is_empty() => text,
-            _ => continue,
-        };
-        let uuid = match value.get("uuid").and_then(Value::as_str) {
-            Some(uuid) => uuid.to_string(),
-            None => continue,
+        let content = value
+            .get("message")
+            .and_then(|message| message.get("content"))
+            .unwrap_or(&Value::Null);
+        match value.get("type").and_then(Value::as_str) {
+            Some("assistant") => {
+                let message = value.get("message").unwrap_or(&Value::Null);
+                if let Some(reason) = message.get("stop_reason").and_then(Value::as_str) {
+                    turn.stop_reason = Some(reason.to_string());
+                }
+                if let (Some(id), Some(usage)) = (
+                    message.get("id").and_then(Value::as_str),
+                    message
+                        .get("usage")
+                        .and_then(|usage| TokenUsage::deserialize(usage).ok()),
+                ) {
+                    match usage_by_message.iter_mut().find(|(known, _)| known == id) {
+                        Some(entry) => entry.1 = usage,
+                        None => usage_by_message.push((id.to_string(), usage)),
+                    }
+                }
+                for item in content.as_array().into_iter().flatten() {
+                    if item.get("type").and_then(Value::as_str) == Some("thinking")
+                        && let Some(thinking) = item.get("thinking").and_then(Value::as_str)
+                        && !thinking.trim().is_empty()
+                    {
+                        turn.thinking.push(thinking.to_string());
+                        continue;
+                    }
+                    if item.get("type").and_then(Value::as_str) != Some("tool_use") {
+                        continue;
+                    }
+                    if let Some(path) = edited_path(item)
+                        && !turn.changed_files.iter().any(|known| known == path)
+                    {
+                        turn.changed_files.push(path.to_string());
+                    }
+                    if item.get("name").and_then(Value::as_str) != Some("Bash") {
+                        continue;
+                    }
+                    let (Some(id), Some(command)) = (
+                        item.get("id").and_then(Value::as_str),
+                        item.pointer("/input/command").and_then(Value::as_str),
+                    ) else {
+                        continue;
+                    };
+                    bash_commands.insert(id.to_string(), command.to_string());
+                }
+            }
+            Some("user") => {
+                let results: Vec<&Value> = content
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter(|item| item.get("type").and_then(Value::as_str) == Some("tool_result"))
+                    .collect();
+                if results.is_empty() {
+                    if let Some(text) = extract_user_text(content)
+                        && !text.trim().is_empty()
+                    {
+                        turn = TurnSummary {
+                            prompt: Some(text),
+                            ..TurnSummary::default()
+                        };
+                        bash_commands.clear();
+                        usage_by_message.clear();
+                    }
+                    continue;
+                }
+                for item in results {
+                    let Some(command) = item
+                        .get("tool_use_id")
+                        .and_then(Value::as_str)
+                        .and_then(|id| bash_commands.remove(id))
+                    else {
+                        continue;
+                    };
+                    turn.bash_results
+                        .push(parse_bash_result(command, item, value.get("toolUseResult")));
+                }
+            }
+            _ => {}
+        }
+    }
+    for (_, usage) in &usage_by_message {
+        turn.usage.add(usage);
+    }
+    Ok(turn)
+}
+
+/// File path of an Edit/Write/MultiEdit/NotebookEdit tool use.
+fn edited_path(tool_use: &Value) -> Option<&str> {
+    let key = match tool_use.get("name").and_then(Value::as_str)? {
+        "Edit" | "Write" | "MultiEdit" => "file_path",
+        "NotebookEdit" => "notebook_path",
+        _ => return None,
+    };
+    tool_use.get("input")?.get(key).and_then(Value::as_str)
+}
+
+/// Rough token count of `text`, at about four characters per token. Good enough to
+/// catch prompts that are far larger than intended, not for billing.
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.chars().count() as u64).div_ceil(4)
+}
+
+/// Whether a prompt asks to see command output ("run the tests and show me the output").
+pub fn prompt_requests_output(prompt: &str) -> bool {
+    OUTPUT_REQUEST.is_match(prompt)
+}
+
+/// Render a Bash result as a fenced block under an exit code header, keeping the tail
+/// of long output.
+pub fn format_bash_result(result: &BashResult) -> String {
+    let mut out = format!(
+        "*`{}`* exited with code {}\n",
+        first_line(&result.command),
+        result.exit_code
+    );
+    let output = result.output.trim_end();
+    let stderr = result.stderr.trim_end();
+    if output.is_empty() && stderr.is_empty() {
+        out.push_str("_(no output)_");
+        return out;
+    }
+    if !output.is_empty() {
+        out.push_str(&fence(output));
+    }
+    if !stderr.is_empty() {
+        if !output.is_empty() {
+            out.push('\n');
+        }
+        out.push_str("stderr:\n");
+        out.push_str(&fence(stderr));
+    }
+    out
+}
+
+/// A quoted "Reasoning" section condensed from thinking blocks: the first paragraph of
+/// each, cut to `REASONING_MAX_CHARS` overall.
+pub fn format_reasoning(thinking: &[String]) -> Option<String> {
+    let mut lines = Vec::new();
+    let mut remaining = REASONING_MAX_CHARS;
+    for block in thinking {
+        if remaining == 0 {
+            break;
+        }
+        let paragraph = block
+            .trim()
+            .split("\n\n")
+            .next()
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if paragraph.is_empty() {
+            continue;
+        }
+        let line = match paragraph.char_indices().nth(remaining) {
+            Some((idx, _)) => format!("{}…", &paragraph[..idx]),
+            None => paragraph,
         };
-        latest = Some((uuid, text));
+        remaining = remaining.saturating_sub(line.chars().count());
+        lines.push(format!("> {line}"));
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    Some(format!("> _Reasoning_\n{}", lines.join("\n")))
+}
+
+fn parse_bash_result(command: String, item: &Value, tool_use_result: Option<&Value>) -> BashResult {
+    let content = match item.get("content") {
+        Some(Value::String(text)) => text.clone(),
+        Some(other) => extract_user_text(other).unwrap_or_default(),
+        None => String::new(),
+    };
+    let is_error = item.get("is_error").and_then(Value::as_bool) == Some(true);
+    let mut exit_code = if is_error { 1 } else { 0 };
+    let mut output = content.clone();
+    if is_error
+        && let Some(rest) = content.strip_prefix("Exit code ")
+    {
+        let (code, body) = rest.split_once('\n').unwrap_or((rest, ""));
+        if let Ok(code) = code.trim().parse() {
+            exit_code = code;
+            output = body.to_string();
+        }
+    }
+
+    let mut stderr = String::new();
+    if !is_error
+        && let Some(Value::Object(result)) = tool_use_result
+    {
+        if let Some(stdout) = result.get("stdout").and_then(Value::as_str) {
+            output = stdout.to_string();
+        }
+        if let Some(text) = result.get("stderr").and_then(Value::as_str) {
+            stderr = text.to_string();
+        }
+    }
+
+    BashResult {
+        command,
+        output,
+        stderr,
+        exit_code,
     }
-    Ok(latest)
 }
 
-pub fn format_history_context(history: &[TranscriptMessage]) -> Option<String> {
+fn fence(text: &str) -> String {
+    let text = match text.char_indices().rev().nth(BASH_OUTPUT_MAX_CHARS) {
+        Some((idx, _)) => format!("…{}", &text[idx..]),
+        None => text.to_string(),
+    };
+    format!("```\n{}\n```", text.replace("```", "`\u{200b}``"))
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default()
+}
+
+/// Prior messages for a new session's context file. Each line carries its send time in
+/// `tz` when known, so Claude can tell how old the discussion is.
+pub fn format_history_context(history: &[TranscriptMessage], tz: &TimeZone) -> Option<String> {
     if history.is_empty() {
         return None;
     }
@@ -89,15 +442,14 @@ pub fn format_history_context(history: &[TranscriptMessage]) -> Option<String> {
     out.push_str("You do not need to focus on it unless it is useful.\n\n");
     out.push_str("## Prior Messages\n");
     for msg in history {
-        match msg.role {
-            Role::User => {
-                out.push_str("User: ");
-                out.push_str(&msg.text);
-            }
-            Role::Assistant => {
-                out.push_str("Assistant: ");
-                out.push_str(&msg.text);
-            }
+        if let Some(ts) = msg.timestamp {
+            out.push_str(&format!("[{}] ", local_time(ts, tz)));
+        }
+        out.push_str(speaker_label(msg));
+        out.push_str(": ");
+        out.push_str(&msg.text);
+        if let Some(marker) = msg.thread {
+            out.push_str(&format!(" {}", thread_note(marker)));
         }
         out.push('\n');
     }
@@ -105,6 +457,55 @@ pub fn format_history_context(history: &[TranscriptMessage]) -> Option<String> {
     Some(out)
 }
 
+/// A transcript as Markdown for people: one section per message, headed by the role and
+/// its time in `tz`.
+pub fn format_transcript(history: &[TranscriptMessage], tz: &TimeZone) -> String {
+    let mut out = String::new();
+    for msg in history {
+        let role = speaker_label(msg);
+        match msg.timestamp {
+            Some(ts) => out.push_str(&format!("### {role} · {}\n\n", local_time(ts, tz))),
+            None => out.push_str(&format!("### {role}\n\n")),
+        }
+        out.push_str(msg.text.trim_end());
+        out.push_str("\n\n");
+        if let Some(marker) = msg.thread {
+            out.push_str(&format!("_{}_\n\n", thread_note(marker)));
+        }
+    }
+    out
+}
+
+/// A message linked from a prompt, quoted under a line naming its sender and time in
+/// `tz`.
+pub fn format_linked_message(msg: &TranscriptMessage, tz: &TimeZone) -> String {
+    let mut out = format!("Linked message from {}", speaker_label(msg));
+    if let Some(ts) = msg.timestamp {
+        out.push_str(&format!(" [{}]", local_time(ts, tz)));
+    }
+    out.push_str(":\n");
+    let quoted: Vec<String> = msg.text.lines().map(|line| format!("> {line}")).collect();
+    out.push_str(&quoted.join("\n"));
+    out
+}
+
+/// The sender's display name when known, otherwise `User` or `Assistant`.
+fn speaker_label(msg: &TranscriptMessage) -> &str {
+    match (&msg.speaker, &msg.role) {
+        (Some(name), Role::User) => name,
+        (_, Role::User) => "User",
+        (_, Role::Assistant) => "Assistant",
+    }
+}
+
+fn thread_note(marker: ThreadMarker) -> String {
+    match marker {
+        ThreadMarker::Parent { replies: 1 } => "(started a thread, 1 reply)".to_string(),
+        ThreadMarker::Parent { replies } => format!("(started a thread, {replies} replies)"),
+        ThreadMarker::Reply => "(reply in a thread)".to_string(),
+    }
+}
+
 fn parse_transcript_line(value: &Value, cutoff_ts: Option<i128>) -> Result<Option<TranscriptMessage>> {
     let line_type = value
         .get("type")
@@ -116,12 +517,11 @@ fn parse_transcript_line(value: &Value, cutoff_ts: Option<i128>) -> Result<Optio
     }
 
     let timestamp = value.get("timestamp").and_then(Value::as_str);
-    if let (Some(cutoff), Some(ts)) = (cutoff_ts, timestamp) {
-        if let Some(ts_nanos) = parse_iso_ts_to_nanos(ts) {
-            if ts_nanos > cutoff {
-                return Ok(None);
-            }
-        }
+    if let (Some(cutoff), Some(ts)) = (cutoff_ts, timestamp)
+        && let Some(ts_nanos) = parse_iso_ts_to_nanos(ts)
+        && ts_nanos > cutoff
+    {
+        return Ok(None);
     }
 
     let message = value.get("message").unwrap_or(&Value::Null);
@@ -147,9 +547,25 @@ fn parse_transcript_line(value: &Value, cutoff_ts: Option<i128>) -> Result<Optio
     Ok(Some(TranscriptMessage {
         role,
         text,
+        timestamp: timestamp
+            .and_then(parse_iso_ts_to_nanos)
+            .and_then(|nanos| Timestamp::from_nanosecond(nanos).ok()),
+        // Prompts already start with the sender's name; see `IncomingMessage::prompt_text`.
+        speaker: None,
+        thread: None,
     }))
 }
 
+/// Time of a Slack message `ts` (`<unix seconds>.<micros>`).
+pub fn slack_ts_time(ts: &str) -> Option<Timestamp> {
+    Timestamp::from_nanosecond(parse_slack_ts_to_nanos(ts)?).ok()
+}
+
+/// `2026-10-16 14:03 JST`, for timestamps read by people.
+pub fn local_time(ts: Timestamp, tz: &TimeZone) -> String {
+    ts.to_zoned(tz.clone()).strftime("%Y-%m-%d %H:%M %Z").to_string()
+}
+
 fn parse_slack_ts_to_nanos(ts: &str) -> Option<i128> {
     let (secs, frac) = ts.split_once('.')?;
     let secs: i128 = secs.parse().ok()?;
@@ -197,7 +613,7 @@ fn parse_fractional_nanos(frac: &str) -> Option<i128> {
     }
     let mut value: i128 = 0;
     for &b in digits {
-        if !(b'0'..=b'9').contains(&b) {
+        if !b.is_ascii_digit() {
             return None;
         }
         value = value * 10 + i128::from(b - b'0');
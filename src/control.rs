@@ -0,0 +1,92 @@
+use crate::hooks::HookFollower;
+use crate::paths;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Operator commands appended by `ccterm ctl` and tailed by the running coordinator,
+/// under `paths::state_dir`.
+pub const CONTROL_FILE: &str = "control.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlCommand {
+    Broadcast {
+        text: String,
+    },
+    /// Refuse new turns with a notice. `message` replaces
+    /// `coordinator.maintenance_message` until maintenance is turned off.
+    Maintenance {
+        enabled: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
+}
+
+pub fn control_path(base_cwd: &Path) -> PathBuf {
+    paths::state_dir(base_cwd).join(CONTROL_FILE)
+}
+
+pub fn append(path: &Path, command: &ControlCommand) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create control dir: {}", parent.display()))?;
+    }
+    let mut line = serde_json::to_string(command).context("failed to encode control command")?;
+    line.push('\n');
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open control file: {}", path.display()))?;
+    file.write_all(line.as_bytes())
+        .context("failed to append control command")?;
+    file.flush().context("failed to flush control file")?;
+    Ok(())
+}
+
+pub fn spawn_control_receiver(path: PathBuf) -> mpsc::UnboundedReceiver<ControlCommand> {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    thread::spawn(move || {
+        let mut follower = match HookFollower::open(&path, true) {
+            Ok(f) => f,
+            Err(err) => {
+                warn!("control receiver failed to open: {err}");
+                return;
+            }
+        };
+
+        loop {
+            match follower.wait_for_line(Duration::from_secs(3600)) {
+                Ok(line) => {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str::<ControlCommand>(&line) {
+                        Ok(command) => {
+                            if tx.send(command).is_err() {
+                                return;
+                            }
+                        }
+                        Err(err) => {
+                            warn!("control receiver parse error: {err}");
+                        }
+                    }
+                }
+                Err(err) => {
+                    warn!("control receiver error: {err}");
+                }
+            }
+        }
+    });
+
+    rx
+}
@@ -1,15 +1,106 @@
-use crate::config::Config;
-use crate::context;
+use crate::approval::{self, ApprovalDecision, PermissionRequest};
+use crate::audit;
+use crate::changes::{self, FileChange};
+use crate::chat::ChatAdapter;
+use crate::clock::{Clock, TokioClock};
+use crate::commands::{self, ChatCommand, TurnDirectives};
+use crate::config::{
+    AutoCommit, BashResultsMode, Config, GitDiff, OutputTarget, PatchApproval, PreflightAction,
+    ProgressUpdates, ReactionAction, ReplyFormat, ThreadWorkspace, TurnStatus, UnfurlPermalinks,
+    UnhealthyAction,
+};
+use crate::context::{self, TranscriptCursor, TurnSummary};
+use crate::diff;
+use crate::events::{self, CoordinatorEvent, EventBus, TurnOutcome};
+use crate::git;
+use crate::guard;
+use crate::control::{self, ControlCommand};
 use crate::hooks::{self, HookEvent};
-use crate::sessions::{self, TmuxSessionManager};
+use crate::mrkdwn;
+use crate::paths;
+use crate::permalinks;
+use crate::permissions::{self, PermissionAction};
+use crate::plan::{self, PlanUpdate};
+use crate::plugins::{self, PluginConfig, PluginRequest};
+use crate::preview;
+use crate::quiet;
+use crate::random;
+use crate::resources::{self, ProcessTable, ProcessUsage};
+use crate::send_queue::{SendOutcome, SendQueues, TurnSetup};
+use crate::sessions::{self, SpawnOptions, TmuxSessionManager};
 use crate::slack_adapter::SlackAdapter;
-use crate::types::{IncomingMessage, OutgoingMessage};
-use anyhow::{bail, Context, Result};
+use crate::snapshot;
+use crate::state::{
+    ChannelSettings, MaintenanceState, ParkedRecord, SessionFingerprint, SessionRecord,
+    StateStore, TurnRecord, APPROVER_SETTING_KEYS,
+};
+use crate::tmux;
+use crate::url_fetch;
+use crate::types::{
+    ActionEvent, HomeSession, HomeView, IncomingEvent, IncomingMessage, OutgoingMessage,
+    ReactionEvent, Role, ShortcutEvent,
+};
+use anyhow::{bail, Context, Error, Result};
+use jiff::tz::TimeZone;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
 use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{debug, info, info_span, warn, Instrument, Span};
+
+const TURN_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const RESOURCE_SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(120);
+/// Longest wait between spawn attempts, however many failed before.
+const MAX_SPAWN_BACKOFF: Duration = Duration::from_secs(60);
+/// How long a message held by the pre-flight check waits for Approve/Reject.
+const PREFLIGHT_EXPIRY: Duration = Duration::from_secs(3600);
+const QUOTA_ACTION_PREFIX: &str = "ccterm_quota";
+const PLAN_ACTION_PREFIX: &str = "ccterm_plan";
+const PERMISSION_ACTION_PREFIX: &str = "ccterm_permission";
+/// Pane lines quoted with a permission prompt, enough for the tool call and the options.
+const PERMISSION_PROMPT_LINES: usize = 20;
+/// Longest prompt typed in a session's terminal that is mirrored to chat as is.
+const TERMINAL_INPUT_MAX_CHARS: usize = 2000;
+/// Message links in one prompt that are looked up and quoted.
+const MAX_UNFURLED_LINKS: usize = 3;
+/// Longest linked message quoted into a prompt.
+const UNFURLED_MAX_CHARS: usize = 4000;
+/// Longest chat message shown in an attached terminal's status line.
+const ATTACHED_NOTICE_MAX_CHARS: usize = 200;
+const HOME_ACTION_PREFIX: &str = "ccterm_home";
+/// Denied tool calls listed in one progress notice.
+const MAX_PROGRESS_LINES: usize = 10;
+/// Recent turns listed on the App Home tab.
+const HOME_RECENT_TURNS: usize = 5;
+/// Per-turn output directories, relative to the session cwd.
+const ARTIFACTS_DIR: &str = ".ccterm/artifacts";
+const UPLOADS_DIR: &str = ".ccterm/uploads";
+/// Largest shared file saved for a session.
+const MAX_UPLOAD_BYTES: usize = 100 * 1024 * 1024;
+const MAX_ARTIFACT_FILES: usize = 20;
+const MAX_ARTIFACT_BYTES: u64 = 100 * 1024 * 1024;
+const FULL_REPLY_REACTION: &str = "heavy_plus_sign";
+const MAX_TRUNCATED_REPLIES: usize = 200;
+const MAX_RECORDED_REPLIES: usize = 200;
+const MAX_PROMPTED_REACTIONS: usize = 200;
+const CHANNEL_SETTINGS_STATE: &str = "channel_settings";
+const MAINTENANCE_STATE: &str = "maintenance";
+const TURNS_STATE: &str = "turns";
+/// How much of the end of `turns.jsonl` `!history`, App Home and button checks read.
+const TURNS_READ_BYTES: u64 = 4 * 1024 * 1024;
+const SESSIONS_STATE: &str = "sessions";
+const PARKED_STATE: &str = "parked";
+const PATCH_ACTION_PREFIX: &str = "ccterm_patch";
+const PREFLIGHT_ACTION_PREFIX: &str = "ccterm_preflight";
+const PATCH_APPROVED_PROMPT: &str =
+    "ccterm: The user approved the patch you proposed. Apply it and commit the change.";
+const THREAD_TITLE_CHARS: usize = 60;
+const RESUMED_NOTICE: &str = ":arrow_forward: Resumed the stopped session.";
+const UNDO_PROMPT: &str = "ccterm: The user undid the file changes of your last turn";
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 struct ConversationKey {
@@ -17,30 +108,312 @@ struct ConversationKey {
     thread_id: Option<String>,
 }
 
+/// Full text of a reply that was posted truncated, keyed by the posted message.
+#[derive(Debug, Clone)]
+struct TruncatedReply {
+    thread_id: Option<String>,
+    text: String,
+}
+
+/// A posted reply that reaction actions refer back to, keyed by the posted message.
+#[derive(Debug, Clone)]
+struct ReplyRecord {
+    key: ConversationKey,
+    thread_id: Option<String>,
+    /// Prompt of the turn that produced the reply, as Claude received it.
+    prompt: Option<String>,
+    claude_session_id: String,
+    transcript_path: PathBuf,
+}
+
+/// Auxiliary session started with `!spawn`, reporting back into the thread it came from.
+#[derive(Debug, Clone)]
+struct SpawnedTask {
+    /// 1-based, per thread.
+    number: usize,
+    label: String,
+    conversation_id: String,
+    thread_id: String,
+    session_name: String,
+    result: Option<String>,
+    /// Included in a combined summary already.
+    joined: bool,
+}
+
+/// A tool permission prompt waiting for an answer from Slack or the approval webhook.
+#[derive(Debug, Clone)]
+struct PendingPermission {
+    key: ConversationKey,
+    /// Who sent the prompt of the turn that asked; they may answer besides
+    /// `permission_approvers`.
+    requester: Option<String>,
+    /// The prompt's message, updated with the answer.
+    message_ts: Option<String>,
+    /// Set when the approval webhook was asked; the prompt is denied after it.
+    deadline: Option<Instant>,
+}
+
+/// A diff from a reply waiting for Approve/Reject.
+#[derive(Debug, Clone)]
+struct PendingPatch {
+    key: ConversationKey,
+    cwd: PathBuf,
+    diff: String,
+    mode: PatchApproval,
+}
+
+/// A prompt prepared as it would have been sent, held over the pre-flight token limit
+/// waiting for Approve/Reject, or waiting for its session to start.
+#[derive(Debug, Clone)]
+struct PreparedPrompt {
+    msg: IncomingMessage,
+    text: String,
+    directives: TurnDirectives,
+}
+
+/// A prompt over the pre-flight token limit and the notice asking to approve it.
+#[derive(Debug, Clone)]
+struct PendingPreflight {
+    prompt: PreparedPrompt,
+    /// Conversation and ts of the notice, updated when the hold expires.
+    notice: (String, String),
+    expires_at: Instant,
+}
+
+/// Prompts for a conversation whose session failed to start, waiting for the next
+/// attempt.
+#[derive(Debug, Default)]
+struct SpawnRetry {
+    /// Attempts made so far.
+    attempts: u32,
+    queued: Vec<PreparedPrompt>,
+}
+
+/// Estimated size of a turn before it is sent.
+#[derive(Debug, Clone, Copy)]
+struct TurnEstimate {
+    prompt_tokens: u64,
+    /// Conversation context a thread session that does not exist yet is seeded with.
+    context_tokens: u64,
+}
+
+impl TurnEstimate {
+    fn total(&self) -> u64 {
+        self.prompt_tokens + self.context_tokens
+    }
+
+    fn describe(&self) -> String {
+        if self.context_tokens == 0 {
+            return format!("about {} tokens", self.total());
+        }
+        format!(
+            "about {} tokens ({} of them the main conversation this thread starts with)",
+            self.total(),
+            self.context_tokens
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 struct SessionEntry {
     session_name: String,
     last_transcript_path: Option<PathBuf>,
+    /// Parsed part of the transcript. Shared by clones of the entry, which stand for the
+    /// same session.
+    transcript: Arc<Mutex<TranscriptCursor>>,
     last_sent_message_uuid: Option<String>,
+    turn_started_at: Option<Instant>,
+    /// Slack `ts` of messages delivered in the current turn, marked done on `Stop`.
+    status_ts: Vec<String>,
+    /// Set when a turn was interrupted for exceeding `max_turn_secs`. The next message
+    /// checks the prompt and respawns the session if the interrupt did not land.
+    timed_out: bool,
+    resources: Option<ResourceSample>,
+    disk: DiskQuotaState,
+    /// Artifacts directory of the running turn, uploaded and cleared on `Stop`.
+    artifacts_dir: Option<PathBuf>,
+    /// TODO list message of the running turn, updated in place on each `TodoWrite`.
+    todo_ts: Option<String>,
+    working: WorkingStatus,
+    /// When the session was started or last began or finished a turn, for
+    /// `idle_session_secs`.
+    last_active: Instant,
+    /// Last message delivered to the session, for `!retry`.
+    last_message: Option<IncomingMessage>,
+    /// Commits made by `auto_commit`, oldest first; `!undo` reverts the last one.
+    turn_commits: Vec<String>,
+    /// Thread title from `coordinator.thread_titles`.
+    title: Option<String>,
+    progress: ProgressBatch,
+    /// The prompt was seen after the last `Stop` and nothing was sent since, so the next
+    /// send skips waiting for it.
+    prompt_ready: bool,
+    fingerprint: SessionFingerprint,
+    /// `/model` and `/permissions` of the last queued prompt, switched back before the
+    /// next one.
+    model_override: Option<String>,
+    permissions_override: Option<PermissionAction>,
+    /// `/timeout` of the running turn, in place of `max_turn_secs`.
+    turn_max_secs: Option<u64>,
+    health: SessionHealth,
+    delivery: DeliveryFailures,
+}
+
+/// Results of the `health_check_secs` probes of a session.
+#[derive(Debug, Clone, Default)]
+struct SessionHealth {
+    /// Consecutive failed probes.
+    failures: u32,
+    last_error: Option<String>,
+    /// Set once `failures` reaches `health_failures`, cleared by a passing probe.
+    unhealthy_since: Option<Instant>,
+}
+
+/// Failed deliveries of a session, counted against `quarantine_failures`.
+#[derive(Debug, Clone, Default)]
+struct DeliveryFailures {
+    /// When each failure within `quarantine_window_secs` happened, oldest first.
+    recent: VecDeque<Instant>,
+    /// Set once the budget is spent; only `!restart` lifts it.
+    quarantined_since: Option<Instant>,
+}
+
+/// Hook-driven updates waiting for the next progress flush.
+#[derive(Debug, Clone, Default)]
+struct ProgressBatch {
+    /// Latest rendered TODO list; versions replaced within one interval are never posted.
+    todos: Option<String>,
+    /// Tool calls denied by the permission policy, as `tool` `argument`.
+    denied: Vec<String>,
+    /// Tool calls made, as `tool` `argument`, for `progress_updates`.
+    tools: Vec<String>,
+}
+
+impl ProgressBatch {
+    fn is_empty(&self) -> bool {
+        self.todos.is_none() && self.denied.is_empty() && self.tools.is_empty()
+    }
+}
+
+/// A session stopped for idleness, or found gone after a crash or restart, resumable
+/// with `--resume` for a while.
+#[derive(Debug, Clone)]
+struct ParkedSession {
+    claude_session_id: String,
+    /// Shown in the stop notice; a reply containing it resumes even after the grace period.
+    token: String,
+    /// Wall-clock time, so the grace period holds across restarts.
+    parked_at: jiff::Timestamp,
 }
 
-pub struct Coordinator {
+/// The "Working…" message of the running turn with `progress_updates = "status"`.
+#[derive(Debug, Clone, Default)]
+struct WorkingStatus {
+    ts: Option<String>,
+    /// Tool calls of the turn so far; the latest few are shown.
+    calls: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct DiskQuotaState {
+    /// Last measured size of the thread directory.
+    used_bytes: Option<u64>,
+    soft_warned: bool,
+    /// Over the hard limit; messages are held back until confirmed or the limit rises.
+    paused: bool,
+    /// Someone chose to continue past the hard limit for this session.
+    confirmed: bool,
+}
+
+/// Latest CPU/memory sample of a session's process tree.
+#[derive(Debug, Clone)]
+struct ResourceSample {
+    pane_pid: u32,
+    usage: ProcessUsage,
+    cpu_percent: f64,
+    sampled_at: Instant,
+}
+
+pub struct Coordinator<C: ChatAdapter = SlackAdapter> {
     config: Config,
     sessions: TmuxSessionManager,
-    slack: SlackAdapter,
+    chat: C,
+    clock: Arc<dyn Clock>,
+    events: EventBus,
     hook_tx: mpsc::UnboundedSender<HookEvent>,
     hook_rx: mpsc::UnboundedReceiver<HookEvent>,
+    control_rx: mpsc::UnboundedReceiver<ControlCommand>,
+    /// Messages produced by background tasks (e.g. previews), posted from the main loop.
+    notice_tx: mpsc::UnboundedSender<OutgoingMessage>,
+    notice_rx: mpsc::UnboundedReceiver<OutgoingMessage>,
+    /// Sessions whose prompt came back after a `Stop`, reported by the pre-warm check.
+    ready_tx: mpsc::UnboundedSender<String>,
+    ready_rx: mpsc::UnboundedReceiver<String>,
+    /// Per-session prompt queues, and what their sender tasks report back.
+    send_queues: SendQueues,
+    send_rx: mpsc::UnboundedReceiver<SendOutcome>,
+    /// Signed decisions from the approval webhook's callback handler.
+    approval_tx: mpsc::UnboundedSender<ApprovalDecision>,
+    approval_rx: mpsc::UnboundedReceiver<ApprovalDecision>,
     sessions_by_key: HashMap<ConversationKey, SessionEntry>,
     key_by_cwd: HashMap<PathBuf, ConversationKey>,
     main_by_conversation: HashMap<String, ConversationKey>,
     hook_paths_by_cwd: HashMap<PathBuf, PathBuf>,
+    /// Receivers started by `register_hook_receiver`, stopped when a worktree goes away.
+    hook_receivers: HashMap<PathBuf, hooks::ReceiverHandle>,
+    truncated_replies: HashMap<(String, String), TruncatedReply>,
+    truncated_order: VecDeque<(String, String)>,
+    replies: HashMap<(String, String), ReplyRecord>,
+    reply_order: VecDeque<(String, String)>,
+    /// Channel, message `ts`, and emoji of reaction prompts already sent, so further
+    /// people adding the same reaction do not send the message again.
+    prompted_reactions: VecDeque<(String, String, String)>,
+    canvases_by_key: HashMap<ConversationKey, String>,
+    pending_patches: HashMap<String, PendingPatch>,
+    pending_preflight: HashMap<String, PendingPreflight>,
+    spawn_retries: HashMap<ConversationKey, SpawnRetry>,
+    /// Conversations whose spawn backoff is over, sent by the timer of `schedule_spawn_retry`.
+    spawn_retry_tx: mpsc::UnboundedSender<ConversationKey>,
+    spawn_retry_rx: mpsc::UnboundedReceiver<ConversationKey>,
+    /// Hard-limit confirmations waiting for a button press, by approval id.
+    pending_quota: HashMap<String, ConversationKey>,
+    /// Plans waiting for Approve plan / Keep planning, by approval id.
+    pending_plans: HashMap<String, ConversationKey>,
+    /// Tool permission prompts waiting for Approve / Always allow / Deny, by approval id.
+    pending_permissions: HashMap<String, PendingPermission>,
+    state: StateStore,
+    /// Sessions as last written to the `sessions` state file.
+    saved_sessions: Vec<SessionRecord>,
+    /// Last `parked` state written, to skip unchanged saves.
+    saved_parked: Vec<ParkedRecord>,
+    /// Messages received during quiet hours, delivered in order once the window ends.
+    quiet_queue: Vec<IncomingMessage>,
+    /// Conversations already told about quiet hours since their queue last drained.
+    quiet_notified: HashSet<ConversationKey>,
+    /// Messages for new thread sessions past `max_sessions`, started in order as sessions
+    /// end.
+    capacity_queue: Vec<IncomingMessage>,
+    /// Conversations muted with `!mute`, until the given time or, without one, their next
+    /// reply.
+    muted: HashMap<ConversationKey, Option<Instant>>,
+    /// Thread sessions stopped by `idle_session_secs`, by conversation.
+    parked: HashMap<ConversationKey, ParkedSession>,
+    tasks_by_cwd: HashMap<PathBuf, SpawnedTask>,
+    channel_settings: BTreeMap<String, ChannelSettings>,
+    maintenance: MaintenanceState,
     settings_template: String,
+    /// Zone for timestamps in thread context and transcript exports.
+    time_zone: TimeZone,
     base_cwd: PathBuf,
     ccterm_path: PathBuf,
+    /// `claude --version`, read once at startup for session fingerprints.
+    claude_version: Option<String>,
+    /// Print what would be done with incoming events instead of doing it.
+    dry_run: bool,
 }
 
-impl Coordinator {
-    pub fn new(config: Config, sessions: TmuxSessionManager, slack: SlackAdapter) -> Result<Self> {
+impl<C: ChatAdapter> Coordinator<C> {
+    pub fn new(config: Config, sessions: TmuxSessionManager, chat: C) -> Result<Self> {
         let base_cwd = normalize_path(config.claude.cwd.clone());
         let settings_path = base_cwd.join(".claude/settings.json");
         let settings_template = std::fs::read_to_string(&settings_path).with_context(|| {
@@ -52,254 +425,4826 @@ impl Coordinator {
         let ccterm_path = std::env::current_exe()
             .context("failed to resolve ccterm path")?;
         let ccterm_path = ccterm_path.canonicalize().unwrap_or(ccterm_path);
+        let time_zone = config.coordinator.time_zone()?;
+        let claude_version = sessions::claude_version(&config.claude.command)
+            .inspect_err(|err| warn!("claude version unknown: {err:#}"))
+            .ok();
+
+        hooks::install_secret(&hooks::secret_path(&base_cwd), &config.hooks.secrets)?;
+        let state = StateStore::new(&base_cwd);
+        let channel_settings = state.load(CHANNEL_SETTINGS_STATE)?;
+        let maintenance: MaintenanceState = state.load(MAINTENANCE_STATE)?;
+        if maintenance.enabled {
+            info!("starting in maintenance mode");
+        }
 
         let (hook_tx, hook_rx) = mpsc::unbounded_channel();
+        let control_rx = control::spawn_control_receiver(control::control_path(&base_cwd));
+        let (notice_tx, notice_rx) = mpsc::unbounded_channel();
+        let (ready_tx, ready_rx) = mpsc::unbounded_channel();
+        let (approval_tx, approval_rx) = mpsc::unbounded_channel();
+        let (spawn_retry_tx, spawn_retry_rx) = mpsc::unbounded_channel();
+        let (send_tx, send_rx) = mpsc::unbounded_channel();
+        let clock: Arc<dyn Clock> = Arc::new(TokioClock);
+        let send_queues = SendQueues::new(sessions.clone(), clock.clone(), send_tx);
         Ok(Self {
             config,
             sessions,
-            slack,
+            chat,
+            clock,
+            events: EventBus::new(),
             hook_tx,
             hook_rx,
+            control_rx,
+            notice_tx,
+            notice_rx,
+            ready_tx,
+            ready_rx,
+            send_queues,
+            send_rx,
+            approval_tx,
+            approval_rx,
             sessions_by_key: HashMap::new(),
             key_by_cwd: HashMap::new(),
             main_by_conversation: HashMap::new(),
             hook_paths_by_cwd: HashMap::new(),
+            hook_receivers: HashMap::new(),
+            truncated_replies: HashMap::new(),
+            truncated_order: VecDeque::new(),
+            replies: HashMap::new(),
+            reply_order: VecDeque::new(),
+            prompted_reactions: VecDeque::new(),
+            canvases_by_key: HashMap::new(),
+            pending_patches: HashMap::new(),
+            pending_preflight: HashMap::new(),
+            spawn_retries: HashMap::new(),
+            spawn_retry_tx,
+            spawn_retry_rx,
+            pending_quota: HashMap::new(),
+            pending_plans: HashMap::new(),
+            pending_permissions: HashMap::new(),
+            state,
+            saved_sessions: Vec::new(),
+            saved_parked: Vec::new(),
+            quiet_queue: Vec::new(),
+            quiet_notified: HashSet::new(),
+            capacity_queue: Vec::new(),
+            muted: HashMap::new(),
+            parked: HashMap::new(),
+            tasks_by_cwd: HashMap::new(),
+            channel_settings,
+            maintenance,
             settings_template,
+            time_zone,
             base_cwd,
             ccterm_path,
+            claude_version,
+            dry_run: false,
         })
     }
 
+    /// Route messages and build context, but print the resulting actions instead of
+    /// spawning sessions or posting to Slack.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Time waits, timeouts and periodic checks with `clock` instead of tokio's, e.g. a
+    /// paused test clock. Call before `run`.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        let (send_tx, send_rx) = mpsc::unbounded_channel();
+        self.send_queues = SendQueues::new(self.sessions.clone(), clock.clone(), send_tx);
+        self.send_rx = send_rx;
+        self.clock = clock;
+        self
+    }
+
     pub async fn run(mut self) -> Result<()> {
         let prompt_timeout = Duration::from_millis(self.config.coordinator.prompt_timeout_ms);
         let _hook_timeout = Duration::from_secs(self.config.coordinator.hook_timeout_secs);
+        let mut turn_check = tokio::time::interval(TURN_CHECK_INTERVAL);
+        let mut resource_check = tokio::time::interval(RESOURCE_SAMPLE_INTERVAL);
+        let mut disk_check = tokio::time::interval(DISK_CHECK_INTERVAL);
+        let health_period = self.config.coordinator.health_check_secs.map(Duration::from_secs);
+        let mut next_health_check = health_period.map(|period| self.clock.now() + period);
+        let mut progress_flush = tokio::time::interval(Duration::from_millis(
+            self.config.coordinator.progress_interval_ms,
+        ));
+        info!("running on {}", self.chat.name());
+        events::spawn_logger(&self.events);
+        if self.config.audit.enabled {
+            audit::spawn_writer(
+                &self.events,
+                audit::audit_path(&self.base_cwd),
+                self.config.audit.clone(),
+            );
+        }
+
+        if let Some(cfg) = &self.config.approval_webhook
+            && !self.dry_run
+        {
+            approval::start(cfg.clone(), self.approval_tx.clone()).await?;
+        }
+        self.restore_sessions();
 
         loop {
             tokio::select! {
-                maybe_msg = self.slack.incoming().recv() => {
-                    let msg = match maybe_msg {
-                        Some(m) => m,
+                maybe_event = self.chat.incoming().recv() => {
+                    let event = match maybe_event {
+                        Some(e) => e,
                         None => break,
                     };
-                    eprintln!(
-                        "coordinator: incoming slack message channel={} thread={} text_len={}",
-                        msg.conversation_id,
-                        msg.thread_id.as_deref().unwrap_or("-"),
-                        msg.text.len()
-                    );
-                    if let Err(err) = self.handle_incoming(msg, prompt_timeout).await {
-                        eprintln!("incoming error: {err}");
+                    match event {
+                        IncomingEvent::Message(msg) => self.on_message(msg, prompt_timeout).await,
+                        IncomingEvent::HomeOpened(user) if self.dry_run => {
+                            println!("dry-run: app home opened by {user}: not published")
+                        }
+                        IncomingEvent::HomeOpened(user) => {
+                            if let Err(err) = self.publish_home(&user).await {
+                                self.publish_error("home", &err);
+                            }
+                        }
+                        IncomingEvent::Shortcut(shortcut) => {
+                            if let Some(msg) = self.shortcut_message(shortcut).await {
+                                self.on_message(msg, prompt_timeout).await;
+                            }
+                        }
+                        IncomingEvent::Reaction(reaction) if self.dry_run => println!(
+                            "dry-run: reaction :{}: on {} in {}: not handled",
+                            reaction.reaction, reaction.message_ts, reaction.conversation_id
+                        ),
+                        IncomingEvent::Reaction(reaction) => {
+                            if let Err(err) = self.handle_reaction(reaction, prompt_timeout).await {
+                                self.publish_error("reaction", &err);
+                            }
+                        }
+                        IncomingEvent::Action(action) if self.dry_run => {
+                            println!("dry-run: button {}: not handled", action.action_id)
+                        }
+                        IncomingEvent::Action(action) => {
+                            if let Err(err) = self.handle_action(action, prompt_timeout).await {
+                                self.publish_error("action", &err);
+                            }
+                        }
                     }
                 }
                 maybe_hook = self.hook_rx.recv() => {
-                    if let Some(hook) = maybe_hook {
-                        if let Err(err) = self.handle_hook(hook).await {
-                            eprintln!("hook error: {err}");
+                    if let Some(hook) = maybe_hook
+                        && let Err(err) = self.handle_hook(hook).await
+                    {
+                        self.publish_error("hook", &err);
+                    }
+                }
+                maybe_control = self.control_rx.recv() => {
+                    if let Some(command) = maybe_control {
+                        if self.dry_run {
+                            println!("dry-run: control command {command:?}: not applied");
+                        } else {
+                            self.handle_control(command).await;
+                        }
+                    }
+                }
+                maybe_ready = self.ready_rx.recv() => {
+                    if let Some(session_name) = maybe_ready {
+                        self.mark_prompt_ready(&session_name);
+                    }
+                }
+                maybe_outcome = self.send_rx.recv() => {
+                    if let Some(outcome) = maybe_outcome {
+                        self.on_send_outcome(outcome).await;
+                    }
+                }
+                maybe_decision = self.approval_rx.recv() => {
+                    if let Some(decision) = maybe_decision {
+                        self.publish_webhook_decision(&decision);
+                        let by = match &decision.approver {
+                            Some(approver) => format!("{approver} (approval system)"),
+                            None => "the approval system".to_string(),
+                        };
+                        if let Err(err) =
+                            self.answer_permission(&decision.id, &decision.decision, &by).await
+                        {
+                            self.publish_error("approval", &err);
                         }
                     }
                 }
+                maybe_retry = self.spawn_retry_rx.recv() => {
+                    if let Some(key) = maybe_retry {
+                        self.retry_spawn(key, prompt_timeout).await;
+                    }
+                }
+                maybe_notice = self.notice_rx.recv() => {
+                    if let Some(notice) = maybe_notice
+                        && let Err(err) = self.chat.send(&notice).await
+                    {
+                        warn!("notice post failed: {err}");
+                    }
+                }
+                _ = turn_check.tick() => {
+                    self.check_turn_timeouts().await;
+                    self.stop_idle_sessions().await;
+                    self.flush_capacity_queue(prompt_timeout).await;
+                    self.expire_permissions().await;
+                    self.expire_preflight().await;
+                    self.flush_quiet_queue(prompt_timeout).await;
+                }
+                _ = resource_check.tick() => {
+                    self.sample_resources().await;
+                }
+                _ = disk_check.tick() => {
+                    self.check_disk_quotas().await;
+                }
+                _ = self.clock.sleep(next_health_check.map_or(Duration::ZERO, |next| {
+                    next.saturating_duration_since(self.clock.now())
+                })), if next_health_check.is_some() => {
+                    self.check_health().await;
+                    next_health_check = health_period.map(|period| self.clock.now() + period);
+                }
+                _ = progress_flush.tick() => {
+                    self.flush_all_progress().await;
+                }
             }
+            self.save_sessions();
+            self.save_parked();
         }
         Ok(())
     }
 
-    async fn handle_incoming(&mut self, msg: IncomingMessage, prompt_timeout: Duration) -> Result<()> {
-        if msg.thread_id.is_none() {
-            let entry = self.ensure_main_session(&msg, prompt_timeout)?;
-            self.enqueue_send(&entry, msg.text, prompt_timeout)?;
-        } else {
-            let entry = self.ensure_thread_session(&msg, prompt_timeout)?;
-            self.enqueue_send(&entry, msg.text, prompt_timeout)?;
+    /// Reattach to the sessions saved by a previous run whose tmux sessions are still
+    /// alive. The rest are dropped and spawned again on their next message.
+    fn restore_sessions(&mut self) {
+        self.restore_parked();
+        let records: Vec<SessionRecord> = match self.state.load(SESSIONS_STATE) {
+            Ok(records) => records,
+            Err(err) => {
+                warn!("saved sessions not loaded: {err:#}");
+                return;
+            }
+        };
+        for record in &records {
+            if !self.sessions.has_session(&record.session_name) || !record.cwd.is_dir() {
+                info!("saved session {} is gone", record.session_name);
+                let key = ConversationKey {
+                    conversation_id: record.conversation_id.clone(),
+                    thread_id: record.thread_id.clone(),
+                };
+                self.keep_resumable(
+                    &key,
+                    &record.session_name,
+                    record.last_transcript_path.as_deref(),
+                );
+                continue;
+            }
+            let hook_path = self.hook_path_for_cwd(&record.cwd);
+            if let Err(err) = self.register_hook_receiver(&record.cwd, &hook_path) {
+                warn!("session {} not restored: {err:#}", record.session_name);
+                continue;
+            }
+            self.sessions.watch(&record.session_name);
+            let key = ConversationKey {
+                conversation_id: record.conversation_id.clone(),
+                thread_id: record.thread_id.clone(),
+            };
+            let entry = SessionEntry {
+                session_name: record.session_name.clone(),
+                last_transcript_path: record.last_transcript_path.clone(),
+                transcript: Arc::default(),
+                last_sent_message_uuid: record.last_sent_message_uuid.clone(),
+                turn_started_at: None,
+                status_ts: Vec::new(),
+                timed_out: false,
+                resources: None,
+                disk: DiskQuotaState::default(),
+                artifacts_dir: None,
+                todo_ts: None,
+                working: WorkingStatus::default(),
+                last_active: self.clock.now(),
+                last_message: None,
+                turn_commits: record.turn_commits.clone(),
+                title: record.title.clone(),
+                progress: ProgressBatch::default(),
+                prompt_ready: false,
+                fingerprint: record.fingerprint.clone(),
+                model_override: None,
+                permissions_override: None,
+                turn_max_secs: None,
+                health: SessionHealth::default(),
+                delivery: DeliveryFailures::default(),
+            };
+            if key.thread_id.is_none() {
+                self.main_by_conversation
+                    .insert(key.conversation_id.clone(), key.clone());
+            }
+            self.sessions_by_key.insert(key.clone(), entry);
+            self.key_by_cwd.insert(normalize_path(record.cwd.clone()), key);
         }
-
-        Ok(())
+        info!(
+            "reattached to {} of {} saved session(s)",
+            self.sessions_by_key.len(),
+            records.len()
+        );
+        self.saved_sessions = records;
     }
 
-    fn ensure_main_session(
-        &mut self,
-        msg: &IncomingMessage,
-        prompt_timeout: Duration,
-    ) -> Result<SessionEntry> {
-        let key = self
-            .main_by_conversation
-            .entry(msg.conversation_id.clone())
-            .or_insert_with(|| ConversationKey {
-                conversation_id: msg.conversation_id.clone(),
-                thread_id: None,
+    /// Write the session mapping to the `sessions` state file when it changed.
+    fn save_sessions(&mut self) {
+        if self.dry_run {
+            return;
+        }
+        let mut records: Vec<SessionRecord> = self
+            .sessions_by_key
+            .iter()
+            .filter_map(|(key, entry)| {
+                // Main sessions of different channels share the base directory, which
+                // `key_by_cwd` maps to only one of them.
+                let cwd = match key.thread_id {
+                    Some(_) => self.cwd_for_key(key)?,
+                    None => self.base_cwd.clone(),
+                };
+                Some(SessionRecord {
+                    conversation_id: key.conversation_id.clone(),
+                    thread_id: key.thread_id.clone(),
+                    session_name: entry.session_name.clone(),
+                    cwd,
+                    last_transcript_path: entry.last_transcript_path.clone(),
+                    last_sent_message_uuid: entry.last_sent_message_uuid.clone(),
+                    title: entry.title.clone(),
+                    turn_commits: entry.turn_commits.clone(),
+                    fingerprint: entry.fingerprint.clone(),
+                })
             })
-            .clone();
+            .collect();
+        records.sort_by(|a, b| {
+            (&a.conversation_id, &a.thread_id).cmp(&(&b.conversation_id, &b.thread_id))
+        });
+        if records == self.saved_sessions {
+            return;
+        }
+        if let Err(err) = self.state.save(SESSIONS_STATE, &records) {
+            warn!("sessions not saved: {err:#}");
+            return;
+        }
+        self.saved_sessions = records;
+    }
 
-        if let Some(entry) = self.sessions_by_key.get(&key) {
-            return Ok(entry.clone());
+    /// Load the stopped sessions saved by a previous run, so replies can still resume
+    /// them.
+    fn restore_parked(&mut self) {
+        let records: Vec<ParkedRecord> = match self.state.load(PARKED_STATE) {
+            Ok(records) => records,
+            Err(err) => {
+                warn!("saved parked sessions not loaded: {err:#}");
+                return;
+            }
+        };
+        for record in &records {
+            let Ok(parked_at) = record.parked_at.parse() else {
+                warn!("parked session {} has no valid time", record.claude_session_id);
+                continue;
+            };
+            let key = ConversationKey {
+                conversation_id: record.conversation_id.clone(),
+                thread_id: record.thread_id.clone(),
+            };
+            self.parked.insert(
+                key,
+                ParkedSession {
+                    claude_session_id: record.claude_session_id.clone(),
+                    token: record.token.clone(),
+                    parked_at,
+                },
+            );
         }
+        self.saved_parked = records;
+    }
 
-        let cwd = self.base_cwd.clone();
-        let hook_path = self.hook_path_for_cwd(&cwd);
-        self.register_hook_receiver(&cwd, &hook_path)?;
+    /// Write the stopped sessions to the `parked` state file when they changed.
+    fn save_parked(&mut self) {
+        if self.dry_run {
+            return;
+        }
+        let mut records: Vec<ParkedRecord> = self
+            .parked
+            .iter()
+            .map(|(key, parked)| ParkedRecord {
+                conversation_id: key.conversation_id.clone(),
+                thread_id: key.thread_id.clone(),
+                claude_session_id: parked.claude_session_id.clone(),
+                token: parked.token.clone(),
+                parked_at: parked.parked_at.to_string(),
+            })
+            .collect();
+        records.sort_by(|a, b| {
+            (&a.conversation_id, &a.thread_id).cmp(&(&b.conversation_id, &b.thread_id))
+        });
+        if records == self.saved_parked {
+            return;
+        }
+        if let Err(err) = self.state.save(PARKED_STATE, &records) {
+            warn!("parked sessions not saved: {err:#}");
+            return;
+        }
+        self.saved_parked = records;
+    }
 
-        let session_name = sessions::timestamp_session_name(&self.config.tmux.session_prefix)?;
-        self.sessions
-            .spawn_in(&session_name, &cwd)
-            .with_context(|| format!("failed to spawn main session {session_name}"))?;
-        sessions::wait_for_prompt(
-            &self.sessions,
-            &session_name,
-            prompt_timeout,
-            Duration::from_millis(200),
-        )?;
+    fn publish_error(&self, context: &'static str, err: &anyhow::Error) {
+        self.events.publish(CoordinatorEvent::Error {
+            context,
+            message: err.to_string(),
+        });
+    }
 
-        let entry = SessionEntry {
-            session_name: session_name.clone(),
-            last_transcript_path: None,
-            last_sent_message_uuid: None,
+    /// Tell the sender that their message was not handled, without internal details,
+    /// and post the full error to `coordinator.admin_channel` when set.
+    async fn report_error(
+        &self,
+        context: &'static str,
+        target: OutgoingMessage,
+        err: &anyhow::Error,
+    ) {
+        let coordinator = &self.config.coordinator;
+        let notice = OutgoingMessage {
+            text: format!(":warning: {}", coordinator.user_error_message),
+            ..target.clone()
         };
-        self.sessions_by_key.insert(key.clone(), entry.clone());
-        self.key_by_cwd.insert(cwd, key);
-        Ok(entry)
+        if let Err(post_err) = self.chat.send(&notice).await {
+            warn!("error notice post failed: {post_err}");
+        }
+        let Some(admin_channel) = &coordinator.admin_channel else {
+            return;
+        };
+        let report = OutgoingMessage {
+            text: format!(
+                "`{context}` error in <#{}> (thread {}):\n```{err:#}```",
+                target.conversation_id,
+                target.thread_id.as_deref().unwrap_or("-")
+            ),
+            conversation_id: admin_channel.clone(),
+            thread_id: None,
+        };
+        if let Err(post_err) = self.chat.send(&report).await {
+            warn!("admin error report failed: {post_err}");
+        }
     }
 
-    fn ensure_thread_session(
-        &mut self,
-        msg: &IncomingMessage,
-        prompt_timeout: Duration,
-    ) -> Result<SessionEntry> {
-        let key = ConversationKey {
-            conversation_id: msg.conversation_id.clone(),
-            thread_id: msg.thread_id.clone(),
+    /// Announce a new session and return its fingerprint, which is also stored on the
+    /// tmux session next to the conversation.
+    async fn publish_spawned(
+        &self,
+        conversation_id: &str,
+        thread_id: Option<&str>,
+        session_name: &str,
+        cwd: &Path,
+        opts: &SpawnOptions,
+    ) -> SessionFingerprint {
+        let fingerprint = self.fingerprint(cwd, opts).await;
+        let summary = fingerprint.summary();
+        let conversation = match thread_id {
+            Some(thread_id) => format!("{conversation_id}/{thread_id}"),
+            None => conversation_id.to_string(),
         };
+        if let Err(err) = self.sessions.set_origin(session_name, &conversation, &summary) {
+            warn!("session origin not recorded: {err}");
+        }
+        self.events.publish(CoordinatorEvent::SessionSpawned {
+            conversation_id: conversation_id.to_string(),
+            thread_id: thread_id.map(str::to_string),
+            session_name: session_name.to_string(),
+            cwd: cwd.to_path_buf(),
+            fingerprint: summary,
+        });
+        fingerprint
+    }
 
-        if let Some(entry) = self.sessions_by_key.get(&key) {
-            return Ok(entry.clone());
+    async fn fingerprint(&self, cwd: &Path, opts: &SpawnOptions) -> SessionFingerprint {
+        let arg = |name: &str| {
+            let idx = opts.args.iter().position(|arg| arg == name)?;
+            opts.args.get(idx + 1).cloned()
+        };
+        let env_model = opts
+            .env
+            .iter()
+            .find(|(key, _)| key == "ANTHROPIC_MODEL")
+            .map(|(_, value)| value.clone());
+        let git_cwd = cwd.to_path_buf();
+        let git_sha = tokio::task::spawn_blocking(move || {
+            git::run(&git_cwd, &["rev-parse", "--short", "HEAD"]).ok()
+        })
+        .await
+        .ok()
+        .flatten();
+        SessionFingerprint {
+            claude_version: self.claude_version.clone(),
+            model: arg("--model").or(env_model),
+            permission_mode: arg("--permission-mode")
+                .unwrap_or_else(|| self.config.permissions.default.as_str().to_string()),
+            git_sha,
         }
+    }
 
-        let thread_id = msg
-            .thread_id
-            .as_deref()
-            .context("thread id missing")?;
-        let cwd = self.ensure_thread_dir(thread_id)?;
-        let hook_path = self.hook_path_for_cwd(&cwd);
-        self.register_hook_receiver(&cwd, &hook_path)?;
+    fn mark_turn_started(&mut self, key: &ConversationKey) {
+        let now = self.clock.now();
+        let Some(entry) = self.sessions_by_key.get_mut(key) else {
+            return;
+        };
+        entry.turn_started_at = Some(now);
+        entry.last_active = now;
+        self.events.publish(CoordinatorEvent::TurnStarted {
+            conversation_id: key.conversation_id.clone(),
+            thread_id: key.thread_id.clone(),
+            session_name: entry.session_name.clone(),
+        });
+    }
 
-        self.ensure_thread_context(&cwd, msg)?;
+    /// Append a finished turn to the `turns` state log.
+    fn record_turn(
+        &self,
+        key: &ConversationKey,
+        started: Option<Instant>,
+        turn: Option<TurnSummary>,
+        stop_reason: Option<&str>,
+        reply_ts: Option<String>,
+    ) {
+        let Some(entry) = self.sessions_by_key.get(key) else {
+            return;
+        };
+        let ended_at = self.clock.timestamp();
+        let elapsed = started
+            .map(|started| self.clock.now().duration_since(started))
+            .unwrap_or_default();
+        let started_at = ended_at.checked_sub(elapsed).unwrap_or(ended_at);
+        let turn = turn.unwrap_or_default();
+        let record = TurnRecord {
+            conversation_id: key.conversation_id.clone(),
+            thread_id: key.thread_id.clone(),
+            session_name: entry.session_name.clone(),
+            user_id: entry
+                .last_message
+                .as_ref()
+                .and_then(|msg| msg.user_id.clone()),
+            prompt: turn.prompt.filter(|_| !self.config.audit.redact_content),
+            started_at: started_at.to_string(),
+            ended_at: ended_at.to_string(),
+            stop_reason: stop_reason
+                .map(str::to_string)
+                .or(turn.stop_reason)
+                .unwrap_or_else(|| "end_turn".to_string()),
+            usage: turn.usage,
+            reply_ts,
+        };
+        if let Err(err) = self.state.append(TURNS_STATE, &record) {
+            warn!("turn record not saved: {err:#}");
+        }
+    }
 
-        let session_name = sessions::timestamp_session_name(&self.config.tmux.session_prefix)?;
-        self.sessions
-            .spawn_in(&session_name, &cwd)
-            .with_context(|| format!("failed to spawn thread session {session_name}"))?;
+    fn publish_turn_completed(&self, key: &ConversationKey, outcome: TurnOutcome) {
+        let Some(entry) = self.sessions_by_key.get(key) else {
+            return;
+        };
+        self.events.publish(CoordinatorEvent::TurnCompleted {
+            conversation_id: key.conversation_id.clone(),
+            thread_id: key.thread_id.clone(),
+            session_name: entry.session_name.clone(),
+            outcome,
+        });
+    }
 
-        sessions::wait_for_prompt(
-            &self.sessions,
-            &session_name,
-            prompt_timeout,
-            Duration::from_millis(200),
-        )?;
+    async fn on_message(&mut self, msg: IncomingMessage, prompt_timeout: Duration) {
+        self.events.publish(CoordinatorEvent::MessageReceived {
+            conversation_id: msg.conversation_id.clone(),
+            thread_id: msg.thread_id.clone(),
+            user_id: msg.user_id.clone(),
+            user_name: msg.user_name.clone(),
+            text: msg.text.clone(),
+        });
+        if self.config.tmux.notify_attached && !self.dry_run {
+            self.notify_attached(&msg);
+        }
+        if self.dry_run {
+            if let Err(err) = self.dry_run_message(&msg).await {
+                self.publish_error("incoming", &err);
+            }
+            return;
+        }
+        let target = error_target(&msg);
+        let span = self.span_for(&ConversationKey {
+            conversation_id: msg.conversation_id.clone(),
+            thread_id: msg.thread_id.clone(),
+        });
+        let result = self.handle_incoming(msg, prompt_timeout).instrument(span).await;
+        if let Err(err) = result {
+            self.publish_error("incoming", &err);
+            self.report_error("incoming", target, &err).await;
+        }
+    }
 
-        let entry = SessionEntry {
-            session_name: session_name.clone(),
-            last_transcript_path: None,
-            last_sent_message_uuid: None,
+    /// Show a chat message in the status line of terminals attached to the session it is
+    /// for, so someone working in the pane sees what the conversation is saying.
+    fn notify_attached(&self, msg: &IncomingMessage) {
+        let Some((_, entry)) = self.session_for(msg) else {
+            return;
         };
-        self.sessions_by_key.insert(key.clone(), entry.clone());
-        self.key_by_cwd.insert(cwd, key);
-        Ok(entry)
+        let clients = match self.sessions.terminal_clients(&entry.session_name) {
+            Ok(clients) => clients,
+            Err(err) => {
+                debug!("no clients listed for {}: {err}", entry.session_name);
+                return;
+            }
+        };
+        if clients.is_empty() {
+            return;
+        }
+        let sender = msg.user_name.as_deref().unwrap_or("someone");
+        let text = msg.text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let notice = format!(
+            "[Slack] {sender}: {}",
+            truncate_chars(&text, ATTACHED_NOTICE_MAX_CHARS)
+        );
+        for client in clients {
+            if let Err(err) = self.sessions.display_message(&client, &notice) {
+                warn!("chat notice not shown on {client}: {err}");
+            }
+        }
     }
 
-    fn build_thread_context(&self, msg: &IncomingMessage) -> Result<ThreadContextDecision> {
-        let main_key = self.main_by_conversation.get(&msg.conversation_id);
-        let main_key = match main_key {
-            Some(key) => key,
-            None => return Ok(ThreadContextDecision::Skipped("main conversation not found")),
+    /// Turn a message shortcut into a message for the session of the channel, or of the
+    /// thread when the message is a reply. The message is quoted with its permalink.
+    async fn shortcut_message(&self, shortcut: ShortcutEvent) -> Option<IncomingMessage> {
+        if shortcut.callback_id != self.config.slack.message_shortcut {
+            warn!("unknown message shortcut {}", shortcut.callback_id);
+            return None;
+        }
+        let author = shortcut.author_name.as_deref().unwrap_or("someone");
+        let source = match self
+            .chat
+            .permalink(&shortcut.conversation_id, &shortcut.message_ts)
+            .await
+        {
+            Ok(link) => format!("this message by {author} ({link})"),
+            Err(err) => {
+                warn!("shortcut permalink failed: {err}");
+                format!("this message by {author}")
+            }
+        };
+        let quoted = match shortcut.message_text.trim() {
+            "" => "> (no text)".to_string(),
+            text => text
+                .lines()
+                .map(|line| format!("> {line}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        Some(IncomingMessage {
+            text: format!("Please look at {source}:\n{quoted}"),
+            user_id: Some(shortcut.user_id),
+            user_name: shortcut.user_name,
+            conversation_id: shortcut.conversation_id,
+            thread_id: shortcut.thread_id,
+            timestamp: Some(shortcut.message_ts),
+            attachments: Vec::new(),
+        })
+    }
+
+    /// Turn a `[reactions.prompts]` reaction into a message for the main session of the
+    /// channel, quoting the reacted message after the configured instruction. Messages
+    /// from bots, including ccterm, are not sent.
+    async fn reaction_prompt(
+        &self,
+        reaction: &ReactionEvent,
+        instruction: &str,
+    ) -> Result<Option<IncomingMessage>> {
+        let channel = &reaction.conversation_id;
+        let ts = &reaction.message_ts;
+        let Some(message) = self.chat.fetch_message(channel, ts, None).await? else {
+            info!("reaction prompt: message {ts} in {channel} not found or has no text");
+            return Ok(None);
+        };
+        if !matches!(message.role, Role::User) {
+            info!("reaction prompt: message {ts} in {channel} is not from a person");
+            return Ok(None);
+        }
+        let author = message.speaker.as_deref().unwrap_or("someone");
+        let source = match self.chat.permalink(channel, ts).await {
+            Ok(link) => format!("Message by {author} ({link})"),
+            Err(err) => {
+                warn!("reaction prompt permalink failed: {err}");
+                format!("Message by {author}")
+            }
+        };
+        let instruction = match instruction.trim() {
+            "" => "Please look at this message",
+            instruction => instruction.trim_end_matches(':'),
+        };
+        Ok(Some(IncomingMessage {
+            text: format!("{instruction}:\n\n{source}:\n{}", quote_lines(&message.text)),
+            user_id: Some(reaction.user_id.clone()),
+            user_name: None,
+            conversation_id: channel.clone(),
+            thread_id: None,
+            timestamp: Some(ts.clone()),
+            attachments: Vec::new(),
+        }))
+    }
+
+    async fn handle_incoming(&mut self, msg: IncomingMessage, prompt_timeout: Duration) -> Result<()> {
+        if let Some(command) = commands::parse(&msg.text) {
+            return self.handle_command(&msg, command, prompt_timeout).await;
+        }
+        if let Some((plugin, args)) = plugins::find(&self.config.plugins, &msg.text) {
+            self.start_plugin(&msg, plugin.clone(), args);
+            return Ok(());
+        }
+        if self.maintenance.enabled {
+            let notice = OutgoingMessage {
+                text: self.maintenance_text(),
+                conversation_id: msg.conversation_id.clone(),
+                thread_id: msg.thread_id.clone(),
+            };
+            return self.chat.send(&notice).await;
+        }
+        if let Err(err) = check_directives(&msg, self.config.permissions.default) {
+            let reply = OutgoingMessage {
+                text: format!(":warning: {err}"),
+                conversation_id: msg.conversation_id.clone(),
+                thread_id: msg.thread_id.clone(),
+            };
+            return self.chat.send(&reply).await;
+        }
+        self.deliver_prompt(msg, prompt_timeout).await
+    }
+
+    /// Print how a message would be handled: command, maintenance or quiet hours, the
+    /// session it would go to or spawn, its context, and the prompt text.
+    async fn dry_run_message(&self, msg: &IncomingMessage) -> Result<()> {
+        let mut lines = vec![format!(
+            "dry-run: message channel={} thread={} user={}",
+            msg.conversation_id,
+            msg.thread_id.as_deref().unwrap_or("-"),
+            msg.user_id.as_deref().unwrap_or("-")
+        )];
+        let outcome = if let Some(command) = commands::parse(&msg.text) {
+            Some(match command {
+                Ok(command) => format!("command {command:?}: not run"),
+                Err(err) => format!("invalid command, would reply: {err}"),
+            })
+        } else if let Some((plugin, args)) = plugins::find(&self.config.plugins, &msg.text) {
+            Some(format!("plugin `{}` with args `{args}`: not run", plugin.name))
+        } else if self.maintenance.enabled {
+            Some(format!("maintenance mode, would reply: {}", self.maintenance_text()))
+        } else {
+            self.quiet_until(&msg.conversation_id)
+                .map(|until| format!("quiet hours, would hold until {until}"))
+        };
+        if let Some(outcome) = outcome {
+            lines.push(format!("  {outcome}"));
+            println!("{}", lines.join("\n"));
+            return Ok(());
+        }
+
+        let route = match self.config.route(&msg.conversation_id) {
+            Some(_) => format!("route for {}", msg.conversation_id),
+            None => "no route, global settings".to_string(),
+        };
+        let opts = self.spawn_options(&msg.conversation_id);
+        let profile = self.config.claude_profile(&msg.conversation_id);
+        lines.push(format!(
+            "  {route}; profile={} args={:?}",
+            profile.map(|(name, _)| name).unwrap_or("-"),
+            opts.args
+        ));
+        let key = match &msg.thread_id {
+            Some(_) => ConversationKey {
+                conversation_id: msg.conversation_id.clone(),
+                thread_id: msg.thread_id.clone(),
+            },
+            None => self
+                .main_by_conversation
+                .get(&msg.conversation_id)
+                .cloned()
+                .unwrap_or_else(|| ConversationKey {
+                    conversation_id: msg.conversation_id.clone(),
+                    thread_id: None,
+                }),
+        };
+        if let Some(entry) = self.sessions_by_key.get(&key) {
+            lines.push(format!("  would send to session `{}`", entry.session_name));
+        } else if let Some(thread_id) = &msg.thread_id {
+            let cwd = self.thread_dir(thread_id);
+            lines.push(format!("  would spawn a thread session in {}", cwd.display()));
+            lines.push(match self.build_thread_context(msg)? {
+                ThreadContextDecision::Built(context) => format!(
+                    "  would write CLAUDE.md ({} chars):\n{}",
+                    context.chars().count(),
+                    indent(&context)
+                ),
+                ThreadContextDecision::Skipped(reason) => format!("  no thread context ({reason})"),
+            });
+        } else {
+            lines.push(format!(
+                "  would spawn the main session in {}",
+                self.base_cwd.display()
+            ));
+            let limit = self.config.coordinator.main_context_messages;
+            if limit > 0 {
+                let history = self
+                    .chat
+                    .channel_history(&msg.conversation_id, msg.timestamp.as_deref(), limit)
+                    .await?;
+                lines.push(match context::format_history_context(&history, &self.time_zone) {
+                    Some(context) => format!(
+                        "  would write CLAUDE.local.md ({} chars):\n{}",
+                        context.chars().count(),
+                        indent(&context)
+                    ),
+                    None => "  no main context (history empty)".to_string(),
+                });
+            }
+        }
+        let (directives, body) = commands::parse_directives(&msg.text)?;
+        if directives != TurnDirectives::default() {
+            lines.push(format!("  directives for this turn: {directives:?}"));
+        }
+        let mut text = msg.prompt_text(body);
+        self.unfurl_permalinks(msg, &mut text).await;
+        self.fetch_urls(msg, &mut text).await;
+        if self.config.input_guard(&msg.conversation_id) {
+            text = guard::wrap_untrusted(&text);
+        }
+        if let Some((max_tokens, action)) = self.config.preflight(&msg.conversation_id) {
+            let estimate = self.estimate_turn(&key, msg, &text);
+            lines.push(format!(
+                "  pre-flight: {} (limit {max_tokens}, {action:?})",
+                estimate.describe()
+            ));
+        }
+        lines.push(format!("  prompt:\n{}", indent(&text)));
+        println!("{}", lines.join("\n"));
+        Ok(())
+    }
+
+    /// Deliver a message that is not a command, tracking it with status reactions, or
+    /// hold it for quiet hours or a paused session.
+    async fn deliver_prompt(&mut self, msg: IncomingMessage, prompt_timeout: Duration) -> Result<()> {
+        if let Some(until) = self.quiet_until(&msg.conversation_id) {
+            return self.hold_for_quiet_hours(msg, until).await;
+        }
+
+        let key = ConversationKey {
+            conversation_id: msg.conversation_id.clone(),
+            thread_id: msg.thread_id.clone(),
+        };
+        if self.needs_capacity(&key) && !self.preempt_for(&key).await {
+            return self.hold_for_capacity(msg).await;
+        }
+        if self
+            .sessions_by_key
+            .get(&key)
+            .is_some_and(|entry| entry.disk.paused)
+        {
+            let notice = OutgoingMessage {
+                text: "This thread's session is paused because its directory is over the disk \
+                       quota. Confirm on the quota notice above, or ask an admin to raise \
+                       `disk_hard_limit_mb`."
+                    .to_string(),
+                conversation_id: key.conversation_id,
+                thread_id: key.thread_id,
+            };
+            return self.chat.send(&notice).await;
+        }
+        if self
+            .sessions_by_key
+            .get(&key)
+            .is_some_and(|entry| entry.delivery.quarantined_since.is_some())
+        {
+            let notice = OutgoingMessage {
+                text: ":no_entry: This session is quarantined after repeated failed deliveries, \
+                       so this message was not sent. `!peek` shows its terminal; `!restart` \
+                       starts a fresh session."
+                    .to_string(),
+                conversation_id: key.conversation_id,
+                thread_id: key.thread_id,
+            };
+            return self.chat.send(&notice).await;
+        }
+
+        let conversation_id = msg.conversation_id.clone();
+        let status_ts = msg.timestamp.clone();
+        if let Some(ts) = &status_ts {
+            self.set_status(&conversation_id, ts, None, TurnStatus::Received)
+                .await;
+        }
+
+        let result = self.deliver_incoming(msg, prompt_timeout).await;
+        // A message held by the pre-flight check keeps its received status until decided.
+        if !matches!(result, Ok(None)) {
+            let delivered = result.as_ref().ok().and_then(Option::as_ref);
+            self.track_delivery(&conversation_id, status_ts, delivered).await;
+        }
+        result.map(|_| ())
+    }
+
+    /// Move a message's status reaction from received to working, or to failed when it
+    /// was not delivered, and keep it with the session for the turn's later statuses.
+    async fn track_delivery(
+        &mut self,
+        conversation_id: &str,
+        status_ts: Option<String>,
+        delivered: Option<&ConversationKey>,
+    ) {
+        let Some(ts) = status_ts else {
+            return;
+        };
+        let next = if delivered.is_some() {
+            TurnStatus::Working
+        } else {
+            TurnStatus::Failed
+        };
+        self.set_status(conversation_id, &ts, Some(TurnStatus::Received), next)
+            .await;
+        if let Some(key) = delivered
+            && let Some(entry) = self.sessions_by_key.get_mut(key)
+        {
+            entry.status_ts.push(ts);
+        }
+    }
+
+    fn maintenance_text(&self) -> String {
+        let message = self
+            .maintenance
+            .message
+            .as_deref()
+            .unwrap_or(&self.config.coordinator.maintenance_message);
+        format!(":construction: {message}")
+    }
+
+    fn quiet_until(&self, conversation_id: &str) -> Option<jiff::Zoned> {
+        let quiet = self.config.quiet_hours(conversation_id)?;
+        quiet::active_until(quiet, self.clock.timestamp())
+            .unwrap_or_else(|err| {
+                warn!("quiet hours check failed: {err}");
+                None
+            })
+    }
+
+    async fn hold_for_quiet_hours(
+        &mut self,
+        msg: IncomingMessage,
+        until: jiff::Zoned,
+    ) -> Result<()> {
+        let key = ConversationKey {
+            conversation_id: msg.conversation_id.clone(),
+            thread_id: msg.thread_id.clone(),
+        };
+        info!(
+            "quiet hours: holding message channel={} thread={}",
+            msg.conversation_id,
+            msg.thread_id.as_deref().unwrap_or("-")
+        );
+        let notice = OutgoingMessage {
+            text: format!(
+                ":crescent_moon: Quiet hours until {}. I'll pick this up then.",
+                until.strftime("%H:%M %Z")
+            ),
+            conversation_id: msg.conversation_id.clone(),
+            thread_id: msg.thread_id.clone(),
+        };
+        self.quiet_queue.push(msg);
+        if self.quiet_notified.insert(key) {
+            self.chat.send(&notice).await?;
+        }
+        Ok(())
+    }
+
+    /// Sessions counted against `max_sessions`.
+    fn live_sessions(&self) -> usize {
+        self.sessions_by_key.len() + self.tasks_by_cwd.len()
+    }
+
+    /// Whether a message for `key` would start a thread session past `max_sessions`, or
+    /// jump ahead of messages already waiting for one.
+    fn needs_capacity(&self, key: &ConversationKey) -> bool {
+        let Some(max) = self.config.coordinator.max_sessions else {
+            return false;
+        };
+        if key.thread_id.is_none() || self.sessions_by_key.contains_key(key) {
+            return false;
+        }
+        self.live_sessions() as u64 >= max || !self.capacity_queue.is_empty()
+    }
+
+    async fn hold_for_capacity(&mut self, msg: IncomingMessage) -> Result<()> {
+        let already_queued = self.capacity_queue.iter().any(|queued| {
+            queued.conversation_id == msg.conversation_id && queued.thread_id == msg.thread_id
+        });
+        info!(
+            "capacity: holding message channel={} thread={} ({} live sessions)",
+            msg.conversation_id,
+            msg.thread_id.as_deref().unwrap_or("-"),
+            self.live_sessions()
+        );
+        let notice = OutgoingMessage {
+            text: format!(
+                ":hourglass: Too many active sessions ({} running); this thread is queued and \
+                 will start when one frees up.",
+                self.live_sessions()
+            ),
+            conversation_id: msg.conversation_id.clone(),
+            thread_id: msg.thread_id.clone(),
+        };
+        self.capacity_queue.push(msg);
+        if already_queued {
+            return Ok(());
+        }
+        self.chat.send(&notice).await
+    }
+
+    /// Start queued thread sessions, by route priority and then oldest first, while there
+    /// is room under `max_sessions`. Later messages of a thread follow its first one.
+    async fn flush_capacity_queue(&mut self, prompt_timeout: Duration) {
+        if self.capacity_queue.is_empty() {
+            return;
+        }
+        let max = self.config.coordinator.max_sessions.unwrap_or(u64::MAX);
+        let mut free = max.saturating_sub(self.live_sessions() as u64);
+        let mut starting = HashSet::new();
+        let mut ready = Vec::new();
+        let mut waiting = Vec::new();
+        let mut queued = std::mem::take(&mut self.capacity_queue);
+        // Higher route priority first; the sort is stable, so arrival order holds within one.
+        queued.sort_by_key(|msg| std::cmp::Reverse(self.config.priority(&msg.conversation_id)));
+        for msg in queued {
+            let key = ConversationKey {
+                conversation_id: msg.conversation_id.clone(),
+                thread_id: msg.thread_id.clone(),
+            };
+            if self.sessions_by_key.contains_key(&key) || starting.contains(&key) {
+                ready.push(msg);
+            } else if free > 0 && waiting.is_empty() {
+                free -= 1;
+                starting.insert(key);
+                ready.push(msg);
+            } else {
+                waiting.push(msg);
+            }
+        }
+        for msg in ready {
+            let target = error_target(&msg);
+            if let Err(err) = self.handle_incoming(msg, prompt_timeout).await {
+                self.publish_error("queued message", &err);
+                self.report_error("queued message", target, &err).await;
+            }
+        }
+        waiting.append(&mut self.capacity_queue);
+        self.capacity_queue = waiting;
+    }
+
+    /// Deliver held messages whose quiet hours are over, one per conversation at a time
+    /// so each waits for the previous turn to finish.
+    async fn flush_quiet_queue(&mut self, prompt_timeout: Duration) {
+        if self.quiet_queue.is_empty() {
+            return;
+        }
+        let queued = std::mem::take(&mut self.quiet_queue);
+        let mut blocked = HashSet::new();
+        let mut ready = Vec::new();
+        for msg in queued {
+            let key = ConversationKey {
+                conversation_id: msg.conversation_id.clone(),
+                thread_id: msg.thread_id.clone(),
+            };
+            let busy = self
+                .sessions_by_key
+                .get(&key)
+                .is_some_and(|entry| entry.turn_started_at.is_some());
+            if blocked.contains(&key) || busy || self.quiet_until(&msg.conversation_id).is_some()
+            {
+                self.quiet_queue.push(msg);
+            } else {
+                self.quiet_notified.remove(&key);
+                ready.push(msg);
+            }
+            blocked.insert(key);
+        }
+        for msg in ready {
+            let target = error_target(&msg);
+            if let Err(err) = self.handle_incoming(msg, prompt_timeout).await {
+                self.publish_error("queued message", &err);
+                self.report_error("queued message", target, &err).await;
+            }
+        }
+    }
+
+    /// Prepare the prompt for `msg` and send it to its session. Returns `None` when the
+    /// pre-flight check holds it for confirmation, or it waits for its session to start.
+    async fn deliver_incoming(
+        &mut self,
+        msg: IncomingMessage,
+        prompt_timeout: Duration,
+    ) -> Result<Option<ConversationKey>> {
+        let key = ConversationKey {
+            conversation_id: msg.conversation_id.clone(),
+            thread_id: msg.thread_id.clone(),
+        };
+        self.recover_timed_out(&key);
+        self.recover_dead(&key);
+
+        let (directives, body) = commands::parse_directives(&msg.text)?;
+        let mut text = msg.prompt_text(body);
+        self.unfurl_permalinks(&msg, &mut text).await;
+        self.fetch_urls(&msg, &mut text).await;
+        if self.config.input_guard(&msg.conversation_id) {
+            text = guard::wrap_untrusted(&text);
+        }
+        if let Some((max_tokens, action)) = self.config.preflight(&msg.conversation_id) {
+            let estimate = self.estimate_turn(&key, &msg, &text);
+            if estimate.total() > max_tokens {
+                info!(
+                    "preflight: prompt of {} tokens over {max_tokens} ({action:?})",
+                    estimate.total()
+                );
+                if action == PreflightAction::Confirm {
+                    let pending = PreparedPrompt {
+                        msg,
+                        text,
+                        directives,
+                    };
+                    self.hold_for_preflight(pending, estimate, max_tokens).await?;
+                    return Ok(None);
+                }
+                let notice = format!(
+                    ":warning: This message comes to {}, over this channel's pre-flight \
+                     limit of {max_tokens}. Sending it anyway.",
+                    estimate.describe()
+                );
+                self.post_notice(&key, &notice).await;
+            }
+        }
+        self.send_prepared(msg, text, &directives, prompt_timeout)
+            .await
+    }
+
+    /// Start or reuse the session for `msg` and queue the prompt `text` prepared from it.
+    /// Returns `None` when the session failed to start and the prompt waits for the next
+    /// attempt, or is queued behind others waiting for it.
+    async fn send_prepared(
+        &mut self,
+        msg: IncomingMessage,
+        mut text: String,
+        directives: &TurnDirectives,
+        prompt_timeout: Duration,
+    ) -> Result<Option<ConversationKey>> {
+        let key = ConversationKey {
+            conversation_id: msg.conversation_id.clone(),
+            thread_id: msg.thread_id.clone(),
+        };
+        if let Some(retry) = self.spawn_retries.get_mut(&key) {
+            retry.queued.push(PreparedPrompt {
+                msg,
+                text,
+                directives: directives.clone(),
+            });
+            return Ok(None);
+        }
+        let entry = match self.ensure_session(&msg, prompt_timeout).await {
+            Ok(entry) => entry,
+            Err(err) if self.config.coordinator.spawn_retries > 0 => {
+                let retry = SpawnRetry {
+                    attempts: 1,
+                    queued: vec![PreparedPrompt {
+                        msg,
+                        text,
+                        directives: directives.clone(),
+                    }],
+                };
+                self.schedule_spawn_retry(key, retry, &err);
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        };
+        if self.config.coordinator.accept_uploads
+            && let Some(note) = self.save_attachments(&key, &msg).await?
+        {
+            text.push_str(&note);
+        }
+        if self.config.coordinator.turn_artifacts {
+            let relative = self.prepare_artifacts_dir(&key, &msg)?;
+            text.push_str(&format!(
+                "\n\n(Write any output files meant for the user to `{}/`; they are uploaded \
+                 to the thread when you finish.)",
+                relative.display()
+            ));
+        }
+        self.enqueue_turn(&entry, text, directives, prompt_timeout);
+        if let Some(entry) = self.sessions_by_key.get_mut(&key) {
+            entry.last_message = Some(msg);
+        }
+
+        Ok(Some(key))
+    }
+
+    async fn ensure_session(
+        &mut self,
+        msg: &IncomingMessage,
+        prompt_timeout: Duration,
+    ) -> Result<SessionEntry> {
+        if msg.thread_id.is_none() {
+            self.ensure_main_session(msg, prompt_timeout).await
+        } else {
+            self.ensure_thread_session(msg, prompt_timeout).await
+        }
+    }
+
+    /// Try starting the session for `key` again once its backoff is over. The loop goes
+    /// on meanwhile; messages for `key` arriving in between queue behind the first.
+    fn schedule_spawn_retry(&mut self, key: ConversationKey, retry: SpawnRetry, err: &Error) {
+        let attempts = self.config.coordinator.spawn_retries + 1;
+        let backoff = spawn_backoff(self.config.coordinator.spawn_retry_backoff_ms, retry.attempts);
+        let delay = backoff + jitter(backoff);
+        warn!(
+            "spawn: attempt {}/{attempts} channel={} thread={} failed, retrying in {}ms: {err:#}",
+            retry.attempts,
+            key.conversation_id,
+            key.thread_id.as_deref().unwrap_or("-"),
+            delay.as_millis()
+        );
+        self.spawn_retries.insert(key.clone(), retry);
+        let tx = self.spawn_retry_tx.clone();
+        let clock = self.clock.clone();
+        tokio::spawn(async move {
+            clock.sleep(delay).await;
+            let _ = tx.send(key);
+        });
+    }
+
+    /// Start the session for `key` again and send the prompts that waited for it, in
+    /// order. After the last attempt fails, they are marked failed and the sender of the
+    /// first gets the error.
+    async fn retry_spawn(&mut self, key: ConversationKey, prompt_timeout: Duration) {
+        let Some(mut retry) = self.spawn_retries.remove(&key) else {
+            return;
+        };
+        let Some(first) = retry.queued.first().map(|prompt| prompt.msg.clone()) else {
+            return;
+        };
+        retry.attempts += 1;
+        if let Err(err) = self.ensure_session(&first, prompt_timeout).await {
+            if retry.attempts <= self.config.coordinator.spawn_retries {
+                self.schedule_spawn_retry(key, retry, &err);
+                return;
+            }
+            let err = err.context(format!("gave up after {} attempt(s)", retry.attempts));
+            for prompt in &retry.queued {
+                if let Some(ts) = &prompt.msg.timestamp {
+                    let received = Some(TurnStatus::Received);
+                    self.set_status(&key.conversation_id, ts, received, TurnStatus::Failed)
+                        .await;
+                }
+            }
+            self.publish_error("incoming", &err);
+            self.report_error("incoming", error_target(&first), &err).await;
+            return;
+        }
+        for prompt in retry.queued {
+            let conversation_id = prompt.msg.conversation_id.clone();
+            let status_ts = prompt.msg.timestamp.clone();
+            let target = error_target(&prompt.msg);
+            let result = self
+                .send_prepared(prompt.msg, prompt.text, &prompt.directives, prompt_timeout)
+                .await;
+            if !matches!(result, Ok(None)) {
+                let delivered = result.as_ref().ok().and_then(Option::as_ref);
+                self.track_delivery(&conversation_id, status_ts, delivered).await;
+            }
+            if let Err(err) = result {
+                self.publish_error("incoming", &err);
+                self.report_error("incoming", target, &err).await;
+            }
+        }
+    }
+
+    /// Size of the prompt `text`, plus the context a new thread session would start with.
+    fn estimate_turn(
+        &self,
+        key: &ConversationKey,
+        msg: &IncomingMessage,
+        text: &str,
+    ) -> TurnEstimate {
+        let mut estimate = TurnEstimate {
+            prompt_tokens: context::estimate_tokens(text),
+            context_tokens: 0,
+        };
+        if key.thread_id.is_some()
+            && !self.sessions_by_key.contains_key(key)
+            && let Ok(ThreadContextDecision::Built(context)) = self.build_thread_context(msg)
+        {
+            estimate.context_tokens = context::estimate_tokens(&context);
+        }
+        estimate
+    }
+
+    async fn hold_for_preflight(
+        &mut self,
+        pending: PreparedPrompt,
+        estimate: TurnEstimate,
+        max_tokens: u64,
+    ) -> Result<()> {
+        let id = approval_id();
+        let prompt = OutgoingMessage {
+            text: format!(
+                ":scales: This message comes to {}, over this channel's pre-flight limit of \
+                 {max_tokens}, so it was not sent yet. Approve to send it anyway, or Reject \
+                 to drop it.",
+                estimate.describe()
+            ),
+            conversation_id: pending.msg.conversation_id.clone(),
+            thread_id: pending.msg.thread_id.clone(),
+        };
+        let ts = self
+            .chat
+            .post_approval(&prompt, PREFLIGHT_ACTION_PREFIX, &id)
+            .await?;
+        let held = PendingPreflight {
+            notice: (prompt.conversation_id, ts),
+            prompt: pending,
+            expires_at: self.clock.now() + PREFLIGHT_EXPIRY,
+        };
+        self.pending_preflight.insert(id, held);
+        Ok(())
+    }
+
+    /// Drop held messages nobody approved within `PREFLIGHT_EXPIRY`, marking them failed.
+    async fn expire_preflight(&mut self) {
+        let now = self.clock.now();
+        let expired: Vec<String> = self
+            .pending_preflight
+            .iter()
+            .filter(|(_, pending)| now >= pending.expires_at)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in expired {
+            let Some(pending) = self.pending_preflight.remove(&id) else {
+                continue;
+            };
+            info!("preflight {id} not answered in time, dropped");
+            let msg = &pending.prompt.msg;
+            if let Some(ts) = &msg.timestamp {
+                let received = Some(TurnStatus::Received);
+                self.set_status(&msg.conversation_id, ts, received, TurnStatus::Failed)
+                    .await;
+            }
+            let text = format!(
+                ":hourglass: Nobody approved this message within {}; it was not sent.",
+                format_duration(PREFLIGHT_EXPIRY)
+            );
+            let (conversation_id, ts) = &pending.notice;
+            if let Err(err) = self.chat.update_text(conversation_id, ts, &text).await {
+                warn!("preflight notice not updated: {err}");
+            }
+        }
+    }
+
+    /// Quote the messages `msg` links to below the prompt `text`, as far as
+    /// `unfurl_permalinks` allows. Links that cannot be fetched are left as they are.
+    async fn unfurl_permalinks(&self, msg: &IncomingMessage, text: &mut String) {
+        let scope = self.config.coordinator.unfurl_permalinks;
+        if scope == UnfurlPermalinks::Off {
+            return;
+        }
+        let links = permalinks::find(&msg.text).into_iter().filter(|link| {
+            scope == UnfurlPermalinks::Workspace || link.channel == msg.conversation_id
+        });
+        for link in links.take(MAX_UNFURLED_LINKS) {
+            let linked = self
+                .chat
+                .fetch_message(&link.channel, &link.ts, link.thread_ts.as_deref())
+                .await;
+            match linked {
+                Ok(Some(mut linked)) => {
+                    linked.text = truncate_chars(&linked.text, UNFURLED_MAX_CHARS);
+                    text.push_str("\n\n");
+                    text.push_str(&context::format_linked_message(&linked, &self.time_zone));
+                }
+                Ok(None) => debug!("linked message {} in {} not found", link.ts, link.channel),
+                Err(err) => warn!("failed to fetch linked message {}: {err:#}", link.ts),
+            }
+        }
+    }
+
+    /// Add the content of URLs in `msg` on the `url_fetch` allowlist below the prompt
+    /// `text`. URLs that fail are noted in the prompt, so Claude does not assume it saw
+    /// them.
+    async fn fetch_urls(&self, msg: &IncomingMessage, text: &mut String) {
+        let cfg = &self.config.url_fetch;
+        if !cfg.enabled {
+            return;
+        }
+        let urls = url_fetch::find_urls(&msg.text)
+            .into_iter()
+            .filter(|url| url_fetch::is_allowed(url, &cfg.allowed_domains));
+        for url in urls.take(cfg.max_urls) {
+            text.push_str("\n\n");
+            match url_fetch::fetch(&url, cfg).await {
+                Ok(content) => {
+                    info!("fetched {url} ({} chars)", content.chars().count());
+                    text.push_str(&url_fetch::format_for_prompt(&url, &content));
+                }
+                Err(err) => {
+                    warn!("failed to fetch {url}: {err:#}");
+                    text.push_str(&format!("(ccterm could not fetch {url}: {err:#})"));
+                }
+            }
+        }
+    }
+
+    /// Download the files shared with `msg` into `.ccterm/uploads/<message>` and return
+    /// a note for the prompt listing their paths. Files that fail are reported in the
+    /// conversation.
+    async fn save_attachments(
+        &self,
+        key: &ConversationKey,
+        msg: &IncomingMessage,
+    ) -> Result<Option<String>> {
+        if msg.attachments.is_empty() {
+            return Ok(None);
+        }
+        let cwd = self
+            .cwd_for_key(key)
+            .context("no session directory for uploads")?;
+        let turn = match &msg.timestamp {
+            Some(ts) => sanitize_thread_id(ts),
+            None => self.clock.timestamp().as_second().to_string(),
+        };
+        let relative_dir = Path::new(UPLOADS_DIR).join(turn);
+        let dir = cwd.join(&relative_dir);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create uploads dir: {}", dir.display()))?;
+        let mut saved = Vec::new();
+        let mut failed = Vec::new();
+        for attachment in &msg.attachments {
+            let name = upload_file_name(&attachment.name, &saved);
+            let content = self
+                .chat
+                .download(&key.conversation_id, &attachment.url, MAX_UPLOAD_BYTES)
+                .await;
+            let result = match content {
+                Ok(content) => {
+                    let path = dir.join(&name);
+                    tokio::task::spawn_blocking(move || std::fs::write(path, content))
+                        .await
+                        .map_err(anyhow::Error::from)
+                        .and_then(|written| written.map_err(anyhow::Error::from))
+                        .with_context(|| format!("failed to write {name}"))
+                }
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(()) => saved.push(name),
+                Err(err) => {
+                    warn!("upload not saved: {}: {err:#}", attachment.name);
+                    failed.push(format!("`{}` ({err:#})", attachment.name));
+                }
+            }
+        }
+        if !failed.is_empty() {
+            let notice = OutgoingMessage {
+                text: format!("_Could not pass on: {}_", failed.join(", ")),
+                conversation_id: key.conversation_id.clone(),
+                thread_id: self.reply_thread_id(key),
+            };
+            if let Err(err) = self.chat.send(&notice).await {
+                warn!("upload notice post failed: {err}");
+            }
+        }
+        if saved.is_empty() {
+            let _ = std::fs::remove_dir(&dir);
+            return Ok(None);
+        }
+        let paths: Vec<String> = saved
+            .iter()
+            .map(|name| format!("`{}`", relative_dir.join(name).display()))
+            .collect();
+        Ok(Some(format!(
+            "\n\n(Files shared with this message, saved in the working directory: {})",
+            paths.join(", ")
+        )))
+    }
+
+    /// Create the artifacts directory for the turn this message starts, or reuse the one
+    /// of the turn already running. Returns it relative to the session cwd.
+    fn prepare_artifacts_dir(
+        &mut self,
+        key: &ConversationKey,
+        msg: &IncomingMessage,
+    ) -> Result<PathBuf> {
+        let cwd = self
+            .cwd_for_key(key)
+            .context("no session directory for artifacts")?;
+        let Some(entry) = self.sessions_by_key.get_mut(key) else {
+            bail!("no session for artifacts");
+        };
+        let dir = match &entry.artifacts_dir {
+            Some(dir) if entry.turn_started_at.is_some() => dir.clone(),
+            _ => {
+                let turn = match &msg.timestamp {
+                    Some(ts) => sanitize_thread_id(ts),
+                    None => self.clock.timestamp().as_second().to_string(),
+                };
+                cwd.join(ARTIFACTS_DIR).join(turn)
+            }
+        };
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create artifacts dir: {}", dir.display()))?;
+        entry.artifacts_dir = Some(dir.clone());
+        Ok(dir.strip_prefix(&cwd).map(Path::to_path_buf).unwrap_or(dir))
+    }
+
+    /// Upload files the turn wrote to its artifacts directory.
+    async fn upload_artifacts(&mut self, key: &ConversationKey, thread_id: Option<&str>) {
+        let Some(dir) = self
+            .sessions_by_key
+            .get_mut(key)
+            .and_then(|entry| entry.artifacts_dir.take())
+        else {
+            return;
+        };
+        let walk_dir = dir.clone();
+        let files = tokio::task::spawn_blocking(move || {
+            let mut files = Vec::new();
+            let mut pending = vec![walk_dir.clone()];
+            while let Some(current) = pending.pop() {
+                let Ok(entries) = std::fs::read_dir(&current) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    match path.symlink_metadata() {
+                        Ok(metadata) if metadata.is_dir() => pending.push(path),
+                        Ok(metadata) if metadata.is_file() => {
+                            files.push((path, metadata.len()))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            if files.is_empty() {
+                let _ = std::fs::remove_dir(&walk_dir);
+            }
+            files.sort();
+            files
+        })
+        .await
+        .unwrap_or_default();
+        if files.is_empty() {
+            return;
+        }
+        let files = files
+            .into_iter()
+            .map(|(path, size)| {
+                let name = path
+                    .strip_prefix(&dir)
+                    .unwrap_or(&path)
+                    .to_string_lossy()
+                    .to_string();
+                (name, path, size)
+            })
+            .collect();
+        self.upload_files(key, thread_id, files).await;
+    }
+
+    /// Upload the files the turn created or modified that `upload_referenced_files` and
+    /// `upload_globs` select.
+    async fn upload_changed_files(
+        &self,
+        key: &ConversationKey,
+        thread_id: Option<&str>,
+        cwd: &Path,
+        uploads: Vec<FileChange>,
+    ) {
+        let files = uploads
+            .into_iter()
+            .filter(|change| {
+                let path = Path::new(&change.path);
+                !path.starts_with(ARTIFACTS_DIR) && !path.starts_with(UPLOADS_DIR)
+            })
+            .filter_map(|change| {
+                let path = cwd.join(&change.path);
+                let metadata = path.symlink_metadata().ok()?;
+                metadata.is_file().then_some((change.path, path, metadata.len()))
+            })
+            .collect();
+        self.upload_files(key, thread_id, files).await;
+    }
+
+    /// Upload `(name, path, size)` files to the conversation, and list the ones over the
+    /// limits or that failed in a note.
+    async fn upload_files(
+        &self,
+        key: &ConversationKey,
+        thread_id: Option<&str>,
+        files: Vec<(String, PathBuf, u64)>,
+    ) {
+        let mut skipped = Vec::new();
+        for (idx, (name, path, size)) in files.into_iter().enumerate() {
+            if idx >= MAX_ARTIFACT_FILES || size > MAX_ARTIFACT_BYTES {
+                skipped.push(name);
+                continue;
+            }
+            let read_path = path.clone();
+            let content = match tokio::task::spawn_blocking(move || std::fs::read(read_path))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|read| read.map_err(anyhow::Error::from))
+            {
+                Ok(content) => content,
+                Err(err) => {
+                    warn!("artifact not readable: {}: {err}", path.display());
+                    skipped.push(name);
+                    continue;
+                }
+            };
+            if let Err(err) = self
+                .chat
+                .upload_bytes(
+                    &key.conversation_id,
+                    thread_id,
+                    &name,
+                    content,
+                    "application/octet-stream",
+                )
+                .await
+            {
+                warn!("artifact upload failed: {name}: {err}");
+                skipped.push(name);
+            }
+        }
+        if !skipped.is_empty() {
+            let notice = OutgoingMessage {
+                text: format!(
+                    "_Not uploaded (limit {MAX_ARTIFACT_FILES} files, {} each, or failed): {}_",
+                    resources::format_bytes(MAX_ARTIFACT_BYTES),
+                    skipped.join(", ")
+                ),
+                conversation_id: key.conversation_id.clone(),
+                thread_id: thread_id.map(str::to_string),
+            };
+            if let Err(err) = self.chat.send(&notice).await {
+                warn!("artifact notice post failed: {err}");
+            }
+        }
+    }
+
+    fn cwd_for_key(&self, key: &ConversationKey) -> Option<PathBuf> {
+        self.key_by_cwd
+            .iter()
+            .find(|(_, k)| *k == key)
+            .map(|(cwd, _)| cwd.clone())
+    }
+
+    async fn handle_command(
+        &mut self,
+        msg: &IncomingMessage,
+        command: Result<ChatCommand>,
+        prompt_timeout: Duration,
+    ) -> Result<()> {
+        info!(
+            "command from {} channel={}: {:?}",
+            msg.user_id.as_deref().unwrap_or("-"),
+            msg.conversation_id,
+            command
+        );
+        let result = match command {
+            Ok(ChatCommand::Spawn { .. }) if self.maintenance.enabled => {
+                Ok(self.maintenance_text())
+            }
+            Ok(ChatCommand::Spawn { task }) => self.spawn_task(msg, &task, prompt_timeout).await,
+            Ok(ChatCommand::Retry { .. }) if self.maintenance.enabled => {
+                Ok(self.maintenance_text())
+            }
+            Ok(ChatCommand::Retry { fresh }) => self.retry(msg, fresh, prompt_timeout).await,
+            Ok(ChatCommand::Undo) if self.maintenance.enabled => Ok(self.maintenance_text()),
+            Ok(ChatCommand::Undo) => self.undo(msg, prompt_timeout).await,
+            Ok(ChatCommand::Restart) => self.restart(msg).await,
+            Ok(ChatCommand::Stop { hard }) => self.stop_turn(msg, hard).await,
+            Ok(ChatCommand::Status) => Ok(self.status_text(msg).await),
+            Ok(ChatCommand::History { count }) => {
+                let text = self
+                    .history_text(msg, count)
+                    .await
+                    .unwrap_or_else(|err| format!(":warning: {err}"));
+                return self.reply_privately(msg, text).await;
+            }
+            Ok(command) => self.run_command(msg, command),
+            Err(err) => Err(err),
+        };
+        let text = match result {
+            Ok(text) => text,
+            Err(err) => format!(":warning: {err}"),
+        };
+        let reply = OutgoingMessage {
+            text,
+            conversation_id: msg.conversation_id.clone(),
+            thread_id: msg.thread_id.clone(),
+        };
+        self.chat.send(&reply).await
+    }
+
+    /// Deliver the last message of this conversation's session again, after restarting
+    /// the session when `fresh`. The `!retry` message gets the status reactions.
+    async fn retry(
+        &mut self,
+        msg: &IncomingMessage,
+        fresh: bool,
+        prompt_timeout: Duration,
+    ) -> Result<String> {
+        let key = ConversationKey {
+            conversation_id: msg.conversation_id.clone(),
+            thread_id: msg.thread_id.clone(),
+        };
+        let Some(entry) = self.sessions_by_key.get(&key) else {
+            bail!("No session in this conversation to retry.");
+        };
+        let Some(last) = entry.last_message.clone() else {
+            bail!("Nothing to retry yet.");
+        };
+        if entry.turn_started_at.is_some() && !fresh {
+            bail!("A turn is running. Wait for it, or restart the session with `!retry --fresh`.");
+        }
+        if fresh {
+            self.stop_entry(&key, "retry").await;
+        }
+        let retry = IncomingMessage {
+            timestamp: msg.timestamp.clone(),
+            ..last
+        };
+        self.deliver_prompt(retry, prompt_timeout).await?;
+        Ok(if fresh {
+            ":repeat: Retrying the last prompt in a fresh session.".to_string()
+        } else {
+            ":repeat: Retrying the last prompt.".to_string()
+        })
+    }
+
+    /// Stop this conversation's session, lifting its quarantine if any, so the next
+    /// message starts a fresh one. A running turn is marked failed.
+    async fn restart(&mut self, msg: &IncomingMessage) -> Result<String> {
+        let Some((key, entry)) = self.session_for(msg) else {
+            // An idle-stopped session is only parked; forget it so the next message does
+            // not resume it.
+            let key = ConversationKey {
+                conversation_id: msg.conversation_id.clone(),
+                thread_id: msg.thread_id.clone(),
+            };
+            if self.parked.remove(&key).is_some() {
+                return Ok(":arrows_counterclockwise: Cleared the stopped session. The next \
+                           message starts a fresh one instead of resuming it."
+                    .to_string());
+            }
+            bail!("No session in this conversation to restart.");
+        };
+        if entry.turn_started_at.is_some() {
+            self.finish_status(&key, TurnStatus::Failed).await;
+        }
+        self.stop_entry(&key, "restart").await;
+        self.parked.remove(&key);
+        Ok(":arrows_counterclockwise: Stopped this session. The next message starts a fresh \
+            one."
+            .to_string())
+    }
+
+    /// Stop the session of `key` and forget it: its send queue is closed, the tmux session
+    /// killed and a thread worktree released. Returns the entry, if there was one.
+    async fn stop_entry(&mut self, key: &ConversationKey, reason: &str) -> Option<SessionEntry> {
+        let entry = self.sessions_by_key.remove(key)?;
+        info!("{reason}: stopping {}", entry.session_name);
+        self.send_queues.close(&entry.session_name);
+        if let Err(err) = self.sessions.stop(&entry.session_name) {
+            warn!("failed to stop {}: {err}", entry.session_name);
+        }
+        self.release_worktree(key).await;
+        self.key_by_cwd.retain(|_, mapped| mapped != key);
+        self.pending_plans.retain(|_, pending| pending != key);
+        self.drop_joined_tasks(key);
+        Some(entry)
+    }
+
+    /// Forget the `!spawn` tasks of `key`'s thread that have reported back, with their
+    /// hook receivers. Running tasks stay until their reply.
+    fn drop_joined_tasks(&mut self, key: &ConversationKey) {
+        let Some(thread_id) = &key.thread_id else {
+            return;
+        };
+        let done: Vec<PathBuf> = self
+            .tasks_by_cwd
+            .iter()
+            .filter(|(_, task)| {
+                task.joined
+                    && task.conversation_id == key.conversation_id
+                    && task.thread_id == *thread_id
+            })
+            .map(|(cwd, _)| cwd.clone())
+            .collect();
+        for cwd in done {
+            self.tasks_by_cwd.remove(&cwd);
+            // An absolute events file is shared and keeps its receiver.
+            if self.config.hooks.events_path.is_relative() {
+                self.hook_paths_by_cwd.remove(&cwd);
+                if let Some(receiver) = self.hook_receivers.remove(&cwd) {
+                    receiver.shutdown();
+                }
+            }
+        }
+    }
+
+    /// Interrupt the running turn of this conversation's session for `!stop`.
+    async fn stop_turn(&mut self, msg: &IncomingMessage, hard: bool) -> Result<String> {
+        let Some((key, entry)) = self.session_for(msg) else {
+            bail!("No session in this conversation.");
+        };
+        info!(
+            "stop: {} interrupted {}{}",
+            msg.user_id.as_deref().unwrap_or("-"),
+            entry.session_name,
+            if hard { " (hard)" } else { "" }
+        );
+        if !self.interrupt_turn(&key, hard).await? {
+            return Ok("No turn is running here.".to_string());
+        }
+        Ok(":octagonal_sign: Stopped this turn. Send another message to continue.".to_string())
+    }
+
+    /// Interrupt the running turn of `key` with Escape, then Ctrl-C when `hard`, and
+    /// record it as interrupted. Returns false when no turn is running.
+    async fn interrupt_turn(&mut self, key: &ConversationKey, hard: bool) -> Result<bool> {
+        let Some(entry) = self.sessions_by_key.get_mut(key) else {
+            return Ok(false);
+        };
+        let Some(started) = entry.turn_started_at else {
+            return Ok(false);
+        };
+        self.sessions.send_key(&entry.session_name, "Escape")?;
+        if hard {
+            self.sessions.send_key(&entry.session_name, "C-c")?;
+        }
+        entry.turn_started_at = None;
+        let turn = entry
+            .last_transcript_path
+            .as_deref()
+            .and_then(|path| context::latest_turn(path).ok());
+        self.record_turn(key, Some(started), turn, Some("interrupted"), None);
+        self.publish_turn_completed(key, TurnOutcome::Interrupted);
+        self.finish_status(key, TurnStatus::Failed).await;
+        Ok(true)
+    }
+
+    /// Revert the last turn committed by `auto_commit` and tell the session about it.
+    async fn undo(&mut self, msg: &IncomingMessage, prompt_timeout: Duration) -> Result<String> {
+        let key = ConversationKey {
+            conversation_id: msg.conversation_id.clone(),
+            thread_id: msg.thread_id.clone(),
+        };
+        let mode = self.config.auto_commit(&key.conversation_id);
+        if mode == AutoCommit::Off {
+            bail!("`!undo` needs `auto_commit` to be enabled for this channel.");
+        }
+        let Some(entry) = self.sessions_by_key.get(&key).cloned() else {
+            bail!("No session in this conversation.");
+        };
+        if entry.turn_started_at.is_some() {
+            bail!("A turn is running. Wait for it to finish before undoing.");
+        }
+        let Some(commit) = entry.turn_commits.last().cloned() else {
+            bail!("No ccterm turn commits to undo in this session.");
+        };
+        let cwd = self
+            .cwd_for_key(&key)
+            .context("no session directory for this conversation")?;
+        let (revert_cwd, reverted) = (cwd.clone(), commit.clone());
+        let files =
+            tokio::task::spawn_blocking(move || git::revert(&revert_cwd, &reverted)).await??;
+        if let Some(entry) = self.sessions_by_key.get_mut(&key) {
+            entry.turn_commits.pop();
+        }
+        let mut reply = format!(
+            ":leftwards_arrow_with_hook: Reverted `{commit}` ({} file(s)).",
+            files.len()
+        );
+        if mode == AutoCommit::Push {
+            let scope = key.thread_id.as_deref().unwrap_or(&key.conversation_id);
+            let remote = self.config.coordinator.auto_push_remote.clone();
+            let branch = format!("ccterm/{}", sanitize_thread_id(scope));
+            let (push_remote, push_branch) = (remote.clone(), branch.clone());
+            let pushed =
+                tokio::task::spawn_blocking(move || git::push(&cwd, &push_remote, &push_branch))
+                    .await?;
+            match pushed {
+                Ok(()) => reply.push_str(&format!(" Pushed to `{remote}/{branch}`.")),
+                Err(err) => reply.push_str(&format!(" Push failed: {err}")),
+            }
+        }
+        let prompt = format!(
+            "{UNDO_PROMPT} with `git revert {commit}`. These files are back to their earlier \
+             state: {}. Do not reapply the change unless asked.",
+            files.join(", ")
+        );
+        self.enqueue_send(&entry, prompt, prompt_timeout);
+        Ok(reply)
+    }
+
+    /// Refuse `!set`/`!unset` of `APPROVER_SETTING_KEYS` from anyone but the permission
+    /// approvers.
+    fn check_setting_access(&self, msg: &IncomingMessage, key: &str) -> Result<()> {
+        if !APPROVER_SETTING_KEYS.contains(&key) {
+            return Ok(());
+        }
+        let approver = msg
+            .user_id
+            .as_ref()
+            .is_some_and(|user| self.config.coordinator.permission_approvers.contains(user));
+        if !approver {
+            bail!("Only permission approvers can change `{key}`.");
+        }
+        Ok(())
+    }
+
+    fn run_command(&mut self, msg: &IncomingMessage, command: ChatCommand) -> Result<String> {
+        let conversation_id = msg.conversation_id.as_str();
+        match command {
+            ChatCommand::Set { key, value } => {
+                self.check_setting_access(msg, &key)?;
+                let mut settings = self
+                    .channel_settings
+                    .get(conversation_id)
+                    .cloned()
+                    .unwrap_or_default();
+                settings.set(&key, &value)?;
+                self.channel_settings
+                    .insert(conversation_id.to_string(), settings);
+                self.state
+                    .save(CHANNEL_SETTINGS_STATE, &self.channel_settings)?;
+                Ok(format!(
+                    "Set `{key}` = `{value}` for this channel. It applies to new sessions."
+                ))
+            }
+            ChatCommand::Unset { key } => {
+                self.check_setting_access(msg, &key)?;
+                let mut settings = self
+                    .channel_settings
+                    .get(conversation_id)
+                    .cloned()
+                    .unwrap_or_default();
+                settings.unset(&key)?;
+                if settings.is_empty() {
+                    self.channel_settings.remove(conversation_id);
+                } else {
+                    self.channel_settings
+                        .insert(conversation_id.to_string(), settings);
+                }
+                self.state
+                    .save(CHANNEL_SETTINGS_STATE, &self.channel_settings)?;
+                Ok(format!("Unset `{key}` for this channel."))
+            }
+            ChatCommand::Settings => {
+                let entries = self
+                    .channel_settings
+                    .get(conversation_id)
+                    .map(ChannelSettings::entries)
+                    .unwrap_or_default();
+                if entries.is_empty() {
+                    return Ok("No channel settings. Use `!set <key> <value>`.".to_string());
+                }
+                let lines: Vec<String> = entries
+                    .into_iter()
+                    .map(|(key, value)| format!("• `{key}` = `{value}`"))
+                    .collect();
+                Ok(format!("Channel settings:\n{}", lines.join("\n")))
+            }
+            ChatCommand::Peek { lines } => self.peek_text(msg, lines),
+            ChatCommand::Spawn { .. } => bail!("!spawn is handled asynchronously"),
+            ChatCommand::Retry { .. } => bail!("!retry is handled asynchronously"),
+            ChatCommand::Undo => bail!("!undo is handled asynchronously"),
+            ChatCommand::History { .. } => bail!("!history is handled asynchronously"),
+            ChatCommand::Restart => bail!("!restart is handled asynchronously"),
+            ChatCommand::Stop { .. } => bail!("!stop is handled asynchronously"),
+            ChatCommand::Status => bail!("!status is handled asynchronously"),
+            ChatCommand::Mute { duration } => {
+                let key = ConversationKey {
+                    conversation_id: conversation_id.to_string(),
+                    thread_id: msg.thread_id.clone(),
+                };
+                let until = duration.map(|duration| self.clock.now() + duration);
+                self.muted.insert(key, until);
+                let span = match duration {
+                    Some(duration) => format!("for {} or until", format_duration(duration)),
+                    None => "until".to_string(),
+                };
+                Ok(format!(
+                    ":mute: Progress notices are muted here {span} the next reply. The session \
+                     keeps working; replies and permission prompts still come through. \
+                     `!unmute` to undo."
+                ))
+            }
+            ChatCommand::Unmute => {
+                let key = ConversationKey {
+                    conversation_id: conversation_id.to_string(),
+                    thread_id: msg.thread_id.clone(),
+                };
+                match self.muted.remove(&key) {
+                    Some(_) => Ok(":loud_sound: Unmuted.".to_string()),
+                    None => Ok("This conversation is not muted.".to_string()),
+                }
+            }
+        }
+    }
+
+    /// The sender's last `count` prompts in this conversation from `turns.jsonl`, oldest
+    /// first, with links to their replies.
+    async fn history_text(&self, msg: &IncomingMessage, count: usize) -> Result<String> {
+        let turns: Vec<TurnRecord> = self.state.load_recent_lines(TURNS_STATE, TURNS_READ_BYTES)?;
+        let mine: Vec<&TurnRecord> = turns
+            .iter()
+            .filter(|turn| {
+                turn.conversation_id == msg.conversation_id
+                    && turn.thread_id == msg.thread_id
+                    && turn.user_id == msg.user_id
+            })
+            .collect();
+        let recent = &mine[mine.len().saturating_sub(count)..];
+        if recent.is_empty() {
+            return Ok("No earlier prompts from you in this conversation.".to_string());
+        }
+        let mut lines = vec![format!("Your last {} prompt(s) here:", recent.len())];
+        for turn in recent {
+            let when = match turn.started_at.parse::<jiff::Timestamp>() {
+                Ok(ts) => context::local_time(ts, &self.time_zone),
+                Err(_) => turn.started_at.clone(),
+            };
+            let prompt = match &turn.prompt {
+                Some(prompt) => {
+                    let flat = prompt.split_whitespace().collect::<Vec<_>>().join(" ");
+                    format!("`{}`", truncate_chars(&flat, 300).replace('`', "'"))
+                }
+                None => "_prompt not recorded_".to_string(),
+            };
+            let mut line = format!("• {when}: {prompt}");
+            if let Some(reply_ts) = &turn.reply_ts {
+                match self.chat.permalink(&turn.conversation_id, reply_ts).await {
+                    Ok(link) => line.push_str(&format!(" (<{link}|reply>)")),
+                    Err(err) => warn!("history: no permalink for {reply_ts}: {err}"),
+                }
+            }
+            lines.push(line);
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// Answer a command only to its sender, or in the open when the sender is unknown.
+    async fn reply_privately(&self, msg: &IncomingMessage, text: String) -> Result<()> {
+        let reply = OutgoingMessage {
+            text,
+            conversation_id: msg.conversation_id.clone(),
+            thread_id: msg.thread_id.clone(),
+        };
+        match &msg.user_id {
+            Some(user_id) => self.chat.send_ephemeral(user_id, &reply).await,
+            None => self.chat.send(&reply).await,
+        }
+    }
+
+    /// Whether `!mute` holds back progress notices for this conversation.
+    fn is_muted(&self, key: &ConversationKey) -> bool {
+        let now = self.clock.now();
+        self.muted
+            .get(key)
+            .is_some_and(|until| until.is_none_or(|until| now < until))
+    }
+
+    /// Publish the App Home tab of `user`: sessions they have sent messages to, and their
+    /// most recent turns from `turns.jsonl`.
+    async fn publish_home(&self, user: &str) -> Result<()> {
+        let turns: Vec<TurnRecord> = self.state.load_recent_lines(TURNS_STATE, TURNS_READ_BYTES)?;
+        let mine: Vec<&TurnRecord> = turns
+            .iter()
+            .filter(|turn| turn.user_id.as_deref() == Some(user))
+            .collect();
+        let mut keys: Vec<&ConversationKey> = self
+            .sessions_by_key
+            .iter()
+            .filter(|(key, entry)| took_part(user, key, entry, &mine))
+            .map(|(key, _)| key)
+            .collect();
+        keys.sort_by(|a, b| {
+            (&a.conversation_id, &a.thread_id).cmp(&(&b.conversation_id, &b.thread_id))
+        });
+
+        let mut view = HomeView::default();
+        for key in keys {
+            let entry = &self.sessions_by_key[key];
+            let mut text = format!("*<#{}>*", key.conversation_id);
+            if let Some(thread_id) = &key.thread_id {
+                text.push_str(&format!(" thread `{thread_id}`"));
+            }
+            text.push_str(&format!(" · `{}`", entry.session_name));
+            if let Some(title) = &entry.title {
+                text.push_str(&format!(": {title}"));
+            }
+            text.push_str(&match entry.turn_started_at {
+                Some(started) => format!(
+                    "\n:hourglass_flowing_sand: running for {}s",
+                    self.clock.now().duration_since(started).as_secs()
+                ),
+                None => "\nidle".to_string(),
+            });
+            let value = match &key.thread_id {
+                Some(thread_id) => format!("{}:{thread_id}", key.conversation_id),
+                None => key.conversation_id.clone(),
+            };
+            view.sessions.push(HomeSession { text, value });
+        }
+        for turn in mine.iter().rev().take(HOME_RECENT_TURNS) {
+            let when = turn.ended_at.get(..16).unwrap_or(&turn.ended_at).replace('T', " ");
+            let prompt = match &turn.prompt {
+                Some(prompt) => truncate_chars(prompt.lines().next().unwrap_or_default(), 80),
+                None => "_prompt not recorded_".to_string(),
+            };
+            let mut line = format!("• <#{}> {when} UTC: {prompt}", turn.conversation_id);
+            if let Some(ts) = &turn.reply_ts {
+                match self.chat.permalink(&turn.conversation_id, ts).await {
+                    Ok(link) => line.push_str(&format!(" · <{link}|reply>")),
+                    Err(err) => warn!("home permalink failed: {err}"),
+                }
+            }
+            view.recent.push(line);
+        }
+        self.chat.publish_home(user, &view, HOME_ACTION_PREFIX).await
+    }
+
+    /// New session, Stop and Export buttons on the App Home tab. The home view is
+    /// published again afterwards to show the new state.
+    async fn handle_home_action(&mut self, action: &ActionEvent, op: &str) -> Result<()> {
+        let Some(value) = action.value.as_deref() else {
+            return Ok(());
+        };
+        let key = match value.split_once(':') {
+            Some((conversation_id, thread_id)) => ConversationKey {
+                conversation_id: conversation_id.to_string(),
+                thread_id: Some(thread_id.to_string()),
+            },
+            None => ConversationKey {
+                conversation_id: value.to_string(),
+                thread_id: None,
+            },
+        };
+        let user = &action.user_id;
+        if let Some(entry) = self.sessions_by_key.get(&key).cloned() {
+            if !self.may_act_on(&key, user) {
+                info!("home: {user} may not {op} {}, ignored", entry.session_name);
+                let text = "Only people who sent messages to this session or a permission \
+                            approver can use its buttons.";
+                return self.refuse_action(user, &key, text).await;
+            }
+            match op {
+                "new" => {
+                    info!("home: {user} ended {}", entry.session_name);
+                    self.stop_entry(&key, "home").await;
+                    let text = format!(
+                        ":recycle: <@{user}> ended this session from the App Home. The next \
+                         message starts a new one."
+                    );
+                    self.post_notice(&key, &text).await;
+                }
+                "stop" if entry.turn_started_at.is_some() => {
+                    info!("home: {user} interrupted {}", entry.session_name);
+                    self.interrupt_turn(&key, false).await?;
+                    let text =
+                        format!(":octagonal_sign: <@{user}> stopped this turn from the App Home.");
+                    self.post_notice(&key, &text).await;
+                }
+                "export" => match &entry.last_transcript_path {
+                    Some(path) => {
+                        let thread_id = key.thread_id.as_deref();
+                        self.upload_transcript(&key, thread_id, path, &entry.session_name)
+                            .await?;
+                    }
+                    None => info!("home: no transcript yet for {}", entry.session_name),
+                },
+                _ => {}
+            }
+        }
+        self.publish_home(user).await
+    }
+
+    /// Run a plugin command in the background; its output is posted where the command
+    /// was sent.
+    fn start_plugin(&self, msg: &IncomingMessage, plugin: PluginConfig, args: String) {
+        info!(
+            "plugin `{}` from {} channel={}",
+            plugin.name,
+            msg.user_id.as_deref().unwrap_or("-"),
+            msg.conversation_id
+        );
+        let notice_tx = self.notice_tx.clone();
+        let cwd = self.base_cwd.clone();
+        let msg = msg.clone();
+        tokio::spawn(async move {
+            let request = PluginRequest {
+                command: &plugin.name,
+                args: &args,
+                conversation_id: &msg.conversation_id,
+                thread_id: msg.thread_id.as_deref(),
+                user_id: msg.user_id.as_deref(),
+                cwd: &cwd,
+            };
+            let text = match plugins::run(&plugin, &request).await {
+                Ok(text) => text,
+                Err(err) => format!(":warning: {err:#}"),
+            };
+            let _ = notice_tx.send(OutgoingMessage {
+                text,
+                conversation_id: msg.conversation_id,
+                thread_id: msg.thread_id,
+            });
+        });
+    }
+
+    /// Start an auxiliary session for `!spawn` in its own directory and send it the task.
+    /// Its reply is posted to the originating thread by `handle_task_stop`.
+    async fn spawn_task(
+        &mut self,
+        msg: &IncomingMessage,
+        task: &str,
+        prompt_timeout: Duration,
+    ) -> Result<String> {
+        let thread_id = msg
+            .thread_id
+            .clone()
+            .or_else(|| msg.timestamp.clone())
+            .context("cannot spawn a task without a thread")?;
+        let mut number = self
+            .tasks_by_cwd
+            .values()
+            .filter(|existing| {
+                existing.conversation_id == msg.conversation_id && existing.thread_id == thread_id
+            })
+            .count()
+            + 1;
+        let task_dir = |number: usize| {
+            self.base_cwd
+                .join(".ccterm/tasks")
+                .join(format!("{}-{number}", sanitize_thread_id(&thread_id)))
+        };
+        // Tasks dropped with a stopped session leave their directories behind.
+        while task_dir(number).exists() {
+            number += 1;
+        }
+        let label = format!("Task {number}: {}", truncate_chars(task.trim(), 60));
+
+        let dir = task_dir(number);
+        let cwd = self.ensure_session_dir(dir)?;
+        let hook_path = self.hook_path_for_cwd(&cwd);
+        self.register_hook_receiver(&cwd, &hook_path)?;
+        self.config.permissions.install(&self.policy_path(&cwd))?;
+
+        let opts = self.spawn_options(&msg.conversation_id);
+        let session_name = self
+            .spawn_session("task", &cwd, &opts, prompt_timeout)
+            .await?;
+        self.publish_spawned(
+            &msg.conversation_id,
+            Some(&thread_id),
+            &session_name,
+            &cwd,
+            &opts,
+        )
+        .await;
+
+        let mut text = format!("{}: {task}", msg.user_name.as_deref().unwrap_or("user"));
+        if self.config.input_guard(&msg.conversation_id) {
+            text = guard::wrap_untrusted(&text);
+        }
+        self.sessions
+            .send_async(&session_name, &text, self.clock.as_ref())
+            .await
+            .with_context(|| format!("failed to send to {session_name}"))?;
+
+        self.tasks_by_cwd.insert(
+            cwd,
+            SpawnedTask {
+                number,
+                label: label.clone(),
+                conversation_id: msg.conversation_id.clone(),
+                thread_id,
+                session_name: session_name.clone(),
+                result: None,
+                joined: false,
+            },
+        );
+        Ok(format!("Started *{label}* in `{session_name}`."))
+    }
+
+    /// The session of the thread `msg` is in, or the conversation's main session.
+    fn session_for(&self, msg: &IncomingMessage) -> Option<(ConversationKey, &SessionEntry)> {
+        let key = match &msg.thread_id {
+            Some(thread_id) => ConversationKey {
+                conversation_id: msg.conversation_id.clone(),
+                thread_id: Some(thread_id.clone()),
+            },
+            None => self.main_by_conversation.get(&msg.conversation_id)?.clone(),
+        };
+        let entry = self.sessions_by_key.get(&key)?;
+        Some((key, entry))
+    }
+
+    /// The last `lines` non-empty lines of the session's pane, for `!peek`.
+    fn peek_text(&self, msg: &IncomingMessage, lines: usize) -> Result<String> {
+        let Some((_, entry)) = self.session_for(msg) else {
+            return Ok("No session for this conversation yet.".to_string());
+        };
+        let pane = self
+            .sessions
+            .capture_pane(&entry.session_name, commands::PEEK_MAX)
+            .with_context(|| format!("failed to capture session {}", entry.session_name))?;
+        let tail = sessions::pane_tail(&pane, lines);
+        if tail.is_empty() {
+            return Ok(format!("The pane of `{}` is empty.", entry.session_name));
+        }
+        Ok(format!(
+            "*Session* `{}`, last {} line(s) of the pane:\n```\n{}\n```",
+            entry.session_name,
+            tail.lines().count(),
+            tail.replace("```", "`\u{200b}``")
+        ))
+    }
+
+    async fn status_text(&self, msg: &IncomingMessage) -> String {
+        let Some((key, entry)) = self.session_for(msg) else {
+            return "No session for this conversation yet.".to_string();
+        };
+
+        let mut lines = vec![match &entry.title {
+            Some(title) => format!("*Session* `{}`: {title}", entry.session_name),
+            None => format!("*Session* `{}`", entry.session_name),
+        }];
+        let turn = match entry.turn_started_at {
+            Some(started) => format!(
+                "running for {}s",
+                self.clock.now().duration_since(started).as_secs()
+            ),
+            None if entry.timed_out => "interrupted (timed out)".to_string(),
+            None => "idle".to_string(),
+        };
+        lines.push(format!("• turn: {turn}"));
+        if let Some(since) = entry.delivery.quarantined_since {
+            lines.push(format!(
+                "• quarantined for {}s after repeated failed deliveries (`!restart` to lift)",
+                self.clock.now().duration_since(since).as_secs()
+            ));
+        }
+        if let Some(since) = entry.health.unhealthy_since {
+            lines.push(format!(
+                "• health: unhealthy for {}s, {} failed probe(s): {}",
+                self.clock.now().duration_since(since).as_secs(),
+                entry.health.failures,
+                entry.health.last_error.as_deref().unwrap_or("-")
+            ));
+        }
+        lines.push(format!("• env: {}", entry.fingerprint.summary()));
+        if let Some(sample) = &entry.resources {
+            lines.push(format!(
+                "• resources: {} RSS · {:.0}% CPU · {} process(es)",
+                resources::format_bytes(sample.usage.rss_bytes),
+                sample.cpu_percent,
+                sample.usage.processes
+            ));
+        }
+        if let Some(used) = entry.disk.used_bytes {
+            let paused = if entry.disk.paused { " (paused: over quota)" } else { "" };
+            lines.push(format!("• disk: {}{paused}", resources::format_bytes(used)));
+        }
+        if let Some(cwd) = self.cwd_for_key(&key) {
+            lines.push(format!("• cwd: `{}`", cwd.display()));
+            let summary = tokio::task::spawn_blocking(move || git::summary(&cwd))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|summary| summary);
+            lines.push(match summary {
+                Ok(Some(git)) => format!(
+                    "• git: `{}` · {} changed file(s) · {}",
+                    git.branch,
+                    git.dirty_files,
+                    git.last_commit
+                        .map(|commit| format!("`{commit}`"))
+                        .unwrap_or_else(|| "no commits".to_string())
+                ),
+                Ok(None) => "• git: not a repository".to_string(),
+                Err(err) => format!("• git: {err}"),
+            });
+        }
+        let tmux = tmux::stats();
+        lines.push(format!(
+            "• tmux: {} call(s) · {} failed · avg {}ms · slowest {}ms",
+            tmux.calls,
+            tmux.failures,
+            tmux.average.as_millis(),
+            tmux.slowest.as_millis()
+        ));
+        match self.chat.identity(&key.conversation_id).await {
+            Ok(identity) => lines.push(format!(
+                "• bot: `{}` in {} (`{}`)",
+                identity.bot_user_id, identity.workspace_name, identity.workspace_id
+            )),
+            Err(err) => warn!("status: no bot identity: {err:#}"),
+        }
+        lines.join("\n")
+    }
+
+    fn channel_setting<T>(
+        &self,
+        conversation_id: &str,
+        pick: impl Fn(&ChannelSettings) -> Option<T>,
+    ) -> Option<T> {
+        self.channel_settings.get(conversation_id).and_then(pick)
+    }
+
+    fn reactions_enabled(&self, conversation_id: &str) -> bool {
+        self.channel_setting(conversation_id, |s| s.reactions)
+            .unwrap_or_else(|| self.config.reactions_enabled(conversation_id))
+    }
+
+    fn output(&self, conversation_id: &str) -> OutputTarget {
+        self.channel_setting(conversation_id, |s| s.output)
+            .unwrap_or_else(|| self.config.output(conversation_id))
+    }
+
+    fn reply_format(&self, conversation_id: &str) -> ReplyFormat {
+        self.channel_setting(conversation_id, |s| s.reply_format)
+            .unwrap_or_else(|| self.config.reply_format(conversation_id))
+    }
+
+    fn max_turn_secs(&self, conversation_id: &str) -> Option<u64> {
+        self.channel_setting(conversation_id, |s| s.max_turn_secs)
+            .or_else(|| self.config.max_turn_secs(conversation_id))
+    }
+
+    fn bash_results(&self, conversation_id: &str) -> BashResultsMode {
+        self.channel_setting(conversation_id, |s| s.bash_results)
+            .unwrap_or(self.config.coordinator.bash_results)
+    }
+
+    /// Move a message's status reaction from `from` to `to`. Failures are logged only.
+    async fn set_status(
+        &self,
+        conversation_id: &str,
+        ts: &str,
+        from: Option<TurnStatus>,
+        to: TurnStatus,
+    ) {
+        if !self.reactions_enabled(conversation_id) {
+            return;
+        }
+        let reactions = &self.config.reactions;
+        if let Some(name) = from.and_then(|status| reactions.emoji(status))
+            && let Err(err) = self.chat.remove_reaction(conversation_id, ts, name).await
+        {
+            warn!("status reaction not removed: {err}");
+        }
+        if let Some(name) = reactions.emoji(to)
+            && let Err(err) = self.chat.add_reaction(conversation_id, ts, name).await
+        {
+            warn!("status reaction not added: {err}");
+        }
+    }
+
+    /// Mark all messages of the session's current turn with `to`.
+    async fn finish_status(&mut self, key: &ConversationKey, to: TurnStatus) {
+        let Some(entry) = self.sessions_by_key.get_mut(key) else {
+            return;
+        };
+        let pending = std::mem::take(&mut entry.status_ts);
+        for ts in pending {
+            self.set_status(&key.conversation_id, &ts, Some(TurnStatus::Working), to)
+                .await;
+        }
+    }
+
+    async fn handle_reaction(
+        &mut self,
+        reaction: ReactionEvent,
+        prompt_timeout: Duration,
+    ) -> Result<()> {
+        let acted_on = self.config.reactions.prompt(&reaction.reaction).is_some()
+            || reaction.reaction == FULL_REPLY_REACTION
+            || self.config.reactions.action(&reaction.reaction).is_some();
+        if acted_on {
+            self.events.publish(CoordinatorEvent::UserAction {
+                conversation_id: reaction.conversation_id.clone(),
+                thread_id: None,
+                user_id: Some(reaction.user_id.clone()),
+                action: format!("reaction:{}", reaction.reaction),
+                target: Some(reaction.message_ts.clone()),
+            });
+        }
+        if let Some(instruction) = self.config.reactions.prompt(&reaction.reaction)
+            && !self
+                .replies
+                .contains_key(&(reaction.conversation_id.clone(), reaction.message_ts.clone()))
+        {
+            let prompted = (
+                reaction.conversation_id.clone(),
+                reaction.message_ts.clone(),
+                reaction.reaction.clone(),
+            );
+            if self.prompted_reactions.contains(&prompted) {
+                return Ok(());
+            }
+            let Some(msg) = self.reaction_prompt(&reaction, instruction).await? else {
+                return Ok(());
+            };
+            info!(
+                "reaction prompt :{}: by {} channel={} ts={}",
+                reaction.reaction, reaction.user_id, prompted.0, prompted.1
+            );
+            self.prompted_reactions.push_back(prompted);
+            if self.prompted_reactions.len() > MAX_PROMPTED_REACTIONS {
+                self.prompted_reactions.pop_front();
+            }
+            self.on_message(msg, prompt_timeout).await;
+            return Ok(());
+        }
+        let key = (reaction.conversation_id, reaction.message_ts);
+        if reaction.reaction == FULL_REPLY_REACTION
+            && let Some(reply) = self.truncated_replies.get(&key)
+        {
+            info!(
+                "full reply requested by {} channel={} ts={}",
+                reaction.user_id, key.0, key.1
+            );
+            let filename = format!("reply-{}.md", key.1);
+            return self
+                .chat
+                .upload_text(&key.0, reply.thread_id.as_deref(), &filename, &reply.text)
+                .await;
+        }
+        let Some(action) = self.config.reactions.action(&reaction.reaction) else {
+            return Ok(());
+        };
+        let Some(record) = self.replies.get(&key).cloned() else {
+            return Ok(());
+        };
+        info!(
+            "reaction action {action:?} by {} channel={} ts={}",
+            reaction.user_id, key.0, key.1
+        );
+        let user = &reaction.user_id;
+        match action {
+            ReactionAction::Rerun => self.rerun_reply(&record, user, prompt_timeout).await,
+            ReactionAction::Fork => self.fork_reply(&record, &key.1, user, prompt_timeout).await,
+            ReactionAction::Transcript => {
+                self.upload_transcript(
+                    &record.key,
+                    record.thread_id.as_deref(),
+                    &record.transcript_path,
+                    &record.claude_session_id,
+                )
+                .await
+            }
+        }
+    }
+
+    /// Upload a transcript as `transcript-<name>.jsonl`, and as `transcript-<name>.md` with
+    /// message times in the configured zone for reading.
+    async fn upload_transcript(
+        &self,
+        key: &ConversationKey,
+        thread_id: Option<&str>,
+        path: &Path,
+        name: &str,
+    ) -> Result<()> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        self.chat
+            .upload_text(
+                &key.conversation_id,
+                thread_id,
+                &format!("transcript-{name}.jsonl"),
+                &content,
+            )
+            .await?;
+        let history = context::read_history(path, None)?;
+        let markdown = context::format_transcript(&history, &self.time_zone);
+        self.chat
+            .upload_text(
+                &key.conversation_id,
+                thread_id,
+                &format!("transcript-{name}.md"),
+                &markdown,
+            )
+            .await
+    }
+
+    /// Send the prompt behind a reply to its session again.
+    async fn rerun_reply(
+        &mut self,
+        record: &ReplyRecord,
+        user: &str,
+        prompt_timeout: Duration,
+    ) -> Result<()> {
+        let entry = self.sessions_by_key.get(&record.key).cloned();
+        let text = match (&record.prompt, entry) {
+            _ if self.maintenance.enabled => self.maintenance_text(),
+            (None, _) => "The prompt behind this reply is not known; send it again.".to_string(),
+            (_, None) => "The session for this reply is gone; send the prompt again.".to_string(),
+            (_, Some(entry)) if entry.turn_started_at.is_some() => {
+                "A turn is running; react again when it finishes.".to_string()
+            }
+            (Some(prompt), Some(entry)) => {
+                self.enqueue_send(&entry, prompt.clone(), prompt_timeout);
+                format!(":repeat: Re-running the prompt for <@{user}>.")
+            }
+        };
+        let notice = OutgoingMessage {
+            text,
+            conversation_id: record.key.conversation_id.clone(),
+            thread_id: record.thread_id.clone(),
+        };
+        self.chat.send(&notice).await
+    }
+
+    /// Continue from a reply in a new top-level thread, whose session resumes a copy of
+    /// the reply's transcript.
+    async fn fork_reply(
+        &mut self,
+        record: &ReplyRecord,
+        reply_ts: &str,
+        user: &str,
+        prompt_timeout: Duration,
+    ) -> Result<()> {
+        let conversation_id = record.key.conversation_id.clone();
+        let link = match self.chat.permalink(&conversation_id, reply_ts).await {
+            Ok(url) => format!("<{url}|this reply>"),
+            Err(err) => {
+                warn!("fork permalink unavailable: {err}");
+                "a reply".to_string()
+            }
+        };
+        let root = OutgoingMessage {
+            text: format!(
+                ":thread: <@{user}> forked the conversation from {link}. Continue in this thread."
+            ),
+            conversation_id: conversation_id.clone(),
+            thread_id: None,
+        };
+        let thread_id = self.chat.post(&root).await?;
+        let cwd = self.ensure_thread_dir(&thread_id).await?;
+        let project_dir = snapshot::claude_project_dir(&cwd)?;
+        std::fs::create_dir_all(&project_dir)
+            .with_context(|| format!("failed to create {}", project_dir.display()))?;
+        let dest = project_dir.join(format!("{}.jsonl", record.claude_session_id));
+        std::fs::copy(&record.transcript_path, &dest)
+            .with_context(|| format!("failed to copy transcript to {}", dest.display()))?;
+
+        let key = ConversationKey {
+            conversation_id,
+            thread_id: Some(thread_id),
+        };
+        let mut opts = self.spawn_options(&key.conversation_id);
+        opts.args
+            .extend(["--resume".to_string(), record.claude_session_id.clone()]);
+        self.start_thread_session(&key, cwd, &opts, prompt_timeout)
+            .await?;
+        Ok(())
+    }
+
+    /// Remember a posted reply for reaction actions, when any are configured.
+    fn record_reply(
+        &mut self,
+        key: &ConversationKey,
+        ts: String,
+        thread_id: Option<String>,
+        prompt: Option<String>,
+        hook: &HookEvent,
+    ) {
+        if self.config.reactions.actions.is_empty() {
+            return;
+        }
+        let id = (key.conversation_id.clone(), ts);
+        self.replies.insert(
+            id.clone(),
+            ReplyRecord {
+                key: key.clone(),
+                thread_id,
+                prompt,
+                claude_session_id: hook.session_id.clone(),
+                transcript_path: hook.transcript_path.clone(),
+            },
+        );
+        self.reply_order.push_back(id);
+        while self.reply_order.len() > MAX_RECORDED_REPLIES {
+            if let Some(old) = self.reply_order.pop_front() {
+                self.replies.remove(&old);
+            }
+        }
+    }
+
+    async fn handle_action(&mut self, action: ActionEvent, prompt_timeout: Duration) -> Result<()> {
+        self.events.publish(CoordinatorEvent::UserAction {
+            conversation_id: action.conversation_id.clone(),
+            thread_id: None,
+            user_id: Some(action.user_id.clone()),
+            action: action.action_id.clone(),
+            target: action.value.clone(),
+        });
+        if let Some(op) = action
+            .action_id
+            .strip_prefix(HOME_ACTION_PREFIX)
+            .and_then(|rest| rest.strip_prefix('_'))
+        {
+            return self.handle_home_action(&action, op).await;
+        }
+        if let Some(decision) = action
+            .action_id
+            .strip_prefix(QUOTA_ACTION_PREFIX)
+            .and_then(|rest| rest.strip_prefix('_'))
+        {
+            return self.handle_quota_action(&action, decision).await;
+        }
+        if let Some(decision) = action
+            .action_id
+            .strip_prefix(PLAN_ACTION_PREFIX)
+            .and_then(|rest| rest.strip_prefix('_'))
+        {
+            return self.handle_plan_action(&action, decision).await;
+        }
+        if let Some(decision) = action
+            .action_id
+            .strip_prefix(PERMISSION_ACTION_PREFIX)
+            .and_then(|rest| rest.strip_prefix('_'))
+        {
+            return self.handle_permission_action(&action, decision).await;
+        }
+        if let Some(decision) = action
+            .action_id
+            .strip_prefix(PREFLIGHT_ACTION_PREFIX)
+            .and_then(|rest| rest.strip_prefix('_'))
+        {
+            return self
+                .handle_preflight_action(&action, decision, prompt_timeout)
+                .await;
+        }
+        let Some(decision) = action
+            .action_id
+            .strip_prefix(PATCH_ACTION_PREFIX)
+            .and_then(|rest| rest.strip_prefix('_'))
+        else {
+            return Ok(());
+        };
+        if decision != "approve" && decision != "reject" {
+            return Ok(());
+        }
+        let Some(id) = action.value.as_deref() else {
+            return Ok(());
+        };
+        let Some(pending) = self.pending_patches.get(id) else {
+            info!("patch approval not pending: {id}");
+            return Ok(());
+        };
+        let user = &action.user_id;
+        if !self.may_act_on(&pending.key, user) {
+            info!("patch {id}: {user} may not answer, ignored");
+            let key = pending.key.clone();
+            let text = "Only people who sent messages to this session or a permission \
+                        approver can answer.";
+            return self.refuse_action(user, &key, text).await;
+        }
+        let Some(pending) = self.pending_patches.remove(id) else {
+            return Ok(());
+        };
+
+        let text = if decision == "approve" {
+            match self.approve_patch(&pending, prompt_timeout).await {
+                Ok(done) => format!(":white_check_mark: Patch approved by <@{user}>; {done}"),
+                Err(err) => {
+                    format!(":warning: Patch approved by <@{user}> but not applied: {err}")
+                }
+            }
+        } else {
+            format!(":no_entry_sign: Patch rejected by <@{user}>.")
+        };
+        info!("patch {id} {decision}d by {user}");
+        match &action.message_ts {
+            Some(ts) => {
+                self.chat
+                    .update_text(&action.conversation_id, ts, &text)
+                    .await
+            }
+            None => {
+                let notice = OutgoingMessage {
+                    text,
+                    conversation_id: pending.key.conversation_id,
+                    thread_id: pending.key.thread_id,
+                };
+                self.chat.send(&notice).await
+            }
+        }
+    }
+
+    /// Approve continues a session paused over the disk quota; Reject stops it.
+    async fn handle_quota_action(&mut self, action: &ActionEvent, decision: &str) -> Result<()> {
+        if decision != "approve" && decision != "reject" {
+            return Ok(());
+        }
+        let Some(id) = action.value.as_deref() else {
+            return Ok(());
+        };
+        let Some(key) = self.pending_quota.get(id).cloned() else {
+            return Ok(());
+        };
+        let user = &action.user_id;
+        if !self.may_act_on(&key, user) {
+            info!("quota {id}: {user} may not answer, ignored");
+            let text = "Only people who sent messages to this session or a permission \
+                        approver can answer.";
+            return self.refuse_action(user, &key, text).await;
+        }
+        self.pending_quota.remove(id);
+        let text = match decision {
+            "approve" => {
+                if let Some(entry) = self.sessions_by_key.get_mut(&key) {
+                    entry.disk.paused = false;
+                    entry.disk.confirmed = true;
+                }
+                format!(":white_check_mark: <@{user}> chose to continue past the disk quota.")
+            }
+            "reject" => {
+                self.stop_entry(&key, "disk quota").await;
+                format!(
+                    ":no_entry_sign: <@{user}> stopped the session. Free up space in the \
+                     thread directory before sending another message."
+                )
+            }
+            _ => return Ok(()),
+        };
+        match &action.message_ts {
+            Some(ts) => {
+                self.chat
+                    .update_text(&action.conversation_id, ts, &text)
+                    .await
+            }
+            None => {
+                let notice = OutgoingMessage {
+                    text,
+                    conversation_id: key.conversation_id,
+                    thread_id: key.thread_id,
+                };
+                self.chat.send(&notice).await
+            }
+        }
+    }
+
+    /// Approve sends a prompt held by the pre-flight check; Reject drops it.
+    async fn handle_preflight_action(
+        &mut self,
+        action: &ActionEvent,
+        decision: &str,
+        prompt_timeout: Duration,
+    ) -> Result<()> {
+        if decision != "approve" && decision != "reject" {
+            return Ok(());
+        }
+        let Some(id) = action.value.as_deref() else {
+            return Ok(());
+        };
+        let Some(pending) = self.pending_preflight.get(id).map(|pending| &pending.prompt) else {
+            return Ok(());
+        };
+        let user = &action.user_id;
+        let key = ConversationKey {
+            conversation_id: pending.msg.conversation_id.clone(),
+            thread_id: pending.msg.thread_id.clone(),
+        };
+        let author = pending.msg.user_id.as_deref() == Some(user.as_str());
+        if !author && !self.config.coordinator.permission_approvers.contains(user) {
+            info!("preflight {id}: {user} may not answer, ignored");
+            let text = "Only the person who sent this message or a permission approver can \
+                        answer.";
+            return self.refuse_action(user, &key, text).await;
+        }
+        let Some(pending) = self.pending_preflight.remove(id).map(|pending| pending.prompt) else {
+            return Ok(());
+        };
+        let status_ts = pending.msg.timestamp.clone();
+        info!("preflight {decision} by {user} channel={}", key.conversation_id);
+        let text = if decision == "approve" {
+            let result = self
+                .send_prepared(pending.msg, pending.text, &pending.directives, prompt_timeout)
+                .await;
+            if !matches!(result, Ok(None)) {
+                let delivered = result.as_ref().ok().and_then(Option::as_ref);
+                self.track_delivery(&key.conversation_id, status_ts, delivered).await;
+            }
+            match result {
+                Ok(Some(_)) => format!(":white_check_mark: Sent by <@{user}> despite its size."),
+                Ok(None) => format!(
+                    ":white_check_mark: Approved by <@{user}>; it is sent once the session starts."
+                ),
+                Err(err) => {
+                    self.publish_error("preflight", &err);
+                    format!(":warning: Approved by <@{user}> but not sent: {err}")
+                }
+            }
+        } else {
+            if let Some(ts) = &status_ts {
+                let received = Some(TurnStatus::Received);
+                self.set_status(&key.conversation_id, ts, received, TurnStatus::Failed)
+                    .await;
+            }
+            format!(":no_entry_sign: <@{user}> dropped the message; it was not sent.")
+        };
+        match &action.message_ts {
+            Some(ts) => {
+                self.chat
+                    .update_text(&action.conversation_id, ts, &text)
+                    .await
+            }
+            None => {
+                self.post_notice(&key, &text).await;
+                Ok(())
+            }
+        }
+    }
+
+    /// Answer Claude's plan approval prompt: Enter accepts the highlighted "Yes" option,
+    /// Escape declines and leaves Claude waiting for feedback.
+    async fn handle_plan_action(&mut self, action: &ActionEvent, decision: &str) -> Result<()> {
+        let (keystroke, verb) = match decision {
+            "approve" => ("Enter", ":white_check_mark: Plan approved"),
+            "reject" => ("Escape", ":memo: Plan sent back"),
+            _ => return Ok(()),
+        };
+        let Some(id) = action.value.as_deref() else {
+            return Ok(());
+        };
+        let Some(key) = self.pending_plans.get(id).cloned() else {
+            return Ok(());
+        };
+        let user = &action.user_id;
+        if !self.may_act_on(&key, user) {
+            info!("plan {id}: {user} may not answer, ignored");
+            let text = "Only people who sent messages to this session or a permission \
+                        approver can answer.";
+            return self.refuse_action(user, &key, text).await;
+        }
+        self.pending_plans.remove(id);
+        let Some(entry) = self.sessions_by_key.get(&key) else {
+            return Ok(());
+        };
+        // The prompt may have been answered in the pane meanwhile; a key sent then would
+        // land in Claude's input instead.
+        let open = self
+            .sessions
+            .capture_pane(&entry.session_name, 200)
+            .map(|pane| {
+                sessions::plan_prompt_open(&pane, self.sessions.profile(), PERMISSION_PROMPT_LINES)
+            });
+        let sent = match open {
+            Ok(true) => self.sessions.send_key(&entry.session_name, keystroke),
+            Ok(false) => Err(anyhow::anyhow!("the plan prompt is no longer open in the terminal")),
+            Err(err) => Err(err),
+        };
+        let text = match sent {
+            Ok(()) if decision == "approve" => format!("{verb} by <@{user}>."),
+            Ok(()) => format!("{verb} by <@{user}>. Reply in the thread with what to change."),
+            Err(err) => {
+                warn!("failed to answer plan prompt in {}: {err}", entry.session_name);
+                format!(":warning: Could not answer the plan prompt: {err}")
+            }
+        };
+        match &action.message_ts {
+            Some(ts) => {
+                self.chat
+                    .update_text(&action.conversation_id, ts, &text)
+                    .await
+            }
+            None => {
+                self.post_notice(&key, &text).await;
+                Ok(())
+            }
+        }
+    }
+
+    async fn approve_patch(
+        &mut self,
+        pending: &PendingPatch,
+        prompt_timeout: Duration,
+    ) -> Result<&'static str> {
+        if pending.mode == PatchApproval::Apply {
+            let (cwd, patch) = (pending.cwd.clone(), pending.diff.clone());
+            tokio::task::spawn_blocking(move || diff::apply_patch(&cwd, &patch)).await??;
+            return Ok("applied with `git apply`.");
+        }
+        let entry = self
+            .sessions_by_key
+            .get(&pending.key)
+            .cloned()
+            .context("the session for this patch is gone")?;
+        self.enqueue_send(&entry, PATCH_APPROVED_PROMPT.to_string(), prompt_timeout);
+        Ok("asked Claude to apply and commit it.")
+    }
+
+    /// Follow a reply containing diffs with Approve/Reject buttons.
+    async fn offer_patch(
+        &mut self,
+        key: &ConversationKey,
+        thread_id: Option<String>,
+        diffs: &[String],
+    ) {
+        let mode = self.config.patch_approval(&key.conversation_id);
+        if mode == PatchApproval::Off || diffs.is_empty() {
+            return;
+        }
+        let Some(cwd) = self
+            .key_by_cwd
+            .iter()
+            .find(|(_, k)| *k == key)
+            .map(|(cwd, _)| cwd.clone())
+        else {
+            return;
+        };
+        let id = approval_id();
+        let diff = diffs.concat();
+        let action = match mode {
+            PatchApproval::Apply => "apply it with `git apply`",
+            _ => "ask Claude to apply and commit it",
+        };
+        let prompt = OutgoingMessage {
+            text: format!(
+                "This reply proposes a patch ({} lines). Approve to {action}.",
+                diff.lines().count()
+            ),
+            conversation_id: key.conversation_id.clone(),
+            thread_id,
+        };
+        match self.chat.post_approval(&prompt, PATCH_ACTION_PREFIX, &id).await {
+            Ok(_) => {
+                self.pending_patches.insert(
+                    id,
+                    PendingPatch {
+                        key: key.clone(),
+                        cwd,
+                        diff,
+                        mode,
+                    },
+                );
+            }
+            Err(err) => warn!("patch approval not posted: {err}"),
+        }
+    }
+
+    /// Post an assistant reply, truncating it when it exceeds `reply_truncate_chars` and
+    /// remembering the full text for a :heavy_plus_sign: reaction. Returns the posted `ts`.
+    async fn send_reply(&mut self, outgoing: OutgoingMessage) -> Result<String> {
+        if self.output(&outgoing.conversation_id) == OutputTarget::Canvas {
+            return self.send_canvas_reply(outgoing).await;
+        }
+        let limit = self.config.coordinator.reply_truncate_chars;
+        let total = outgoing.text.chars().count();
+        if limit == 0 || total <= limit {
+            return self.post_formatted(&outgoing).await;
+        }
+
+        let truncated = OutgoingMessage {
+            text: format!(
+                "{}\n\n_Reply truncated ({total} chars). React with :{FULL_REPLY_REACTION}: to get the full reply as a file._",
+                truncate_chars(&outgoing.text, limit)
+            ),
+            conversation_id: outgoing.conversation_id.clone(),
+            thread_id: outgoing.thread_id.clone(),
+        };
+        let ts = self.post_formatted(&truncated).await?;
+        let key = (outgoing.conversation_id, ts.clone());
+        self.truncated_replies.insert(
+            key.clone(),
+            TruncatedReply {
+                thread_id: outgoing.thread_id,
+                text: outgoing.text,
+            },
+        );
+        self.truncated_order.push_back(key);
+        while self.truncated_order.len() > MAX_TRUNCATED_REPLIES {
+            if let Some(old) = self.truncated_order.pop_front() {
+                self.truncated_replies.remove(&old);
+            }
+        }
+        Ok(ts)
+    }
+
+    /// Post reply Markdown in the conversation's `reply_format`.
+    async fn post_formatted(&self, outgoing: &OutgoingMessage) -> Result<String> {
+        match self.reply_format(&outgoing.conversation_id) {
+            ReplyFormat::Raw => self.chat.post(outgoing).await,
+            ReplyFormat::Mrkdwn => {
+                let converted = OutgoingMessage {
+                    text: mrkdwn::to_mrkdwn(&outgoing.text),
+                    ..outgoing.clone()
+                };
+                self.chat.post(&converted).await
+            }
+            ReplyFormat::Blocks => {
+                let blocks = mrkdwn::to_blocks(&outgoing.text);
+                self.chat
+                    .post_blocks(&outgoing.conversation_id, outgoing.thread_id.as_deref(), &blocks)
+                    .await
+            }
+        }
+    }
+
+    /// Write the reply into the conversation's canvas, creating it on first use, and
+    /// post a link in place of the reply.
+    async fn send_canvas_reply(&mut self, outgoing: OutgoingMessage) -> Result<String> {
+        let key = ConversationKey {
+            conversation_id: outgoing.conversation_id.clone(),
+            thread_id: outgoing.thread_id.clone(),
+        };
+        let (canvas_id, verb) = match self.canvases_by_key.get(&key) {
+            Some(canvas_id) => {
+                self.chat
+                    .replace_canvas(&outgoing.conversation_id, canvas_id, &outgoing.text)
+                    .await?;
+                (canvas_id.clone(), "Updated")
+            }
+            None => {
+                let title = canvas_title(&outgoing.text);
+                let canvas_id = self
+                    .chat
+                    .create_canvas(&outgoing.conversation_id, &title, &outgoing.text)
+                    .await?;
+                self.canvases_by_key.insert(key, canvas_id.clone());
+                (canvas_id, "Wrote")
+            }
+        };
+        let url = self.chat.canvas_url(&outgoing.conversation_id, &canvas_id).await?;
+        let title = canvas_title(&outgoing.text);
+        let link = OutgoingMessage {
+            text: format!("{verb} the reply in a canvas: <{url}|{title}>"),
+            conversation_id: outgoing.conversation_id,
+            thread_id: outgoing.thread_id,
+        };
+        self.chat.post(&link).await
+    }
+
+    async fn handle_control(&mut self, command: ControlCommand) {
+        match command {
+            ControlCommand::Broadcast { text } => {
+                let mut keys: Vec<ConversationKey> = self.sessions_by_key.keys().cloned().collect();
+                keys.sort_by(|a, b| {
+                    (&a.conversation_id, &a.thread_id).cmp(&(&b.conversation_id, &b.thread_id))
+                });
+                info!("control: broadcast to {} conversations", keys.len());
+                for key in keys {
+                    let outgoing = OutgoingMessage {
+                        text: format!(":mega: {text}"),
+                        conversation_id: key.conversation_id,
+                        thread_id: key.thread_id,
+                    };
+                    if let Err(err) = self.chat.send(&outgoing).await {
+                        warn!("control: broadcast failed: {err}");
+                    }
+                }
+            }
+            ControlCommand::Maintenance { enabled, message } => {
+                info!(
+                    "control: maintenance {}",
+                    if enabled { "on" } else { "off" }
+                );
+                self.maintenance = MaintenanceState { enabled, message };
+                if let Err(err) = self.state.save(MAINTENANCE_STATE, &self.maintenance) {
+                    warn!("control: failed to save maintenance state: {err}");
+                }
+            }
+        }
+    }
+
+    /// Drop a timed-out session whose prompt never came back so the next ensure call
+    /// spawns a fresh one.
+    fn recover_timed_out(&mut self, key: &ConversationKey) {
+        let Some(entry) = self.sessions_by_key.get_mut(key) else {
+            return;
+        };
+        if !entry.timed_out {
+            return;
+        }
+        let ready = self
+            .sessions
+            .capture_pane(&entry.session_name, 200)
+            .map(|pane| sessions::prompt_ready(&pane, self.sessions.profile()))
+            .unwrap_or(false);
+        if ready {
+            entry.timed_out = false;
+            return;
+        }
+
+        let session_name = entry.session_name.clone();
+        info!("timed-out session not at prompt, respawning: {session_name}");
+        if let Err(err) = self.sessions.stop(&session_name) {
+            warn!("failed to stop timed-out session {session_name}: {err}");
+        }
+        self.send_queues.close(&session_name);
+        self.sessions_by_key.remove(key);
+    }
+
+    /// Drop a session whose tmux session is gone, e.g. after `ccterm sessions kill`, so
+    /// the next ensure call spawns a fresh one.
+    fn recover_dead(&mut self, key: &ConversationKey) {
+        let Some(entry) = self.sessions_by_key.get(key) else {
+            return;
+        };
+        if self.sessions.has_session(&entry.session_name) {
+            return;
+        }
+        info!("session {} is gone, respawning", entry.session_name);
+        let session_name = entry.session_name.clone();
+        let transcript_path = entry.last_transcript_path.clone();
+        self.send_queues.close(&session_name);
+        self.sessions_by_key.remove(key);
+        self.keep_resumable(key, &session_name, transcript_path.as_deref());
+    }
+
+    /// Let the next message of a session that died or did not survive a restart resume
+    /// its Claude session, within `idle_resume_secs`.
+    fn keep_resumable(
+        &mut self,
+        key: &ConversationKey,
+        session_name: &str,
+        transcript_path: Option<&Path>,
+    ) {
+        if self.config.coordinator.idle_resume_secs == 0 {
+            return;
+        }
+        let Some(claude_session_id) = resumable_session_id(transcript_path) else {
+            return;
+        };
+        info!("session {session_name} can resume claude session {claude_session_id}");
+        self.parked.insert(
+            key.clone(),
+            ParkedSession {
+                claude_session_id,
+                token: resume_token(),
+                parked_at: self.clock.timestamp(),
+            },
+        );
+    }
+
+    /// Stop thread sessions idle for `idle_session_secs`, noting how to resume them.
+    async fn stop_idle_sessions(&mut self) {
+        let Some(limit) = self.config.coordinator.idle_session_secs.map(Duration::from_secs)
+        else {
+            return;
+        };
+        let now = self.clock.now();
+        let idle: Vec<ConversationKey> = self
+            .sessions_by_key
+            .iter()
+            .filter(|(key, entry)| {
+                self.is_parkable(key, entry) && now.duration_since(entry.last_active) >= limit
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in idle {
+            let reason = format!(
+                ":zzz: This session was idle for {} and has been stopped.",
+                format_duration(limit)
+            );
+            self.park_session(&key, &reason).await;
+        }
+    }
+
+    /// A thread session with nothing in flight.
+    fn is_parkable(&self, key: &ConversationKey, entry: &SessionEntry) -> bool {
+        key.thread_id.is_some() && self.is_settled(key, entry)
+    }
+
+    /// No running turn, queued prompt or open permission prompt, so the session should
+    /// be sitting at its input prompt.
+    fn is_settled(&self, key: &ConversationKey, entry: &SessionEntry) -> bool {
+        entry.turn_started_at.is_none()
+            && self.send_queues.pending(&entry.session_name) == 0
+            && !self.pending_permissions.values().any(|pending| pending.key == *key)
+    }
+
+    /// Probe every session: its tmux session must exist, and a settled one must show
+    /// its prompt. Sessions failing `health_failures` probes in a row are reported, and
+    /// stopped with `unhealthy_action = "restart"`.
+    async fn check_health(&mut self) {
+        if self.config.coordinator.health_check_secs.is_none() {
+            return;
+        }
+        let now = self.clock.now();
+        let threshold = self.config.coordinator.health_failures;
+        let keys: Vec<ConversationKey> = self.sessions_by_key.keys().cloned().collect();
+        let mut unhealthy = Vec::new();
+        for key in keys {
+            let Some(entry) = self.sessions_by_key.get(&key) else {
+                continue;
+            };
+            let probe = self.probe(&key, entry);
+            let Some(entry) = self.sessions_by_key.get_mut(&key) else {
+                continue;
+            };
+            let health = &mut entry.health;
+            match probe {
+                Ok(()) => {
+                    if health.unhealthy_since.is_some() {
+                        info!("health: {} recovered", entry.session_name);
+                    }
+                    *health = SessionHealth::default();
+                }
+                Err(reason) => {
+                    health.failures += 1;
+                    health.last_error = Some(reason.clone());
+                    if health.failures == threshold {
+                        health.unhealthy_since = Some(now);
+                        unhealthy.push((key, entry.session_name.clone(), reason));
+                    }
+                }
+            }
+        }
+
+        for (key, session_name, reason) in unhealthy {
+            let err = anyhow::anyhow!(
+                "session {session_name} failed {threshold} health probe(s): {reason}"
+            );
+            warn!("health: {err}");
+            self.publish_error("health", &err);
+            if self.config.coordinator.unhealthy_action != UnhealthyAction::Restart {
+                continue;
+            }
+            let turn_running = self
+                .sessions_by_key
+                .get(&key)
+                .is_some_and(|entry| entry.turn_started_at.is_some());
+            if turn_running {
+                self.finish_status(&key, TurnStatus::Failed).await;
+            }
+            let reason = format!(
+                ":adhesive_bandage: This session stopped responding ({reason}) and was stopped."
+            );
+            self.park_session(&key, &reason).await;
+        }
+    }
+
+    /// One health probe, returning why it failed.
+    fn probe(&self, key: &ConversationKey, entry: &SessionEntry) -> Result<(), String> {
+        if !self.sessions.has_session(&entry.session_name) {
+            return Err("tmux session is gone".to_string());
+        }
+        if !self.is_settled(key, entry) || entry.disk.paused {
+            return Ok(());
+        }
+        let pane = self
+            .sessions
+            .capture_pane(&entry.session_name, 200)
+            .map_err(|err| format!("pane capture failed: {err}"))?;
+        if !sessions::prompt_ready(&pane, self.sessions.profile()) {
+            return Err("not at its prompt while idle".to_string());
+        }
+        Ok(())
+    }
+
+    /// Stop a thread session, keeping its Claude session resumable for
+    /// `idle_resume_secs`, and tell the thread. `reason` opens the notice.
+    async fn park_session(&mut self, key: &ConversationKey, reason: &str) {
+        let Some(entry) = self.stop_entry(key, "park").await else {
+            return;
+        };
+        info!("parked {}: {reason}", entry.session_name);
+        let claude_session_id = resumable_session_id(entry.last_transcript_path.as_deref());
+        let grace = Duration::from_secs(self.config.coordinator.idle_resume_secs);
+        let text = match claude_session_id {
+            Some(claude_session_id) if !grace.is_zero() => {
+                let token = resume_token();
+                let text = format!(
+                    "{reason} Reply here within {} to pick up where it left off (resume token \
+                     `{token}`); later replies start a new session.",
+                    format_duration(grace)
+                );
+                self.parked.insert(
+                    key.clone(),
+                    ParkedSession {
+                        claude_session_id,
+                        token,
+                        parked_at: self.clock.timestamp(),
+                    },
+                );
+                text
+            }
+            _ => format!("{reason} A reply starts a new one."),
+        };
+        self.post_notice(key, &text).await;
+    }
+
+    /// At `max_sessions`, stop the lowest-priority parkable thread session whose route
+    /// priority is below that of `key`, least recently active first. Returns whether
+    /// room was made.
+    async fn preempt_for(&mut self, key: &ConversationKey) -> bool {
+        let Some(max) = self.config.coordinator.max_sessions else {
+            return false;
+        };
+        if (self.live_sessions() as u64) < max {
+            return false;
+        }
+        let priority = self.config.priority(&key.conversation_id);
+        let victim = self
+            .sessions_by_key
+            .iter()
+            .filter(|(other, entry)| {
+                self.is_parkable(other, entry)
+                    && self.config.priority(&other.conversation_id) < priority
+            })
+            .min_by_key(|(other, entry)| {
+                (self.config.priority(&other.conversation_id), entry.last_active)
+            })
+            .map(|(other, _)| other.clone());
+        let Some(victim) = victim else {
+            return false;
+        };
+        info!(
+            "capacity: preempting channel={} thread={} for priority {priority}",
+            victim.conversation_id,
+            victim.thread_id.as_deref().unwrap_or("-")
+        );
+        let reason =
+            ":pause_button: This session was stopped to make room for higher-priority work.";
+        self.park_session(&victim, reason).await;
+        true
+    }
+
+    /// The stopped session to resume for a message: within `idle_resume_secs` of the
+    /// stop, or at any time when the message quotes its resume token.
+    fn take_parked(&mut self, key: &ConversationKey, text: &str) -> Option<ParkedSession> {
+        let parked = self.parked.remove(key)?;
+        let grace = Duration::from_secs(self.config.coordinator.idle_resume_secs);
+        let within = self
+            .clock
+            .timestamp()
+            .duration_since(parked.parked_at)
+            .unsigned_abs()
+            <= grace;
+        if within || text.contains(&parked.token) {
+            return Some(parked);
+        }
+        info!("resume window for {} passed", parked.claude_session_id);
+        None
+    }
+
+    /// Add `--resume` for the stopped session of `key`, if it can still be resumed.
+    fn resume_parked(
+        &mut self,
+        key: &ConversationKey,
+        text: &str,
+        opts: &mut SpawnOptions,
+    ) -> bool {
+        let Some(parked) = self.take_parked(key, text) else {
+            return false;
+        };
+        info!("resuming claude session {}", parked.claude_session_id);
+        opts.args
+            .extend(["--resume".to_string(), parked.claude_session_id]);
+        true
+    }
+
+    async fn check_turn_timeouts(&mut self) {
+        let now = self.clock.now();
+        let mut expired = Vec::new();
+        for (key, entry) in &self.sessions_by_key {
+            let (Some(started), Some(max_secs)) = (
+                entry.turn_started_at,
+                entry
+                    .turn_max_secs
+                    .or_else(|| self.max_turn_secs(&key.conversation_id)),
+            ) else {
+                continue;
+            };
+            if now.duration_since(started) > Duration::from_secs(max_secs) {
+                expired.push((key.clone(), entry.session_name.clone(), max_secs, started));
+            }
+        }
+
+        for (key, session_name, max_secs, started) in expired {
+            warn!("turn exceeded {max_secs}s, interrupting: {session_name}");
+            if let Err(err) = self.sessions.send_key(&session_name, "Escape") {
+                warn!("failed to interrupt {session_name}: {err}");
+            }
+            let tail = self
+                .sessions
+                .capture_pane(&session_name, 200)
+                .map(|pane| sessions::pane_tail(&pane, 15))
+                .unwrap_or_else(|err| format!("(pane capture failed: {err})"));
+            let mut transcript_path = None;
+            if let Some(entry) = self.sessions_by_key.get_mut(&key) {
+                entry.turn_started_at = None;
+                entry.timed_out = true;
+                transcript_path = entry.last_transcript_path.clone();
+            }
+            let turn = transcript_path.and_then(|path| context::latest_turn(&path).ok());
+            self.record_turn(&key, Some(started), turn, Some("timed_out"), None);
+            self.publish_turn_completed(&key, TurnOutcome::TimedOut);
+            self.finish_status(&key, TurnStatus::Failed).await;
+
+            let notice = OutgoingMessage {
+                text: format!(
+                    "This turn ran longer than {max_secs}s and was interrupted. Send another message to continue.\n```\n{tail}\n```"
+                ),
+                conversation_id: key.conversation_id.clone(),
+                thread_id: key.thread_id.clone(),
+            };
+            if let Err(err) = self.chat.send(&notice).await {
+                warn!("failed to post timeout notice: {err}");
+            }
+        }
+    }
+
+    /// Sample every session's process tree and stop sessions over
+    /// `max_session_memory_mb`.
+    async fn sample_resources(&mut self) {
+        let table = match ProcessTable::read() {
+            Ok(table) => table,
+            Err(err) => {
+                warn!("resource sampling unavailable: {err}");
+                return;
+            }
+        };
+        let now = self.clock.now();
+        let limit = self
+            .config
+            .coordinator
+            .max_session_memory_mb
+            .map(|mb| mb.saturating_mul(1024 * 1024));
+        let mut over_limit = Vec::new();
+        for (key, entry) in self.sessions_by_key.iter_mut() {
+            let pane_pid = match &entry.resources {
+                Some(sample) => sample.pane_pid,
+                None => match self.sessions.pane_pid(&entry.session_name) {
+                    Ok(pid) => pid,
+                    Err(err) => {
+                        warn!("no pane pid for {}: {err}", entry.session_name);
+                        continue;
+                    }
+                },
+            };
+            let Some(usage) = table.usage(pane_pid) else {
+                entry.resources = None;
+                continue;
+            };
+            let cpu_percent = match &entry.resources {
+                Some(previous) => usage.cpu_percent_since(
+                    &previous.usage,
+                    now.duration_since(previous.sampled_at).as_secs_f64(),
+                ),
+                None => 0.0,
+            };
+            entry.resources = Some(ResourceSample {
+                pane_pid,
+                usage,
+                cpu_percent,
+                sampled_at: now,
+            });
+            if limit.is_some_and(|limit| usage.rss_bytes > limit) {
+                over_limit.push((key.clone(), entry.session_name.clone(), usage.rss_bytes));
+            }
+        }
+
+        for (key, session_name, rss_bytes) in over_limit {
+            let used = resources::format_bytes(rss_bytes);
+            warn!("session {session_name} uses {used}, over the memory limit; stopping");
+            let turn_running = self
+                .stop_entry(&key, "memory limit")
+                .await
+                .is_some_and(|entry| entry.turn_started_at.is_some());
+            if turn_running {
+                self.finish_status(&key, TurnStatus::Failed).await;
+            }
+            let notice = OutgoingMessage {
+                text: format!(
+                    "The session was stopped because it used {used} of memory, over the \
+                     configured limit. Send another message to start a new one."
+                ),
+                conversation_id: key.conversation_id.clone(),
+                thread_id: key.thread_id.clone(),
+            };
+            if let Err(err) = self.chat.send(&notice).await {
+                warn!("failed to post memory limit notice: {err}");
+            }
+        }
+    }
+
+    fn disk_hard_limit_bytes(&self, conversation_id: &str) -> Option<u64> {
+        self.channel_setting(conversation_id, |s| s.disk_hard_limit_mb)
+            .or(self.config.coordinator.disk_hard_limit_mb)
+            .map(|mb| mb.saturating_mul(1024 * 1024))
+    }
+
+    /// Measure thread directories, warn once over the soft limit, and pause sessions over
+    /// the hard limit. Paused sessions resume when the limit is raised above their size.
+    async fn check_disk_quotas(&mut self) {
+        let soft_limit = self
+            .config
+            .coordinator
+            .disk_soft_limit_mb
+            .map(|mb| mb.saturating_mul(1024 * 1024));
+        let threads: Vec<(PathBuf, ConversationKey)> = self
+            .key_by_cwd
+            .iter()
+            .filter(|(_, key)| key.thread_id.is_some() && self.sessions_by_key.contains_key(*key))
+            .map(|(cwd, key)| (cwd.clone(), key.clone()))
+            .collect();
+        let any_limit = soft_limit.is_some()
+            || threads
+                .iter()
+                .any(|(_, key)| self.disk_hard_limit_bytes(&key.conversation_id).is_some());
+        if threads.is_empty() || !any_limit {
+            return;
+        }
+        let dirs: Vec<PathBuf> = threads.iter().map(|(cwd, _)| cwd.clone()).collect();
+        let sizes = match tokio::task::spawn_blocking(move || {
+            dirs.iter().map(|dir| resources::dir_size(dir)).collect::<Vec<_>>()
+        })
+        .await
+        {
+            Ok(sizes) => sizes,
+            Err(err) => {
+                warn!("disk usage check failed: {err}");
+                return;
+            }
+        };
+
+        for ((_, key), used) in threads.into_iter().zip(sizes) {
+            let hard_limit = self.disk_hard_limit_bytes(&key.conversation_id);
+            let Some(entry) = self.sessions_by_key.get_mut(&key) else {
+                continue;
+            };
+            entry.disk.used_bytes = Some(used);
+            let over_hard = hard_limit.is_some_and(|limit| used > limit);
+            let used_text = resources::format_bytes(used);
+
+            if entry.disk.paused && !over_hard {
+                entry.disk.paused = false;
+                self.pending_quota.retain(|_, pending| *pending != key);
+                self.post_notice(&key, "The disk quota was raised; the session is running again.")
+                    .await;
+                continue;
+            }
+            if over_hard && !entry.disk.paused && !entry.disk.confirmed {
+                entry.disk.paused = true;
+                let session_name = entry.session_name.clone();
+                let turn_running = entry.turn_started_at.take().is_some();
+                if turn_running {
+                    warn!("{session_name} over the disk quota, interrupting");
+                    if let Err(err) = self.sessions.send_key(&session_name, "Escape") {
+                        warn!("failed to interrupt {session_name}: {err}");
+                    }
+                    self.finish_status(&key, TurnStatus::Failed).await;
+                }
+                self.request_quota_confirmation(&key, &used_text, hard_limit.unwrap_or(0))
+                    .await;
+                continue;
+            }
+            if soft_limit.is_some_and(|limit| used > limit) && !entry.disk.soft_warned {
+                entry.disk.soft_warned = true;
+                let text = format!(
+                    ":warning: This thread's directory uses {used_text}, over the soft disk \
+                     limit. Consider cleaning up build outputs or dependencies."
+                );
+                self.post_notice(&key, &text).await;
+            }
+        }
+    }
+
+    async fn request_quota_confirmation(
+        &mut self,
+        key: &ConversationKey,
+        used: &str,
+        limit_bytes: u64,
+    ) {
+        let id = approval_id();
+        let prompt = OutgoingMessage {
+            text: format!(
+                ":octagonal_sign: This thread's directory uses {used}, over the {} disk \
+                 quota, so the session is paused. Approve to continue anyway, or Reject to \
+                 stop the session. An admin can also raise it with `!set disk_hard_limit_mb`.",
+                resources::format_bytes(limit_bytes)
+            ),
+            conversation_id: key.conversation_id.clone(),
+            thread_id: key.thread_id.clone(),
+        };
+        match self.chat.post_approval(&prompt, QUOTA_ACTION_PREFIX, &id).await {
+            Ok(_) => {
+                self.pending_quota.insert(id, key.clone());
+            }
+            Err(err) => warn!("disk quota notice not posted: {err}"),
+        }
+    }
+
+    async fn post_notice(&self, key: &ConversationKey, text: &str) {
+        let notice = OutgoingMessage {
+            text: text.to_string(),
+            conversation_id: key.conversation_id.clone(),
+            thread_id: key.thread_id.clone(),
+        };
+        if let Err(err) = self.chat.send(&notice).await {
+            warn!("notice post failed: {err}");
+        }
+    }
+
+    async fn ensure_main_session(
+        &mut self,
+        msg: &IncomingMessage,
+        prompt_timeout: Duration,
+    ) -> Result<SessionEntry> {
+        let key = self
+            .main_by_conversation
+            .entry(msg.conversation_id.clone())
+            .or_insert_with(|| ConversationKey {
+                conversation_id: msg.conversation_id.clone(),
+                thread_id: None,
+            })
+            .clone();
+
+        if let Some(entry) = self.sessions_by_key.get(&key) {
+            return Ok(entry.clone());
+        }
+
+        let cwd = self.base_cwd.clone();
+        let hook_path = self.hook_path_for_cwd(&cwd);
+        self.register_hook_receiver(&cwd, &hook_path)?;
+        self.config.permissions.install(&self.policy_path(&cwd))?;
+
+        if let Err(err) = self.ensure_main_context(&cwd, msg).await {
+            warn!("main context not written: {err}");
+        }
+
+        let mut opts = self.spawn_options(&msg.conversation_id);
+        let resumed = self.resume_parked(&key, &msg.text, &mut opts);
+        let session_name = self
+            .spawn_session("main", &cwd, &opts, prompt_timeout)
+            .await?;
+        let fingerprint = self
+            .publish_spawned(&msg.conversation_id, None, &session_name, &cwd, &opts)
+            .await;
+        if resumed {
+            self.post_notice(&key, RESUMED_NOTICE).await;
+        }
+
+        let entry = SessionEntry {
+            session_name: session_name.clone(),
+            last_transcript_path: None,
+            transcript: Arc::default(),
+            last_sent_message_uuid: None,
+            turn_started_at: None,
+            status_ts: Vec::new(),
+            timed_out: false,
+            resources: None,
+            disk: DiskQuotaState::default(),
+            artifacts_dir: None,
+            todo_ts: None,
+            working: WorkingStatus::default(),
+            last_active: self.clock.now(),
+            last_message: None,
+            turn_commits: Vec::new(),
+            title: None,
+            progress: ProgressBatch::default(),
+            prompt_ready: false,
+            fingerprint,
+            model_override: None,
+            permissions_override: None,
+            turn_max_secs: None,
+            health: SessionHealth::default(),
+            delivery: DeliveryFailures::default(),
+        };
+        self.sessions_by_key.insert(key.clone(), entry.clone());
+        self.key_by_cwd.insert(cwd, key);
+        Ok(entry)
+    }
+
+    async fn ensure_thread_session(
+        &mut self,
+        msg: &IncomingMessage,
+        prompt_timeout: Duration,
+    ) -> Result<SessionEntry> {
+        let key = ConversationKey {
+            conversation_id: msg.conversation_id.clone(),
+            thread_id: msg.thread_id.clone(),
+        };
+
+        if let Some(entry) = self.sessions_by_key.get(&key) {
+            return Ok(entry.clone());
+        }
+
+        let thread_id = msg
+            .thread_id
+            .as_deref()
+            .context("thread id missing")?;
+        let cwd = self.ensure_thread_dir(thread_id).await?;
+        self.ensure_thread_context(&cwd, msg)?;
+        let mut opts = self.spawn_options(&msg.conversation_id);
+        let resumed = self.resume_parked(&key, &msg.text, &mut opts);
+        let entry = self
+            .start_thread_session(&key, cwd, &opts, prompt_timeout)
+            .await?;
+        if resumed {
+            self.post_notice(&key, RESUMED_NOTICE).await;
+        }
+        if !self.config.coordinator.thread_titles {
+            return Ok(entry);
+        }
+        let Some(title) = thread_title(&msg.text) else {
+            return Ok(entry);
+        };
+        if let Err(err) = self.sessions.set_label(&entry.session_name, &title) {
+            warn!("session label not set: {err}");
+        }
+        self.post_notice(&key, &format!(":label: *{title}*")).await;
+        let entry = self
+            .sessions_by_key
+            .get_mut(&key)
+            .context("thread session missing after spawn")?;
+        entry.title = Some(title);
+        Ok(entry.clone())
+    }
+
+    /// Register hooks for a prepared thread directory and start its session.
+    async fn start_thread_session(
+        &mut self,
+        key: &ConversationKey,
+        cwd: PathBuf,
+        opts: &SpawnOptions,
+        prompt_timeout: Duration,
+    ) -> Result<SessionEntry> {
+        let hook_path = self.hook_path_for_cwd(&cwd);
+        self.register_hook_receiver(&cwd, &hook_path)?;
+        self.config.permissions.install(&self.policy_path(&cwd))?;
+
+        let session_name = self
+            .spawn_session("thread", &cwd, opts, prompt_timeout)
+            .await?;
+        let fingerprint = self.publish_spawned(
+            &key.conversation_id,
+            key.thread_id.as_deref(),
+            &session_name,
+            &cwd,
+            opts,
+        )
+        .await;
+
+        let entry = SessionEntry {
+            session_name: session_name.clone(),
+            last_transcript_path: None,
+            transcript: Arc::default(),
+            last_sent_message_uuid: None,
+            turn_started_at: None,
+            status_ts: Vec::new(),
+            timed_out: false,
+            resources: None,
+            disk: DiskQuotaState::default(),
+            artifacts_dir: None,
+            todo_ts: None,
+            working: WorkingStatus::default(),
+            last_active: self.clock.now(),
+            last_message: None,
+            turn_commits: Vec::new(),
+            title: None,
+            progress: ProgressBatch::default(),
+            prompt_ready: false,
+            fingerprint,
+            model_override: None,
+            permissions_override: None,
+            turn_max_secs: None,
+            health: SessionHealth::default(),
+            delivery: DeliveryFailures::default(),
+        };
+        self.sessions_by_key.insert(key.clone(), entry.clone());
+        self.key_by_cwd.insert(cwd, key.clone());
+        Ok(entry)
+    }
+
+    /// Permission policy file of the session running in `cwd`.
+    fn policy_path(&self, cwd: &Path) -> PathBuf {
+        permissions::policy_path(&paths::state_dir(&self.base_cwd), cwd)
+    }
+
+    fn spawn_options(&self, conversation_id: &str) -> SpawnOptions {
+        let mut opts = SpawnOptions::default();
+        if let Some((name, profile)) = self.config.claude_profile(conversation_id) {
+            info!("spawn: using claude profile {name} for {conversation_id}");
+            opts.env = profile.session_env();
+        }
+        if let Some(model) = self.channel_setting(conversation_id, |s| s.model.clone()) {
+            opts.args = vec!["--model".to_string(), model];
+        }
+        opts
+    }
+
+    fn build_thread_context(&self, msg: &IncomingMessage) -> Result<ThreadContextDecision> {
+        let main_key = self.main_by_conversation.get(&msg.conversation_id);
+        let main_key = match main_key {
+            Some(key) => key,
+            None => return Ok(ThreadContextDecision::Skipped("main conversation not found")),
+        };
+        let main_entry = match self.sessions_by_key.get(main_key) {
+            Some(entry) => entry,
+            None => return Ok(ThreadContextDecision::Skipped("main session not available")),
+        };
+        let transcript_path = match &main_entry.last_transcript_path {
+            Some(path) => path,
+            None => {
+                return Ok(ThreadContextDecision::Skipped(
+                    "main transcript not available yet",
+                ))
+            }
+        };
+
+        let history = {
+            let mut cursor = lock_transcript(&main_entry.transcript);
+            cursor.read(transcript_path)?;
+            cursor.history(msg.timestamp.as_deref())
+        };
+        match context::format_history_context(&history, &self.time_zone) {
+            Some(context) => Ok(ThreadContextDecision::Built(context)),
+            None => Ok(ThreadContextDecision::Skipped("history empty")),
+        }
+    }
+
+    /// Spawn a session in `cwd` and wait for its prompt. After a failure, a session left
+    /// over from the attempt is killed and a missing `cwd` is recreated, ready for a retry
+    /// by `schedule_spawn_retry`.
+    async fn spawn_session(
+        &self,
+        kind: &str,
+        cwd: &Path,
+        opts: &SpawnOptions,
+        prompt_timeout: Duration,
+    ) -> Result<String> {
+        let mut opts = opts.clone();
+        opts.env.push((
+            permissions::POLICY_ENV.to_string(),
+            self.policy_path(cwd).to_string_lossy().into_owned(),
+        ));
+        let session_name = sessions::timestamp_session_name(&self.config.tmux.session_prefix)?;
+        let result = match self.sessions.spawn_with(&session_name, cwd, &opts) {
+            Ok(()) => self.wait_for_prompt(&session_name, prompt_timeout).await,
+            Err(err) => Err(err),
+        };
+        let err = match result {
+            Ok(()) => return Ok(session_name),
+            Err(err) => err,
+        };
+        if self.sessions.has_session(&session_name)
+            && let Err(stop_err) = self.sessions.stop(&session_name)
+        {
+            warn!("failed to stop session {session_name}: {stop_err}");
+        }
+        if let Err(dir_err) = std::fs::create_dir_all(cwd) {
+            warn!("spawn: failed to recreate {}: {dir_err}", cwd.display());
+        }
+        Err(err.context(format!("failed to start {kind} session")))
+    }
+
+    async fn wait_for_prompt(&self, session_name: &str, timeout: Duration) -> Result<()> {
+        sessions::wait_for_prompt_async(
+            &self.sessions,
+            session_name,
+            timeout,
+            Duration::from_millis(200),
+            self.clock.as_ref(),
+        )
+        .await
+    }
+
+    /// Watch for the prompt of a session that just finished a turn, in the background,
+    /// so the next message does not have to wait for it.
+    fn prewarm_prompt(&self, session_name: String) {
+        let sessions = self.sessions.clone();
+        let clock = self.clock.clone();
+        let timeout = Duration::from_millis(self.config.coordinator.prompt_timeout_ms);
+        let ready_tx = self.ready_tx.clone();
+        tokio::spawn(async move {
+            let ready = sessions::wait_for_prompt_async(
+                &sessions,
+                &session_name,
+                timeout,
+                Duration::from_millis(200),
+                clock.as_ref(),
+            )
+            .await;
+            match ready {
+                Ok(()) => {
+                    let _ = ready_tx.send(session_name);
+                }
+                Err(err) => warn!("prompt pre-warm for {session_name} failed: {err:#}"),
+            }
+        });
+    }
+
+    /// Record a pre-warmed prompt, unless a turn started while it was being checked.
+    fn mark_prompt_ready(&mut self, session_name: &str) {
+        if let Some(entry) = self
+            .sessions_by_key
+            .values_mut()
+            .find(|entry| entry.session_name == session_name)
+            && entry.turn_started_at.is_none()
+        {
+            entry.prompt_ready = true;
+        }
+    }
+
+    /// Queue `text` for the session's sender task. The turn starts when it is typed;
+    /// see `on_send_outcome`.
+    fn enqueue_send(&mut self, entry: &SessionEntry, text: String, prompt_timeout: Duration) {
+        self.enqueue_turn(entry, text, &TurnDirectives::default(), prompt_timeout);
+    }
+
+    /// `enqueue_send` with the directives of the message. Overrides left by the previous
+    /// prompt's directives are switched back first, so each lasts a single turn.
+    fn enqueue_turn(
+        &mut self,
+        entry: &SessionEntry,
+        text: String,
+        directives: &TurnDirectives,
+        prompt_timeout: Duration,
+    ) {
+        let mut setup = TurnSetup {
+            max_turn_secs: directives.timeout.map(|timeout| timeout.as_secs()),
+            ..TurnSetup::default()
+        };
+        let key = self
+            .sessions_by_key
+            .iter()
+            .find(|(_, current)| current.session_name == entry.session_name)
+            .map(|(key, _)| key.clone());
+        let policy_path = key
+            .as_ref()
+            .and_then(|key| self.cwd_for_key(key))
+            .map(|cwd| self.policy_path(&cwd));
+        let mut prewarmed = false;
+        if let Some(current) = key.and_then(|key| self.sessions_by_key.get_mut(&key)) {
+            prewarmed = std::mem::take(&mut current.prompt_ready);
+            if current.model_override != directives.model {
+                let model = directives
+                    .model
+                    .as_deref()
+                    .or(current.fingerprint.model.as_deref())
+                    .unwrap_or("default");
+                setup.commands.push(format!("/model {model}"));
+                current.model_override = directives.model.clone();
+            }
+            if current.permissions_override != directives.permissions
+                && let Some(policy_path) = policy_path
+            {
+                let mut policy = self.config.permissions.clone();
+                if let Some(action) = directives.permissions {
+                    policy.default = action;
+                }
+                setup.policy = Some((policy_path, policy));
+                current.permissions_override = directives.permissions;
+            }
+        }
+        let ahead = self
+            .send_queues
+            .push(&entry.session_name, text, setup, prewarmed, prompt_timeout);
+        if ahead > 0 {
+            debug!("send queue: {} has {ahead} prompt(s) ahead", entry.session_name);
+        }
+    }
+
+    /// Start the turn of a prompt the sender task typed, or report why it could not.
+    async fn on_send_outcome(&mut self, outcome: SendOutcome) {
+        self.send_queues.finished(&outcome.session_name);
+        let key = self
+            .sessions_by_key
+            .iter()
+            .find(|(_, entry)| entry.session_name == outcome.session_name)
+            .map(|(key, _)| key.clone());
+        let Some(key) = key else {
+            info!("send queue: {} is no longer in use", outcome.session_name);
+            self.send_queues.close(&outcome.session_name);
+            return;
+        };
+        match outcome.result {
+            Ok(()) => {
+                if let Some(entry) = self.sessions_by_key.get_mut(&key) {
+                    entry.turn_max_secs = outcome.max_turn_secs;
+                }
+                self.mark_turn_started(&key);
+            }
+            Err(err) => {
+                self.publish_error("send", &err);
+                self.finish_status(&key, TurnStatus::Failed).await;
+                let target = OutgoingMessage {
+                    text: String::new(),
+                    conversation_id: key.conversation_id.clone(),
+                    thread_id: key.thread_id.clone(),
+                };
+                self.report_error("send", target, &err).await;
+                self.note_delivery_failure(&key).await;
+            }
+        }
+    }
+
+    /// Count a failed delivery against `quarantine_failures`, and quarantine the session
+    /// once that many failed within `quarantine_window_secs`.
+    async fn note_delivery_failure(&mut self, key: &ConversationKey) {
+        let Some(limit) = self.config.coordinator.quarantine_failures else {
+            return;
+        };
+        let window = Duration::from_secs(self.config.coordinator.quarantine_window_secs);
+        let now = self.clock.now();
+        let Some(entry) = self.sessions_by_key.get_mut(key) else {
+            return;
+        };
+        let delivery = &mut entry.delivery;
+        if delivery.quarantined_since.is_some() {
+            return;
+        }
+        delivery.recent.push_back(now);
+        while delivery
+            .recent
+            .front()
+            .is_some_and(|failed| now.duration_since(*failed) > window)
+        {
+            delivery.recent.pop_front();
+        }
+        if delivery.recent.len() < limit as usize {
+            return;
+        }
+        delivery.recent.clear();
+        delivery.quarantined_since = Some(now);
+        let session_name = entry.session_name.clone();
+        let span = format_duration(window);
+        let err = anyhow::anyhow!(
+            "session {session_name} quarantined after {limit} failed deliveries within {span}"
+        );
+        warn!("quarantine: {err}");
+        self.publish_error("quarantine", &err);
+        let notice = format!(
+            ":no_entry: This session failed to take {limit} prompts within {span} and is \
+             quarantined: new messages here are not sent to it. `!peek` shows its terminal; \
+             `!restart` starts a fresh session."
+        );
+        self.post_notice(key, &notice).await;
+        let Some(admin_channel) = &self.config.coordinator.admin_channel else {
+            return;
+        };
+        let report = OutgoingMessage {
+            text: format!(
+                "Session `{session_name}` in <#{}> (thread {}) is quarantined after {limit} \
+                 failed deliveries within {span}.",
+                key.conversation_id,
+                key.thread_id.as_deref().unwrap_or("-")
+            ),
+            conversation_id: admin_channel.clone(),
+            thread_id: None,
+        };
+        if let Err(err) = self.chat.send(&report).await {
+            warn!("admin quarantine report failed: {err}");
+        }
+    }
+
+    /// Seed a new thread session with the main conversation. A worktree has the
+    /// repository's own `CLAUDE.md`, so it goes to `CLAUDE.local.md` there.
+    fn ensure_thread_context(&self, cwd: &Path, msg: &IncomingMessage) -> Result<()> {
+        let name = if cwd.join(".git").is_file() { "CLAUDE.local.md" } else { "CLAUDE.md" };
+        let path = cwd.join(name);
+        let decision = self.build_thread_context(msg)?;
+        let context = match decision {
+            ThreadContextDecision::Built(context) => context,
+            ThreadContextDecision::Skipped(reason) => {
+                info!(
+                    "thread context not written: {} ({})",
+                    path.display(),
+                    reason
+                );
+                return Ok(());
+            }
+        };
+        if path.exists() {
+            info!("thread context exists, skip: {}", path.display());
+            return Ok(());
+        }
+        paths::write_atomic(&path, context)
+            .with_context(|| format!("failed to write {name}: {}", path.display()))?;
+        info!("thread context written: {}", path.display());
+        Ok(())
+    }
+
+    /// Seed a new main session with recent channel messages. Main sessions share the
+    /// project directory, so this goes to `CLAUDE.local.md` and never replaces a file
+    /// that ccterm did not generate.
+    async fn ensure_main_context(&self, cwd: &Path, msg: &IncomingMessage) -> Result<()> {
+        let limit = self.config.coordinator.main_context_messages;
+        if limit == 0 {
+            return Ok(());
+        }
+        let path = cwd.join("CLAUDE.local.md");
+        if path.exists() {
+            let existing = std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            if !existing.starts_with(context::GENERATED_CONTEXT_MARKER) {
+                info!("main context exists and is not generated, skip: {}", path.display());
+                return Ok(());
+            }
+        }
+
+        let history = self
+            .chat
+            .channel_history(&msg.conversation_id, msg.timestamp.as_deref(), limit)
+            .await?;
+        let Some(context) = context::format_history_context(&history, &self.time_zone) else {
+            info!("main context not written: {} (history empty)", path.display());
+            return Ok(());
+        };
+        let content = format!("{}\n{}", context::GENERATED_CONTEXT_MARKER, context);
+        paths::write_atomic(&path, content)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        info!("main context written: {}", path.display());
+        Ok(())
+    }
+
+    /// Span naming the conversation and, once it has one, its session, so log lines
+    /// emitted while handling it can be told apart.
+    fn span_for(&self, key: &ConversationKey) -> Span {
+        let session = self
+            .sessions_by_key
+            .get(key)
+            .map(|entry| entry.session_name.as_str());
+        info_span!(
+            "conversation",
+            id = %key.conversation_id,
+            thread = key.thread_id.as_deref(),
+            session
+        )
+    }
+
+    async fn handle_hook(&mut self, hook: HookEvent) -> Result<()> {
+        let span = match self.key_by_cwd.get(&normalize_path(hook.cwd.clone())) {
+            Some(key) => self.span_for(key),
+            None => info_span!("hook", cwd = %hook.cwd.display()),
+        };
+        self.dispatch_hook(hook).instrument(span).await
+    }
+
+    async fn dispatch_hook(&mut self, hook: HookEvent) -> Result<()> {
+        match hook.event_name.as_str() {
+            "Stop" => self.handle_stop_hook(hook).await,
+            "PreToolUse" => {
+                self.record_tool_use(&hook);
+                // A plan that could not be posted must not keep a denial from being reported.
+                if let Err(err) = self.handle_plan_hook(&hook).await {
+                    self.publish_error("plan", &err);
+                }
+                self.handle_permission_hook(hook).await
+            }
+            "PreCompact" => self.handle_compact_hook(hook).await,
+            "Notification" => self.handle_notification_hook(hook).await,
+            _ => Ok(()),
+        }
+    }
+
+    async fn handle_permission_hook(&mut self, hook: HookEvent) -> Result<()> {
+        if hook.permission_action != Some(PermissionAction::Deny) {
+            return Ok(());
+        }
+        let cwd = normalize_path(hook.cwd.clone());
+        let Some(key) = self.key_by_cwd.get(&cwd).cloned() else {
+            debug!("hook cwd not registered: {}", cwd.display());
+            return Ok(());
+        };
+        let tool = hook.tool_name.as_deref().unwrap_or("tool");
+        let argument = hook.tool_argument.as_deref().unwrap_or_default();
+        if let Some(entry) = self.sessions_by_key.get_mut(&key) {
+            let denied = format!("`{tool}` `{}`", truncate_chars(argument, 200));
+            entry.progress.denied.push(denied);
+        }
+        Ok(())
+    }
+
+    /// Collect a tool call for the next progress flush when `progress_updates` is on.
+    /// Denied calls are reported on their own.
+    fn record_tool_use(&mut self, hook: &HookEvent) {
+        if self.config.coordinator.progress_updates == ProgressUpdates::Off
+            || hook.permission_action == Some(PermissionAction::Deny)
+        {
+            return;
+        }
+        let Some(tool) = hook.tool_name.as_deref() else {
+            return;
+        };
+        let cwd = normalize_path(hook.cwd.clone());
+        let Some(key) = self.key_by_cwd.get(&cwd) else {
+            return;
+        };
+        let call = match hook.tool_argument.as_deref() {
+            Some(argument) if !argument.is_empty() => {
+                let argument = argument.lines().next().unwrap_or_default();
+                format!("`{tool}` `{}`", truncate_chars(argument, 120))
+            }
+            _ => format!("`{tool}`"),
+        };
+        if let Some(entry) = self.sessions_by_key.get_mut(key) {
+            entry.progress.tools.push(call);
+        }
+    }
+
+    /// Post or edit the tool calls collected since the last flush, per `progress_updates`.
+    async fn flush_tool_calls(&mut self, key: &ConversationKey, tools: Vec<String>) {
+        if tools.is_empty() {
+            return;
+        }
+        match self.config.coordinator.progress_updates {
+            ProgressUpdates::Off => {}
+            ProgressUpdates::Thread => {
+                let mut lines = vec![format!(":gear: {}", tool_calls_heading(tools.len()))];
+                lines.extend(bullets(&tools));
+                let message = OutgoingMessage {
+                    text: lines.join("\n"),
+                    conversation_id: key.conversation_id.clone(),
+                    thread_id: self.reply_thread_id(key),
+                };
+                if let Err(err) = self.chat.send(&message).await {
+                    warn!("progress update failed: {err}");
+                }
+            }
+            ProgressUpdates::Status => {
+                let Some(entry) = self.sessions_by_key.get_mut(key) else {
+                    return;
+                };
+                entry.working.calls.extend(tools);
+                let working = entry.working.clone();
+                let text = working_text(":hourglass_flowing_sand: Working…", &working.calls);
+                self.edit_working_status(key, working.ts, &text).await;
+            }
+        }
+    }
+
+    /// Turn the "Working…" message into a summary of the finished turn.
+    async fn finish_working_status(&mut self, key: &ConversationKey) {
+        let Some(entry) = self.sessions_by_key.get_mut(key) else {
+            return;
+        };
+        let working = std::mem::take(&mut entry.working);
+        let Some(ts) = working.ts else {
+            return;
+        };
+        let text = working_text(":white_check_mark: Finished", &working.calls);
+        if let Err(err) = self.chat.update_text(&key.conversation_id, &ts, &text).await {
+            warn!("progress update failed: {err}");
+        }
+    }
+
+    async fn edit_working_status(&mut self, key: &ConversationKey, ts: Option<String>, text: &str) {
+        let result = match ts {
+            Some(ts) => self.chat.update_text(&key.conversation_id, &ts, text).await,
+            None => {
+                let message = OutgoingMessage {
+                    text: text.to_string(),
+                    conversation_id: key.conversation_id.clone(),
+                    thread_id: self.reply_thread_id(key),
+                };
+                self.chat.post(&message).await.map(|ts| {
+                    if let Some(entry) = self.sessions_by_key.get_mut(key) {
+                        entry.working.ts = Some(ts);
+                    }
+                })
+            }
+        };
+        if let Err(err) = result {
+            warn!("progress update failed: {err}");
+        }
+    }
+
+    async fn flush_all_progress(&mut self) {
+        let keys: Vec<ConversationKey> = self
+            .sessions_by_key
+            .iter()
+            .filter(|(_, entry)| !entry.progress.is_empty())
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys {
+            self.flush_progress(&key).await;
+        }
+    }
+
+    /// Post the session's pending hook updates: one edit of the TODO list message and
+    /// one notice for all denied tool calls.
+    async fn flush_progress(&mut self, key: &ConversationKey) {
+        let muted = self.is_muted(key);
+        let Some(entry) = self.sessions_by_key.get_mut(key) else {
+            return;
+        };
+        let batch = std::mem::take(&mut entry.progress);
+        if muted {
+            return;
+        }
+        let todo_ts = entry.todo_ts.clone();
+        if let Some(text) = batch.todos {
+            let result = match todo_ts {
+                Some(ts) => self.chat.update_text(&key.conversation_id, &ts, &text).await,
+                None => {
+                    let message = OutgoingMessage {
+                        text,
+                        conversation_id: key.conversation_id.clone(),
+                        thread_id: self.reply_thread_id(key),
+                    };
+                    self.chat.post(&message).await.map(|ts| {
+                        if let Some(entry) = self.sessions_by_key.get_mut(key) {
+                            entry.todo_ts = Some(ts);
+                        }
+                    })
+                }
+            };
+            if let Err(err) = result {
+                warn!("todo list update failed: {err}");
+            }
+        }
+        self.flush_tool_calls(key, batch.tools).await;
+        let text = match batch.denied.as_slice() {
+            [] => return,
+            [denied] => format!("Denied by permission policy: {denied}"),
+            denied => {
+                let mut lines = vec![format!(
+                    "Denied by permission policy ({} calls):",
+                    denied.len()
+                )];
+                lines.extend(
+                    denied
+                        .iter()
+                        .take(MAX_PROGRESS_LINES)
+                        .map(|denied| format!("• {denied}")),
+                );
+                if denied.len() > MAX_PROGRESS_LINES {
+                    lines.push(format!("…and {} more", denied.len() - MAX_PROGRESS_LINES));
+                }
+                lines.join("\n")
+            }
+        };
+        self.post_notice(key, &text).await;
+    }
+
+    /// Render `ExitPlanMode` plans and `TodoWrite` lists when `render_plans` is on.
+    async fn handle_plan_hook(&mut self, hook: &HookEvent) -> Result<()> {
+        if !self.config.coordinator.render_plans {
+            return Ok(());
+        }
+        let (Some(tool_name), Some(input)) = (&hook.tool_name, &hook.tool_input) else {
+            return Ok(());
+        };
+        let Some(update) = plan::from_tool_use(tool_name, input) else {
+            return Ok(());
+        };
+        let cwd = normalize_path(hook.cwd.clone());
+        let Some(key) = self.key_by_cwd.get(&cwd).cloned() else {
+            return Ok(());
+        };
+        let thread_id = self.reply_thread_id(&key);
+        match update {
+            PlanUpdate::Plan(text) => {
+                let id = approval_id();
+                let message = OutgoingMessage {
+                    text: "Proposed plan".to_string(),
+                    conversation_id: key.conversation_id.clone(),
+                    thread_id,
+                };
+                let sections = plan::plan_sections(&text);
+                self.chat
+                    .post_plan(&message, &sections, PLAN_ACTION_PREFIX, &id)
+                    .await?;
+                self.pending_plans.insert(id, key);
+            }
+            PlanUpdate::Todos(todos) => {
+                if let Some(entry) = self.sessions_by_key.get_mut(&key) {
+                    entry.progress.todos = Some(plan::format_todos(&todos));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Post a tool permission prompt with buttons. Claude reports the prompt only as
+    /// `Claude needs your permission to use <tool>`, so the pane is quoted for details.
+    async fn handle_notification_hook(&mut self, hook: HookEvent) -> Result<()> {
+        let webhook = self.config.approval_webhook.clone();
+        if !self.config.coordinator.permission_buttons && webhook.is_none() {
+            return Ok(());
+        }
+        let Some(message) = hook.message.as_deref() else {
+            return Ok(());
+        };
+        if !message.contains("permission") {
+            return Ok(());
+        }
+        let cwd = normalize_path(hook.cwd.clone());
+        let Some(key) = self.key_by_cwd.get(&cwd).cloned() else {
+            debug!("hook cwd not registered: {}", cwd.display());
+            return Ok(());
         };
-        let main_entry = match self.sessions_by_key.get(main_key) {
-            Some(entry) => entry,
-            None => return Ok(ThreadContextDecision::Skipped("main session not available")),
+        let Some(entry) = self.sessions_by_key.get(&key) else {
+            return Ok(());
         };
-        let transcript_path = match &main_entry.last_transcript_path {
-            Some(path) => path,
-            None => {
-                return Ok(ThreadContextDecision::Skipped(
-                    "main transcript not available yet",
-                ))
+        let session_name = entry.session_name.clone();
+        let requester = entry.last_message.as_ref().and_then(|msg| msg.user_id.clone());
+        let mut text = format!(":raised_hand: {message}");
+        let mut tail = String::new();
+        match self.sessions.capture_pane(&session_name, 200) {
+            Ok(pane) => {
+                tail = sessions::pane_tail(&pane, PERMISSION_PROMPT_LINES);
+                text.push_str(&format!("\n```{}```", truncate_chars(&tail, 2500)));
+            }
+            Err(err) => warn!("permission prompt not captured: {err}"),
+        }
+        let id = approval_id();
+        let buttons = self.config.coordinator.permission_buttons
+            && webhook.as_ref().is_none_or(|cfg| cfg.slack_buttons);
+        if !buttons {
+            text.push_str("\n_Waiting for the approval system._");
+        }
+        let prompt = OutgoingMessage {
+            text,
+            conversation_id: key.conversation_id.clone(),
+            thread_id: self.reply_thread_id(&key),
+        };
+        let posted = if buttons {
+            self.chat
+                .post_permission_prompt(&prompt, PERMISSION_ACTION_PREFIX, &id)
+                .await
+        } else {
+            self.chat.post(&prompt).await
+        };
+        let message_ts = match posted {
+            Ok(ts) => Some(ts),
+            // The approval system can still answer the prompt.
+            Err(err) if webhook.is_some() => {
+                warn!("permission prompt not posted: {err}");
+                None
             }
+            Err(err) => return Err(err),
         };
 
-        let cutoff = msg.timestamp.as_deref();
-        let history = context::read_history(transcript_path, cutoff)?;
-        match context::format_history_context(&history) {
-            Some(context) => Ok(ThreadContextDecision::Built(context)),
-            None => Ok(ThreadContextDecision::Skipped("history empty")),
+        let mut deadline = None;
+        if let Some(cfg) = webhook {
+            let timeout = Duration::from_secs(cfg.timeout_secs);
+            deadline = Some(self.clock.now() + timeout);
+            let request = PermissionRequest {
+                id: id.clone(),
+                session_name,
+                conversation_id: key.conversation_id.clone(),
+                thread_id: key.thread_id.clone(),
+                message: message.to_string(),
+                prompt: tail,
+                expires_at: (self.clock.timestamp() + timeout).to_string(),
+                callback_url: approval::callback_url(&cfg),
+            };
+            tokio::spawn(async move {
+                if let Err(err) = approval::notify(&cfg, &request).await {
+                    warn!("approval webhook not notified of {}: {err:#}", request.id);
+                }
+            });
         }
+        self.pending_permissions.insert(
+            id,
+            PendingPermission {
+                key,
+                requester,
+                message_ts,
+                deadline,
+            },
+        );
+        Ok(())
+    }
+
+    /// Record an answer from the approval system, with the conversation of its prompt.
+    fn publish_webhook_decision(&self, decision: &ApprovalDecision) {
+        let key = self.pending_permissions.get(&decision.id).map(|pending| &pending.key);
+        self.events.publish(CoordinatorEvent::UserAction {
+            conversation_id: key.map(|key| key.conversation_id.clone()).unwrap_or_default(),
+            thread_id: key.and_then(|key| key.thread_id.clone()),
+            user_id: decision.approver.clone(),
+            action: format!("approval_webhook:{}", decision.decision),
+            target: Some(decision.id.clone()),
+        });
     }
 
-    fn enqueue_send(
+    async fn handle_permission_action(
         &mut self,
-        entry: &SessionEntry,
-        text: String,
-        prompt_timeout: Duration,
+        action: &ActionEvent,
+        decision: &str,
     ) -> Result<()> {
-        sessions::wait_for_prompt(
-            &self.sessions,
-            &entry.session_name,
-            prompt_timeout,
-            Duration::from_millis(200),
-        )?;
-        self.sessions
-            .send(&entry.session_name, &text)
-            .with_context(|| format!("failed to send to {}", entry.session_name))?;
-        Ok(())
+        let Some(id) = action.value.as_deref() else {
+            return Ok(());
+        };
+        let Some(pending) = self.pending_permissions.get(id) else {
+            return self.answer_permission(id, decision, &format!("<@{}>", action.user_id)).await;
+        };
+        let allowed = pending.requester.as_deref() == Some(action.user_id.as_str())
+            || self
+                .config
+                .coordinator
+                .permission_approvers
+                .contains(&action.user_id);
+        if !allowed {
+            info!("permission {id}: {} may not answer, ignored", action.user_id);
+            let key = pending.key.clone();
+            let text = "Only the person who started this turn or a permission approver can \
+                        answer this prompt.";
+            return self.refuse_action(&action.user_id, &key, text).await;
+        }
+        self.answer_permission(id, decision, &format!("<@{}>", action.user_id))
+            .await
     }
 
-    fn ensure_thread_context(&self, cwd: &Path, msg: &IncomingMessage) -> Result<()> {
-        let path = cwd.join("CLAUDE.md");
-        let decision = self.build_thread_context(msg)?;
-        let context = match decision {
-            ThreadContextDecision::Built(context) => context,
-            ThreadContextDecision::Skipped(reason) => {
-                eprintln!(
-                    "thread context not written: {} ({})",
-                    path.display(),
-                    reason
-                );
+    /// Whether `user` may use buttons acting on the session for `key`: they sent one of
+    /// its messages, or they are a permission approver.
+    fn may_act_on(&self, key: &ConversationKey, user: &str) -> bool {
+        if self.config.coordinator.permission_approvers.iter().any(|id| id == user) {
+            return true;
+        }
+        let Some(entry) = self.sessions_by_key.get(key) else {
+            return false;
+        };
+        let turns: Vec<TurnRecord> = self
+            .state
+            .load_recent_lines(TURNS_STATE, TURNS_READ_BYTES)
+            .unwrap_or_else(|err| {
+                warn!("failed to read turns: {err:#}");
+                Vec::new()
+            });
+        let mine: Vec<&TurnRecord> = turns
+            .iter()
+            .filter(|turn| turn.user_id.as_deref() == Some(user))
+            .collect();
+        took_part(user, key, entry, &mine)
+    }
+
+    /// Tell `user`, and only them, why their button press was ignored.
+    async fn refuse_action(&self, user: &str, key: &ConversationKey, text: &str) -> Result<()> {
+        let notice = OutgoingMessage {
+            text: text.to_string(),
+            conversation_id: key.conversation_id.clone(),
+            thread_id: self.reply_thread_id(key),
+        };
+        self.chat.send_ephemeral(user, &notice).await
+    }
+
+    /// Answer a permission prompt: `1` accepts once, `2` accepts and stops asking for
+    /// this kind of call, Escape declines and leaves Claude waiting for instructions.
+    /// Prompts already answered are ignored, so the first of Slack and the approval
+    /// webhook wins.
+    async fn answer_permission(&mut self, id: &str, decision: &str, by: &str) -> Result<()> {
+        let (keystroke, verb) = match decision {
+            "approve" => ("1", ":white_check_mark: Approved"),
+            "always" => ("2", ":white_check_mark: Always allowed"),
+            "deny" => ("Escape", ":no_entry_sign: Denied"),
+            other => {
+                warn!("unknown permission decision `{other}` for {id}");
                 return Ok(());
             }
         };
-        if path.exists() {
-            eprintln!("thread context exists, skip: {}", path.display());
+        let Some(pending) = self.pending_permissions.remove(id) else {
+            debug!("permission {id} is not pending, {decision} by {by} ignored");
+            return Ok(());
+        };
+        let Some(entry) = self.sessions_by_key.get(&pending.key) else {
             return Ok(());
+        };
+        // The dialog may have been answered in the pane meanwhile; a key sent then would
+        // land in Claude's input instead.
+        let open = self
+            .sessions
+            .capture_pane(&entry.session_name, 200)
+            .map(|pane| {
+                sessions::permission_dialog_open(
+                    &pane,
+                    self.sessions.profile(),
+                    PERMISSION_PROMPT_LINES,
+                )
+            });
+        let sent = match open {
+            Ok(true) => self.sessions.send_key(&entry.session_name, keystroke),
+            Ok(false) => Err(anyhow::anyhow!("the prompt is no longer open in the terminal")),
+            Err(err) => Err(err),
+        };
+        let text = match sent {
+            Ok(()) if decision == "deny" => {
+                format!("{verb} by {by}. Reply in the thread with what to do instead.")
+            }
+            Ok(()) => format!("{verb} by {by}."),
+            Err(err) => {
+                warn!("failed to answer permission prompt in {}: {err}", entry.session_name);
+                format!(":warning: Could not answer the permission prompt: {err}")
+            }
+        };
+        match &pending.message_ts {
+            Some(ts) => {
+                self.chat
+                    .update_text(&pending.key.conversation_id, ts, &text)
+                    .await
+            }
+            None => {
+                self.post_notice(&pending.key, &text).await;
+                Ok(())
+            }
         }
-        std::fs::write(&path, context)
-            .with_context(|| format!("failed to write CLAUDE.md: {}", path.display()))?;
-        eprintln!("thread context written: {}", path.display());
-        Ok(())
     }
 
-    async fn handle_hook(&mut self, hook: HookEvent) -> Result<()> {
-        if hook.event_name != "Stop" {
+    /// Deny permission prompts the approval webhook did not answer in time.
+    async fn expire_permissions(&mut self) {
+        let now = self.clock.now();
+        let expired: Vec<String> = self
+            .pending_permissions
+            .iter()
+            .filter(|(_, pending)| pending.deadline.is_some_and(|deadline| now >= deadline))
+            .map(|(id, _)| id.clone())
+            .collect();
+        let secs = self
+            .config
+            .approval_webhook
+            .as_ref()
+            .map_or(0, |cfg| cfg.timeout_secs);
+        for id in expired {
+            info!("permission {id} not answered within {secs}s, denying");
+            let by = format!("timeout (no approval within {secs}s)");
+            if let Err(err) = self.answer_permission(&id, "deny", &by).await {
+                self.publish_error("approval", &err);
+            }
+        }
+    }
+
+    async fn handle_compact_hook(&mut self, hook: HookEvent) -> Result<()> {
+        let cwd = normalize_path(hook.cwd.clone());
+        let Some(key) = self.key_by_cwd.get(&cwd).cloned() else {
+            debug!("hook cwd not registered: {}", cwd.display());
+            return Ok(());
+        };
+        let trigger = hook.trigger.as_deref().unwrap_or("auto");
+        info!("context compaction ({trigger}): session_id={}", hook.session_id);
+        if self.quiet_until(&key.conversation_id).is_some() || self.is_muted(&key) {
             return Ok(());
         }
+        let notice = OutgoingMessage {
+            text: format!(
+                ":card_index_dividers: Context was compacted ({trigger}); earlier details may be summarized."
+            ),
+            conversation_id: key.conversation_id,
+            thread_id: key.thread_id,
+        };
+        self.chat.send(&notice).await
+    }
+
+    async fn handle_stop_hook(&mut self, hook: HookEvent) -> Result<()> {
 
         let cwd = normalize_path(hook.cwd.clone());
+        if self.tasks_by_cwd.contains_key(&cwd) {
+            return self.handle_task_stop(&cwd, hook).await;
+        }
         let key = match self.key_by_cwd.get(&cwd) {
             Some(k) => k.clone(),
             None => {
-                eprintln!("hook cwd not registered: {}", cwd.display());
+                debug!("hook cwd not registered: {}", cwd.display());
                 return Ok(());
             }
         };
 
-        let latest = self.wait_for_latest_assistant(&hook)?;
+        self.flush_progress(&key).await;
+        self.finish_working_status(&key).await;
+        self.muted.remove(&key);
+        let mut started = None;
+        if let Some(entry) = self.sessions_by_key.get_mut(&key) {
+            started = entry.turn_started_at.take();
+            entry.last_active = self.clock.now();
+            entry.todo_ts = None;
+            let session_name = entry.session_name.clone();
+            self.prewarm_prompt(session_name);
+        }
+        self.pending_plans.retain(|_, pending| *pending != key);
+        self.pending_permissions.retain(|_, pending| pending.key != key);
+
+        let transcript = self
+            .sessions_by_key
+            .get(&key)
+            .map(|entry| entry.transcript.clone())
+            .unwrap_or_default();
+        let latest = self.wait_for_latest_assistant(&hook, &transcript).await?;
 
         let last_sent_uuid = match self.sessions_by_key.get(&key) {
             Some(entry) => entry.last_sent_message_uuid.clone(),
             None => {
-                eprintln!("hook session not registered: {}", hook.session_id);
+                debug!("hook session not registered: {}", hook.session_id);
                 return Ok(());
             }
         };
 
-        let latest = self.wait_for_new_uuid(&hook, last_sent_uuid.as_deref(), latest)?;
+        let latest = self
+            .wait_for_new_uuid(&hook, &transcript, last_sent_uuid.as_deref(), latest)
+            .await?;
         if last_sent_uuid.as_deref() == Some(latest.0.as_str()) {
-            eprintln!(
+            warn!(
                 "hook stop but assistant uuid unchanged after retry: session_id={} uuid={} transcript={}",
                 hook.session_id,
                 latest.0,
@@ -311,34 +5256,390 @@ impl Coordinator {
         let entry = match self.sessions_by_key.get_mut(&key) {
             Some(entry) => entry,
             None => {
-                eprintln!("hook session not registered: {}", hook.session_id);
+                debug!("hook session not registered: {}", hook.session_id);
                 return Ok(());
             }
         };
         entry.last_transcript_path = Some(hook.transcript_path.clone());
+        let last_message = entry.last_message.clone();
 
-        let assistant_text = latest.1;
+        let mut assistant_text = latest.1;
+        if let Some(block) =
+            self.bash_results_block(&key.conversation_id, &hook.transcript_path)
+        {
+            assistant_text.push_str("\n\n");
+            assistant_text.push_str(&block);
+        }
+        if self.config.coordinator.reasoning_summary
+            && let Some(block) = reasoning_block(&hook.transcript_path)
+        {
+            assistant_text.push_str("\n\n");
+            assistant_text.push_str(&block);
+        }
+        let mut rendered =
+            diff::render_diffs(&assistant_text, self.config.coordinator.diff_upload_lines);
+        let mut text = rendered.text;
+        let coordinator = &self.config.coordinator;
+        let auto_commit = self.config.auto_commit(&key.conversation_id);
+        let wants_changes = coordinator.files_changed
+            || coordinator.upload_referenced_files
+            || !coordinator.upload_globs.is_empty()
+            || auto_commit != AutoCommit::Off;
+        let changes = match started {
+            Some(started) if wants_changes => {
+                let elapsed = self.clock.now().duration_since(started);
+                let since = SystemTime::now() - elapsed;
+                let cwd = cwd.clone();
+                tokio::task::spawn_blocking(move || changes::changed_since(&cwd, since)).await?
+            }
+            _ => Vec::new(),
+        };
+        if coordinator.files_changed
+            && let Some(footer) = changes::footer(&changes)
+        {
+            text.push_str("\n\n");
+            text.push_str(&footer);
+        }
+        let uploads: Vec<FileChange> = changes::to_upload(
+            &changes,
+            &assistant_text,
+            coordinator.upload_referenced_files,
+            &coordinator.upload_globs,
+        )
+        .into_iter()
+        .cloned()
+        .collect();
+        let git_diff = self.config.git_diff(&key.conversation_id);
+        if git_diff != GitDiff::Off {
+            let diff_cwd = cwd.clone();
+            let patch = git_diff == GitDiff::Patch;
+            let diff =
+                tokio::task::spawn_blocking(move || git::working_diff(&diff_cwd, patch)).await?;
+            match diff {
+                Ok(Some((stat, patch))) => {
+                    text.push_str(&format!("\n\n_Changes against `HEAD`:_\n```\n{stat}\n```"));
+                    if let Some(content) = patch {
+                        rendered.patches.push(diff::Patch {
+                            name: "working-tree.patch".to_string(),
+                            content,
+                        });
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => warn!("git diff failed: {err}"),
+            }
+        }
+        if let Some(note) = self.auto_commit(&key, &cwd, &changes).await {
+            text.push_str("\n\n");
+            text.push_str(&note);
+        }
 
         let outgoing = OutgoingMessage {
-            text: assistant_text,
+            text,
             conversation_id: key.conversation_id.clone(),
-            thread_id: key.thread_id.clone(),
+            thread_id: self.reply_thread_id(&key),
+        };
+
+        let turn = match context::latest_turn(&hook.transcript_path) {
+            Ok(turn) => Some(turn),
+            Err(err) => {
+                warn!("turn summary unavailable: {err}");
+                None
+            }
+        };
+        let prompt = turn.as_ref().and_then(|turn| turn.prompt.clone());
+        if started.is_none()
+            && let Some(prompt) = &prompt
+            && typed_in_terminal(prompt, last_message.as_ref())
+        {
+            info!("mirroring terminal input of {}", hook.session_id);
+            let mirror = OutgoingMessage {
+                text: format!(
+                    ":keyboard: *Typed in the terminal:*\n{}",
+                    quote_lines(&truncate_chars(prompt, TERMINAL_INPUT_MAX_CHARS))
+                ),
+                ..outgoing.clone()
+            };
+            if let Err(err) = self.chat.send(&mirror).await {
+                warn!("terminal input mirror failed: {err}");
+            }
+        }
+        let reply_ts = self.send_reply(outgoing.clone()).await?;
+        self.record_reply(&key, reply_ts.clone(), outgoing.thread_id.clone(), prompt, &hook);
+        self.record_turn(&key, started, turn, None, Some(reply_ts));
+        for patch in rendered.patches {
+            if let Err(err) = self
+                .chat
+                .upload_text(
+                    &outgoing.conversation_id,
+                    outgoing.thread_id.as_deref(),
+                    &patch.name,
+                    &patch.content,
+                )
+                .await
+            {
+                warn!("patch upload failed: {err}");
+            }
+        }
+        self.offer_patch(&key, outgoing.thread_id.clone(), &rendered.diffs)
+            .await;
+        self.upload_changed_files(&key, outgoing.thread_id.as_deref(), &cwd, uploads)
+            .await;
+        self.upload_artifacts(&key, outgoing.thread_id.as_deref())
+            .await;
+        if let Some(entry) = self.sessions_by_key.get_mut(&key) {
+            entry.last_sent_message_uuid = Some(latest.0);
+        }
+        self.publish_turn_completed(&key, TurnOutcome::Replied);
+        self.finish_status(&key, TurnStatus::Done).await;
+        self.start_preview(&key, &cwd, &hook.transcript_path, outgoing.thread_id);
+        Ok(())
+    }
+
+    /// Run `preview_command` in the background when the turn edited files, and post its
+    /// result next to the reply.
+    fn start_preview(
+        &self,
+        key: &ConversationKey,
+        cwd: &Path,
+        transcript_path: &Path,
+        thread_id: Option<String>,
+    ) {
+        let Some(command) = self.config.preview_command(&key.conversation_id) else {
+            return;
+        };
+        match context::latest_turn(transcript_path) {
+            Ok(turn) if !turn.changed_files.is_empty() => {}
+            Ok(_) => return,
+            Err(err) => {
+                warn!("preview skipped: {err}");
+                return;
+            }
+        }
+        let notice_tx = self.notice_tx.clone();
+        let command = command.to_string();
+        let cwd = cwd.to_path_buf();
+        let timeout = Duration::from_secs(self.config.coordinator.preview_timeout_secs);
+        let conversation_id = key.conversation_id.clone();
+        tokio::spawn(async move {
+            let text = match preview::run(&cwd, &command, timeout).await {
+                Ok(result) => preview::format_result(&result),
+                Err(err) => format!("Preview failed: {err}"),
+            };
+            let _ = notice_tx.send(OutgoingMessage {
+                text,
+                conversation_id,
+                thread_id,
+            });
+        });
+    }
+
+    /// Commit (and push) the files the turn changed, depending on `auto_commit`. Returns
+    /// a line for the reply.
+    async fn auto_commit(
+        &mut self,
+        key: &ConversationKey,
+        cwd: &Path,
+        changes: &[FileChange],
+    ) -> Option<String> {
+        let mode = self.config.auto_commit(&key.conversation_id);
+        if mode == AutoCommit::Off {
+            return None;
+        }
+        let scope = key.thread_id.as_deref().unwrap_or(&key.conversation_id);
+        let message = format!("ccterm: turn in {scope}");
+        let own_repo = key.thread_id.is_some();
+        let files: Vec<String> = changes.iter().map(|change| change.path.clone()).collect();
+        let commit_cwd = cwd.to_path_buf();
+        let committed = tokio::task::spawn_blocking(move || {
+            git::commit_files(&commit_cwd, &message, own_repo, &files)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|committed| committed);
+        let sha = match committed {
+            Ok(Some(sha)) => {
+                if let Some(entry) = self.sessions_by_key.get_mut(key) {
+                    entry.turn_commits.push(sha.clone());
+                }
+                sha
+            }
+            Ok(None) => return None,
+            Err(err) => {
+                warn!("auto commit failed: {err}");
+                return Some(format!("_Auto commit failed: {err}_"));
+            }
+        };
+        if mode == AutoCommit::Commit {
+            return Some(format!("_Committed `{sha}`_"));
+        }
+        let remote = self.config.coordinator.auto_push_remote.clone();
+        let branch = format!("ccterm/{}", sanitize_thread_id(scope));
+        let push_cwd = cwd.to_path_buf();
+        let (push_remote, push_branch) = (remote.clone(), branch.clone());
+        let pushed =
+            tokio::task::spawn_blocking(move || git::push(&push_cwd, &push_remote, &push_branch))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|pushed| pushed);
+        match pushed {
+            Ok(()) => Some(format!("_Committed `{sha}` and pushed to `{remote}/{branch}`_")),
+            Err(err) => {
+                warn!("auto push failed: {err}");
+                Some(format!("_Committed `{sha}`; push to `{remote}/{branch}` failed: {err}_"))
+            }
+        }
+    }
+
+    /// Post a `!spawn` task's reply to its thread and stop the task session.
+    async fn handle_task_stop(&mut self, cwd: &Path, hook: HookEvent) -> Result<()> {
+        let transcript = Mutex::default();
+        let (_, text) = self.wait_for_latest_assistant(&hook, &transcript).await?;
+        let Some(task) = self.tasks_by_cwd.get_mut(cwd) else {
+            return Ok(());
+        };
+        if task.result.is_some() {
+            return Ok(());
+        }
+        task.result = Some(text.clone());
+        let task = task.clone();
+        self.events.publish(CoordinatorEvent::TurnCompleted {
+            conversation_id: task.conversation_id.clone(),
+            thread_id: Some(task.thread_id.clone()),
+            session_name: task.session_name.clone(),
+            outcome: TurnOutcome::Replied,
+        });
+
+        let rendered = diff::render_diffs(&text, self.config.coordinator.diff_upload_lines);
+        let reply = OutgoingMessage {
+            text: format!("*{}*\n{}", task.label, rendered.text),
+            conversation_id: task.conversation_id.clone(),
+            thread_id: Some(task.thread_id.clone()),
         };
+        self.chat.send(&reply).await?;
+        if let Err(err) = self.sessions.stop(&task.session_name) {
+            warn!("failed to stop task session {}: {err}", task.session_name);
+        }
+        self.join_tasks(&task.conversation_id, &task.thread_id)
+            .await
+    }
+
+    /// Once every pending task of a thread has replied, post a combined summary and,
+    /// with `task_results_to_session`, hand the results to the thread's session.
+    async fn join_tasks(&mut self, conversation_id: &str, thread_id: &str) -> Result<()> {
+        let mut batch: Vec<&mut SpawnedTask> = self
+            .tasks_by_cwd
+            .values_mut()
+            .filter(|task| {
+                task.conversation_id == conversation_id
+                    && task.thread_id == thread_id
+                    && !task.joined
+            })
+            .collect();
+        if batch.iter().any(|task| task.result.is_none()) {
+            return Ok(());
+        }
+        batch.sort_by_key(|task| task.number);
+        let mut summary = Vec::new();
+        let mut context = Vec::new();
+        for task in batch {
+            task.joined = true;
+            let result = task.result.as_deref().unwrap_or_default();
+            let first = result
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .unwrap_or("(no reply)");
+            summary.push(format!("• *{}*: {}", task.label, truncate_chars(first, 200)));
+            context.push(format!("## {}\n{}", task.label, result.trim()));
+        }
+        if summary.len() > 1 {
+            let notice = OutgoingMessage {
+                text: format!("All {} tasks finished:\n{}", summary.len(), summary.join("\n")),
+                conversation_id: conversation_id.to_string(),
+                thread_id: Some(thread_id.to_string()),
+            };
+            self.chat.send(&notice).await?;
+        }
+        if !self.config.coordinator.task_results_to_session {
+            return Ok(());
+        }
 
-        self.slack.send(&outgoing).await?;
-        entry.last_sent_message_uuid = Some(latest.0);
+        let thread_key = ConversationKey {
+            conversation_id: conversation_id.to_string(),
+            thread_id: Some(thread_id.to_string()),
+        };
+        let key = if self.sessions_by_key.contains_key(&thread_key) {
+            thread_key
+        } else {
+            match self.main_by_conversation.get(conversation_id) {
+                Some(key) => key.clone(),
+                None => return Ok(()),
+            }
+        };
+        let Some(entry) = self.sessions_by_key.get(&key).cloned() else {
+            return Ok(());
+        };
+        let text = format!(
+            "ccterm: Results of the tasks spawned from this conversation:\n\n{}",
+            context.join("\n\n")
+        );
+        let prompt_timeout = Duration::from_millis(self.config.coordinator.prompt_timeout_ms);
+        self.enqueue_send(&entry, text, prompt_timeout);
         Ok(())
     }
 
-    fn wait_for_latest_assistant(
+    /// Main-channel replies go under the triggering message when `reply_in_thread` is set.
+    fn reply_thread_id(&self, key: &ConversationKey) -> Option<String> {
+        if key.thread_id.is_some() {
+            return key.thread_id.clone();
+        }
+        if self.channel_setting(&key.conversation_id, |s| s.reply_in_thread) != Some(true) {
+            return None;
+        }
+        self.sessions_by_key
+            .get(key)
+            .and_then(|entry| entry.status_ts.first().cloned())
+    }
+
+    /// Format the last Bash result of the turn, depending on `coordinator.bash_results`.
+    fn bash_results_block(
+        &self,
+        conversation_id: &str,
+        transcript_path: &Path,
+    ) -> Option<String> {
+        let mode = self.bash_results(conversation_id);
+        if mode == BashResultsMode::Off {
+            return None;
+        }
+        let turn = match context::latest_turn(transcript_path) {
+            Ok(turn) => turn,
+            Err(err) => {
+                warn!("bash results skipped: {err}");
+                return None;
+            }
+        };
+        if mode == BashResultsMode::Requested
+            && !turn
+                .prompt
+                .as_deref()
+                .is_some_and(context::prompt_requests_output)
+        {
+            return None;
+        }
+        turn.bash_results.last().map(context::format_bash_result)
+    }
+
+    async fn wait_for_latest_assistant(
         &self,
         hook: &HookEvent,
+        transcript: &Mutex<TranscriptCursor>,
     ) -> Result<(String, String)> {
         let mut attempt = 0;
         let max_attempts = 8;
         let delay = Duration::from_millis(150);
         loop {
-            if let Some(latest) = context::latest_assistant_text_uuid(&hook.transcript_path)? {
+            if let Some(latest) = read_latest_assistant(transcript, &hook.transcript_path)? {
                 return Ok(latest);
             }
             attempt += 1;
@@ -350,13 +5651,14 @@ impl Coordinator {
                     hook.transcript_path.display()
                 );
             }
-            std::thread::sleep(delay);
+            self.clock.sleep(delay).await;
         }
     }
 
-    fn wait_for_new_uuid(
+    async fn wait_for_new_uuid(
         &self,
         hook: &HookEvent,
+        transcript: &Mutex<TranscriptCursor>,
         last_uuid: Option<&str>,
         initial: (String, String),
     ) -> Result<(String, String)> {
@@ -371,16 +5673,16 @@ impl Coordinator {
         let max_attempts = 6;
         let delay = Duration::from_millis(150);
         loop {
-            if let Some(latest) = context::latest_assistant_text_uuid(&hook.transcript_path)? {
-                if Some(latest.0.as_str()) != last_uuid {
-                    return Ok(latest);
-                }
+            if let Some(latest) = read_latest_assistant(transcript, &hook.transcript_path)?
+                && Some(latest.0.as_str()) != last_uuid
+            {
+                return Ok(latest);
             }
             attempt += 1;
             if attempt >= max_attempts {
                 return Ok(initial);
             }
-            std::thread::sleep(delay);
+            self.clock.sleep(delay).await;
         }
     }
 
@@ -399,7 +5701,10 @@ impl Coordinator {
         }
 
         sessions::ensure_dir(hook_path)?;
-        let receiver = hooks::spawn_hook_receiver(hook_path.to_path_buf());
+        let (receiver, handle) = hooks::spawn_hook_receiver(
+            hook_path.to_path_buf(),
+            self.config.hooks.secrets.clone(),
+        );
         let tx = self.hook_tx.clone();
         tokio::spawn(async move {
             let mut rx = receiver;
@@ -408,28 +5713,120 @@ impl Coordinator {
             }
         });
 
+        self.hook_receivers.insert(cwd.clone(), handle);
         self.hook_paths_by_cwd
             .insert(cwd, hook_path.to_path_buf());
         Ok(())
     }
 
-    fn ensure_thread_dir(&self, thread_id: &str) -> Result<PathBuf> {
-        let dir = self
-            .base_cwd
+    fn thread_dir(&self, thread_id: &str) -> PathBuf {
+        self.base_cwd
             .join(".ccterm/threads")
-            .join(sanitize_thread_id(thread_id));
+            .join(sanitize_thread_id(thread_id))
+    }
+
+    async fn ensure_thread_dir(&self, thread_id: &str) -> Result<PathBuf> {
+        let dir = self.thread_dir(thread_id);
+        if self.config.coordinator.thread_workspace == ThreadWorkspace::Worktree && !dir.exists()
+        {
+            self.add_thread_worktree(thread_id, &dir).await?;
+        }
+        self.ensure_session_dir(dir)
+    }
+
+    /// Check out the base cwd's `HEAD` on `ccterm/<thread>` at `dir`, with the hook
+    /// settings ccterm renders in place of the repository's own. Files ccterm writes into
+    /// the worktree are excluded so they are not committed.
+    async fn add_thread_worktree(&self, thread_id: &str, dir: &Path) -> Result<()> {
+        let branch = format!("ccterm/{}", sanitize_thread_id(thread_id));
+        let repo = self.base_cwd.clone();
+        let worktree = dir.to_path_buf();
+        let excludes = self.worktree_excludes();
+        let checkout = branch.clone();
+        tokio::task::spawn_blocking(move || {
+            git::add_worktree(&repo, &worktree, &checkout)
+                .context("failed to create thread worktree")?;
+            git::exclude(&repo, &excludes)
+        })
+        .await??;
+        let settings_path = dir.join(".claude/settings.json");
+        std::fs::create_dir_all(dir.join(".claude"))
+            .with_context(|| format!("failed to create .claude dir in {}", dir.display()))?;
+        paths::write_atomic(&settings_path, self.render_thread_settings()?).with_context(|| {
+            format!("failed to write session settings.json: {}", settings_path.display())
+        })?;
+        git::skip_worktree(dir, ".claude/settings.json")?;
+        info!("created worktree {} on {branch}", dir.display());
+        Ok(())
+    }
+
+    fn worktree_excludes(&self) -> Vec<String> {
+        let mut patterns: Vec<String> = ["/.ccterm/", "/.claude/settings.json", "/CLAUDE.local.md"]
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect();
+        let events = &self.config.hooks.events_path;
+        if events.is_relative() {
+            let events = events.strip_prefix(".").unwrap_or(events);
+            patterns.push(format!("/{}", events.display()));
+            patterns.push(format!("/{}", hooks::socket_path(events).display()));
+        }
+        patterns
+    }
+
+    /// Remove the worktree of a thread session that ended. Its branch stays, so the next
+    /// message checks it out again; a worktree with uncommitted changes is kept.
+    async fn release_worktree(&mut self, key: &ConversationKey) {
+        let Some(thread_id) = &key.thread_id else {
+            return;
+        };
+        let dir = self.thread_dir(thread_id);
+        if !dir.join(".git").is_file() {
+            return;
+        }
+        // A relative events file lives in the worktree. Stop its receiver while the socket
+        // still exists to wake it; the next session there listens anew, whether the
+        // worktree goes or stays. An absolute one is shared and keeps its receiver.
+        if self.config.hooks.events_path.is_relative() {
+            let cwd = normalize_path(dir.clone());
+            self.hook_paths_by_cwd.remove(&cwd);
+            if let Some(receiver) = self.hook_receivers.remove(&cwd) {
+                receiver.shutdown();
+            }
+        }
+        let repo = self.base_cwd.clone();
+        let worktree = dir.clone();
+        match tokio::task::spawn_blocking(move || git::remove_worktree(&repo, &worktree)).await {
+            Ok(Ok(())) => info!("removed worktree {}", dir.display()),
+            Ok(Err(err)) => info!("kept worktree {}: {err}", dir.display()),
+            Err(err) => warn!("worktree removal failed for {}: {err}", dir.display()),
+        }
+    }
+
+    /// Create a session directory with hook settings pointing at this binary.
+    fn ensure_session_dir(&self, dir: PathBuf) -> Result<PathBuf> {
         std::fs::create_dir_all(&dir)
-            .with_context(|| format!("failed to create thread dir: {}", dir.display()))?;
+            .with_context(|| format!("failed to create session dir: {}", dir.display()))?;
 
         let claude_dir = dir.join(".claude");
         std::fs::create_dir_all(&claude_dir)
             .with_context(|| format!("failed to create .claude dir: {}", claude_dir.display()))?;
         let settings_path = claude_dir.join("settings.json");
-        if !settings_path.exists() {
+        // Settings left unparsable by an older, non-atomic write would disable the hooks
+        // without any error, so they are rendered again.
+        let valid = std::fs::read_to_string(&settings_path)
+            .is_ok_and(|content| serde_json::from_str::<Value>(&content).is_ok());
+        if settings_path.exists() && !valid {
+            info!(
+                "session settings.json is not valid JSON, rewriting: {}",
+                settings_path.display()
+            );
+        }
+        if !valid {
             let settings = self.render_thread_settings()?;
-            std::fs::write(&settings_path, settings).with_context(|| {
+            paths::write_atomic(&settings_path, settings).with_context(|| {
                 format!(
-                    "failed to write thread settings.json: {}",
+                    "failed to write session settings.json: {}",
                     settings_path.display()
                 )
             })?;
@@ -438,22 +5835,266 @@ impl Coordinator {
     }
 
     fn render_thread_settings(&self) -> Result<String> {
-        let mut settings: Value = serde_json::from_str(&self.settings_template)
-            .context("failed to parse base settings.json")?;
-        let exe_path = self.ccterm_path.to_string_lossy();
-        rewrite_hook_commands(&mut settings, &exe_path);
-        let mut out =
-            serde_json::to_string_pretty(&settings).context("failed to render settings.json")?;
-        out.push('\n');
-        Ok(out)
+        render_thread_settings(&self.settings_template, &self.ccterm_path.to_string_lossy())
     }
 }
 
+/// Render a thread `settings.json` from the base template, pointing hook commands at
+/// `exe_path` because thread directories have no `target/`.
+pub fn render_thread_settings(template: &str, exe_path: &str) -> Result<String> {
+    let mut settings: Value =
+        serde_json::from_str(template).context("failed to parse base settings.json")?;
+    rewrite_hook_commands(&mut settings, exe_path);
+    let mut out =
+        serde_json::to_string_pretty(&settings).context("failed to render settings.json")?;
+    out.push('\n');
+    Ok(out)
+}
+
 enum ThreadContextDecision {
     Built(String),
     Skipped(&'static str),
 }
 
+/// First heading or non-empty line of a reply, without markdown markers.
+fn canvas_title(text: &str) -> String {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("ccterm reply");
+    truncate_chars(line.trim_start_matches('#').trim(), 80)
+}
+
+/// `2h`, `90m`: the largest unit that divides the duration evenly.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        _ if secs >= 86_400 && secs.is_multiple_of(86_400) => format!("{}d", secs / 86_400),
+        _ if secs >= 3600 && secs.is_multiple_of(3600) => format!("{}h", secs / 3600),
+        _ if secs >= 60 && secs.is_multiple_of(60) => format!("{}m", secs / 60),
+        _ => format!("{secs}s"),
+    }
+}
+
+fn tool_calls_heading(count: usize) -> String {
+    match count {
+        1 => "1 tool call".to_string(),
+        count => format!("{count} tool calls"),
+    }
+}
+
+/// The last `MAX_PROGRESS_LINES` items as bullets, after a count of the ones left out.
+fn bullets(items: &[String]) -> Vec<String> {
+    let skipped = items.len().saturating_sub(MAX_PROGRESS_LINES);
+    let mut lines = Vec::new();
+    if skipped > 0 {
+        lines.push(format!("…{skipped} earlier"));
+    }
+    lines.extend(items[skipped..].iter().map(|item| format!("• {item}")));
+    lines
+}
+
+fn working_text(status: &str, calls: &[String]) -> String {
+    let mut lines = vec![format!("{status} ({})", tool_calls_heading(calls.len()))];
+    lines.extend(bullets(calls));
+    lines.join("\n")
+}
+
+fn reasoning_block(transcript_path: &Path) -> Option<String> {
+    match context::latest_turn(transcript_path) {
+        Ok(turn) => context::format_reasoning(&turn.thinking),
+        Err(err) => {
+            warn!("reasoning summary skipped: {err}");
+            None
+        }
+    }
+}
+
+/// Whether the prompt of a turn ccterm did not start was typed by someone attached to the
+/// session, rather than being a chat message whose turn outlived its tracking (after a
+/// timeout, `!stop`, or a ccterm restart). A last message without a body, e.g. only
+/// directives, is part of every prompt and so tells nothing; the prompt counts as typed.
+fn typed_in_terminal(prompt: &str, last: Option<&IncomingMessage>) -> bool {
+    match last.and_then(|msg| commands::parse_directives(&msg.text).ok()) {
+        Some((_, body)) if !body.trim().is_empty() => !prompt.contains(body.trim()),
+        _ => true,
+    }
+}
+
+/// Prefix every line with `> ` so it renders as a block quote.
+fn quote_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("> {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether `user` sent the last message of a session or one of `mine`, their recorded
+/// turns, belongs to it.
+fn took_part(
+    user: &str,
+    key: &ConversationKey,
+    entry: &SessionEntry,
+    mine: &[&TurnRecord],
+) -> bool {
+    let last_user = entry.last_message.as_ref().and_then(|msg| msg.user_id.as_deref());
+    last_user == Some(user)
+        || mine.iter().any(|turn| {
+            turn.conversation_id == key.conversation_id && turn.thread_id == key.thread_id
+        })
+}
+
+fn truncate_chars(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((idx, _)) => format!("{}…", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+/// Short title for a thread: the first line of its first prompt without markup, cut at
+/// a word boundary.
+fn thread_title(text: &str) -> Option<String> {
+    let line = text
+        .lines()
+        .map(|line| line.replace(['*', '_', '`', '~', '#', '>'], " "))
+        .find(|line| !line.trim().is_empty())?;
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let mut title = String::new();
+    for word in &words {
+        if !title.is_empty() && title.chars().count() + word.chars().count() >= THREAD_TITLE_CHARS
+        {
+            title.push('…');
+            break;
+        }
+        if !title.is_empty() {
+            title.push(' ');
+        }
+        title.push_str(word);
+    }
+    let title = truncate_chars(title.trim_end_matches([':', ',', '.']), THREAD_TITLE_CHARS);
+    Some(title).filter(|title| !title.is_empty())
+}
+
+/// Indent every line of `text` for dry-run output.
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("    | {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Claude session id of a transcript, the file name of `<session id>.jsonl`, when the
+/// transcript is still there to resume from.
+fn resumable_session_id(transcript_path: Option<&Path>) -> Option<String> {
+    let path = transcript_path.filter(|path| path.is_file())?;
+    Some(path.file_stem()?.to_string_lossy().to_string())
+}
+
+/// Id of a pending approval, carried in button values and webhook callbacks. Random, so
+/// one cannot be guessed or replayed against a later prompt.
+fn approval_id() -> String {
+    random::token(16)
+}
+
+/// Token naming an idle-stopped session in its notice. Quoting it resumes the session
+/// at any time, so it must not be guessed.
+fn resume_token() -> String {
+    format!("resume-{}", random::token(6))
+}
+
+/// Delay before the attempt after `attempts` failed ones: `spawn_retry_backoff_ms`,
+/// doubled for each failure after the first, up to `MAX_SPAWN_BACKOFF`.
+fn spawn_backoff(base_ms: u64, attempts: u32) -> Duration {
+    let doublings = attempts.saturating_sub(1).min(16);
+    Duration::from_millis(base_ms.saturating_mul(1 << doublings)).min(MAX_SPAWN_BACKOFF)
+}
+
+/// Random delay between zero and `max`, so sessions failing together do not retry in
+/// lockstep.
+fn jitter(max: Duration) -> Duration {
+    max.mul_f64(random::unit())
+}
+
+/// Where to tell the sender of `msg` that it failed: its thread, or a new thread under it.
+fn error_target(msg: &IncomingMessage) -> OutgoingMessage {
+    OutgoingMessage {
+        text: String::new(),
+        conversation_id: msg.conversation_id.clone(),
+        thread_id: msg.thread_id.clone().or_else(|| msg.timestamp.clone()),
+    }
+}
+
+/// Reject directives a message cannot use before it is queued or held.
+fn check_directives(msg: &IncomingMessage, configured: PermissionAction) -> Result<()> {
+    let (directives, _) = commands::parse_directives(&msg.text)?;
+    let Some(action) = directives.permissions else {
+        return Ok(());
+    };
+    // Main sessions of different channels share the base directory and its policy.
+    if msg.thread_id.is_none() {
+        bail!("`/permissions` only applies in threads, where the session has its own directory");
+    }
+    // Anyone in the channel can send a directive, so it may only tighten the policy.
+    if !action.is_at_least_as_strict_as(configured) {
+        bail!(
+            "`/permissions {}` would loosen the configured default `{}`; only `{}` or \
+             stricter is allowed",
+            action.as_str(),
+            configured.as_str(),
+            configured.as_str()
+        );
+    }
+    Ok(())
+}
+
+fn lock_transcript(transcript: &Mutex<TranscriptCursor>) -> MutexGuard<'_, TranscriptCursor> {
+    transcript.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Parse what was appended to the transcript and return the latest assistant text with
+/// its uuid.
+fn read_latest_assistant(
+    transcript: &Mutex<TranscriptCursor>,
+    path: &Path,
+) -> Result<Option<(String, String)>> {
+    let mut cursor = lock_transcript(transcript);
+    cursor.read(path)?;
+    Ok(cursor.latest_assistant().cloned())
+}
+
+/// The last path component of an uploaded file's name, with characters that are awkward
+/// in a shell replaced, made unique among `taken`.
+fn upload_file_name(name: &str, taken: &[String]) -> String {
+    let base = Path::new(name)
+        .file_name()
+        .map(|base| base.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut base: String = base
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if base.trim_matches('.').is_empty() {
+        base = "file".to_string();
+    }
+    let mut name = base.clone();
+    let mut n = 1;
+    while taken.contains(&name) {
+        n += 1;
+        name = match base.split_once('.') {
+            Some((stem, ext)) if !stem.is_empty() => format!("{stem}-{n}.{ext}"),
+            _ => format!("{base}-{n}"),
+        };
+    }
+    name
+}
+
 fn sanitize_thread_id(thread_id: &str) -> String {
     thread_id
         .chars()
@@ -0,0 +1,171 @@
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// A diff taken out of a reply to be uploaded as a `.patch` file.
+#[derive(Debug, Clone)]
+pub struct Patch {
+    pub name: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderedReply {
+    pub text: String,
+    pub patches: Vec<Patch>,
+    /// Every diff found in the reply, inline or uploaded.
+    pub diffs: Vec<String>,
+}
+
+/// Render unified diffs in a reply as ```diff blocks. Bare diffs and fenced blocks
+/// holding a diff are both detected. Diffs longer than `upload_lines` (when non-zero)
+/// are replaced with a note and returned as patches.
+pub fn render_diffs(text: &str, upload_lines: usize) -> RenderedReply {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out: Vec<String> = Vec::new();
+    let mut patches = Vec::new();
+    let mut diffs = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if let Some(lang) = line.trim_start().strip_prefix("```") {
+            let Some(close) =
+                (i + 1..lines.len()).find(|&j| lines[j].trim_start().starts_with("```"))
+            else {
+                out.extend(lines[i..].iter().map(|l| l.to_string()));
+                break;
+            };
+            let body = &lines[i + 1..close];
+            let lang = lang.trim();
+            if matches!(lang, "" | "diff" | "patch") && looks_like_diff(body) {
+                diffs.push(join_diff(body));
+                out.push(emit_diff(body, upload_lines, &mut patches));
+            } else {
+                out.extend(lines[i..=close].iter().map(|l| l.to_string()));
+            }
+            i = close + 1;
+            continue;
+        }
+        if starts_diff(&lines[i..]) {
+            let end = diff_end(&lines, i);
+            let body = &lines[i..end];
+            if looks_like_diff(body) {
+                diffs.push(join_diff(body));
+                out.push(emit_diff(body, upload_lines, &mut patches));
+                i = end;
+                continue;
+            }
+        }
+        out.push(line.to_string());
+        i += 1;
+    }
+    RenderedReply {
+        text: out.join("\n"),
+        patches,
+        diffs,
+    }
+}
+
+/// Apply a diff to the working tree at `cwd` with `git apply`.
+pub fn apply_patch(cwd: &Path, diff: &str) -> Result<()> {
+    let mut child = Command::new("git")
+        .args(["apply", "--whitespace=nowarn", "-"])
+        .current_dir(cwd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to run git apply")?;
+    child
+        .stdin
+        .take()
+        .context("git apply stdin unavailable")?
+        .write_all(diff.as_bytes())
+        .context("failed to write patch to git apply")?;
+    let output = child
+        .wait_with_output()
+        .context("failed to wait for git apply")?;
+    if !output.status.success() {
+        bail!(
+            "git apply failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+fn join_diff(body: &[&str]) -> String {
+    let mut content = body.join("\n");
+    content.push('\n');
+    content
+}
+
+fn emit_diff(body: &[&str], upload_lines: usize, patches: &mut Vec<Patch>) -> String {
+    if upload_lines > 0 && body.len() > upload_lines {
+        let name = format!("changes-{}.patch", patches.len() + 1);
+        patches.push(Patch {
+            name: name.clone(),
+            content: join_diff(body),
+        });
+        return format!("_(diff with {} lines attached as `{name}`)_", body.len());
+    }
+    format!("```diff\n{}\n```", body.join("\n"))
+}
+
+fn starts_diff(lines: &[&str]) -> bool {
+    let Some(first) = lines.first() else {
+        return false;
+    };
+    if first.starts_with("diff --git ") {
+        return true;
+    }
+    first.starts_with("--- ") && lines.get(1).is_some_and(|next| next.starts_with("+++ "))
+}
+
+/// Index after the last line of a bare diff starting at `start`.
+fn diff_end(lines: &[&str], start: usize) -> usize {
+    let mut end = start;
+    while end < lines.len() {
+        let line = lines[end];
+        if is_diff_line(line) {
+            end += 1;
+            continue;
+        }
+        // Trailing whitespace on blank context lines is often trimmed.
+        if line.is_empty() && lines.get(end + 1).is_some_and(|next| is_hunk_body(next)) {
+            end += 1;
+            continue;
+        }
+        break;
+    }
+    end
+}
+
+fn is_diff_line(line: &str) -> bool {
+    const HEADERS: [&str; 11] = [
+        "diff ",
+        "index ",
+        "--- ",
+        "+++ ",
+        "@@",
+        "new file mode",
+        "deleted file mode",
+        "old mode",
+        "new mode",
+        "similarity index",
+        "rename ",
+    ];
+    HEADERS.iter().any(|prefix| line.starts_with(prefix)) || is_hunk_body(line)
+}
+
+fn is_hunk_body(line: &str) -> bool {
+    matches!(line.chars().next(), Some('+' | '-' | ' ' | '\\'))
+}
+
+fn looks_like_diff(body: &[&str]) -> bool {
+    body.iter().any(|line| line.starts_with("@@ -"))
+        && body
+            .iter()
+            .any(|line| line.starts_with("--- ") || line.starts_with("diff --git "))
+}
@@ -0,0 +1,540 @@
+use crate::config::SlackConfig;
+use crate::hooks;
+use crate::sessions::{self, PromptProfile, TmuxSessionManager};
+use crate::{slack_check, tmux};
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const PROMPT_CANDIDATES: [&str; 3] = ["❯", "›", ">"];
+const TRUST_CANDIDATES: [&str; 2] = ["Do you trust the files in this folder?", "trust this folder"];
+const PROBE_MESSAGE: &str = "Reply with the single word: ok";
+/// Oldest tmux with the `-C` flags `tmux.control_mode` relies on.
+const CONTROL_MODE_TMUX: (u32, u32) = (3, 2);
+/// `sun_path` is 104 bytes on macOS and 108 on Linux; the smaller limit keeps the
+/// check portable.
+const MAX_SOCKET_PATH_BYTES: usize = 103;
+const PROJECT_DIR_VARS: [&str; 2] = ["${CLAUDE_PROJECT_DIR}", "$CLAUDE_PROJECT_DIR"];
+
+pub fn profile_path(cwd: &Path) -> PathBuf {
+    cwd.join(sessions::PROMPT_PROFILE_PATH)
+}
+
+pub struct ProbeOptions {
+    pub claude_cmd: String,
+    pub prefix: String,
+    pub timeout: Duration,
+}
+
+/// Spawn a disposable session in a scratch directory and fingerprint the TUI.
+pub fn probe(opts: &ProbeOptions) -> Result<PromptProfile> {
+    let version = sessions::claude_version(&opts.claude_cmd)?;
+    let session_name = sessions::timestamp_session_name(&format!("{}-probe", opts.prefix))?;
+    let scratch = std::env::temp_dir().join(&session_name);
+    std::fs::create_dir_all(&scratch)
+        .with_context(|| format!("failed to create probe dir: {}", scratch.display()))?;
+
+    let manager = TmuxSessionManager::new(&opts.claude_cmd, &scratch);
+    manager
+        .spawn(&session_name)
+        .with_context(|| format!("failed to spawn probe session {session_name}"))?;
+
+    let result = fingerprint(&manager, &session_name, opts.timeout);
+    if let Err(err) = manager.stop(&session_name) {
+        eprintln!("probe: failed to stop {session_name}: {err}");
+    }
+    let _ = std::fs::remove_dir_all(&scratch);
+
+    let mut profile = result?;
+    profile.claude_version = Some(version);
+    Ok(profile)
+}
+
+fn fingerprint(
+    manager: &TmuxSessionManager,
+    session_name: &str,
+    timeout: Duration,
+) -> Result<PromptProfile> {
+    let defaults = PromptProfile::default();
+    let mut trust_markers = Vec::new();
+    let start = Instant::now();
+    let prompt_marker = loop {
+        let pane = manager.capture_pane(session_name, 200)?;
+        if let Some(marker) = TRUST_CANDIDATES.iter().find(|m| pane.contains(*m)) {
+            if !trust_markers.iter().any(|m| m == marker) {
+                eprintln!("probe: trust dialog detected ({marker}), accepting");
+                trust_markers.push(marker.to_string());
+                manager.send(session_name, "1")?;
+            }
+        } else if let Some(marker) = detect_prompt_marker(&pane) {
+            break marker;
+        }
+        if start.elapsed() > timeout {
+            bail!(
+                "no known prompt marker found; pane tail:\n{}",
+                sessions::pane_tail(&pane, 10)
+            );
+        }
+        std::thread::sleep(Duration::from_millis(250));
+    };
+    eprintln!("probe: prompt marker {prompt_marker:?}");
+
+    manager.send(session_name, PROBE_MESSAGE)?;
+    let mut profile = PromptProfile {
+        claude_version: None,
+        prompt_markers: vec![prompt_marker],
+        busy_markers: Vec::new(),
+        trust_markers,
+        permission_markers: PromptProfile::default().permission_markers,
+        plan_markers: PromptProfile::default().plan_markers,
+    };
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        let pane = manager.capture_pane(session_name, 200)?;
+        for marker in detect_busy_markers(&pane) {
+            if !profile.busy_markers.contains(&marker) {
+                eprintln!("probe: busy marker {marker:?}");
+                profile.busy_markers.push(marker);
+            }
+        }
+        if !profile.busy_markers.is_empty() && sessions::prompt_ready(&pane, &profile) {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    if profile.busy_markers.is_empty() {
+        eprintln!("probe: no busy marker observed, keeping defaults");
+        profile.busy_markers = defaults.busy_markers;
+    }
+    if profile.trust_markers.is_empty() {
+        profile.trust_markers = defaults.trust_markers;
+    }
+    Ok(profile)
+}
+
+fn detect_prompt_marker(pane: &str) -> Option<String> {
+    for line in pane.lines().rev().take(20) {
+        let line = line.replace('\u{00A0}', " ");
+        let trimmed = line.trim_start().trim_start_matches('│').trim_start();
+        for candidate in PROMPT_CANDIDATES {
+            if trimmed.starts_with(candidate) {
+                return Some(candidate.to_string());
+            }
+        }
+    }
+    None
+}
+
+fn detect_busy_markers(pane: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for line in pane.lines() {
+        let Some(idx) = line.find("to interrupt") else {
+            continue;
+        };
+        let key = line[..idx]
+            .trim_end()
+            .rsplit(|c: char| c.is_whitespace() || c == '(' || c == '·')
+            .next()
+            .unwrap_or_default();
+        if key.is_empty() {
+            continue;
+        }
+        let marker = format!("{key} to interrupt");
+        if !out.contains(&marker) {
+            out.push(marker);
+        }
+    }
+    out
+}
+
+/// Outcome of one environment check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+    /// Not checked, e.g. the Slack tokens without a config file.
+    Skip,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+            Status::Skip => "SKIP",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    pub fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+pub struct CheckOptions {
+    pub claude_cmd: String,
+    /// Base directory whose `.claude/settings.json` main sessions run with.
+    pub cwd: PathBuf,
+    pub events_path: PathBuf,
+    pub control_mode: bool,
+    /// Whether `hooks.secrets` is set, so unsigned payloads are dropped.
+    pub signed: bool,
+    /// Bot scopes the configured features need.
+    pub scopes: Vec<&'static str>,
+    /// `None` without a config file; the token checks are skipped.
+    pub slack: Option<SlackConfig>,
+}
+
+/// Check what `ccterm serve` needs from its environment, without starting a session.
+pub async fn run_checks(opts: &CheckOptions) -> Vec<Check> {
+    let mut checks = vec![check_tmux(opts.control_mode)];
+    let version = match sessions::claude_version(&opts.claude_cmd) {
+        Ok(version) => {
+            checks.push(Check::new("claude", Status::Pass, &version));
+            Some(version)
+        }
+        Err(err) => {
+            // Name a missing command rather than its exit status.
+            let err = sessions::ensure_claude_available(&opts.claude_cmd).err().unwrap_or(err);
+            checks.push(Check::new("claude", Status::Fail, format!("{err:#}")));
+            None
+        }
+    };
+    checks.push(check_prompt_profile(&opts.cwd, version.as_deref()));
+    let commands = match hook_commands(&opts.cwd) {
+        Ok(commands) => commands,
+        Err(err) => {
+            checks.push(Check::new("hook settings", Status::Fail, format!("{err:#}")));
+            Vec::new()
+        }
+    };
+    if !commands.is_empty() {
+        checks.push(check_hook_events(&commands));
+        checks.push(check_hook_binary(&commands));
+    }
+    let events_path = if opts.events_path.is_absolute() {
+        opts.events_path.clone()
+    } else {
+        opts.cwd.join(&opts.events_path)
+    };
+    checks.push(check_hook_paths(&events_path, &commands));
+    checks.push(check_hook_key(&hooks::secret_path(&opts.cwd), opts.signed, &commands));
+    checks.extend(check_slack(opts.slack.as_ref(), &opts.scopes).await);
+    checks
+}
+
+/// Print one line per check and a summary; returns the number of failed checks.
+pub fn print_report(checks: &[Check]) -> usize {
+    let width = checks.iter().map(|check| check.name.len()).max().unwrap_or(0);
+    for check in checks {
+        println!("{}  {:<width$}  {}", check.status, check.name, check.detail);
+    }
+    let count = |status| checks.iter().filter(|check| check.status == status).count();
+    let failed = count(Status::Fail);
+    println!(
+        "\n{} passed, {} warnings, {failed} failed, {} skipped",
+        count(Status::Pass),
+        count(Status::Warn),
+        count(Status::Skip)
+    );
+    failed
+}
+
+fn check_tmux(control_mode: bool) -> Check {
+    let output = match tmux::run(&["-V"]) {
+        Ok(output) => output.trim().to_string(),
+        Err(err) => return Check::new("tmux", Status::Fail, format!("{err:#}")),
+    };
+    match parse_tmux_version(&output) {
+        Some(version) if version >= CONTROL_MODE_TMUX => Check::new("tmux", Status::Pass, output),
+        Some(_) if control_mode => Check::new(
+            "tmux",
+            Status::Fail,
+            format!("{output}; tmux.control_mode needs 3.2 or later"),
+        ),
+        Some(_) => Check::new(
+            "tmux",
+            Status::Warn,
+            format!("{output}; tmux.control_mode would need 3.2 or later"),
+        ),
+        None => Check::new("tmux", Status::Warn, format!("{output} (version not recognized)")),
+    }
+}
+
+/// `tmux 3.4`, `tmux 3.3a` or `tmux next-3.5` as (major, minor).
+fn parse_tmux_version(output: &str) -> Option<(u32, u32)> {
+    let version = output.split_whitespace().nth(1)?;
+    let version = version.strip_prefix("next-").unwrap_or(version);
+    let (major, rest) = version.split_once('.')?;
+    let minor: String = rest.chars().take_while(char::is_ascii_digit).collect();
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+fn check_prompt_profile(cwd: &Path, installed: Option<&str>) -> Check {
+    let path = profile_path(cwd);
+    match PromptProfile::load(&path) {
+        Ok(Some(profile)) => match (profile.claude_version.as_deref(), installed) {
+            (Some(probed), Some(installed)) if probed != installed => Check::new(
+                "prompt profile",
+                Status::Warn,
+                format!("probed with {probed}, installed {installed}; run `ccterm doctor --probe`"),
+            ),
+            _ => Check::new("prompt profile", Status::Pass, path.display().to_string()),
+        },
+        Ok(None) => Check::new(
+            "prompt profile",
+            Status::Warn,
+            format!(
+                "none at {} (using defaults); run `ccterm doctor --probe`",
+                path.display()
+            ),
+        ),
+        Err(err) => Check::new("prompt profile", Status::Fail, format!("{err:#}")),
+    }
+}
+
+/// A hook command of `settings.json` that runs `ccterm hook`, with `$CLAUDE_PROJECT_DIR`
+/// expanded for the base directory.
+struct HookCommand {
+    event: String,
+    program: PathBuf,
+    out: Option<PathBuf>,
+    socket: Option<PathBuf>,
+    secret_file: Option<PathBuf>,
+}
+
+fn hook_commands(cwd: &Path) -> Result<Vec<HookCommand>> {
+    let path = cwd.join(".claude/settings.json");
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let settings: Value = serde_json::from_str(&content)
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    let project_dir = cwd.to_string_lossy();
+    let mut commands = Vec::new();
+    let events = settings.get("hooks").and_then(Value::as_object);
+    for (event, entries) in events.into_iter().flatten() {
+        let hooks = entries
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.get("hooks").and_then(Value::as_array))
+            .flatten();
+        for hook in hooks {
+            let Some(command) = hook.get("command").and_then(Value::as_str) else {
+                continue;
+            };
+            let words: Vec<String> = command
+                .split_whitespace()
+                .map(|word| {
+                    let word = word.trim_matches(|c| c == '"' || c == '\'');
+                    PROJECT_DIR_VARS
+                        .iter()
+                        .fold(word.to_string(), |word, var| word.replace(var, &project_dir))
+                })
+                .collect();
+            let is_ccterm = words.first().is_some_and(|program| {
+                Path::new(program).file_name().is_some_and(|name| name == "ccterm")
+            });
+            if !is_ccterm || words.get(1).map(String::as_str) != Some("hook") {
+                continue;
+            }
+            let arg = |flag: &str| {
+                let idx = words.iter().position(|word| word == flag)?;
+                words.get(idx + 1).map(PathBuf::from)
+            };
+            commands.push(HookCommand {
+                event: event.clone(),
+                program: PathBuf::from(&words[0]),
+                out: arg("--out"),
+                socket: arg("--socket"),
+                secret_file: arg("--secret-file"),
+            });
+        }
+    }
+    if commands.is_empty() {
+        bail!("{} registers no `ccterm hook` command", path.display());
+    }
+    Ok(commands)
+}
+
+fn check_hook_events(commands: &[HookCommand]) -> Check {
+    let mut events: Vec<&str> = commands.iter().map(|command| command.event.as_str()).collect();
+    events.dedup();
+    if !events.contains(&"Stop") {
+        return Check::new(
+            "hook settings",
+            Status::Fail,
+            format!("no Stop hook runs `ccterm hook` (registered: {})", events.join(", ")),
+        );
+    }
+    Check::new("hook settings", Status::Pass, events.join(", "))
+}
+
+/// Main sessions run the hook command as written; thread directories get it rewritten
+/// to the running binary, so a different binary here only affects the main session.
+fn check_hook_binary(commands: &[HookCommand]) -> Check {
+    let current = std::env::current_exe().ok().map(|exe| exe.canonicalize().unwrap_or(exe));
+    let mut programs: Vec<&Path> =
+        commands.iter().map(|command| command.program.as_path()).collect();
+    programs.sort();
+    programs.dedup();
+    let mut others = Vec::new();
+    for program in &programs {
+        let Ok(resolved) = program.canonicalize() else {
+            return Check::new(
+                "hook binary",
+                Status::Fail,
+                format!("{} does not exist; build it or fix the hook command", program.display()),
+            );
+        };
+        if current.as_ref() != Some(&resolved) {
+            others.push(program.display().to_string());
+        }
+    }
+    if others.is_empty() {
+        return Check::new("hook binary", Status::Pass, "hooks run this ccterm binary");
+    }
+    let current = current.map_or_else(|| "unknown".to_string(), |exe| exe.display().to_string());
+    Check::new(
+        "hook binary",
+        Status::Warn,
+        format!(
+            "main session hooks run {}, not this binary ({current}); thread sessions use \
+             this one",
+            others.join(", ")
+        ),
+    )
+}
+
+/// The events file must be writable by the hook and its socket path short enough to
+/// bind; hooks writing elsewhere than ccterm follows never arrive.
+fn check_hook_paths(events_path: &Path, commands: &[HookCommand]) -> Check {
+    let socket = hooks::socket_path(events_path);
+    if let Err(err) = check_writable(events_path) {
+        return Check::new("hook paths", Status::Fail, format!("{err:#}"));
+    }
+    let elsewhere: Vec<String> = commands
+        .iter()
+        .flat_map(|command| {
+            let out = command.out.as_ref().filter(|out| *out != events_path);
+            let sock = command.socket.as_ref().filter(|sock| **sock != socket);
+            out.into_iter().chain(sock)
+        })
+        .map(|path| path.display().to_string())
+        .collect();
+    if !elsewhere.is_empty() {
+        return Check::new(
+            "hook paths",
+            Status::Fail,
+            format!(
+                "hooks write to {}, but ccterm follows {} (hooks.events_path)",
+                elsewhere.join(", "),
+                events_path.display()
+            ),
+        );
+    }
+    let socket_len = socket.as_os_str().len();
+    if socket_len > MAX_SOCKET_PATH_BYTES {
+        return Check::new(
+            "hook paths",
+            Status::Warn,
+            format!(
+                "{} is {socket_len} bytes, too long for a Unix socket; hooks fall back to the \
+                 events file",
+                socket.display()
+            ),
+        );
+    }
+    Check::new("hook paths", Status::Pass, format!("{} writable", events_path.display()))
+}
+
+/// With `hooks.secrets`, every hook must sign with the key file ccterm writes, or its
+/// payloads are dropped.
+fn check_hook_key(key_path: &Path, signed: bool, commands: &[HookCommand]) -> Check {
+    if !signed {
+        return Check::new("hook key", Status::Skip, "hooks.secrets is not set");
+    }
+    let mut unsigned: Vec<&str> = commands
+        .iter()
+        .filter(|command| command.secret_file.as_deref() != Some(key_path))
+        .map(|command| command.event.as_str())
+        .collect();
+    unsigned.dedup();
+    if !unsigned.is_empty() {
+        return Check::new(
+            "hook key",
+            Status::Fail,
+            format!(
+                "{} hooks do not pass --secret-file {}; their payloads are dropped",
+                unsigned.join(", "),
+                key_path.display()
+            ),
+        );
+    }
+    Check::new("hook key", Status::Pass, format!("hooks sign with {}", key_path.display()))
+}
+
+/// Whether `path` can be appended to, or created in its nearest existing directory.
+fn check_writable(path: &Path) -> Result<()> {
+    if path.exists() {
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .with_context(|| format!("{} is not writable", path.display()))?;
+        return Ok(());
+    }
+    let dir = path
+        .ancestors()
+        .skip(1)
+        .find(|dir| dir.is_dir())
+        .context("no existing parent directory")?;
+    let probe = dir.join(format!(".ccterm-doctor-{}", std::process::id()));
+    std::fs::write(&probe, b"")
+        .with_context(|| format!("{} is not writable", dir.display()))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+async fn check_slack(slack: Option<&SlackConfig>, scopes: &[&str]) -> Vec<Check> {
+    let Some(slack) = slack else {
+        let skipped = "no config file; pass --config to check the tokens";
+        return vec![
+            Check::new("slack app token", Status::Skip, skipped),
+            Check::new("slack bot token", Status::Skip, skipped),
+        ];
+    };
+    let app = match slack_check::verify_app_token(&slack.app_token).await {
+        Ok(()) => Check::new("slack app token", Status::Pass, "Socket Mode connection allowed"),
+        Err(err) => Check::new("slack app token", Status::Fail, format!("{err:#}")),
+    };
+    let bot_token = slack.bot_token.trim();
+    let bot = if bot_token.is_empty() {
+        Check::new("slack bot token", Status::Skip, "tokens come from slack.oauth installs")
+    } else {
+        match slack_check::verify_bot_token(bot_token, scopes).await {
+            Ok(team) => Check::new(
+                "slack bot token",
+                Status::Pass,
+                format!("{team}, required scopes granted"),
+            ),
+            Err(err) => Check::new("slack bot token", Status::Fail, format!("{err:#}")),
+        }
+    };
+    vec![app, bot]
+}
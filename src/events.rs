@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::{broadcast, mpsc};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, info, warn};
+
+const EVENT_BUS_CAPACITY: usize = 256;
+
+/// Coordinator activity published on the event bus.
+#[derive(Debug, Clone)]
+pub enum CoordinatorEvent {
+    MessageReceived {
+        conversation_id: String,
+        thread_id: Option<String>,
+        user_id: Option<String>,
+        user_name: Option<String>,
+        text: String,
+    },
+    SessionSpawned {
+        conversation_id: String,
+        thread_id: Option<String>,
+        session_name: String,
+        cwd: PathBuf,
+        /// `SessionFingerprint::summary` of the new session.
+        fingerprint: String,
+    },
+    TurnStarted {
+        conversation_id: String,
+        thread_id: Option<String>,
+        session_name: String,
+    },
+    TurnCompleted {
+        conversation_id: String,
+        thread_id: Option<String>,
+        session_name: String,
+        outcome: TurnOutcome,
+    },
+    /// A button pressed, an approval answered, or a reaction acted on.
+    UserAction {
+        conversation_id: String,
+        thread_id: Option<String>,
+        /// `None` for answers without a Slack user, e.g. from the approval system.
+        user_id: Option<String>,
+        /// Button action id (`ccterm_permission_approve`), `reaction:<emoji>`, or
+        /// `approval_webhook:<decision>`.
+        action: String,
+        /// What it applied to: an approval id, a message ts or a session name.
+        target: Option<String>,
+    },
+    Error {
+        /// What was being handled (`incoming`, `hook`, ...).
+        context: &'static str,
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TurnOutcome {
+    Replied,
+    TimedOut,
+    /// Stopped by a user, e.g. from the App Home tab.
+    Interrupted,
+}
+
+/// In-process broadcast bus. Publishing never blocks; events are dropped when nobody
+/// subscribes, and slow subscribers skip ahead instead of holding the coordinator up.
+/// Subscribers that must see every event, like the audit log, use `subscribe_all`.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<CoordinatorEvent>,
+    lossless: Arc<Mutex<Vec<mpsc::UnboundedSender<CoordinatorEvent>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self {
+            tx,
+            lossless: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn publish(&self, event: CoordinatorEvent) {
+        let mut lossless = self.lossless.lock().unwrap_or_else(PoisonError::into_inner);
+        lossless.retain(|tx| tx.send(event.clone()).is_ok());
+        drop(lossless);
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<CoordinatorEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Receive every event published from now on, queued without a limit, so a slow
+    /// subscriber falls behind instead of skipping events.
+    pub fn subscribe_all(&self) -> mpsc::UnboundedReceiver<CoordinatorEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.lossless
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(tx);
+        rx
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Subscriber writing coordinator activity to the log.
+pub fn spawn_logger(bus: &EventBus) {
+    let mut rx = bus.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(event @ CoordinatorEvent::Error { .. }) => error!("{}", describe(&event)),
+                Ok(event) => info!("{}", describe(&event)),
+                Err(RecvError::Lagged(skipped)) => warn!("event log: skipped {skipped} events"),
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
+}
+
+fn describe(event: &CoordinatorEvent) -> String {
+    match event {
+        CoordinatorEvent::MessageReceived {
+            conversation_id,
+            thread_id,
+            user_id,
+            text,
+            ..
+        } => format!(
+            "coordinator: incoming slack message channel={conversation_id} thread={} user={} text_len={}",
+            thread_id.as_deref().unwrap_or("-"),
+            user_id.as_deref().unwrap_or("-"),
+            text.len()
+        ),
+        CoordinatorEvent::SessionSpawned {
+            conversation_id,
+            thread_id,
+            session_name,
+            cwd,
+            fingerprint,
+        } => format!(
+            "coordinator: session spawned {session_name} channel={conversation_id} thread={} cwd={} ({fingerprint})",
+            thread_id.as_deref().unwrap_or("-"),
+            cwd.display()
+        ),
+        CoordinatorEvent::TurnStarted {
+            conversation_id,
+            thread_id,
+            session_name,
+        } => format!(
+            "coordinator: turn started {session_name} channel={conversation_id} thread={}",
+            thread_id.as_deref().unwrap_or("-")
+        ),
+        CoordinatorEvent::TurnCompleted {
+            conversation_id,
+            thread_id,
+            session_name,
+            outcome,
+        } => format!(
+            "coordinator: turn {outcome:?} {session_name} channel={conversation_id} thread={}",
+            thread_id.as_deref().unwrap_or("-")
+        ),
+        CoordinatorEvent::UserAction {
+            conversation_id,
+            user_id,
+            action,
+            target,
+            ..
+        } => format!(
+            "coordinator: {action} by {} channel={conversation_id} target={}",
+            user_id.as_deref().unwrap_or("-"),
+            target.as_deref().unwrap_or("-")
+        ),
+        CoordinatorEvent::Error { context, message } => format!("{context} error: {message}"),
+    }
+}
@@ -0,0 +1,120 @@
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+/// Sleep between reads when the file cannot be watched.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Wakes a follower when a file is written to: inotify on Linux, a short sleep elsewhere
+/// or when the watch cannot be set up.
+pub struct FileWatch {
+    watch: Option<inotify::Watch>,
+}
+
+impl FileWatch {
+    pub fn new(path: &Path) -> Self {
+        let watch = match inotify::Watch::open(path) {
+            Ok(watch) => Some(watch),
+            Err(err) => {
+                if err.kind() != io::ErrorKind::Unsupported {
+                    warn!("file watch: polling {} instead: {err}", path.display());
+                }
+                None
+            }
+        };
+        Self { watch }
+    }
+
+    /// Block until the file may have been written to, or `timeout` passed.
+    pub fn wait(&self, timeout: Duration) {
+        if let Some(watch) = &self.watch {
+            match watch.wait(timeout) {
+                Ok(()) => return,
+                Err(err) => warn!("file watch: wait failed: {err}"),
+            }
+        }
+        thread::sleep(timeout.min(POLL_INTERVAL));
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::time::Duration;
+
+    pub struct Watch {
+        fd: OwnedFd,
+    }
+
+    impl Watch {
+        pub fn open(path: &Path) -> io::Result<Self> {
+            let path = CString::new(path.as_os_str().as_bytes())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            // SAFETY: plain syscall; a non-negative result is a new descriptor we own.
+            let raw = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if raw < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: `raw` was just returned by inotify_init1 and is not owned elsewhere.
+            let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+            // SAFETY: `path` is a valid NUL-terminated string for the duration of the call.
+            let wd =
+                unsafe { libc::inotify_add_watch(fd.as_raw_fd(), path.as_ptr(), libc::IN_MODIFY) };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { fd })
+        }
+
+        /// Wait for a modify event, then drain the queue; only the wake-up matters.
+        pub fn wait(&self, timeout: Duration) -> io::Result<()> {
+            let mut pollfd = libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let millis = timeout.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32;
+            // SAFETY: `pollfd` is a valid array of one entry.
+            if unsafe { libc::poll(&mut pollfd, 1, millis) } < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            let mut buf = [0u8; 4096];
+            loop {
+                // SAFETY: `buf` is writable for `buf.len()` bytes; the fd is non-blocking.
+                let read =
+                    unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+                if read <= 0 {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod inotify {
+    use std::io;
+    use std::path::Path;
+    use std::time::Duration;
+
+    pub enum Watch {}
+
+    impl Watch {
+        pub fn open(_path: &Path) -> io::Result<Self> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+
+        pub fn wait(&self, _timeout: Duration) -> io::Result<()> {
+            match *self {}
+        }
+    }
+}
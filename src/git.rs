@@ -0,0 +1,204 @@
+use anyhow::{bail, Context, Result};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Clone)]
+pub struct GitSummary {
+    pub branch: String,
+    pub dirty_files: usize,
+    pub last_commit: Option<String>,
+}
+
+/// Branch, number of changed files, and last commit of the repository at `cwd`.
+/// Returns `None` when `cwd` is not inside a git work tree.
+pub fn summary(cwd: &Path) -> Result<Option<GitSummary>> {
+    if run(cwd, &["rev-parse", "--is-inside-work-tree"]).is_err() {
+        return Ok(None);
+    }
+    let branch = run(cwd, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+    let status = run(cwd, &["status", "--porcelain"])?;
+    let last_commit = run(cwd, &["log", "-1", "--format=%h %s"]).ok();
+    Ok(Some(GitSummary {
+        branch,
+        dirty_files: status.lines().filter(|line| !line.is_empty()).count(),
+        last_commit: last_commit.filter(|commit| !commit.is_empty()),
+    }))
+}
+
+/// Commit `files` (relative to `cwd`) and tracked files deleted under `cwd`, and return
+/// the short SHA, or `None` when nothing changed. Other changes, staged or not, are left
+/// alone. With `own_repo`, a repository is initialized in `cwd` unless it already is the
+/// root of one, so work in nested thread directories is not committed to the parent.
+pub fn commit_files(
+    cwd: &Path,
+    message: &str,
+    own_repo: bool,
+    files: &[String],
+) -> Result<Option<String>> {
+    let is_root = run(cwd, &["rev-parse", "--show-toplevel"])
+        .is_ok_and(|top| Path::new(&top).canonicalize().ok() == cwd.canonicalize().ok());
+    if own_repo && !is_root {
+        run(cwd, &["init", "--quiet"])?;
+    }
+    let deleted = run(cwd, &["ls-files", "--deleted", "--", "."])?;
+    let mut paths: Vec<&str> = files.iter().map(String::as_str).collect();
+    paths.extend(deleted.lines().filter(|line| !line.is_empty()));
+    if paths.is_empty() {
+        return Ok(None);
+    }
+    run(cwd, &with_paths(&["add", "--all"], &paths))?;
+    if run(cwd, &with_paths(&["diff", "--cached", "--quiet"], &paths)).is_ok() {
+        return Ok(None);
+    }
+    run(cwd, &with_paths(&["commit", "--quiet", "--message", message], &paths))?;
+    run(cwd, &["rev-parse", "--short", "HEAD"]).map(Some)
+}
+
+fn with_paths<'a>(args: &[&'a str], paths: &[&'a str]) -> Vec<&'a str> {
+    let mut args = args.to_vec();
+    args.push("--");
+    args.extend(paths);
+    args
+}
+
+/// Revert `commit` with a new commit and return the files it touched.
+pub fn revert(cwd: &Path, commit: &str) -> Result<Vec<String>> {
+    let files = run(cwd, &["show", "--name-only", "--format=", commit])?;
+    if let Err(err) = run(cwd, &["revert", "--no-edit", commit]) {
+        let _ = run(cwd, &["revert", "--abort"]);
+        return Err(err);
+    }
+    Ok(files
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Push `HEAD` to `branch` on `remote`, creating or fast-forwarding it.
+pub fn push(cwd: &Path, remote: &str, branch: &str) -> Result<()> {
+    let refspec = format!("HEAD:refs/heads/{branch}");
+    run(cwd, &["push", "--quiet", remote, &refspec])?;
+    Ok(())
+}
+
+/// Check out `branch` into a new worktree at `dir`, creating the branch from `HEAD` of
+/// `repo` when it does not exist yet.
+pub fn add_worktree(repo: &Path, dir: &Path, branch: &str) -> Result<()> {
+    let dir = dir.to_string_lossy();
+    let branch_ref = format!("refs/heads/{branch}");
+    if run(repo, &["rev-parse", "--verify", "--quiet", &branch_ref]).is_ok() {
+        run(repo, &["worktree", "add", "--quiet", &dir, branch])?;
+    } else {
+        run(repo, &["worktree", "add", "--quiet", "-b", branch, &dir, "HEAD"])?;
+    }
+    Ok(())
+}
+
+/// Remove the worktree at `dir`. Git refuses when it has uncommitted changes or
+/// untracked files that are not ignored.
+pub fn remove_worktree(repo: &Path, dir: &Path) -> Result<()> {
+    run(repo, &["worktree", "remove", &dir.to_string_lossy()])?;
+    Ok(())
+}
+
+/// Add `patterns` missing from the repository's `info/exclude`, which applies to all of
+/// its worktrees.
+pub fn exclude(repo: &Path, patterns: &[String]) -> Result<()> {
+    let common_dir = PathBuf::from(run(repo, &["rev-parse", "--git-common-dir"])?);
+    let path = repo.join(common_dir).join("info/exclude");
+    let existing = fs::read_to_string(&path).unwrap_or_default();
+    let missing: Vec<&String> = patterns
+        .iter()
+        .filter(|pattern| !existing.lines().any(|line| line == pattern.as_str()))
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let mut content = existing;
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    for pattern in missing {
+        content.push_str(pattern);
+        content.push('\n');
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    fs::write(&path, content).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Keep local changes to `file` out of status and commits, when it is tracked.
+pub fn skip_worktree(cwd: &Path, file: &str) -> Result<()> {
+    if run(cwd, &["ls-files", "--error-unmatch", "--", file]).is_ok() {
+        run(cwd, &["update-index", "--skip-worktree", "--", file])?;
+    }
+    Ok(())
+}
+
+/// Changes under `cwd` against `HEAD`, untracked files included as new files: the
+/// `--stat` summary and, with `patch`, the full patch, with paths relative to `cwd`.
+/// `None` when `cwd` is not inside a git work tree, is ignored, or nothing changed.
+/// Untracked files are added to a copy of the index, so the repository's own index is
+/// left alone.
+pub fn working_diff(cwd: &Path, patch: bool) -> Result<Option<(String, Option<String>)>> {
+    if run(cwd, &["rev-parse", "--is-inside-work-tree"]).is_err()
+        || run(cwd, &["check-ignore", "--quiet", "."]).is_ok()
+    {
+        return Ok(None);
+    }
+    let index = cwd.join(run(cwd, &["rev-parse", "--git-path", "index"])?);
+    let scratch = cwd.join(run(cwd, &["rev-parse", "--git-path", "ccterm-diff.index"])?);
+    if index.exists() {
+        fs::copy(&index, &scratch)
+            .with_context(|| format!("failed to copy {}", index.display()))?;
+    } else {
+        let _ = fs::remove_file(&scratch);
+    }
+    let env = [("GIT_INDEX_FILE", scratch.as_os_str())];
+    let result = run_env(cwd, &env, &["add", "--all", "--", "."]).and_then(|_| {
+        let stat = run_env(
+            cwd,
+            &env,
+            &["diff", "--cached", "--relative", "--stat=72", "--stat-count=20", "--", "."],
+        )?;
+        if stat.trim().is_empty() {
+            return Ok(None);
+        }
+        let patch = if patch {
+            Some(run_env(cwd, &env, &["diff", "--cached", "--relative", "--", "."])?)
+        } else {
+            None
+        };
+        Ok(Some((stat.trim_end().to_string(), patch)))
+    });
+    let _ = fs::remove_file(&scratch);
+    result
+}
+
+/// Run git in `cwd` and return trimmed stdout.
+pub fn run(cwd: &Path, args: &[&str]) -> Result<String> {
+    run_env(cwd, &[], args).map(|stdout| stdout.trim().to_string())
+}
+
+/// Run git in `cwd` with extra environment variables and return stdout.
+fn run_env(cwd: &Path, env: &[(&str, &OsStr)], args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .envs(env.iter().copied())
+        .current_dir(cwd)
+        .output()
+        .with_context(|| format!("failed to run git {}", args.join(" ")))?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
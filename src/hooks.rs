@@ -1,27 +1,116 @@
+use crate::file_watch::FileWatch;
+use crate::paths;
+use crate::permissions::{self, PermissionAction};
 use anyhow::{bail, Context, Result};
+use hmac::{Hmac, KeyInit, Mac};
 use serde::Deserialize;
-use std::fs::{self, File, OpenOptions};
+use serde_json::Value;
+use sha2::Sha256;
+use std::fs::{self, File, OpenOptions, Permissions};
 use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
-use std::path::Path;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc;
+use tracing::warn;
 
-pub fn append_stdin_to_file(out_path: &Path) -> Result<()> {
-    if let Some(parent) = out_path.parent() {
+/// How often a receiver following the events file checks whether it was shut down.
+const FOLLOW_STOP_CHECK: Duration = Duration::from_secs(1);
+
+/// A client that connects but does not finish its payload within this is dropped.
+const SOCKET_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Owner-only file in the coordinator state dir holding the key `ccterm hook
+/// --secret-file` signs payloads with. Kept out of the session env and the project.
+const SECRET_FILE: &str = "hook.key";
+/// Payload field carrying `t=<unix secs>,v1=<hex HMAC-SHA256 of "<t>.<payload>">`.
+const SIGNATURE_FIELD: &str = "ccterm_signature";
+/// Signed payloads further than this from the receiver's clock are rejected as replays.
+const SIGNATURE_MAX_SKEW_SECS: u64 = 300;
+
+/// Signing key file of the coordinator serving `base_cwd`.
+pub fn secret_path(base_cwd: &Path) -> PathBuf {
+    paths::state_dir(base_cwd).join(SECRET_FILE)
+}
+
+/// Write the first of `secrets` to `path`, owner-only, or remove a stale key when none
+/// are configured.
+pub fn install_secret(path: &Path, secrets: &[String]) -> Result<()> {
+    let Some(secret) = secrets.first() else {
+        if path.exists() {
+            fs::remove_file(path)
+                .with_context(|| format!("failed to remove hook key: {}", path.display()))?;
+        }
+        return Ok(());
+    };
+    if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)
-            .with_context(|| format!("failed to create hook output dir: {}", parent.display()))?;
+            .with_context(|| format!("failed to create dir: {}", parent.display()))?;
     }
+    paths::write_private(path, secret)
+        .with_context(|| format!("failed to write hook key: {}", path.display()))
+}
+
+/// Socket the coordinator listens on for hook payloads, next to the events file.
+pub fn socket_path(events_path: &Path) -> PathBuf {
+    events_path.with_extension("sock")
+}
 
+/// Read the hook payload from stdin as it is, ending in a newline.
+pub fn read_stdin_line() -> Result<String> {
     let mut input = String::new();
     std::io::stdin()
         .read_to_string(&mut input)
         .context("failed to read hook payload from stdin")?;
+    if !input.ends_with('\n') {
+        input.push('\n');
+    }
+    Ok(input)
+}
 
+/// Read the hook payload from stdin, answered by the permission policy and signed with
+/// the key in `secret_file`, as one line.
+pub fn read_stdin_payload(secret_file: Option<&Path>) -> Result<String> {
+    let mut input = read_stdin_line()?;
+    if let Some(annotated) = apply_permission_policy(&input) {
+        input = annotated;
+    }
+    let secret = secret_file.and_then(|path| match fs::read_to_string(path) {
+        Ok(secret) => Some(secret.trim().to_string()).filter(|s| !s.is_empty()),
+        Err(err) => {
+            eprintln!("ccterm hook: failed to read {}, sent unsigned: {err}", path.display());
+            None
+        }
+    });
+    if let Some(secret) = secret {
+        match sign_payload(&input, &secret, unix_now()) {
+            Some(signed) => input = signed,
+            None => eprintln!("ccterm hook: payload is not a JSON object, sent unsigned"),
+        }
+    }
     if !input.ends_with('\n') {
         input.push('\n');
     }
+    Ok(input)
+}
+
+pub fn send_to_socket(socket_path: &Path, input: &str) -> Result<()> {
+    let mut stream = UnixStream::connect(socket_path)
+        .with_context(|| format!("failed to connect to hook socket: {}", socket_path.display()))?;
+    stream
+        .write_all(input.as_bytes())
+        .context("failed to write hook payload to socket")
+}
+
+pub fn append_to_file(out_path: &Path, input: &str) -> Result<()> {
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create hook output dir: {}", parent.display()))?;
+    }
 
     let mut file = OpenOptions::new()
         .create(true)
@@ -35,8 +124,88 @@ pub fn append_stdin_to_file(out_path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Answer PreToolUse payloads from the policy file named by `CCTERM_PERMISSION_POLICY`,
+/// which the coordinator sets in the session env. The decision is
+/// printed for Claude and recorded on the payload so the coordinator can report it.
+fn apply_permission_policy(input: &str) -> Option<String> {
+    let mut payload: Value = serde_json::from_str(input).ok()?;
+    let policy_path = std::env::var_os(permissions::POLICY_ENV)?;
+    let decision = match permissions::decide_for_payload(&payload, Path::new(&policy_path)) {
+        Ok(Some(decision)) => decision,
+        Ok(None) => return None,
+        Err(err) => {
+            eprintln!("ccterm permission policy error: {err}");
+            return None;
+        }
+    };
+    if let Some(output) = permissions::hook_output(&decision) {
+        println!("{output}");
+    }
+    payload["ccterm_permission"] = serde_json::json!({
+        "action": decision.action,
+        "reason": decision.reason,
+    });
+    Some(payload.to_string())
+}
+
+/// Add the signature field to a JSON object payload.
+fn sign_payload(input: &str, secret: &str, timestamp: u64) -> Option<String> {
+    let mut payload: Value = serde_json::from_str(input).ok()?;
+    let body = serde_json::to_string(&payload).ok()?;
+    let signature = hex::encode(mac(secret, timestamp, &body).finalize().into_bytes());
+    payload
+        .as_object_mut()?
+        .insert(SIGNATURE_FIELD.to_string(), Value::from(format!("t={timestamp},v1={signature}")));
+    Some(payload.to_string())
+}
+
+/// Check the signature field against every accepted secret, so payloads signed with a
+/// key being rotated out still pass while it is listed.
+fn verify_payload(line: &str, secrets: &[String], now: u64) -> Result<()> {
+    let mut payload: Value = serde_json::from_str(line).context("failed to parse hook json")?;
+    let signature = payload
+        .as_object_mut()
+        .and_then(|fields| fields.remove(SIGNATURE_FIELD))
+        .context("unsigned payload")?;
+    let signature = signature.as_str().context("signature is not a string")?;
+    let (timestamp, expected) = signature
+        .split_once(',')
+        .and_then(|(t, v)| Some((t.strip_prefix("t=")?, v.strip_prefix("v1=")?)))
+        .context("malformed signature")?;
+    let timestamp: u64 = timestamp.parse().context("malformed signature timestamp")?;
+    if now.abs_diff(timestamp) > SIGNATURE_MAX_SKEW_SECS {
+        bail!("signature timestamp {timestamp} is outside the accepted window");
+    }
+    let expected = hex::decode(expected).context("malformed signature")?;
+    let body = serde_json::to_string(&payload)?;
+    if secrets
+        .iter()
+        .any(|secret| mac(secret, timestamp, &body).verify_slice(&expected).is_ok())
+    {
+        Ok(())
+    } else {
+        bail!("signature does not match any configured secret")
+    }
+}
+
+fn mac(secret: &str, timestamp: u64, body: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{timestamp}.").as_bytes());
+    mac.update(body.as_bytes());
+    mac
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 pub struct HookFollower {
     reader: BufReader<File>,
+    watch: FileWatch,
 }
 
 impl HookFollower {
@@ -48,6 +217,7 @@ impl HookFollower {
 
         let mut file = OpenOptions::new()
             .create(true)
+            .truncate(false)
             .read(true)
             .write(true)
             .open(path)
@@ -58,12 +228,21 @@ impl HookFollower {
                 .context("failed to seek hook output")?;
         }
 
+        // Watch before reading so a write landing in between still wakes us.
+        let watch = FileWatch::new(path);
         Ok(Self {
             reader: BufReader::new(file),
+            watch,
         })
     }
 
     pub fn wait_for_line(&mut self, timeout: Duration) -> Result<String> {
+        self.next_line(timeout)?
+            .context("timed out waiting for hook event")
+    }
+
+    /// The next line appended, or `None` when none arrives within `timeout`.
+    pub fn next_line(&mut self, timeout: Duration) -> Result<Option<String>> {
         let start = Instant::now();
         let mut buf = String::new();
         loop {
@@ -73,12 +252,32 @@ impl HookFollower {
                 .read_line(&mut buf)
                 .context("failed reading hook output")?;
             if read > 0 {
-                return Ok(buf.trim_end().to_string());
+                return Ok(Some(buf.trim_end().to_string()));
             }
-            if start.elapsed() > timeout {
-                bail!("timed out waiting for hook event");
-            }
-            thread::sleep(Duration::from_millis(200));
+            let Some(remaining) = timeout.checked_sub(start.elapsed()) else {
+                return Ok(None);
+            };
+            self.watch.wait(remaining);
+        }
+    }
+}
+
+/// Stops the threads of a hook receiver. Dropping it leaves them running.
+pub struct ReceiverHandle {
+    stop: Arc<AtomicBool>,
+    /// Socket this receiver bound, `None` when another listener already served it.
+    socket: Option<PathBuf>,
+}
+
+impl ReceiverHandle {
+    /// Stop following the events file and close the socket, removing it so a later
+    /// receiver for the same path binds it again.
+    pub fn shutdown(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(socket) = self.socket {
+            // Wake the accept loop so it sees the flag and drops the listener.
+            let _ = UnixStream::connect(&socket);
+            let _ = fs::remove_file(&socket);
         }
     }
 }
@@ -89,6 +288,14 @@ pub struct HookEvent {
     pub session_id: String,
     pub transcript_path: PathBuf,
     pub cwd: PathBuf,
+    pub tool_name: Option<String>,
+    pub tool_argument: Option<String>,
+    pub tool_input: Option<Value>,
+    pub permission_action: Option<PermissionAction>,
+    /// `manual` or `auto` on PreCompact.
+    pub trigger: Option<String>,
+    /// Notification text, e.g. `Claude needs your permission to use Bash`.
+    pub message: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -99,6 +306,16 @@ struct HookPayload {
     transcript_path: Option<String>,
     agent_transcript_path: Option<String>,
     cwd: Option<String>,
+    tool_name: Option<String>,
+    tool_input: Option<Value>,
+    ccterm_permission: Option<PermissionRecord>,
+    trigger: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PermissionRecord {
+    action: PermissionAction,
 }
 
 pub fn parse_hook_line(line: &str) -> Result<HookEvent> {
@@ -109,48 +326,149 @@ pub fn parse_hook_line(line: &str) -> Result<HookEvent> {
         .or(payload.agent_transcript_path)
         .context("missing transcript_path")?;
     let cwd = payload.cwd.context("missing cwd")?;
+    let tool_argument = match (&payload.tool_name, &payload.tool_input) {
+        (Some(name), Some(input)) => Some(permissions::primary_argument(name, input)),
+        _ => None,
+    };
 
     Ok(HookEvent {
         event_name: payload.event_name,
         session_id: payload.session_id,
         transcript_path: PathBuf::from(transcript_path),
         cwd: PathBuf::from(cwd),
+        tool_name: payload.tool_name,
+        tool_argument,
+        tool_input: payload.tool_input,
+        permission_action: payload.ccterm_permission.map(|p| p.action),
+        trigger: payload.trigger,
+        message: payload.message,
     })
 }
 
-pub fn spawn_hook_receiver(path: PathBuf) -> mpsc::UnboundedReceiver<HookEvent> {
+/// Receive hook events from the socket next to `path`, and from the events file itself
+/// for hooks without `--socket` or that could not connect. With `secrets`, only payloads
+/// signed with one of them are forwarded.
+pub fn spawn_hook_receiver(
+    path: PathBuf,
+    secrets: Vec<String>,
+) -> (mpsc::UnboundedReceiver<HookEvent>, ReceiverHandle) {
     let (tx, rx) = mpsc::unbounded_channel();
+    let secrets = Arc::new(secrets);
+    let handle = spawn_line_receiver(path, move |line| forward_line(line, &tx, &secrets));
+    (rx, handle)
+}
 
+/// Call `handle` with every line written to the socket next to `path` or appended to
+/// `path`, on the receiver's threads, until the returned handle shuts them down.
+pub fn spawn_line_receiver(
+    path: PathBuf,
+    handle: impl Fn(&str) + Clone + Send + 'static,
+) -> ReceiverHandle {
+    let stop = Arc::new(AtomicBool::new(false));
+    let socket = spawn_socket_listener(socket_path(&path), handle.clone(), stop.clone());
+    let stopped = stop.clone();
     thread::spawn(move || {
         let mut follower = match HookFollower::open(&path, true) {
             Ok(f) => f,
             Err(err) => {
-                eprintln!("hook receiver failed to open: {err}");
+                warn!("hook receiver failed to open: {err}");
                 return;
             }
         };
 
-        loop {
-            match follower.wait_for_line(Duration::from_secs(3600)) {
-                Ok(line) => {
-                    if line.trim().is_empty() {
-                        continue;
-                    }
-                    match parse_hook_line(&line) {
-                        Ok(event) => {
-                            let _ = tx.send(event);
-                        }
-                        Err(err) => {
-                            eprintln!("hook receiver parse error: {err}");
-                        }
-                    }
+        while !stopped.load(Ordering::Relaxed) {
+            match follower.next_line(FOLLOW_STOP_CHECK) {
+                Ok(Some(line)) => handle(&line),
+                Ok(None) => {}
+                Err(err) => {
+                    warn!("hook receiver error: {err}");
+                    thread::sleep(FOLLOW_STOP_CHECK);
                 }
+            }
+        }
+    });
+    ReceiverHandle { stop, socket }
+}
+
+/// Bind the socket and serve it on a thread; returns the path when this listener bound it.
+fn spawn_socket_listener(
+    path: PathBuf,
+    handle: impl Fn(&str) + Send + 'static,
+    stop: Arc<AtomicBool>,
+) -> Option<PathBuf> {
+    let listener = match bind_socket(&path) {
+        Ok(Some(listener)) => listener,
+        Ok(None) => return None,
+        Err(err) => {
+            warn!("hook socket unavailable, following the events file only: {err:#}");
+            return None;
+        }
+    };
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
                 Err(err) => {
-                    eprintln!("hook receiver error: {err}");
+                    warn!("hook socket accept error: {err}");
+                    continue;
+                }
+            };
+            if let Err(err) = stream.set_read_timeout(Some(SOCKET_READ_TIMEOUT)) {
+                warn!("hook socket error: {err}");
+                continue;
+            }
+            for line in BufReader::new(stream).lines() {
+                match line {
+                    Ok(line) => handle(&line),
+                    Err(err) => {
+                        warn!("hook socket read error: {err}");
+                        break;
+                    }
                 }
             }
         }
     });
+    Some(path)
+}
+
+/// Bind the hook socket, replacing a stale one. Returns `None` when a listener already
+/// serves it, e.g. another session directory sharing an absolute `events_path`.
+fn bind_socket(path: &Path) -> Result<Option<UnixListener>> {
+    if UnixStream::connect(path).is_ok() {
+        return Ok(None);
+    }
+    if path.exists() {
+        fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale hook socket: {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("failed to bind hook socket: {}", path.display()))?;
+    fs::set_permissions(path, Permissions::from_mode(0o600))
+        .with_context(|| format!("failed to restrict hook socket: {}", path.display()))?;
+    Ok(Some(listener))
+}
+
+fn forward_line(line: &str, tx: &mpsc::UnboundedSender<HookEvent>, secrets: &[String]) {
+    if line.trim().is_empty() {
+        return;
+    }
+    match accept_line(line, secrets) {
+        Ok(event) => {
+            let _ = tx.send(event);
+        }
+        Err(err) => {
+            warn!("hook receiver dropped a payload: {err:#}");
+        }
+    }
+}
 
-    rx
+/// Verify the signature of a received line when `secrets` are set, and parse it.
+pub fn accept_line(line: &str, secrets: &[String]) -> Result<HookEvent> {
+    if !secrets.is_empty() {
+        verify_payload(line, secrets, unix_now())?;
+    }
+    parse_hook_line(line)
 }
@@ -0,0 +1,22 @@
+use crate::config::LogFormat;
+use anyhow::{anyhow, Result};
+use std::io::IsTerminal;
+use tracing_subscriber::EnvFilter;
+
+/// Filter used by subcommands that do not read `[log]` from the config.
+pub const DEFAULT_LEVEL: &str = "info";
+
+/// Install the global subscriber writing to stderr. `level` takes `tracing` filter
+/// directives, e.g. `debug` or `warn,ccterm::coordinator=info`.
+pub fn init(level: &str, format: LogFormat) -> Result<()> {
+    let filter =
+        EnvFilter::try_new(level).map_err(|err| anyhow!("invalid log level {level:?}: {err}"))?;
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    let installed = match format {
+        LogFormat::Text => builder.with_ansi(std::io::stderr().is_terminal()).try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).try_init(),
+    };
+    installed.map_err(|err| anyhow!("failed to install the logger: {err}"))
+}
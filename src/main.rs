@@ -1,39 +1,93 @@
+mod approval;
+mod audit;
+mod changes;
+mod chat;
 mod config;
 mod coordinator;
+mod diff;
 mod cli_adapter;
+mod clock;
+mod commands;
 mod context;
+mod control;
+mod doctor;
+mod events;
+mod file_watch;
+mod git;
+mod guard;
 mod hooks;
+mod logging;
+mod mrkdwn;
+mod oauth;
+mod outgoing;
+mod paths;
+mod permalinks;
+mod permissions;
+mod plan;
+mod plugins;
+mod preview;
+mod quiet;
+mod random;
+mod resources;
+mod self_test;
+mod send_queue;
 mod sessions;
 mod slack_adapter;
+mod slack_check;
+mod snapshot;
+mod state;
+mod tmux;
 mod types;
+mod url_fetch;
 
 use anyhow::{Context, Result};
-use config::Config;
+use config::{Config, ConfigLayers, LogFormat, SelfTestMode};
 use coordinator::Coordinator;
+use std::collections::VecDeque;
 use std::env;
 use std::io::BufRead;
 use std::path::PathBuf;
-use std::time::Duration;
+use std::sync::Arc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
 
 const DEFAULT_MESSAGE: &str = "hello from ccterm";
 const DEFAULT_TIMEOUT_SECS: u64 = 180;
 const DEFAULT_PREFIX: &str = "ccterm";
 const DEFAULT_CLAUDE_CMD: &str = "claude";
+const CONFIG_OPTIONS_USAGE: &str =
+    "  --config <path>\n  --profile <name>\n  --set <key.path=value> (repeatable)";
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     let mut args: Vec<String> = env::args().collect();
     let _bin = args.remove(0);
     if args.is_empty() {
         print_usage();
         return Ok(());
     }
+    // `hook` runs inside Claude: no runtime, and it stays quiet.
+    if args[0] == "hook" {
+        return run_hook(&args[1..]);
+    }
+    run(args)
+}
+
+#[tokio::main]
+async fn run(args: Vec<String>) -> Result<()> {
+    // `serve` configures logging from `[log]`.
+    if args[0] != "serve" {
+        logging::init(logging::DEFAULT_LEVEL, LogFormat::Text)?;
+    }
 
     match args[0].as_str() {
-        "hook" => run_hook(&args[1..]),
         "run" => run_session(&args[1..]),
         "cli" => run_cli(&args[1..]),
         "serve" => run_serve(&args[1..]).await,
+        "doctor" => run_doctor(&args[1..]).await,
+        "ctl" => run_ctl(&args[1..]),
+        "snapshot" => run_snapshot(&args[1..]),
+        "audit" => run_audit(&args[1..]),
+        "sessions" => run_sessions(&args[1..]),
         "help" | "-h" | "--help" => {
             print_usage();
             Ok(())
@@ -45,16 +99,39 @@ async fn main() -> Result<()> {
     }
 }
 
+/// Forward a hook payload to the coordinator. With `--stdin-passthrough`, the
+/// environment is cleared first and the payload is forwarded unchanged, without the
+/// permission policy or a signature, so nothing but stdin and the given paths is read.
 fn run_hook(args: &[String]) -> Result<()> {
     let mut out_path: Option<PathBuf> = None;
+    let mut socket_path: Option<PathBuf> = None;
+    let mut secret_file: Option<PathBuf> = None;
+    let passthrough = args.iter().any(|arg| arg == "--stdin-passthrough");
+    if passthrough {
+        for (key, _) in env::vars_os() {
+            // SAFETY: `main` calls this before starting the runtime or any other thread.
+            unsafe { env::remove_var(key) };
+        }
+    }
     let mut i = 0;
     while i < args.len() {
         match args[i].as_str() {
+            "--stdin-passthrough" => i += 1,
             "--out" => {
                 let value = args.get(i + 1).context("--out requires a value")?;
                 out_path = Some(PathBuf::from(value));
                 i += 2;
             }
+            "--socket" => {
+                let value = args.get(i + 1).context("--socket requires a value")?;
+                socket_path = Some(PathBuf::from(value));
+                i += 2;
+            }
+            "--secret-file" => {
+                let value = args.get(i + 1).context("--secret-file requires a value")?;
+                secret_file = Some(PathBuf::from(value));
+                i += 2;
+            }
             "--help" | "-h" => {
                 print_hook_usage();
                 return Ok(());
@@ -65,8 +142,24 @@ fn run_hook(args: &[String]) -> Result<()> {
         }
     }
 
+    if out_path.is_none() && socket_path.is_none() {
+        anyhow::bail!("--out or --socket is required");
+    }
+    let payload = if passthrough {
+        hooks::read_stdin_line()?
+    } else {
+        hooks::read_stdin_payload(secret_file.as_deref())?
+    };
+    if let Some(socket_path) = &socket_path {
+        // Without a listener (ccterm not serving this directory), fall back to the file.
+        match hooks::send_to_socket(socket_path, &payload) {
+            Ok(()) => return Ok(()),
+            Err(err) if out_path.is_none() => return Err(err),
+            Err(_) => {}
+        }
+    }
     let out_path = out_path.context("--out is required")?;
-    hooks::append_stdin_to_file(&out_path)
+    hooks::append_to_file(&out_path, &payload)
 }
 
 fn run_session(args: &[String]) -> Result<()> {
@@ -168,7 +261,8 @@ fn run_session(args: &[String]) -> Result<()> {
     sessions::ensure_dir(&hook_path)?;
 
     let session_name = sessions::timestamp_session_name(&prefix)?;
-    let manager = sessions::TmuxSessionManager::new(&claude_cmd, &cwd);
+    let profile = sessions::PromptProfile::load_or_default(&doctor::profile_path(&cwd));
+    let manager = sessions::TmuxSessionManager::new(&claude_cmd, &cwd).with_profile(profile);
 
     manager
         .spawn(&session_name)
@@ -220,6 +314,8 @@ fn run_cli(args: &[String]) -> Result<()> {
     let mut post_trust_wait_ms: u64 = 1500;
     let mut wait_prompt = true;
     let mut prompt_timeout_ms: u64 = 15000;
+    let mut offline = false;
+    let mut retry_interval_ms: u64 = 5000;
 
     let mut i = 0;
     while i < args.len() {
@@ -286,6 +382,17 @@ fn run_cli(args: &[String]) -> Result<()> {
                 prompt_timeout_ms = value.parse().context("invalid --prompt-timeout-ms")?;
                 i += 2;
             }
+            "--offline" => {
+                offline = true;
+                i += 1;
+            }
+            "--retry-interval-ms" => {
+                let value = args
+                    .get(i + 1)
+                    .context("--retry-interval-ms requires a value")?;
+                retry_interval_ms = value.parse().context("invalid --retry-interval-ms")?;
+                i += 2;
+            }
             "--help" | "-h" => {
                 print_cli_usage();
                 return Ok(());
@@ -296,59 +403,173 @@ fn run_cli(args: &[String]) -> Result<()> {
         }
     }
 
-    sessions::ensure_tmux_available()?;
-    sessions::ensure_claude_available(&claude_cmd)?;
-    sessions::ensure_dir(&hook_path)?;
-
-    let session_name = sessions::timestamp_session_name(&prefix)?;
-    let manager = sessions::TmuxSessionManager::new(&claude_cmd, &cwd);
-    manager
-        .spawn(&session_name)
-        .with_context(|| format!("failed to spawn tmux session {session_name}"))?;
+    let profile = sessions::PromptProfile::load_or_default(&doctor::profile_path(&cwd));
+    let manager = sessions::TmuxSessionManager::new(&claude_cmd, &cwd).with_profile(profile);
+    let start_session = || -> Result<String> {
+        sessions::ensure_tmux_available()?;
+        sessions::ensure_claude_available(&claude_cmd)?;
+        sessions::ensure_dir(&hook_path)?;
 
-    std::thread::sleep(Duration::from_millis(startup_wait_ms));
-    if accept_trust {
+        let session_name = sessions::timestamp_session_name(&prefix)?;
         manager
-            .send(&session_name, "1")
-            .with_context(|| format!("failed to accept trust for {session_name}"))?;
-        std::thread::sleep(Duration::from_millis(post_trust_wait_ms));
+            .spawn(&session_name)
+            .with_context(|| format!("failed to spawn tmux session {session_name}"))?;
+
+        std::thread::sleep(Duration::from_millis(startup_wait_ms));
+        if accept_trust {
+            manager
+                .send(&session_name, "1")
+                .with_context(|| format!("failed to accept trust for {session_name}"))?;
+            std::thread::sleep(Duration::from_millis(post_trust_wait_ms));
+        }
+        Ok(session_name)
+    };
+
+    let mut session_name = None;
+    let mut follower = None;
+    if !offline {
+        session_name = Some(start_session()?);
+        follower = Some(hooks::HookFollower::open(&hook_path, true)?);
     }
 
-    let stdin = std::io::stdin();
-    let mut lines = stdin.lock().lines();
-    let mut follower = hooks::HookFollower::open(&hook_path, true)?;
+    let (line_tx, line_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let stdin = std::io::stdin();
+        for line in stdin.lock().lines() {
+            if line_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
 
-    while let Some(line) = lines.next() {
-        let line = line.context("failed to read stdin")?;
-        let input = match cli_adapter::parse_input(&line) {
-            Ok(value) => value,
-            Err(err) => {
-                eprintln!("invalid input: {err}");
-                continue;
+    let delivery = CliDelivery {
+        manager: &manager,
+        wait_prompt,
+        prompt_timeout: Duration::from_millis(prompt_timeout_ms),
+        hook_timeout: Duration::from_secs(timeout_secs),
+    };
+    let retry_interval = Duration::from_millis(retry_interval_ms);
+    let mut queue: VecDeque<types::IncomingMessage> = VecDeque::new();
+    let mut stdin_open = true;
+    let mut next_attempt = Instant::now();
+
+    loop {
+        if session_name.is_none() && Instant::now() >= next_attempt {
+            match start_session() {
+                Ok(name) => {
+                    eprintln!("cli: session {name} started, flushing {} queued", queue.len());
+                    follower = Some(hooks::HookFollower::open(&hook_path, true)?);
+                    session_name = Some(name);
+                }
+                Err(err) => {
+                    eprintln!(
+                        "cli: backend unavailable ({err}); queued={} retry in {}ms",
+                        queue.len(),
+                        retry_interval_ms
+                    );
+                    next_attempt = Instant::now() + retry_interval;
+                }
             }
-        };
+        }
 
-        if wait_prompt {
-            if let Err(err) = sessions::wait_for_prompt(
-                &manager,
-                &session_name,
-                Duration::from_millis(prompt_timeout_ms),
-                Duration::from_millis(200),
-            ) {
-                eprintln!("prompt not ready: {err}");
-                continue;
+        if let (Some(name), Some(hook_follower)) = (session_name.clone(), follower.as_mut()) {
+            while let Some(input) = queue.pop_front() {
+                match delivery.deliver(&name, hook_follower, &input)? {
+                    CliDeliveryResult::Done => {}
+                    CliDeliveryResult::BackendLost(err) => {
+                        if !offline {
+                            return Err(err);
+                        }
+                        eprintln!("cli: session {name} lost ({err}); message re-queued");
+                        queue.push_front(input);
+                        session_name = None;
+                        follower = None;
+                        next_attempt = Instant::now() + retry_interval;
+                        break;
+                    }
+                }
             }
         }
 
+        if !stdin_open {
+            if queue.is_empty() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(200));
+            continue;
+        }
+        match line_rx.recv_timeout(Duration::from_millis(200)) {
+            Ok(line) => {
+                let line = line.context("failed to read stdin")?;
+                match cli_adapter::parse_input(&line) {
+                    Ok(input) => queue.push_back(input),
+                    Err(err) => eprintln!("invalid input: {err}"),
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => stdin_open = false,
+        }
+    }
+
+    if let Some(session_name) = session_name
+        && !keep_session
+    {
         manager
-            .send(&session_name, &input.text)
-            .with_context(|| format!("failed to send message to {session_name}"))?;
+            .stop(&session_name)
+            .with_context(|| format!("failed to stop session {session_name}"))?;
+    }
+
+    Ok(())
+}
+
+struct CliDelivery<'a> {
+    manager: &'a sessions::TmuxSessionManager,
+    wait_prompt: bool,
+    prompt_timeout: Duration,
+    hook_timeout: Duration,
+}
+
+enum CliDeliveryResult {
+    Done,
+    BackendLost(anyhow::Error),
+}
+
+impl CliDelivery<'_> {
+    /// Send one message and print the reply. Per-message failures are logged and the
+    /// message is dropped; only a vanished tmux session is reported as `BackendLost`.
+    fn deliver(
+        &self,
+        session_name: &str,
+        follower: &mut hooks::HookFollower,
+        input: &types::IncomingMessage,
+    ) -> Result<CliDeliveryResult> {
+        if self.wait_prompt
+            && let Err(err) = sessions::wait_for_prompt(
+                self.manager,
+                session_name,
+                self.prompt_timeout,
+                Duration::from_millis(200),
+            )
+        {
+            if !self.manager.has_session(session_name) {
+                return Ok(CliDeliveryResult::BackendLost(err));
+            }
+            eprintln!("prompt not ready: {err}");
+            return Ok(CliDeliveryResult::Done);
+        }
 
-        let hook_line = match follower.wait_for_line(Duration::from_secs(timeout_secs)) {
+        if let Err(err) = self.manager.send(session_name, &input.text) {
+            if !self.manager.has_session(session_name) {
+                return Ok(CliDeliveryResult::BackendLost(err));
+            }
+            return Err(err.context(format!("failed to send message to {session_name}")));
+        }
+
+        let hook_line = match follower.wait_for_line(self.hook_timeout) {
             Ok(value) => value,
             Err(err) => {
                 eprintln!("hook timeout: {err}");
-                continue;
+                return Ok(CliDeliveryResult::Done);
             }
         };
 
@@ -356,7 +577,7 @@ fn run_cli(args: &[String]) -> Result<()> {
             Ok(path) => path,
             Err(err) => {
                 eprintln!("failed to parse hook event: {err}");
-                continue;
+                return Ok(CliDeliveryResult::Done);
             }
         };
 
@@ -364,30 +585,23 @@ fn run_cli(args: &[String]) -> Result<()> {
             Ok(Some(text)) => text,
             Ok(None) => {
                 eprintln!("no assistant text found");
-                continue;
+                return Ok(CliDeliveryResult::Done);
             }
             Err(err) => {
                 eprintln!("failed to read transcript: {err}");
-                continue;
+                return Ok(CliDeliveryResult::Done);
             }
         };
 
         let outgoing = types::OutgoingMessage {
             text: assistant_text,
-            conversation_id: input.conversation_id,
-            thread_id: input.thread_id,
+            conversation_id: input.conversation_id.clone(),
+            thread_id: input.thread_id.clone(),
         };
         let pretty = cli_adapter::pretty_outgoing(&outgoing)?;
         println!("{pretty}");
+        Ok(CliDeliveryResult::Done)
     }
-
-    if !keep_session {
-        manager
-            .stop(&session_name)
-            .with_context(|| format!("failed to stop session {session_name}"))?;
-    }
-
-    Ok(())
 }
 
 fn extract_transcript_path(hook_line: &str) -> Result<PathBuf> {
@@ -402,41 +616,554 @@ fn extract_transcript_path(hook_line: &str) -> Result<PathBuf> {
 }
 
 async fn run_serve(args: &[String]) -> Result<()> {
-    let mut config_path: Option<PathBuf> = None;
+    let mut config_args = ConfigArgs::default();
+    let mut dry_run = false;
+    let mut log_level = None;
     let mut i = 0;
     while i < args.len() {
+        if config_args.parse(args, &mut i)? {
+            continue;
+        }
         match args[i].as_str() {
-            "--config" => {
-                let value = args.get(i + 1).context("--config requires a value")?;
-                config_path = Some(PathBuf::from(value));
-                i += 2;
-            }
             "--help" | "-h" => {
                 print_serve_usage();
                 return Ok(());
             }
+            "--dry-run" => {
+                dry_run = true;
+                i += 1;
+            }
+            "--log-level" => {
+                let value = args.get(i + 1).context("--log-level requires a value")?;
+                log_level = Some(value.to_string());
+                i += 2;
+            }
             other => {
                 return Err(anyhow::anyhow!("unknown serve argument: {other}"));
             }
         }
     }
 
-    let config_path = config_path.unwrap_or_else(|| PathBuf::from("ccterm.toml"));
-    let config = Config::load(&config_path)?;
+    let config = config_args.load()?;
+    let level = log_level.as_deref().unwrap_or(&config.log.level);
+    logging::init(level, config.log.format)?;
 
-    sessions::ensure_tmux_available()?;
-    sessions::ensure_claude_available(&config.claude.command)?;
+    if dry_run {
+        tracing::info!("dry run, no sessions are spawned and nothing is posted to Slack");
+    } else {
+        sessions::ensure_tmux_available()?;
+        sessions::ensure_claude_available(&config.claude.command)?;
+    }
+
+    let profile =
+        sessions::PromptProfile::load_or_default(&doctor::profile_path(&config.claude.cwd));
+    profile.check_version(&config.claude.command);
+
+    if config.coordinator.self_test != SelfTestMode::Off && !dry_run {
+        let opts = self_test::SelfTestOptions::from_config(&config, profile.clone());
+        tracing::info!("self-test: checking the hook round trip");
+        match tokio::task::spawn_blocking(move || self_test::run(&opts)).await? {
+            Ok(elapsed) => {
+                tracing::info!("self-test: Stop hook arrived in {}ms", elapsed.as_millis())
+            }
+            Err(failure) if config.coordinator.self_test == SelfTestMode::Require => {
+                return Err(failure.into());
+            }
+            Err(failure) => tracing::warn!("{failure}"),
+        }
+    }
 
+    slack_check::verify(&config).await?;
     let slack = slack_adapter::SlackAdapter::connect(&config.slack).await?;
-    let sessions = sessions::TmuxSessionManager::new(&config.claude.command, &config.claude.cwd);
+    if let Some(oauth_cfg) = config.slack.oauth.clone()
+        && !dry_run
+    {
+        let store = state::StateStore::new(&config.claude.cwd);
+        oauth::start(oauth_cfg, slack.client(), slack.tokens(), store).await?;
+    }
+    let sessions = sessions::TmuxSessionManager::new(&config.claude.command, &config.claude.cwd)
+        .with_profile(profile)
+        .with_control_mode(config.tmux.control_mode);
 
-    let coordinator = Coordinator::new(config, sessions, slack)?;
+    let coordinator = Coordinator::new(config, sessions, slack)?
+        .with_dry_run(dry_run)
+        .with_clock(Arc::new(clock::TokioClock));
     coordinator.run().await?;
     Ok(())
 }
 
+async fn run_doctor(args: &[String]) -> Result<()> {
+    let mut config_args = ConfigArgs::default();
+    let mut probe = false;
+    let mut self_test = false;
+    let mut self_test_timeout_secs: u64 = 120;
+    let mut prefix = DEFAULT_PREFIX.to_string();
+    let mut claude_cmd: Option<String> = None;
+    let mut cwd: Option<PathBuf> = None;
+    let mut probe_timeout_ms: u64 = 60_000;
+
+    let mut i = 0;
+    while i < args.len() {
+        if config_args.parse(args, &mut i)? {
+            continue;
+        }
+        match args[i].as_str() {
+            "--probe" => {
+                probe = true;
+                i += 1;
+            }
+            "--self-test" => {
+                self_test = true;
+                i += 1;
+            }
+            "--self-test-timeout-secs" => {
+                let value = args
+                    .get(i + 1)
+                    .context("--self-test-timeout-secs requires a value")?;
+                self_test_timeout_secs =
+                    value.parse().context("invalid --self-test-timeout-secs")?;
+                i += 2;
+            }
+            "--prefix" => {
+                let value = args.get(i + 1).context("--prefix requires a value")?;
+                prefix = value.to_string();
+                i += 2;
+            }
+            "--claude-cmd" => {
+                let value = args.get(i + 1).context("--claude-cmd requires a value")?;
+                claude_cmd = Some(value.to_string());
+                i += 2;
+            }
+            "--cwd" => {
+                let value = args.get(i + 1).context("--cwd requires a value")?;
+                cwd = Some(PathBuf::from(value));
+                i += 2;
+            }
+            "--probe-timeout-ms" => {
+                let value = args
+                    .get(i + 1)
+                    .context("--probe-timeout-ms requires a value")?;
+                probe_timeout_ms = value.parse().context("invalid --probe-timeout-ms")?;
+                i += 2;
+            }
+            "--help" | "-h" => {
+                print_doctor_usage();
+                return Ok(());
+            }
+            other => {
+                return Err(anyhow::anyhow!("unknown doctor argument: {other}"));
+            }
+        }
+    }
+
+    if !probe && !self_test {
+        return run_doctor_checks(&config_args, claude_cmd, cwd).await;
+    }
+    let claude_cmd = claude_cmd.unwrap_or_else(|| DEFAULT_CLAUDE_CMD.to_string());
+    let cwd = match cwd {
+        Some(cwd) => cwd,
+        None => sessions::default_cwd()?,
+    };
+    let profile_path = doctor::profile_path(&cwd);
+    if probe {
+        sessions::ensure_tmux_available()?;
+        sessions::ensure_claude_available(&claude_cmd)?;
+        let profile = doctor::probe(&doctor::ProbeOptions {
+            claude_cmd,
+            prefix,
+            timeout: Duration::from_millis(probe_timeout_ms),
+        })?;
+        profile.save(&profile_path)?;
+        println!("prompt profile written: {}", profile_path.display());
+        println!("{}", serde_json::to_string_pretty(&profile)?);
+        return Ok(());
+    }
+
+    if self_test {
+        sessions::ensure_tmux_available()?;
+        sessions::ensure_claude_available(&claude_cmd)?;
+        let elapsed = self_test::run(&self_test::SelfTestOptions {
+            claude_cmd,
+            prefix,
+            profile: sessions::PromptProfile::load_or_default(&profile_path),
+            base_cwd: cwd,
+            // Where the hook command of the README writes, relative to the session dir.
+            events_path: PathBuf::from(".claude/hooks/events.jsonl"),
+            secrets: Vec::new(),
+            timeout: Duration::from_secs(self_test_timeout_secs),
+        })?;
+        println!("self-test passed: Stop hook arrived in {}ms", elapsed.as_millis());
+    }
+    Ok(())
+}
+
+/// Report on tmux, the claude binary, hook settings and paths, and the Slack tokens.
+/// Settings come from the config file when there is one, with `--claude-cmd` and
+/// `--cwd` taking precedence.
+async fn run_doctor_checks(
+    config_args: &ConfigArgs,
+    claude_cmd: Option<String>,
+    cwd: Option<PathBuf>,
+) -> Result<()> {
+    let config_path = config_args
+        .path
+        .clone()
+        .unwrap_or_else(paths::default_config_path);
+    let (config_check, config) = if config_args.path.is_none() && !config_path.exists() {
+        let detail = format!("no config at {}; using defaults", config_path.display());
+        (doctor::Check::new("config", doctor::Status::Skip, detail), None)
+    } else {
+        match config_args.load() {
+            Ok(config) => {
+                let detail = config_path.display().to_string();
+                (doctor::Check::new("config", doctor::Status::Pass, detail), Some(config))
+            }
+            Err(err) => {
+                let detail = format!("{err:#}");
+                (doctor::Check::new("config", doctor::Status::Fail, detail), None)
+            }
+        }
+    };
+    let cwd = match cwd.or_else(|| config.as_ref().map(|config| config.claude.cwd.clone())) {
+        Some(cwd) => cwd,
+        None => sessions::default_cwd()?,
+    };
+    let opts = doctor::CheckOptions {
+        claude_cmd: claude_cmd
+            .or_else(|| config.as_ref().map(|config| config.claude.command.clone()))
+            .unwrap_or_else(|| DEFAULT_CLAUDE_CMD.to_string()),
+        cwd,
+        // Without a config, where the hook command of the README writes.
+        events_path: config.as_ref().map_or_else(
+            || PathBuf::from(".claude/hooks/events.jsonl"),
+            |config| config.hooks.events_path.clone(),
+        ),
+        control_mode: config.as_ref().is_some_and(|config| config.tmux.control_mode),
+        signed: config.as_ref().is_some_and(|config| !config.hooks.secrets.is_empty()),
+        scopes: config.as_ref().map(slack_check::required_scopes).unwrap_or_default(),
+        slack: config.map(|config| config.slack),
+    };
+    let mut checks = vec![config_check];
+    checks.extend(doctor::run_checks(&opts).await);
+    let failed = doctor::print_report(&checks);
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{failed} doctor check(s) failed"));
+    }
+    Ok(())
+}
+
+fn run_ctl(args: &[String]) -> Result<()> {
+    let mut config_args = ConfigArgs::default();
+    let mut rest: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        if config_args.parse(args, &mut i)? {
+            continue;
+        }
+        match args[i].as_str() {
+            "--help" | "-h" => {
+                print_ctl_usage();
+                return Ok(());
+            }
+            other => {
+                rest.push(other.to_string());
+                i += 1;
+            }
+        }
+    }
+
+    let command = match rest.first().map(String::as_str) {
+        Some("broadcast") => {
+            let text = rest[1..].join(" ");
+            if text.trim().is_empty() {
+                return Err(anyhow::anyhow!("broadcast requires a message"));
+            }
+            control::ControlCommand::Broadcast { text }
+        }
+        Some("maintenance") => {
+            let enabled = match rest.get(1).map(String::as_str) {
+                Some("on") => true,
+                Some("off") => false,
+                _ => return Err(anyhow::anyhow!("usage: maintenance on [message] | off")),
+            };
+            let message = rest[2..].join(" ");
+            control::ControlCommand::Maintenance {
+                enabled,
+                message: Some(message).filter(|text| enabled && !text.trim().is_empty()),
+            }
+        }
+        Some(other) => return Err(anyhow::anyhow!("unknown ctl command: {other}")),
+        None => {
+            print_ctl_usage();
+            return Ok(());
+        }
+    };
+
+    let config = config_args.load()?;
+    let base_cwd = config.claude.cwd.canonicalize().unwrap_or(config.claude.cwd);
+    let path = control::control_path(&base_cwd);
+    control::append(&path, &command)?;
+    println!("queued for coordinator: {}", path.display());
+    Ok(())
+}
+
+fn run_audit(args: &[String]) -> Result<()> {
+    let mut config_args = ConfigArgs::default();
+    let mut since: Option<String> = None;
+    let mut format = audit::ExportFormat::Jsonl;
+    let mut rest: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        if config_args.parse(args, &mut i)? {
+            continue;
+        }
+        match args[i].as_str() {
+            "--since" => {
+                let value = args.get(i + 1).context("--since requires a value")?;
+                since = Some(value.to_string());
+                i += 2;
+            }
+            "--format" => {
+                let value = args.get(i + 1).context("--format requires a value")?;
+                format = audit::ExportFormat::parse(value)?;
+                i += 2;
+            }
+            "--help" | "-h" => {
+                print_audit_usage();
+                return Ok(());
+            }
+            other => {
+                rest.push(other.to_string());
+                i += 1;
+            }
+        }
+    }
+    if rest.first().map(String::as_str) != Some("export") {
+        print_audit_usage();
+        return Ok(());
+    }
+
+    let config = config_args.load()?;
+    let base_cwd = config.claude.cwd.canonicalize().unwrap_or(config.claude.cwd);
+    let since = since
+        .map(|value| audit::parse_since(&value, jiff::Timestamp::now()))
+        .transpose()?;
+    let path = audit::audit_path(&base_cwd);
+    let mut out = std::io::stdout().lock();
+    let count = audit::export(&path, since, format, &mut out)?;
+    eprintln!("exported {count} records from {}", path.display());
+    Ok(())
+}
+
+fn run_sessions(args: &[String]) -> Result<()> {
+    let mut config_args = ConfigArgs::default();
+    let mut rest: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        if config_args.parse(args, &mut i)? {
+            continue;
+        }
+        match args[i].as_str() {
+            "--help" | "-h" => {
+                print_sessions_usage();
+                return Ok(());
+            }
+            other => {
+                rest.push(other.to_string());
+                i += 1;
+            }
+        }
+    }
+    let Some(command) = rest.first().map(String::as_str) else {
+        print_sessions_usage();
+        return Ok(());
+    };
+
+    let config = config_args.load()?;
+    let prefix = &config.tmux.session_prefix;
+    let sessions = sessions::list_sessions(prefix)?;
+    if command == "list" {
+        if sessions.is_empty() {
+            eprintln!("no {prefix}-* tmux sessions");
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        for session in &sessions {
+            let mut line = format!(
+                "{}\t{}\t{}\t{}",
+                session.name,
+                format_age(now.saturating_sub(session.created)),
+                session.conversation.as_deref().unwrap_or("-"),
+                session.cwd.display()
+            );
+            if session.attached {
+                line.push_str("\t(attached)");
+            }
+            if let Some(fingerprint) = &session.fingerprint {
+                line.push_str(&format!("\t{fingerprint}"));
+            }
+            if let Some(label) = &session.label {
+                line.push_str(&format!("\t{label}"));
+            }
+            println!("{line}");
+        }
+        return Ok(());
+    }
+
+    let name = rest
+        .get(1)
+        .with_context(|| format!("sessions {command} requires a session name"))?;
+    if !sessions.iter().any(|session| &session.name == name) {
+        return Err(anyhow::anyhow!("no {prefix}-* tmux session named {name}"));
+    }
+    match command {
+        "kill" => {
+            tmux::run(&["kill-session", "-t", name])?;
+            eprintln!("killed {name}; the coordinator starts a new session on the next message");
+            Ok(())
+        }
+        "attach" => {
+            // Inside tmux, attaching would nest clients; switch the current one instead.
+            let verb = if env::var_os("TMUX").is_some() {
+                "switch-client"
+            } else {
+                "attach-session"
+            };
+            let status = std::process::Command::new("tmux")
+                .args([verb, "-t", name])
+                .status()
+                .context("failed to run tmux")?;
+            if !status.success() {
+                return Err(anyhow::anyhow!("tmux {verb} failed with status: {status}"));
+            }
+            Ok(())
+        }
+        other => Err(anyhow::anyhow!("unknown sessions command: {other}")),
+    }
+}
+
+/// Compact age such as `45s`, `12m`, `3h` or `2d`.
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86_400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86_400),
+    }
+}
+
+fn run_snapshot(args: &[String]) -> Result<()> {
+    let mut config_args = ConfigArgs::default();
+    let mut conversation_id: Option<String> = None;
+    let mut target_cwd: Option<PathBuf> = None;
+    let mut resume = false;
+    let mut rest: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        if config_args.parse(args, &mut i)? {
+            continue;
+        }
+        match args[i].as_str() {
+            "--conversation" => {
+                let value = args.get(i + 1).context("--conversation requires a value")?;
+                conversation_id = Some(value.to_string());
+                i += 2;
+            }
+            "--cwd" => {
+                let value = args.get(i + 1).context("--cwd requires a value")?;
+                target_cwd = Some(PathBuf::from(value));
+                i += 2;
+            }
+            "--resume" => {
+                resume = true;
+                i += 1;
+            }
+            "--help" | "-h" => {
+                print_snapshot_usage();
+                return Ok(());
+            }
+            other => {
+                rest.push(other.to_string());
+                i += 1;
+            }
+        }
+    }
+
+    let config = config_args.load()?;
+    let base_cwd = config
+        .claude
+        .cwd
+        .canonicalize()
+        .unwrap_or(config.claude.cwd.clone());
+    let manager = sessions::TmuxSessionManager::new(&config.claude.command, &base_cwd);
+
+    match (rest.first().map(String::as_str), rest.get(1)) {
+        (Some("create"), Some(session_name)) => {
+            let paths = snapshot::SnapshotPaths {
+                base_cwd: &base_cwd,
+                hook_events_path: &config.hooks.events_path,
+            };
+            let dir = snapshot::create(&manager, &paths, session_name, conversation_id)?;
+            println!("snapshot written: {}", dir.display());
+        }
+        (Some("restore"), Some(dir)) => {
+            let settings_template =
+                std::fs::read_to_string(base_cwd.join(".claude/settings.json")).ok();
+            let opts = snapshot::RestoreOptions {
+                base_cwd: &base_cwd,
+                settings_template: settings_template.as_deref(),
+                target_cwd,
+                resume,
+                session_prefix: &config.tmux.session_prefix,
+            };
+            match snapshot::restore(&manager, &PathBuf::from(dir), &opts)? {
+                Some(session_name) => println!("restored into session {session_name}"),
+                None => println!("snapshot restored (no session started; pass --resume)"),
+            }
+        }
+        _ => {
+            print_snapshot_usage();
+        }
+    }
+    Ok(())
+}
+
+/// `--config`, `--profile` and `--set`, shared by subcommands that read the config.
+#[derive(Default)]
+struct ConfigArgs {
+    path: Option<PathBuf>,
+    layers: ConfigLayers,
+}
+
+impl ConfigArgs {
+    /// Consume a config option at `args[*i]`; returns false for other arguments.
+    fn parse(&mut self, args: &[String], i: &mut usize) -> Result<bool> {
+        let flag = args[*i].as_str();
+        if !matches!(flag, "--config" | "--profile" | "--set") {
+            return Ok(false);
+        }
+        let value = args
+            .get(*i + 1)
+            .with_context(|| format!("{flag} requires a value"))?;
+        match flag {
+            "--config" => self.path = Some(PathBuf::from(value)),
+            "--profile" => self.layers.profile = Some(value.to_string()),
+            _ => self.layers.overrides.push(value.to_string()),
+        }
+        *i += 2;
+        Ok(true)
+    }
+
+    fn load(&self) -> Result<Config> {
+        let path = self.path.clone().unwrap_or_else(paths::default_config_path);
+        Config::load(&path, &self.layers)
+    }
+}
+
 fn print_usage() {
-    eprintln!("ccterm usage:\n  ccterm run [options]\n  ccterm cli [options]\n  ccterm serve [options]\n  ccterm hook --out <path>");
+    eprintln!("ccterm usage:\n  ccterm run [options]\n  ccterm cli [options]\n  ccterm serve [options]\n  ccterm doctor [options]\n  ccterm ctl [config options] <command>\n  ccterm snapshot create|restore [options]\n  ccterm audit export [options]\n  ccterm sessions list|kill|attach [options]\n  ccterm hook [--socket <path>] [--out <path>]");
 }
 
 fn print_run_usage() {
@@ -447,14 +1174,47 @@ fn print_run_usage() {
 
 fn print_cli_usage() {
     eprintln!(
-        "ccterm cli options:\n  --timeout <secs>\n  --prefix <session-prefix>\n  --claude-cmd <command>\n  --hook-path <path>\n  --cwd <path>\n  --keep-session\n  --accept-trust\n  --startup-wait-ms <ms>\n  --post-trust-wait-ms <ms>\n  --wait-prompt | --no-wait-prompt\n  --prompt-timeout-ms <ms>\n\ninput format:\n  thread:<id> <text>\n  <text>"
+        "ccterm cli options:\n  --timeout <secs>\n  --prefix <session-prefix>\n  --claude-cmd <command>\n  --hook-path <path>\n  --cwd <path>\n  --keep-session\n  --accept-trust\n  --startup-wait-ms <ms>\n  --post-trust-wait-ms <ms>\n  --wait-prompt | --no-wait-prompt\n  --prompt-timeout-ms <ms>\n  --offline\n  --retry-interval-ms <ms>\n\ninput format:\n  thread:<id> <text>\n  <text>"
     );
 }
 
 fn print_hook_usage() {
-    eprintln!("ccterm hook --out <path>");
+    eprintln!(
+        "ccterm hook [--socket <path>] [--out <path>] [--secret-file <path>] \
+         [--stdin-passthrough]"
+    );
+}
+
+fn print_doctor_usage() {
+    eprintln!(
+        "ccterm doctor options:\n  --probe\n  --self-test\n  --self-test-timeout-secs <secs>\n  --prefix <session-prefix>\n  --claude-cmd <command>\n  --cwd <path>\n  --probe-timeout-ms <ms>\n{CONFIG_OPTIONS_USAGE}"
+    );
+}
+
+fn print_ctl_usage() {
+    eprintln!(
+        "ccterm ctl options:\n{CONFIG_OPTIONS_USAGE}\n\ncommands:\n  broadcast <text>\n  maintenance on [message] | off"
+    );
+}
+
+fn print_snapshot_usage() {
+    eprintln!(
+        "ccterm snapshot usage:\n  ccterm snapshot create <session-name> [config options] [--conversation <id>]\n  ccterm snapshot restore <snapshot-dir> [config options] [--cwd <path>] [--resume]\n\nconfig options:\n{CONFIG_OPTIONS_USAGE}"
+    );
+}
+
+fn print_audit_usage() {
+    eprintln!(
+        "ccterm audit usage:\n  ccterm audit export [config options] [--since <when>] [--format csv|jsonl]\n\n--since takes an RFC 3339 timestamp, YYYY-MM-DD, or an age such as 7d or 12h.\n\nconfig options:\n{CONFIG_OPTIONS_USAGE}"
+    );
+}
+
+fn print_sessions_usage() {
+    eprintln!(
+        "ccterm sessions usage:\n  ccterm sessions list [config options]\n  ccterm sessions kill <session-name> [config options]\n  ccterm sessions attach <session-name> [config options]\n\nconfig options:\n{CONFIG_OPTIONS_USAGE}"
+    );
 }
 
 fn print_serve_usage() {
-    eprintln!("ccterm serve options:\n  --config <path>");
+    eprintln!("ccterm serve options:\n{CONFIG_OPTIONS_USAGE}\n  --dry-run\n  --log-level <filter>");
 }
@@ -0,0 +1,410 @@
+use crate::outgoing::split_text;
+use crate::types::ReplyBlock;
+
+/// Slack rejects section blocks with more than 3000 characters of text.
+const SECTION_MAX_CHARS: usize = 3000;
+/// Header blocks take at most 150 characters of plain text.
+const HEADER_MAX_CHARS: usize = 150;
+/// Bullets of nested list levels, repeated for deeper ones.
+const BULLETS: [&str; 3] = ["•", "◦", "▪"];
+const RULE: &str = "──────────";
+
+/// A converted source line.
+enum Line {
+    /// Heading text without markers, for a header block or a bold line.
+    Heading(String),
+    Rule,
+    Text(String),
+}
+
+/// Convert Markdown to mrkdwn: headings become bold lines, `**bold**` `*bold*`,
+/// `*italic*` `_italic_`, links `<url|text>`, lists get bullets, and tables are laid out
+/// in a code block. Code is kept as is, without the fence's language.
+pub fn to_mrkdwn(markdown: &str) -> String {
+    convert(markdown)
+        .into_iter()
+        .map(|line| match line {
+            Line::Heading(text) => format!("*{}*", escape(&text)),
+            Line::Rule => RULE.to_string(),
+            Line::Text(text) => text,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Convert Markdown to header, divider and mrkdwn section blocks.
+pub fn to_blocks(markdown: &str) -> Vec<ReplyBlock> {
+    let mut blocks = Vec::new();
+    let mut text: Vec<String> = Vec::new();
+    for line in convert(markdown) {
+        match line {
+            Line::Text(line) => {
+                text.push(line);
+                continue;
+            }
+            Line::Heading(heading) => {
+                push_sections(&mut blocks, &text);
+                let heading: String = heading.chars().take(HEADER_MAX_CHARS).collect();
+                blocks.push(ReplyBlock::Header(heading));
+            }
+            Line::Rule => {
+                push_sections(&mut blocks, &text);
+                blocks.push(ReplyBlock::Divider);
+            }
+        }
+        text.clear();
+    }
+    push_sections(&mut blocks, &text);
+    blocks
+}
+
+fn push_sections(blocks: &mut Vec<ReplyBlock>, lines: &[String]) {
+    let text = lines.join("\n");
+    let text = text.trim_matches('\n');
+    if text.trim().is_empty() {
+        return;
+    }
+    for chunk in split_text(text, SECTION_MAX_CHARS) {
+        blocks.push(ReplyBlock::Section(chunk));
+    }
+}
+
+fn convert(markdown: &str) -> Vec<Line> {
+    let source: Vec<&str> = markdown.lines().collect();
+    let mut lines = Vec::new();
+    let mut in_fence = false;
+    let mut i = 0;
+    while i < source.len() {
+        let line = source[i];
+        i += 1;
+        if line.trim_start().starts_with("```") {
+            // Slack shows the language as the first line of the code.
+            in_fence = !in_fence;
+            lines.push(Line::Text("```".to_string()));
+            continue;
+        }
+        if in_fence {
+            lines.push(Line::Text(escape_code(line)));
+            continue;
+        }
+        if let Some(rows) = table_rows(&source[i - 1..]) {
+            lines.extend(format_table(&source[i - 1..i - 1 + rows]).into_iter().map(Line::Text));
+            i += rows - 1;
+            continue;
+        }
+        let trimmed = line.trim();
+        if let Some(heading) = heading(trimmed) {
+            lines.push(Line::Heading(plain(heading)));
+        } else if is_rule(trimmed) {
+            lines.push(Line::Rule);
+        } else {
+            lines.push(Line::Text(block_line(line)));
+        }
+    }
+    lines
+}
+
+fn heading(line: &str) -> Option<&str> {
+    let text = line.trim_start_matches('#');
+    let level = line.len() - text.len();
+    if (1..=6).contains(&level) && (text.is_empty() || text.starts_with(' ')) {
+        Some(text.trim().trim_end_matches('#').trim_end())
+    } else {
+        None
+    }
+}
+
+fn is_rule(line: &str) -> bool {
+    let compact: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    compact.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|marker| compact.chars().all(|c| c == *marker))
+}
+
+/// A quote or list line, or a paragraph line, with its inline formatting converted.
+fn block_line(line: &str) -> String {
+    if let Some(quoted) = line.trim_start().strip_prefix('>') {
+        let quoted = quoted.trim_start_matches(['>', ' ']);
+        return format!("> {}", block_line(quoted));
+    }
+    let text = line.trim_start();
+    let width: usize = line[..line.len() - text.len()]
+        .chars()
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum();
+    let level = width / 2;
+    let indent = "    ".repeat(level);
+    if let Some(item) = ["- ", "* ", "+ "].iter().find_map(|m| text.strip_prefix(m)) {
+        let item = item.trim_start();
+        if let Some(rest) = item.strip_prefix("[ ] ") {
+            return format!("{indent}:white_large_square: {}", inline(rest));
+        }
+        if let Some(rest) = item.strip_prefix("[x] ").or_else(|| item.strip_prefix("[X] ")) {
+            return format!("{indent}:white_check_mark: {}", inline(rest));
+        }
+        return format!("{indent}{} {}", BULLETS[level % BULLETS.len()], inline(item));
+    }
+    let digits = text.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 {
+        let rest = &text[digits..];
+        if let Some(item) = rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") ")) {
+            return format!("{indent}{}. {}", &text[..digits], inline(item.trim_start()));
+        }
+    }
+    if level > 0 {
+        return format!("{indent}{}", inline(text));
+    }
+    inline(line)
+}
+
+/// Number of lines of the table starting at `lines[0]`: a header row, a delimiter row,
+/// and the body rows after them.
+fn table_rows(lines: &[&str]) -> Option<usize> {
+    let is_row = |line: &str| line.trim_start().starts_with('|');
+    let delimiter = lines.get(1)?.trim();
+    if !is_row(lines[0])
+        || !delimiter.contains('-')
+        || !delimiter.chars().all(|c| matches!(c, '|' | ':' | '-' | ' '))
+    {
+        return None;
+    }
+    Some(2 + lines[2..].iter().take_while(|line| is_row(line)).count())
+}
+
+/// Lay out a table in a code block, with padded columns and without inline markers.
+fn format_table(lines: &[&str]) -> Vec<String> {
+    let rows: Vec<Vec<String>> = lines
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != 1)
+        .map(|(_, line)| cells(line))
+        .collect();
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let render = |row: &[String]| {
+        let padded: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(column, width)| {
+                let cell = row.get(column).map(String::as_str).unwrap_or("");
+                format!("{cell}{}", " ".repeat(width - cell.chars().count()))
+            })
+            .collect();
+        escape_code(padded.join(" | ").trim_end())
+    };
+    let mut out = vec!["```".to_string()];
+    for (index, row) in rows.iter().enumerate() {
+        out.push(render(row));
+        if index == 0 {
+            let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+            out.push(rule.join("-+-"));
+        }
+    }
+    out.push("```".to_string());
+    out
+}
+
+fn cells(line: &str) -> Vec<String> {
+    let line = line.trim().replace("\\|", "\u{0}");
+    let line = line.strip_prefix('|').unwrap_or(&line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    line.split('|')
+        .map(|cell| plain(cell.trim()).replace('\u{0}', "|"))
+        .collect()
+}
+
+/// Text without emphasis and code markers, for plain-text contexts.
+fn plain(text: &str) -> String {
+    text.replace("**", "").replace("__", "").replace('`', "")
+}
+
+/// Convert emphasis, strikethrough and links within one line. Code spans are kept as
+/// they are.
+fn inline(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest = &chars[i..];
+        if c == '\\' && rest.get(1).is_some_and(|next| next.is_ascii_punctuation()) {
+            push_escaped(&mut out, rest[1]);
+            i += 2;
+            continue;
+        }
+        if c == '`' {
+            let ticks = rest.iter().take_while(|t| **t == '`').count();
+            if let Some(end) = find_run(&chars, i + ticks, '`', ticks) {
+                let span: String = chars[i..end + ticks].iter().collect();
+                out.push_str(&escape_code(&span));
+                i = end + ticks;
+                continue;
+            }
+            out.push_str(&"`".repeat(ticks));
+            i += ticks;
+            continue;
+        }
+        if let Some(len) = slack_token(rest) {
+            out.extend(&rest[..len]);
+            i += len;
+            continue;
+        }
+        if let Some((converted, len)) = link(rest) {
+            out.push_str(&converted);
+            i += len;
+            continue;
+        }
+        let pair = rest.len() > 1 && rest[1] == c;
+        if pair && matches!(c, '*' | '_' | '~') {
+            if let Some(end) = find_run(&chars, i + 2, c, 2)
+                && end > i + 2
+            {
+                let marker = if c == '~' { '~' } else { '*' };
+                let inner: String = chars[i + 2..end].iter().collect();
+                out.push(marker);
+                out.push_str(&inline(&inner));
+                out.push(marker);
+                i = end + 2;
+                continue;
+            }
+        } else if c == '*'
+            && rest.get(1).is_some_and(|next| !next.is_whitespace())
+            && let Some(end) = find_italic_end(&chars, i + 1)
+        {
+            let inner: String = chars[i + 1..end].iter().collect();
+            out.push('_');
+            out.push_str(&inline(&inner));
+            out.push('_');
+            i = end + 1;
+            continue;
+        }
+        push_escaped(&mut out, c);
+        i += 1;
+    }
+    out
+}
+
+/// Start of the next run of exactly `count` `marker`s at or after `from`.
+fn find_run(chars: &[char], from: usize, marker: char, count: usize) -> Option<usize> {
+    let mut i = from;
+    while i < chars.len() {
+        if chars[i] != marker {
+            i += 1;
+            continue;
+        }
+        let run = chars[i..].iter().take_while(|c| **c == marker).count();
+        if run == count {
+            return Some(i);
+        }
+        i += run;
+    }
+    None
+}
+
+/// Closing `*` of an italic span: a single `*` after a non-space character.
+fn find_italic_end(chars: &[char], from: usize) -> Option<usize> {
+    (from + 1..chars.len()).find(|&i| {
+        chars[i] == '*'
+            && !chars[i - 1].is_whitespace()
+            && chars.get(i + 1) != Some(&'*')
+            && chars[i - 1] != '*'
+    })
+}
+
+/// Length of Slack markup at the start of `chars`, which is kept as it is: a mention
+/// `<@U…>`, a channel `<#C…|name>`, a special mention `<!here>`, or a link `<url>` or
+/// `<url|text>`.
+fn slack_token(chars: &[char]) -> Option<usize> {
+    if chars.first() != Some(&'<') {
+        return None;
+    }
+    let end = chars.iter().position(|c| *c == '>')?;
+    let inner: String = chars[1..end].iter().collect();
+    let (target, label) = inner.split_once('|').unwrap_or((&inner, ""));
+    let id = |sigil: char, kinds: &[char]| {
+        target.strip_prefix(sigil).is_some_and(|id| {
+            id.starts_with(kinds) && id.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+    };
+    let special = target
+        .strip_prefix('!')
+        .is_some_and(|name| !name.is_empty() && !name.contains(char::is_whitespace));
+    let url = ["https://", "http://", "mailto:"]
+        .iter()
+        .any(|scheme| target.starts_with(scheme))
+        && !target.contains(char::is_whitespace);
+    let known = id('@', &['U', 'W']) || id('#', &['C', 'G']) || special || url;
+    (known && !label.contains('<')).then_some(end + 1)
+}
+
+/// `[text](url)` or `![alt](url)` at the start of `chars`, as a Slack link and the number
+/// of characters it took.
+fn link(chars: &[char]) -> Option<(String, usize)> {
+    let start = usize::from(chars[0] == '!');
+    if chars.get(start) != Some(&'[') {
+        return None;
+    }
+    let close = start + chars[start..].iter().position(|c| *c == ']')?;
+    if chars.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let end = close + 1 + chars[close + 1..].iter().position(|c| *c == ')')?;
+    let label: String = chars[start + 1..close].iter().collect();
+    let url: String = chars[close + 2..end].iter().collect();
+    let url = url.split_whitespace().next()?.trim_matches(['<', '>']);
+    if url.contains('|') {
+        return None;
+    }
+    let label = escape(&plain(&label)).replace('|', "¦");
+    let converted = if label.is_empty() {
+        format!("<{url}>")
+    } else {
+        format!("<{url}|{label}>")
+    };
+    Some((converted, end + 1))
+}
+
+/// Escape prose, keeping Slack's own markup (`slack_token`) as it is.
+fn escape(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some(len) = slack_token(&chars[i..]) {
+            out.extend(&chars[i..i + len]);
+            i += len;
+            continue;
+        }
+        push_escaped(&mut out, chars[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Escape code, where Slack markup is shown as typed.
+fn escape_code(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        push_escaped(&mut out, c);
+    }
+    out
+}
+
+/// Slack reads `&`, `<` and `>` as control characters in message text.
+fn push_escaped(out: &mut String, c: char) {
+    match c {
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        c => out.push(c),
+    }
+}
@@ -0,0 +1,241 @@
+use crate::config::SlackOAuthConfig;
+use crate::random;
+use crate::slack_adapter::BotTokens;
+use crate::state::StateStore;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use slack_morphism::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// Workspaces installed through OAuth, by team id, in the state store.
+pub const SLACK_INSTALLS_STATE: &str = "slack_installs";
+pub const INSTALL_PATH: &str = "/slack/install";
+pub const CALLBACK_PATH: &str = "/slack/oauth/callback";
+
+const AUTHORIZE_URL: &str = "https://slack.com/oauth/v2/authorize";
+/// How long an install link stays valid between `/slack/install` and the callback.
+const STATE_TTL: Duration = Duration::from_secs(600);
+const MAX_REQUEST_BYTES: usize = 8192;
+/// A client that does not finish its request head within this is disconnected.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Client = Arc<SlackClient<SlackClientHyperHttpsConnector>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlackInstall {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team_name: Option<String>,
+    pub bot_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bot_user_id: Option<String>,
+    pub scope: String,
+}
+
+struct OAuthServer {
+    cfg: SlackOAuthConfig,
+    client: Client,
+    tokens: BotTokens,
+    store: StateStore,
+    /// `state` values handed out by `/slack/install` and not used yet.
+    pending: Mutex<HashMap<String, Instant>>,
+}
+
+/// Register the bot tokens of previously installed workspaces and start the install
+/// and redirect handler on `cfg.listen`.
+pub async fn start(
+    cfg: SlackOAuthConfig,
+    client: Client,
+    tokens: BotTokens,
+    store: StateStore,
+) -> Result<()> {
+    let installs: BTreeMap<String, SlackInstall> = store.load(SLACK_INSTALLS_STATE)?;
+    if !installs.is_empty() {
+        // Rewritten owner-only, in case an older version saved it readable by others.
+        store.save_private(SLACK_INSTALLS_STATE, &installs)?;
+    }
+    for (team_id, install) in &installs {
+        tokens.insert(team_id, &install.bot_token).await;
+    }
+    let listener = TcpListener::bind(&cfg.listen)
+        .await
+        .with_context(|| format!("failed to listen for slack oauth on {}", cfg.listen))?;
+    info!(
+        "slack: oauth install at http://{}{INSTALL_PATH} ({} workspace(s) installed)",
+        cfg.listen,
+        installs.len()
+    );
+    let server = Arc::new(OAuthServer {
+        cfg,
+        client,
+        tokens,
+        store,
+        pending: Mutex::new(HashMap::new()),
+    });
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    warn!("slack oauth accept error: {err}");
+                    continue;
+                }
+            };
+            let server = server.clone();
+            tokio::spawn(async move {
+                if let Err(err) = server.handle(stream).await {
+                    warn!("slack oauth error: {err}");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+impl OAuthServer {
+    async fn handle(&self, mut stream: TcpStream) -> Result<()> {
+        let target = tokio::time::timeout(READ_TIMEOUT, read_request_target(&mut stream))
+            .await
+            .map_err(|_| anyhow!("timed out reading the oauth request"))??;
+        let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+        let response = match path {
+            INSTALL_PATH => {
+                let state = self.new_state().await;
+                let location = format!(
+                    "{AUTHORIZE_URL}?client_id={}&scope={}&state={state}",
+                    self.cfg.client_id,
+                    self.cfg.scopes.join(",")
+                );
+                format!("HTTP/1.1 302 Found\r\nLocation: {location}\r\nContent-Length: 0\r\n\r\n")
+            }
+            CALLBACK_PATH => match self.complete(query).await {
+                Ok(team) => text_response("200 OK", &format!("ccterm is installed in {team}.")),
+                Err(err) => {
+                    warn!("slack oauth install failed: {err:#}");
+                    text_response("400 Bad Request", &format!("Install failed: {err}"))
+                }
+            },
+            _ => text_response("404 Not Found", "Not found"),
+        };
+        stream
+            .write_all(response.as_bytes())
+            .await
+            .context("failed to write oauth response")?;
+        Ok(())
+    }
+
+    async fn new_state(&self) -> String {
+        let state = random::token(16);
+        let mut pending = self.pending.lock().await;
+        pending.retain(|_, issued| issued.elapsed() < STATE_TTL);
+        pending.insert(state.clone(), Instant::now());
+        state
+    }
+
+    /// Exchange the code for a bot token, store it, and return the workspace name.
+    async fn complete(&self, query: &str) -> Result<String> {
+        if let Some(error) = query_param(query, "error") {
+            bail!("slack returned {error}");
+        }
+        let state = query_param(query, "state").context("missing state")?;
+        let issued = self.pending.lock().await.remove(&state);
+        if issued.is_none_or(|issued| issued.elapsed() >= STATE_TTL) {
+            bail!("unknown or expired install link, start again from {INSTALL_PATH}");
+        }
+        let code = query_param(query, "code").context("missing code")?;
+        let resp = self
+            .client
+            .oauth2_access(&SlackOAuthV2AccessTokenRequest {
+                client_id: SlackClientId(self.cfg.client_id.clone()),
+                client_secret: SlackClientSecret(self.cfg.client_secret.clone()),
+                code: SlackOAuthCode(code),
+                redirect_uri: None,
+            })
+            .await
+            .context("failed to call oauth.v2.access")?;
+
+        let team_id = resp.team.id.to_string();
+        let install = SlackInstall {
+            team_name: resp.team.name,
+            bot_token: resp.access_token.0,
+            bot_user_id: resp.bot_user_id.map(|id| id.to_string()),
+            scope: resp.scope.0,
+        };
+        let mut installs: BTreeMap<String, SlackInstall> =
+            self.store.load(SLACK_INSTALLS_STATE)?;
+        installs.insert(team_id.clone(), install.clone());
+        self.store.save_private(SLACK_INSTALLS_STATE, &installs)?;
+        self.tokens.insert(&team_id, &install.bot_token).await;
+        info!("slack: installed in workspace {team_id}");
+        Ok(install.team_name.unwrap_or(team_id))
+    }
+}
+
+/// Read the request head and return the target of a GET request.
+async fn read_request_target(stream: &mut TcpStream) -> Result<String> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|window| window == b"\r\n\r\n") {
+        if buf.len() > MAX_REQUEST_BYTES {
+            bail!("request head too large");
+        }
+        let read = stream
+            .read(&mut chunk)
+            .await
+            .context("failed to read oauth request")?;
+        if read == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    }
+    let head = String::from_utf8_lossy(&buf);
+    let mut parts = head.lines().next().unwrap_or_default().split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => Ok(target.to_string()),
+        _ => bail!("unsupported request: {}", head.lines().next().unwrap_or_default()),
+    }
+}
+
+fn text_response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| percent_decode(value))
+    })
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
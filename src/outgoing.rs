@@ -0,0 +1,112 @@
+/// Slack truncates message text beyond this many characters.
+pub const MESSAGE_MAX_CHARS: usize = 4000;
+
+/// Room kept in a chunk for closing and reopening a code fence split across chunks.
+const FENCE_RESERVE: usize = 32;
+
+/// Split `text` into chunks of at most `max_chars` characters, at paragraph boundaries
+/// where possible. A code block is kept in one chunk when it fits; otherwise it is split
+/// at line boundaries and each part is closed and reopened with its fence.
+pub fn split_text(text: &str, max_chars: usize) -> Vec<String> {
+    if text.chars().count() <= max_chars {
+        return vec![text.to_string()];
+    }
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for block in blocks(text) {
+        let len = current.chars().count() + 2 + block.chars().count();
+        if current.is_empty() || len > max_chars {
+            if !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+            }
+            if block.chars().count() > max_chars {
+                split_block(&block, max_chars, &mut chunks);
+                continue;
+            }
+        } else {
+            current.push_str("\n\n");
+        }
+        current.push_str(&block);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Paragraphs separated by blank lines. Blank lines inside a code fence do not end one.
+fn blocks(text: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        if is_fence(line) {
+            in_fence = !in_fence;
+        }
+        if line.trim().is_empty() && !in_fence {
+            if !current.is_empty() {
+                blocks.push(current.join("\n"));
+                current.clear();
+            }
+            continue;
+        }
+        current.push(line);
+    }
+    if !current.is_empty() {
+        blocks.push(current.join("\n"));
+    }
+    blocks
+}
+
+/// Split a block longer than `max_chars` at line boundaries, and lines longer than a
+/// chunk at character boundaries.
+fn split_block(block: &str, max_chars: usize, chunks: &mut Vec<String>) {
+    let piece_chars = max_chars.saturating_sub(FENCE_RESERVE).max(1);
+    let mut current = String::new();
+    // Opening line of the code fence the current line is in.
+    let mut fence: Option<String> = None;
+    for line in block.lines() {
+        for piece in pieces(line, piece_chars) {
+            let closing = if fence.is_some() { 4 } else { 0 };
+            let len = current.chars().count() + 1 + piece.chars().count() + closing;
+            if !current.is_empty() && len > max_chars {
+                if fence.is_some() {
+                    current.push_str("\n```");
+                }
+                chunks.push(std::mem::take(&mut current));
+                if let Some(open) = &fence {
+                    current.push_str(open);
+                }
+            }
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.push_str(&piece);
+        }
+        if is_fence(line) {
+            fence = match fence {
+                Some(_) => None,
+                // Only the backticks and language are repeated, to stay within the reserve.
+                None => Some(line.trim().chars().take(FENCE_RESERVE / 2).collect()),
+            };
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+}
+
+fn pieces(line: &str, max_chars: usize) -> Vec<String> {
+    if line.chars().count() <= max_chars {
+        return vec![line.to_string()];
+    }
+    let chars: Vec<char> = line.chars().collect();
+    chars
+        .chunks(max_chars)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+fn is_fence(line: &str) -> bool {
+    line.trim_start().starts_with("```")
+}
@@ -0,0 +1,105 @@
+use std::fs::{File, OpenOptions, Permissions};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Config file looked up in the working directory before the XDG location.
+pub const LOCAL_CONFIG_FILE: &str = "ccterm.toml";
+
+/// Extensions tried for default config files, in order.
+const CONFIG_EXTENSIONS: [&str; 4] = ["toml", "yaml", "yml", "json"];
+
+/// Legacy in-project location of coordinator state, still read as a fallback.
+pub const LEGACY_STATE_DIR: &str = ".ccterm";
+
+/// `./ccterm.<ext>` when it exists, otherwise `$XDG_CONFIG_HOME/ccterm/config.<ext>`
+/// (`~/.config/ccterm/...`), trying `CONFIG_EXTENSIONS` in order. Falls back to the
+/// TOML name of the last location so the error names a sensible path.
+pub fn default_config_path() -> PathBuf {
+    let mut candidates = vec![PathBuf::from(LOCAL_CONFIG_FILE)];
+    if let Some(dir) = xdg_dir("XDG_CONFIG_HOME", ".config") {
+        candidates.push(dir.join("ccterm/config.toml"));
+    }
+    for candidate in &candidates {
+        for ext in CONFIG_EXTENSIONS {
+            let path = candidate.with_extension(ext);
+            if path.exists() {
+                return path;
+            }
+        }
+    }
+    candidates.pop().expect("local candidate is always present")
+}
+
+/// Per-project state directory: `$XDG_STATE_HOME/ccterm/<base cwd>` (with
+/// `~/.local/state` as the fallback), or `<base cwd>/.ccterm` when no home is known.
+/// The base cwd is encoded like Claude's project directories, so every subcommand
+/// that loads the same config finds the same directory.
+pub fn state_dir(base_cwd: &Path) -> PathBuf {
+    let base_cwd = base_cwd
+        .canonicalize()
+        .unwrap_or_else(|_| base_cwd.to_path_buf());
+    let Some(root) = xdg_dir("XDG_STATE_HOME", ".local/state") else {
+        return base_cwd.join(LEGACY_STATE_DIR);
+    };
+    root.join("ccterm").join(encode_path(&base_cwd))
+}
+
+/// `path` with non-alphanumerics replaced by `-`, like Claude's project directories.
+pub fn encode_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// Write `contents` to a temporary file next to `path`, sync it, and rename it over
+/// `path`, so a crash leaves the old file or the new one but never a partial write.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+    let result = File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(contents.as_ref())?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// `write_atomic` for secrets: the temporary file is created owner-only (0600) before
+/// anything is written to it, so the contents are never readable by others.
+pub fn write_private(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+    let result = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&tmp)
+        .and_then(|mut file| {
+            file.set_permissions(Permissions::from_mode(0o600))?;
+            file.write_all(contents.as_ref())?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Absolute `$<var>`, else `$HOME/<fallback>`.
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os(var).map(PathBuf::from)
+        && dir.is_absolute()
+    {
+        return Some(dir);
+    }
+    let home = std::env::var_os("HOME").filter(|home| !home.is_empty())?;
+    Some(PathBuf::from(home).join(fallback))
+}
@@ -0,0 +1,55 @@
+/// A link to a Slack message, as copied with "Copy link".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Permalink {
+    pub channel: String,
+    pub ts: String,
+    /// Parent of the thread the message is a reply in.
+    pub thread_ts: Option<String>,
+}
+
+const ARCHIVES: &str = ".slack.com/archives/";
+
+/// Message permalinks in `text`, in order and without duplicates. Links arrive wrapped
+/// as `<url>` or `<url|label>`, with `&` escaped as `&amp;`.
+pub fn find(text: &str) -> Vec<Permalink> {
+    let mut found: Vec<Permalink> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(ARCHIVES) {
+        rest = &rest[start + ARCHIVES.len()..];
+        let end = rest
+            .find(|c: char| c == '>' || c == '|' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        if let Some(link) = parse_path(&rest[..end])
+            && !found.contains(&link)
+        {
+            found.push(link);
+        }
+        rest = &rest[end..];
+    }
+    found
+}
+
+/// `C0123/p1700000000123456?thread_ts=1699999999.000100&amp;cid=C0123`
+fn parse_path(path: &str) -> Option<Permalink> {
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    let (channel, message) = path.split_once('/')?;
+    if channel.is_empty() || !channel.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let digits = message.trim_end_matches('/').strip_prefix('p')?;
+    if digits.len() <= 6 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (seconds, micros) = digits.split_at(digits.len() - 6);
+    let thread_ts = query
+        .split('&')
+        .filter_map(|pair| pair.strip_prefix("amp;").unwrap_or(pair).split_once('='))
+        .find(|(key, _)| *key == "thread_ts")
+        .map(|(_, value)| value.to_string())
+        .filter(|value| value.chars().all(|c| c.is_ascii_digit() || c == '.'));
+    Some(Permalink {
+        channel: channel.to_string(),
+        ts: format!("{seconds}.{micros}"),
+        thread_ts,
+    })
+}
@@ -0,0 +1,228 @@
+use crate::paths;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+/// Directory under the coordinator state dir holding one policy file per session cwd, so
+/// `ccterm hook` can answer PreToolUse events without reading the coordinator config.
+/// Kept out of the session cwd, where Claude could edit it.
+const POLICY_DIR: &str = "permissions";
+
+/// Set in the session env to the policy file `ccterm hook` evaluates. The session can read
+/// it but not change what its parent Claude process passes to hooks.
+pub const POLICY_ENV: &str = "CCTERM_PERMISSION_POLICY";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionAction {
+    Allow,
+    Deny,
+    Ask,
+}
+
+impl PermissionAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PermissionAction::Allow => "allow",
+            PermissionAction::Deny => "deny",
+            PermissionAction::Ask => "ask",
+        }
+    }
+
+    /// Whether `self` allows no more than `other`, ordering `allow` < `ask` < `deny`.
+    pub fn is_at_least_as_strict_as(self, other: PermissionAction) -> bool {
+        let rank = |action| match action {
+            PermissionAction::Allow => 0,
+            PermissionAction::Ask => 1,
+            PermissionAction::Deny => 2,
+        };
+        rank(self) >= rank(other)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PermissionRule {
+    /// Tool name as reported by Claude (`Bash`, `Edit`, ...), or `*` for any tool.
+    pub tool: String,
+    /// Regex matched against the tool's primary argument (command, file path, url),
+    /// or the JSON-encoded input for other tools. Matches everything when unset.
+    #[serde(default)]
+    pub pattern: Option<String>,
+    pub action: PermissionAction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PermissionPolicy {
+    #[serde(default = "default_action")]
+    pub default: PermissionAction,
+    #[serde(default)]
+    pub rules: Vec<PermissionRule>,
+}
+
+impl Default for PermissionPolicy {
+    fn default() -> Self {
+        Self {
+            default: default_action(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// A policy with its rule patterns compiled, as evaluated for each tool call.
+#[derive(Debug)]
+pub struct CompiledPolicy {
+    default: PermissionAction,
+    rules: Vec<(PermissionRule, Option<Regex>)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub action: PermissionAction,
+    pub reason: String,
+}
+
+impl PermissionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.default == PermissionAction::Ask
+    }
+
+    pub fn compile(&self) -> Result<CompiledPolicy> {
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| {
+                let re = rule
+                    .pattern
+                    .as_deref()
+                    .map(|pattern| {
+                        Regex::new(pattern)
+                            .with_context(|| format!("invalid permission pattern: {pattern}"))
+                    })
+                    .transpose()?;
+                Ok((rule.clone(), re))
+            })
+            .collect::<Result<_>>()?;
+        Ok(CompiledPolicy {
+            default: self.default,
+            rules,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Option<CompiledPolicy>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read permission policy: {}", path.display()))?;
+        let policy: Self = serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse permission policy: {}", path.display()))?;
+        policy.compile().map(Some)
+    }
+
+    /// Write the policy to `path`, or remove a stale one when there is nothing to enforce.
+    pub fn install(&self, path: &Path) -> Result<()> {
+        if self.is_empty() {
+            if path.exists() {
+                std::fs::remove_file(path).with_context(|| {
+                    format!("failed to remove permission policy: {}", path.display())
+                })?;
+            }
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create dir: {}", parent.display()))?;
+        }
+        let mut out =
+            serde_json::to_string_pretty(self).context("failed to render permission policy")?;
+        out.push('\n');
+        paths::write_atomic(path, out)
+            .with_context(|| format!("failed to write permission policy: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+impl CompiledPolicy {
+    pub fn evaluate(&self, tool_name: &str, tool_input: &Value) -> Decision {
+        let argument = primary_argument(tool_name, tool_input);
+        for (idx, (rule, re)) in self.rules.iter().enumerate() {
+            if rule.tool != "*" && rule.tool != tool_name {
+                continue;
+            }
+            if let Some(re) = re
+                && !re.is_match(&argument)
+            {
+                continue;
+            }
+            return Decision {
+                action: rule.action,
+                reason: format!("ccterm permission rule #{} ({})", idx + 1, rule.tool),
+            };
+        }
+        Decision {
+            action: self.default,
+            reason: "ccterm permission default".to_string(),
+        }
+    }
+}
+
+/// Policy file for the session running in `cwd`, under the coordinator state dir.
+pub fn policy_path(state_dir: &Path, cwd: &Path) -> PathBuf {
+    state_dir
+        .join(POLICY_DIR)
+        .join(format!("{}.json", paths::encode_path(cwd)))
+}
+
+/// Evaluate a raw PreToolUse payload against the policy at `policy_path`.
+/// Returns `None` for other events or when no policy is installed.
+pub fn decide_for_payload(payload: &Value, policy_path: &Path) -> Result<Option<Decision>> {
+    if payload.get("hook_event_name").and_then(Value::as_str) != Some("PreToolUse") {
+        return Ok(None);
+    }
+    let Some(policy) = PermissionPolicy::load(policy_path)? else {
+        return Ok(None);
+    };
+    let tool_name = payload
+        .get("tool_name")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let tool_input = payload.get("tool_input").unwrap_or(&Value::Null);
+    Ok(Some(policy.evaluate(tool_name, tool_input)))
+}
+
+/// Hook stdout understood by Claude Code for PreToolUse. `ask` leaves the decision
+/// to Claude's own permission prompt.
+pub fn hook_output(decision: &Decision) -> Option<Value> {
+    if decision.action == PermissionAction::Ask {
+        return None;
+    }
+    Some(json!({
+        "hookSpecificOutput": {
+            "hookEventName": "PreToolUse",
+            "permissionDecision": decision.action.as_str(),
+            "permissionDecisionReason": decision.reason,
+        }
+    }))
+}
+
+pub fn primary_argument(tool_name: &str, tool_input: &Value) -> String {
+    let key = match tool_name {
+        "Bash" => "command",
+        "Read" | "Write" | "Edit" | "MultiEdit" | "NotebookEdit" => "file_path",
+        "WebFetch" => "url",
+        "Glob" | "Grep" => "pattern",
+        _ => "",
+    };
+    if let Some(value) = tool_input.get(key).and_then(Value::as_str) {
+        return value.to_string();
+    }
+    tool_input.to_string()
+}
+
+fn default_action() -> PermissionAction {
+    PermissionAction::Ask
+}
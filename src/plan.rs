@@ -0,0 +1,81 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Slack rejects section blocks with more than 3000 characters of text.
+const SECTION_MAX_CHARS: usize = 3000;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TodoItem {
+    pub content: String,
+    /// `pending`, `in_progress` or `completed`.
+    pub status: String,
+}
+
+/// Structured planning output of a tool use, taken from its `PreToolUse` input.
+#[derive(Debug, Clone)]
+pub enum PlanUpdate {
+    /// `ExitPlanMode`: the plan Claude asks to proceed with.
+    Plan(String),
+    /// `TodoWrite`: the whole TODO list after the update.
+    Todos(Vec<TodoItem>),
+}
+
+pub fn from_tool_use(tool_name: &str, input: &Value) -> Option<PlanUpdate> {
+    match tool_name {
+        "ExitPlanMode" => {
+            let plan = input.get("plan").and_then(Value::as_str)?;
+            Some(PlanUpdate::Plan(plan.to_string()))
+        }
+        "TodoWrite" => {
+            let todos = serde_json::from_value(input.get("todos")?.clone()).ok()?;
+            Some(PlanUpdate::Todos(todos))
+        }
+        _ => None,
+    }
+}
+
+/// One line per item with a status emoji, in Claude's order.
+pub fn format_todos(todos: &[TodoItem]) -> String {
+    let done = todos.iter().filter(|todo| todo.status == "completed").count();
+    let mut out = format!("*TODO* ({done}/{})", todos.len());
+    for todo in todos {
+        let (icon, content) = match todo.status.as_str() {
+            "completed" => (":white_check_mark:", format!("~{}~", todo.content.trim())),
+            "in_progress" => (":arrow_forward:", format!("*{}*", todo.content.trim())),
+            _ => (":white_large_square:", todo.content.trim().to_string()),
+        };
+        out.push_str(&format!("\n{icon} {content}"));
+    }
+    out
+}
+
+/// Convert the plan's Markdown headings and bold text to mrkdwn and split it into
+/// section-sized chunks at line boundaries.
+pub fn plan_sections(plan: &str) -> Vec<String> {
+    let mut sections = Vec::new();
+    let mut current = String::new();
+    for line in plan.lines() {
+        let line = to_mrkdwn(line);
+        let len = current.chars().count() + line.chars().count();
+        if !current.is_empty() && len >= SECTION_MAX_CHARS {
+            sections.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.extend(line.chars().take(SECTION_MAX_CHARS - 1));
+    }
+    if !current.trim().is_empty() {
+        sections.push(current);
+    }
+    sections
+}
+
+fn to_mrkdwn(line: &str) -> String {
+    let trimmed = line.trim_start();
+    let heading = trimmed.trim_start_matches('#');
+    if heading.len() < trimmed.len() && heading.starts_with(' ') {
+        return format!("*{}*", heading.trim().replace("**", ""));
+    }
+    line.replace("**", "*")
+}
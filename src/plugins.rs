@@ -0,0 +1,106 @@
+use crate::commands;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// An external executable registered as a `!<name>` chat command.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    pub name: String,
+    pub command: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default = "default_plugin_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Written to the plugin's stdin as one JSON object.
+#[derive(Debug, Serialize)]
+pub struct PluginRequest<'a> {
+    pub command: &'a str,
+    /// Text after the command name, trimmed.
+    pub args: &'a str,
+    pub conversation_id: &'a str,
+    pub thread_id: Option<&'a str>,
+    pub user_id: Option<&'a str>,
+    pub cwd: &'a Path,
+}
+
+/// Expected on the plugin's stdout.
+#[derive(Debug, Deserialize)]
+pub struct PluginResponse {
+    pub text: String,
+}
+
+/// The plugin a message invokes and its arguments. Built-in commands are never
+/// matched here, so a message reaching this is not one of them.
+pub fn find<'a>(plugins: &'a [PluginConfig], text: &str) -> Option<(&'a PluginConfig, String)> {
+    let (name, args) = commands::split(text)?;
+    let plugin = plugins.iter().find(|plugin| plugin.name == name)?;
+    Some((plugin, args.to_string()))
+}
+
+pub fn validate(plugins: &[PluginConfig]) -> Result<()> {
+    for (idx, plugin) in plugins.iter().enumerate() {
+        let name = plugin.name.as_str();
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            bail!("plugins[{idx}].name must be letters, digits, '-' or '_' (got `{name}`)");
+        }
+        if commands::BUILTIN_COMMANDS.contains(&name) {
+            bail!("plugins[{idx}].name `{name}` is a built-in command");
+        }
+        if plugins[..idx].iter().any(|other| other.name == name) {
+            bail!("plugins[{idx}].name `{name}` is defined twice");
+        }
+    }
+    Ok(())
+}
+
+/// Run the plugin with the request on stdin, killing it after `timeout_secs`.
+pub async fn run(plugin: &PluginConfig, request: &PluginRequest<'_>) -> Result<String> {
+    let name = &plugin.name;
+    let mut input = serde_json::to_vec(request).context("failed to encode plugin request")?;
+    input.push(b'\n');
+    let mut child = Command::new(&plugin.command)
+        .args(&plugin.args)
+        .current_dir(request.cwd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("failed to run plugin `{name}`"))?;
+    let mut stdin = child.stdin.take().context("plugin stdin unavailable")?;
+    let timeout = Duration::from_secs(plugin.timeout_secs);
+    let output = tokio::time::timeout(timeout, async {
+        stdin.write_all(&input).await?;
+        drop(stdin);
+        child.wait_with_output().await
+    })
+    .await
+    .with_context(|| format!("plugin `{name}` timed out after {}s", plugin.timeout_secs))?
+    .with_context(|| format!("failed to wait for plugin `{name}`"))?;
+    if !output.status.success() {
+        bail!(
+            "plugin `{name}` failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let response: PluginResponse = serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("plugin `{name}` did not print a JSON response"))?;
+    Ok(response.text)
+}
+
+fn default_plugin_timeout_secs() -> u64 {
+    300
+}
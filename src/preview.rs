@@ -0,0 +1,45 @@
+use crate::context::{self, BashResult};
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// URLs in preview output, for `format_result`.
+static URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"https?://[^\s<>`'\x22]+").expect("valid url regex"));
+
+/// Run the preview command with `sh -c` in `cwd`, killing it after `timeout`.
+pub async fn run(cwd: &Path, command: &str, timeout: Duration) -> Result<BashResult> {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(cwd)
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(timeout, child)
+        .await
+        .with_context(|| format!("preview command timed out after {}s", timeout.as_secs()))?
+        .context("failed to run preview command")?;
+    Ok(BashResult {
+        command: command.to_string(),
+        output: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        exit_code: output.status.code().unwrap_or(-1),
+    })
+}
+
+/// Reply text for a finished preview: the last URL printed, if any, above the output.
+pub fn format_result(result: &BashResult) -> String {
+    let url = URL
+        .find_iter(&result.output)
+        .chain(URL.find_iter(&result.stderr))
+        .last()
+        .map(|m| m.as_str().trim_end_matches(['.', ',', ')']));
+    let body = context::format_bash_result(result);
+    match url {
+        Some(url) if result.exit_code == 0 => format!("Preview: {url}\n{body}"),
+        _ => body,
+    }
+}
@@ -0,0 +1,62 @@
+use crate::config::QuietHoursConfig;
+use anyhow::{bail, Context, Result};
+use jiff::civil::Time;
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, Zoned};
+
+/// When `now` falls inside the quiet window, the local time it ends.
+pub fn active_until(cfg: &QuietHoursConfig, now: Timestamp) -> Result<Option<Zoned>> {
+    let (start, end) = window(cfg)?;
+    let local = now.to_zoned(time_zone(cfg)?);
+    let time = local.time();
+    let inside = if start <= end {
+        time >= start && time < end
+    } else {
+        time >= start || time < end
+    };
+    if !inside {
+        return Ok(None);
+    }
+    // A window spanning midnight that started today ends tomorrow.
+    let mut date = local.date();
+    if time >= end {
+        date = date.tomorrow().context("quiet hours end out of range")?;
+    }
+    let until = date
+        .to_datetime(end)
+        .to_zoned(local.time_zone().clone())
+        .context("failed to resolve quiet hours end")?;
+    Ok(Some(until))
+}
+
+/// Check times and the time zone at config load.
+pub fn validate(cfg: &QuietHoursConfig) -> Result<()> {
+    window(cfg)?;
+    time_zone(cfg)?;
+    Ok(())
+}
+
+fn window(cfg: &QuietHoursConfig) -> Result<(Time, Time)> {
+    let start = parse_time(&cfg.start)?;
+    let end = parse_time(&cfg.end)?;
+    if start == end {
+        bail!("quiet_hours.start and quiet_hours.end must differ");
+    }
+    Ok((start, end))
+}
+
+fn parse_time(value: &str) -> Result<Time> {
+    value
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid quiet hours time (expected HH:MM): {value}"))
+}
+
+fn time_zone(cfg: &QuietHoursConfig) -> Result<TimeZone> {
+    match &cfg.timezone {
+        Some(name) => {
+            TimeZone::get(name).with_context(|| format!("unknown quiet hours timezone: {name}"))
+        }
+        None => Ok(TimeZone::system()),
+    }
+}
@@ -0,0 +1,14 @@
+/// `bytes` bytes from the operating system's CSPRNG, hex encoded. For ids and tokens
+/// that must not be guessed, such as approval ids and OAuth state.
+pub fn token(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    getrandom::fill(&mut buf).expect("the OS random source is available");
+    hex::encode(buf)
+}
+
+/// Uniformly distributed in `[0, 1)`, from the same source as `token`.
+pub fn unit() -> f64 {
+    let mut buf = [0u8; 8];
+    getrandom::fill(&mut buf).expect("the OS random source is available");
+    (u64::from_le_bytes(buf) >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Clock ticks per second in `/proc/<pid>/stat`. `USER_HZ` is 100 on every Linux
+/// architecture ccterm runs on.
+const TICKS_PER_SEC: f64 = 100.0;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessUsage {
+    pub rss_bytes: u64,
+    /// User plus system time in clock ticks, summed over the tree.
+    pub cpu_ticks: u64,
+    pub processes: usize,
+}
+
+impl ProcessUsage {
+    /// CPU use between two samples of the same tree, in percent of one core.
+    pub fn cpu_percent_since(&self, earlier: &ProcessUsage, elapsed_secs: f64) -> f64 {
+        if elapsed_secs <= 0.0 {
+            return 0.0;
+        }
+        let ticks = self.cpu_ticks.saturating_sub(earlier.cpu_ticks) as f64;
+        ticks / TICKS_PER_SEC / elapsed_secs * 100.0
+    }
+}
+
+struct ProcStat {
+    ppid: u32,
+    cpu_ticks: u64,
+}
+
+/// One read of `/proc`, used to sum usage over the process tree of each session.
+pub struct ProcessTable {
+    stats: HashMap<u32, ProcStat>,
+    children: HashMap<u32, Vec<u32>>,
+}
+
+impl ProcessTable {
+    pub fn read() -> Result<Self> {
+        let mut stats = HashMap::new();
+        let entries = std::fs::read_dir("/proc").context("failed to read /proc")?;
+        for entry in entries.flatten() {
+            let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse().ok()) else {
+                continue;
+            };
+            // Processes can exit between listing and reading.
+            let Ok(content) = std::fs::read_to_string(entry.path().join("stat")) else {
+                continue;
+            };
+            if let Some(stat) = parse_stat(&content) {
+                stats.insert(pid, stat);
+            }
+        }
+        let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (pid, stat) in &stats {
+            children.entry(stat.ppid).or_default().push(*pid);
+        }
+        Ok(Self { stats, children })
+    }
+
+    /// Usage of `root` and all of its descendants.
+    pub fn usage(&self, root: u32) -> Option<ProcessUsage> {
+        let root_stat = self.stats.get(&root)?;
+        let mut usage = ProcessUsage {
+            rss_bytes: rss_bytes(root),
+            cpu_ticks: root_stat.cpu_ticks,
+            processes: 1,
+        };
+        let mut pending = vec![root];
+        while let Some(parent) = pending.pop() {
+            for pid in self.children.get(&parent).into_iter().flatten() {
+                let Some(stat) = self.stats.get(pid) else {
+                    continue;
+                };
+                usage.rss_bytes += rss_bytes(*pid);
+                usage.cpu_ticks += stat.cpu_ticks;
+                usage.processes += 1;
+                pending.push(*pid);
+            }
+        }
+        Some(usage)
+    }
+}
+
+/// `/proc/<pid>/stat` fields after the parenthesized command name, which may itself
+/// contain spaces and parentheses.
+fn parse_stat(content: &str) -> Option<ProcStat> {
+    let rest = &content[content.rfind(')')? + 1..];
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    // Field 4 (ppid) and fields 14-15 (utime, stime), counted from 1 with pid and comm.
+    let ppid = fields.get(1)?.parse().ok()?;
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(ProcStat {
+        ppid,
+        cpu_ticks: utime + stime,
+    })
+}
+
+fn rss_bytes(pid: u32) -> u64 {
+    let path = Path::new("/proc").join(pid.to_string()).join("status");
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return 0;
+    };
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map(|kib| kib * 1024)
+        .unwrap_or(0)
+}
+
+/// Total size of the files under `dir`, without following symlinks. Entries that
+/// disappear or cannot be read while walking are skipped.
+pub fn dir_size(dir: &Path) -> u64 {
+    let mut total = 0;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                total += metadata.len();
+            }
+        }
+    }
+    total
+}
+
+/// Human-readable size, e.g. `1.2 GiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
@@ -0,0 +1,265 @@
+use crate::config::Config;
+use crate::coordinator::render_thread_settings;
+use crate::hooks::{self, HookEvent};
+use crate::sessions::{self, PromptProfile, SpawnOptions, TmuxSessionManager};
+use anyhow::{anyhow, Context};
+use serde_json::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+const SELF_TEST_MESSAGE: &str = "Reply with the single word: ok";
+
+/// Events file of the scratch directory when the configured one is absolute.
+const SCRATCH_EVENTS_PATH: &str = ".claude/hooks/events.jsonl";
+
+/// Step of the hook round trip, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Preparing the scratch directory and starting the tmux session.
+    Spawn,
+    /// Claude showing its input prompt and taking the message.
+    Prompt,
+    /// The `Stop` hook writing its payload to the socket or the events file.
+    HookWrite,
+    /// The payload passing the signature check and parsing as a hook event.
+    Parse,
+    /// The event matching the session the way the coordinator looks it up.
+    Routing,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Spawn => "spawn",
+            Stage::Prompt => "prompt",
+            Stage::HookWrite => "hook write",
+            Stage::Parse => "parse",
+            Stage::Routing => "routing",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Failure {
+    pub stage: Stage,
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "self-test failed at {}: {:#}", self.stage, self.error)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+pub struct SelfTestOptions {
+    pub claude_cmd: String,
+    pub prefix: String,
+    /// Directory whose `.claude/settings.json` session directories are rendered from.
+    pub base_cwd: PathBuf,
+    pub events_path: PathBuf,
+    pub secrets: Vec<String>,
+    pub profile: PromptProfile,
+    pub timeout: Duration,
+}
+
+impl SelfTestOptions {
+    pub fn from_config(config: &Config, profile: PromptProfile) -> Self {
+        Self {
+            claude_cmd: config.claude.command.clone(),
+            prefix: config.tmux.session_prefix.clone(),
+            base_cwd: config.claude.cwd.clone(),
+            events_path: config.hooks.events_path.clone(),
+            secrets: config.hooks.secrets.clone(),
+            profile,
+            timeout: Duration::from_secs(config.coordinator.self_test_timeout_secs),
+        }
+    }
+}
+
+/// Spawn a disposable session in a scratch directory set up like a thread directory,
+/// send a trivial prompt, and wait for its `Stop` hook to arrive the way the coordinator
+/// receives it. Returns how long the round trip took.
+pub fn run(opts: &SelfTestOptions) -> Result<Duration, Failure> {
+    let fail = |stage| move |error| Failure { stage, error };
+    let session_name = sessions::timestamp_session_name(&format!("{}-selftest", opts.prefix))
+        .map_err(fail(Stage::Spawn))?;
+    let scratch = std::env::temp_dir().join(&session_name);
+    let events_path = prepare_dir(opts, &scratch).map_err(fail(Stage::Spawn))?;
+    let (tx, rx) = mpsc::channel();
+    let receiver = hooks::spawn_line_receiver(events_path.clone(), move |line| {
+        let _ = tx.send(line.to_string());
+    });
+
+    let manager =
+        TmuxSessionManager::new(&opts.claude_cmd, &scratch).with_profile(opts.profile.clone());
+    let spawn = SpawnOptions::default();
+    let start = Instant::now();
+    let result = manager
+        .spawn_with(&session_name, &scratch, &spawn)
+        .with_context(|| format!("failed to spawn {session_name}"))
+        .map_err(fail(Stage::Spawn))
+        .and_then(|()| {
+            send_prompt(&manager, &session_name, &opts.profile, opts.timeout)
+                .map_err(fail(Stage::Prompt))
+        })
+        .and_then(|()| {
+            let remaining = opts.timeout.saturating_sub(start.elapsed());
+            let event = wait_for_stop(&rx, &opts.secrets, &events_path, remaining)?;
+            check_routing(&event, &scratch).map_err(fail(Stage::Routing))
+        })
+        .map(|()| start.elapsed());
+    if manager.has_session(&session_name)
+        && let Err(err) = manager.stop(&session_name)
+    {
+        warn!("self-test: failed to stop {session_name}: {err}");
+    }
+    receiver.shutdown();
+    let _ = std::fs::remove_dir_all(&scratch);
+    result
+}
+
+/// Create the scratch directory with rendered hook settings, and return its events path.
+fn prepare_dir(opts: &SelfTestOptions, scratch: &Path) -> anyhow::Result<PathBuf> {
+    let settings_path = opts.base_cwd.join(".claude/settings.json");
+    let template = std::fs::read_to_string(&settings_path).with_context(|| {
+        format!("failed to read base settings.json: {}", settings_path.display())
+    })?;
+    let settings: Value =
+        serde_json::from_str(&template).context("failed to parse base settings.json")?;
+    if settings.pointer("/hooks/Stop").is_none() {
+        return Err(anyhow!("{} registers no Stop hook", settings_path.display()));
+    }
+    // The copied hook commands sign with the coordinator's key file.
+    hooks::install_secret(&hooks::secret_path(&opts.base_cwd), &opts.secrets)?;
+    let exe = std::env::current_exe().context("failed to locate the ccterm binary")?;
+    let mut rendered = render_thread_settings(&template, &exe.to_string_lossy())?;
+    // An absolute events path is the coordinator's own; the test must not bind its socket
+    // or append to its file, so the hooks are pointed at scratch copies instead.
+    let events_path = if opts.events_path.is_absolute() {
+        let events_path = scratch.join(SCRATCH_EVENTS_PATH);
+        for (from, to) in [
+            (hooks::socket_path(&opts.events_path), hooks::socket_path(&events_path)),
+            (opts.events_path.clone(), events_path.clone()),
+        ] {
+            rendered = rendered.replace(&*from.to_string_lossy(), &to.to_string_lossy());
+        }
+        events_path
+    } else {
+        scratch.join(&opts.events_path)
+    };
+    let claude_dir = scratch.join(".claude");
+    std::fs::create_dir_all(&claude_dir)
+        .with_context(|| format!("failed to create {}", claude_dir.display()))?;
+    std::fs::write(claude_dir.join("settings.json"), rendered)
+        .context("failed to write scratch settings.json")?;
+    sessions::ensure_dir(&events_path)?;
+    Ok(events_path)
+}
+
+/// Wait for the input prompt, accepting the trust dialog of the new directory, and send
+/// the message.
+fn send_prompt(
+    manager: &TmuxSessionManager,
+    session_name: &str,
+    profile: &PromptProfile,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let mut trusted = false;
+    loop {
+        let pane = manager.capture_pane(session_name, 200)?;
+        if !trusted && profile.trust_markers.iter().any(|marker| pane.contains(marker.as_str())) {
+            info!("self-test: accepting the trust dialog");
+            manager.send(session_name, "1")?;
+            trusted = true;
+        } else if sessions::prompt_ready(&pane, profile) {
+            break;
+        }
+        if start.elapsed() > timeout {
+            return Err(anyhow!(
+                "no input prompt within {}s; pane tail:\n{}",
+                timeout.as_secs(),
+                sessions::pane_tail(&pane, 10)
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(250));
+    }
+    std::thread::sleep(Duration::from_millis(1000));
+    manager.send(session_name, SELF_TEST_MESSAGE)?;
+    info!("self-test: prompt sent to {session_name}");
+    Ok(())
+}
+
+/// Take received lines until a `Stop` event parses. Lines that do not parse are kept to
+/// tell a parse failure from a hook that never wrote anything.
+fn wait_for_stop(
+    rx: &mpsc::Receiver<String>,
+    secrets: &[String],
+    events_path: &Path,
+    timeout: Duration,
+) -> Result<HookEvent, Failure> {
+    let deadline = Instant::now() + timeout;
+    let mut seen: Vec<String> = Vec::new();
+    let mut parse_error = None;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let line = match rx.recv_timeout(remaining) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match hooks::accept_line(&line, secrets) {
+            Ok(event) if event.event_name == "Stop" => return Ok(event),
+            Ok(event) => seen.push(event.event_name),
+            Err(err) => parse_error = Some(err),
+        }
+    }
+    if let Some(error) = parse_error {
+        return Err(Failure {
+            stage: Stage::Parse,
+            error,
+        });
+    }
+    let error = if seen.is_empty() {
+        anyhow!(
+            "no hook payload reached {} or {} within {}s",
+            hooks::socket_path(events_path).display(),
+            events_path.display(),
+            timeout.as_secs()
+        )
+    } else {
+        anyhow!("received {} but no Stop event", seen.join(", "))
+    };
+    Err(Failure {
+        stage: Stage::HookWrite,
+        error,
+    })
+}
+
+/// The coordinator finds the session by the event's `cwd` and reads the reply from its
+/// transcript.
+fn check_routing(event: &HookEvent, scratch: &Path) -> anyhow::Result<()> {
+    let expected = scratch.canonicalize().unwrap_or_else(|_| scratch.to_path_buf());
+    let cwd = event.cwd.canonicalize().unwrap_or_else(|_| event.cwd.clone());
+    if cwd != expected {
+        return Err(anyhow!(
+            "Stop event reports cwd {}, expected {}",
+            event.cwd.display(),
+            expected.display()
+        ));
+    }
+    if !event.transcript_path.exists() {
+        return Err(anyhow!(
+            "transcript {} from the Stop event does not exist",
+            event.transcript_path.display()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use crate::clock::Clock;
+use crate::permissions::PermissionPolicy;
+use crate::sessions::{self, TmuxSessionManager};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+const PROMPT_POLL: Duration = Duration::from_millis(200);
+
+/// Reported by a session's sender task once a queued prompt was typed, or failed to be.
+pub struct SendOutcome {
+    pub session_name: String,
+    pub result: Result<()>,
+    /// `TurnSetup::max_turn_secs` of the prompt.
+    pub max_turn_secs: Option<u64>,
+}
+
+/// Applied by the sender task right before a prompt is typed, so it does not affect
+/// prompts queued ahead.
+#[derive(Debug, Clone, Default)]
+pub struct TurnSetup {
+    /// Slash commands typed first, each once the prompt is back, e.g. `/model opus`.
+    pub commands: Vec<String>,
+    /// Permission policy and the file it is installed to.
+    pub policy: Option<(PathBuf, PermissionPolicy)>,
+    /// Turn limit overriding `max_turn_secs`, passed back with the outcome.
+    pub max_turn_secs: Option<u64>,
+}
+
+struct QueuedPrompt {
+    text: String,
+    setup: TurnSetup,
+    /// The prompt was seen ready after the last turn, so it is not waited for again.
+    prompt_ready: bool,
+    timeout: Duration,
+}
+
+struct Queue {
+    tx: mpsc::UnboundedSender<QueuedPrompt>,
+    task: JoinHandle<()>,
+    /// Prompts queued and not yet reported back.
+    pending: usize,
+}
+
+/// One sender task per session, typing queued prompts in order once the session's input
+/// prompt is ready. Waiting happens in the task, so a busy session does not hold up the
+/// coordinator or other conversations.
+pub struct SendQueues {
+    sessions: TmuxSessionManager,
+    clock: Arc<dyn Clock>,
+    outcome_tx: mpsc::UnboundedSender<SendOutcome>,
+    queues: HashMap<String, Queue>,
+}
+
+impl SendQueues {
+    pub fn new(
+        sessions: TmuxSessionManager,
+        clock: Arc<dyn Clock>,
+        outcome_tx: mpsc::UnboundedSender<SendOutcome>,
+    ) -> Self {
+        Self {
+            sessions,
+            clock,
+            outcome_tx,
+            queues: HashMap::new(),
+        }
+    }
+
+    /// Queue `text` for `session_name` and return how many prompts are ahead of it.
+    /// `prompt_ready` only counts when nothing is ahead.
+    pub fn push(
+        &mut self,
+        session_name: &str,
+        text: String,
+        setup: TurnSetup,
+        prompt_ready: bool,
+        timeout: Duration,
+    ) -> usize {
+        let queue = self
+            .queues
+            .entry(session_name.to_string())
+            .or_insert_with(|| {
+                spawn_sender(
+                    self.sessions.clone(),
+                    self.clock.clone(),
+                    session_name.to_string(),
+                    self.outcome_tx.clone(),
+                )
+            });
+        let ahead = queue.pending;
+        queue.pending += 1;
+        let _ = queue.tx.send(QueuedPrompt {
+            text,
+            setup,
+            prompt_ready: prompt_ready && ahead == 0,
+            timeout,
+        });
+        ahead
+    }
+
+    /// Prompts queued for `session_name` and not yet typed.
+    pub fn pending(&self, session_name: &str) -> usize {
+        self.queues.get(session_name).map_or(0, |queue| queue.pending)
+    }
+
+    /// Count a reported outcome off the session's queue.
+    pub fn finished(&mut self, session_name: &str) {
+        if let Some(queue) = self.queues.get_mut(session_name) {
+            queue.pending = queue.pending.saturating_sub(1);
+        }
+    }
+
+    /// Drop the prompts still queued for a session that was stopped or replaced.
+    pub fn close(&mut self, session_name: &str) {
+        if let Some(queue) = self.queues.remove(session_name) {
+            queue.task.abort();
+            if queue.pending > 0 {
+                warn!("send queue: dropped {} prompt(s) for {session_name}", queue.pending);
+            }
+        }
+    }
+}
+
+fn spawn_sender(
+    sessions: TmuxSessionManager,
+    clock: Arc<dyn Clock>,
+    session_name: String,
+    outcome_tx: mpsc::UnboundedSender<SendOutcome>,
+) -> Queue {
+    let (tx, mut rx) = mpsc::unbounded_channel::<QueuedPrompt>();
+    let task = tokio::spawn(async move {
+        while let Some(prompt) = rx.recv().await {
+            let max_turn_secs = prompt.setup.max_turn_secs;
+            let result = deliver(&sessions, &session_name, prompt, clock.as_ref()).await;
+            let outcome = SendOutcome {
+                session_name: session_name.clone(),
+                result,
+                max_turn_secs,
+            };
+            if outcome_tx.send(outcome).is_err() {
+                return;
+            }
+        }
+    });
+    Queue {
+        tx,
+        task,
+        pending: 0,
+    }
+}
+
+async fn deliver(
+    sessions: &TmuxSessionManager,
+    session_name: &str,
+    prompt: QueuedPrompt,
+    clock: &dyn Clock,
+) -> Result<()> {
+    let mut ready = prompt.prompt_ready;
+    for command in &prompt.setup.commands {
+        if !ready {
+            wait(sessions, session_name, prompt.timeout, clock).await?;
+        }
+        sessions
+            .send_async(session_name, command, clock)
+            .await
+            .with_context(|| format!("failed to send `{command}` to {session_name}"))?;
+        ready = false;
+    }
+    if let Some((path, policy)) = prompt.setup.policy {
+        tokio::task::spawn_blocking(move || policy.install(&path)).await??;
+    }
+    if !ready {
+        wait(sessions, session_name, prompt.timeout, clock).await?;
+    }
+    sessions
+        .send_async(session_name, &prompt.text, clock)
+        .await
+        .with_context(|| format!("failed to send to {session_name}"))
+}
+
+async fn wait(
+    sessions: &TmuxSessionManager,
+    session_name: &str,
+    timeout: Duration,
+    clock: &dyn Clock,
+) -> Result<()> {
+    sessions::wait_for_prompt_async(sessions, session_name, timeout, PROMPT_POLL, clock).await
+}
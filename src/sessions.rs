@@ -1,11 +1,151 @@
+use crate::clock::Clock;
+use crate::paths;
+use crate::tmux;
 use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use tracing::warn;
 
+pub const PROMPT_PROFILE_PATH: &str = ".ccterm/prompt_profile.json";
+/// Longest wait between prompt checks of a pane followed in control mode, in case its
+/// output was missed.
+const CONTROL_RECHECK: Duration = Duration::from_secs(2);
+/// Directory in the state dir for the env files sessions source when they start.
+const ENV_DIR: &str = "env";
+/// Pause between typing text and pressing Enter, so the TUI takes the text as typed input.
+const SEND_ENTER_DELAY: Duration = Duration::from_millis(100);
+/// How long a chat notice stays in an attached terminal's status line.
+const NOTICE_DISPLAY_MS: u64 = 10_000;
+/// tmux user option holding the session's label (e.g. a thread title).
+pub const LABEL_OPTION: &str = "@ccterm_label";
+/// tmux user option holding the conversation a coordinator session serves, as
+/// `<channel>` or `<channel>/<thread ts>`.
+pub const CONVERSATION_OPTION: &str = "@ccterm_conversation";
+/// tmux user option holding `SessionFingerprint::summary` of a coordinator session.
+pub const FINGERPRINT_OPTION: &str = "@ccterm_fingerprint";
+
+/// A tmux session as reported by `list-sessions`.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub name: String,
+    /// Unix time the session was created.
+    pub created: u64,
+    /// A terminal client is attached; control-mode clients such as ccterm's own are not
+    /// counted.
+    pub attached: bool,
+    pub cwd: PathBuf,
+    pub conversation: Option<String>,
+    pub label: Option<String>,
+    pub fingerprint: Option<String>,
+}
+
+/// TUI markers used to decide whether Claude Code is idle at its input prompt.
+/// Detected by `ccterm doctor --probe` and stored per project directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptProfile {
+    #[serde(default)]
+    pub claude_version: Option<String>,
+    pub prompt_markers: Vec<String>,
+    pub busy_markers: Vec<String>,
+    #[serde(default)]
+    pub trust_markers: Vec<String>,
+    /// Text of Claude's tool permission dialog, to tell whether it is still open.
+    #[serde(default = "default_permission_markers")]
+    pub permission_markers: Vec<String>,
+    /// Text of Claude's plan approval prompt, to tell whether it is still open.
+    #[serde(default = "default_plan_markers")]
+    pub plan_markers: Vec<String>,
+}
+
+impl Default for PromptProfile {
+    fn default() -> Self {
+        Self {
+            claude_version: None,
+            prompt_markers: vec!["❯".to_string(), ">".to_string()],
+            busy_markers: vec!["esc to interrupt".to_string()],
+            trust_markers: vec!["Do you trust the files in this folder?".to_string()],
+            permission_markers: default_permission_markers(),
+            plan_markers: default_plan_markers(),
+        }
+    }
+}
+
+fn default_permission_markers() -> Vec<String> {
+    vec!["Do you want to".to_string()]
+}
+
+fn default_plan_markers() -> Vec<String> {
+    vec!["Would you like to proceed".to_string()]
+}
+
+impl PromptProfile {
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read prompt profile: {}", path.display()))?;
+        let profile = serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse prompt profile: {}", path.display()))?;
+        Ok(Some(profile))
+    }
+
+    pub fn load_or_default(path: &Path) -> Self {
+        match Self::load(path) {
+            Ok(Some(profile)) => profile,
+            Ok(None) => Self::default(),
+            Err(err) => {
+                warn!("prompt profile ignored: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        ensure_dir(path)?;
+        let mut out =
+            serde_json::to_string_pretty(self).context("failed to render prompt profile")?;
+        out.push('\n');
+        paths::write_atomic(path, out)
+            .with_context(|| format!("failed to write prompt profile: {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Warn when the installed CLI differs from the one the profile was probed with.
+    pub fn check_version(&self, claude_cmd: &str) {
+        let Some(probed) = self.claude_version.as_deref() else {
+            return;
+        };
+        match claude_version(claude_cmd) {
+            Ok(current) if current != probed => warn!(
+                "claude CLI changed since last probe ({probed} -> {current}); run `ccterm doctor --probe`"
+            ),
+            Ok(_) => {}
+            Err(err) => warn!("failed to check claude version: {err}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SpawnOptions {
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+}
+
+#[derive(Clone)]
 pub struct TmuxSessionManager {
     claude_cmd: String,
     cwd: PathBuf,
+    profile: PromptProfile,
+    /// Watch panes through tmux control mode instead of only polling them.
+    control_mode: bool,
+    /// Output counters of the control-mode clients, by session name.
+    watchers: Arc<Mutex<HashMap<String, watch::Receiver<u64>>>>,
 }
 
 impl TmuxSessionManager {
@@ -13,90 +153,293 @@ impl TmuxSessionManager {
         Self {
             claude_cmd: claude_cmd.into(),
             cwd: cwd.into(),
+            profile: PromptProfile::default(),
+            control_mode: false,
+            watchers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn with_profile(mut self, profile: PromptProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_control_mode(mut self, enabled: bool) -> Self {
+        self.control_mode = enabled;
+        self
+    }
+
+    /// Start following the session's output in control mode, when enabled and not
+    /// already following it. Failures are logged; prompt checks then fall back to polling.
+    pub fn watch(&self, session_name: &str) {
+        if !self.control_mode {
+            return;
+        }
+        let mut watchers = self.watchers.lock().unwrap_or_else(|err| err.into_inner());
+        watchers.retain(|_, rx| rx.has_changed().is_ok());
+        if watchers.contains_key(session_name) {
+            return;
+        }
+        match tmux::watch_output(session_name) {
+            Ok(rx) => {
+                watchers.insert(session_name.to_string(), rx);
+            }
+            Err(err) => warn!("tmux: control mode for {session_name} failed: {err:#}"),
         }
     }
 
+    /// Output counter of the session's control-mode client, if it is still attached.
+    pub fn output_updates(&self, session_name: &str) -> Option<watch::Receiver<u64>> {
+        let watchers = self.watchers.lock().unwrap_or_else(|err| err.into_inner());
+        let rx = watchers.get(session_name)?;
+        rx.has_changed().is_ok().then(|| rx.clone())
+    }
+
+    pub fn profile(&self) -> &PromptProfile {
+        &self.profile
+    }
+
     pub fn spawn(&self, session_name: &str) -> Result<()> {
         self.spawn_in(session_name, &self.cwd)
     }
 
     pub fn spawn_in(&self, session_name: &str, cwd: &Path) -> Result<()> {
-        let status = Command::new("tmux")
-            .args([
-                "new-session",
-                "-d",
-                "-s",
-                session_name,
-                "-c",
-                cwd.to_str()
-                    .context("failed to convert cwd to string")?,
-                &self.claude_cmd,
-            ])
-            .status()
-            .context("failed to start tmux session")?;
-
-        if !status.success() {
-            bail!("tmux new-session failed with status: {status}");
+        self.spawn_with(session_name, cwd, &SpawnOptions::default())
+    }
+
+    /// Spawn with extra claude arguments (e.g. `--resume <id>`) and session environment.
+    pub fn spawn_with(&self, session_name: &str, cwd: &Path, opts: &SpawnOptions) -> Result<()> {
+        let mut command = self.claude_cmd.clone();
+        for arg in &opts.args {
+            command.push(' ');
+            command.push_str(&shell_quote(arg));
+        }
+        let mut tmux_args = vec![
+            "new-session".to_string(),
+            "-d".to_string(),
+            "-s".to_string(),
+            session_name.to_string(),
+            "-c".to_string(),
+            cwd.to_str()
+                .context("failed to convert cwd to string")?
+                .to_string(),
+        ];
+        let mut env_file = None;
+        if !opts.env.is_empty() {
+            // Values on the tmux command line would be visible in `ps`; the session
+            // sources them from an owner-only file and removes it before Claude starts.
+            let path = self.write_env_file(session_name, &opts.env)?;
+            let quoted = shell_quote(&path.to_string_lossy());
+            command = format!(". {quoted}; rm -f {quoted}; {command}");
+            env_file = Some(path);
         }
+        tmux_args.push(command);
+        let tmux_args: Vec<&str> = tmux_args.iter().map(String::as_str).collect();
+        if let Err(err) = tmux::run(&tmux_args) {
+            if let Some(path) = env_file {
+                let _ = std::fs::remove_file(path);
+            }
+            return Err(err);
+        }
+        self.watch(session_name);
         Ok(())
     }
 
+    /// Write `env` as `export` lines to an owner-only file in the state dir.
+    fn write_env_file(&self, session_name: &str, env: &[(String, String)]) -> Result<PathBuf> {
+        let mut content = String::new();
+        for (key, value) in env {
+            let valid = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                bail!("invalid environment variable name: {key}");
+            }
+            content.push_str(&format!("export {key}={}\n", shell_quote(value)));
+        }
+        let dir = paths::state_dir(&self.cwd).join(ENV_DIR);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create dir: {}", dir.display()))?;
+        let path = dir.join(format!("{session_name}.env"));
+        paths::write_private(&path, content)
+            .with_context(|| format!("failed to write session env: {}", path.display()))?;
+        Ok(path)
+    }
+
     pub fn send(&self, session_name: &str, text: &str) -> Result<()> {
-        let status = Command::new("tmux")
-            .args([
-                "send-keys",
-                "-t",
-                session_name,
-                text,
-            ])
-            .status()
-            .context("failed to send keys to tmux")?;
-
-        if !status.success() {
-            bail!("tmux send-keys failed with status: {status}");
-        }
-        std::thread::sleep(Duration::from_millis(100));
+        tmux::run(&["send-keys", "-t", session_name, text])?;
+        std::thread::sleep(SEND_ENTER_DELAY);
         self.send_enter(session_name)?;
         Ok(())
     }
 
-    fn send_enter(&self, session_name: &str) -> Result<()> {
-        let status = Command::new("tmux")
-            .args(["send-keys", "-t", session_name, "C-m"])
-            .status()
-            .context("failed to send enter to tmux")?;
+    /// `send` for async callers: tmux runs on the blocking pool and the pause before Enter
+    /// is a `clock` sleep, so runtime workers are not held.
+    pub async fn send_async(
+        &self,
+        session_name: &str,
+        text: &str,
+        clock: &dyn Clock,
+    ) -> Result<()> {
+        let target = session_name.to_string();
+        let text = text.to_string();
+        tokio::task::spawn_blocking(move || tmux::run(&["send-keys", "-t", &target, &text]))
+            .await??;
+        clock.sleep(SEND_ENTER_DELAY).await;
+        let manager = self.clone();
+        let target = session_name.to_string();
+        tokio::task::spawn_blocking(move || manager.send_enter(&target)).await?
+    }
 
-        if !status.success() {
-            bail!("tmux send-keys C-m failed with status: {status}");
-        }
+    pub fn send_enter(&self, session_name: &str) -> Result<()> {
+        self.send_key(session_name, "C-m")
+    }
+
+    /// Send a single tmux key name (e.g. `Escape`, `C-c`) without a trailing Enter.
+    pub fn send_key(&self, session_name: &str, key: &str) -> Result<()> {
+        tmux::run(&["send-keys", "-t", session_name, key])
+            .with_context(|| format!("failed to send {key}"))?;
+        Ok(())
+    }
+
+    /// Store a human-readable label on the tmux session, as the `@ccterm_label` option.
+    pub fn set_label(&self, session_name: &str, label: &str) -> Result<()> {
+        tmux::run(&["set-option", "-t", session_name, LABEL_OPTION, label])?;
+        Ok(())
+    }
+
+    /// Record the conversation the session serves and what it was started with, as the
+    /// `@ccterm_conversation` and `@ccterm_fingerprint` options.
+    pub fn set_origin(
+        &self,
+        session_name: &str,
+        conversation: &str,
+        fingerprint: &str,
+    ) -> Result<()> {
+        tmux::run(&["set-option", "-t", session_name, CONVERSATION_OPTION, conversation])?;
+        tmux::run(&["set-option", "-t", session_name, FINGERPRINT_OPTION, fingerprint])?;
         Ok(())
     }
 
     pub fn stop(&self, session_name: &str) -> Result<()> {
-        let status = Command::new("tmux")
-            .args(["kill-session", "-t", session_name])
-            .status()
-            .context("failed to stop tmux session")?;
+        tmux::run(&["kill-session", "-t", session_name])?;
+        Ok(())
+    }
 
-        if !status.success() {
-            bail!("tmux kill-session failed with status: {status}");
-        }
+    pub fn pane_cwd(&self, session_name: &str) -> Result<PathBuf> {
+        let path = tmux::run(&[
+            "display-message",
+            "-p",
+            "-t",
+            session_name,
+            "#{pane_current_path}",
+        ])?;
+        Ok(PathBuf::from(path.trim()))
+    }
+
+    /// PID of the process started in the session's pane.
+    pub fn pane_pid(&self, session_name: &str) -> Result<u32> {
+        let pid = tmux::run(&["display-message", "-p", "-t", session_name, "#{pane_pid}"])?;
+        pid.trim()
+            .parse()
+            .with_context(|| format!("unexpected tmux pane pid: {}", pid.trim()))
+    }
+
+    /// Names of the terminal clients attached to the session; control-mode clients such
+    /// as ccterm's own are left out.
+    pub fn terminal_clients(&self, session_name: &str) -> Result<Vec<String>> {
+        let clients = tmux::run(&[
+            "list-clients",
+            "-t",
+            session_name,
+            "-F",
+            "#{client_name}\t#{client_control_mode}",
+        ])?;
+        Ok(clients
+            .lines()
+            .filter_map(|line| line.strip_suffix("\t0"))
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Show `text` in the status line of `client` for `NOTICE_DISPLAY_MS`.
+    pub fn display_message(&self, client: &str, text: &str) -> Result<()> {
+        let delay = NOTICE_DISPLAY_MS.to_string();
+        // `display-message` expands formats; `##` is a literal `#`.
+        let text = text.replace('#', "##");
+        tmux::run(&["display-message", "-c", client, "-d", &delay, &text])?;
         Ok(())
     }
 
+    pub fn has_session(&self, session_name: &str) -> bool {
+        tmux::succeeds(&["has-session", "-t", session_name])
+    }
+
     pub fn capture_pane(&self, session_name: &str, lines: usize) -> Result<String> {
         let line_arg = format!("-{}", lines);
-        let output = Command::new("tmux")
-            .args(["capture-pane", "-t", session_name, "-p", "-S", &line_arg])
-            .output()
-            .context("failed to capture tmux pane")?;
+        tmux::run(&["capture-pane", "-t", session_name, "-p", "-S", &line_arg])
+    }
+}
 
-        if !output.status.success() {
-            bail!("tmux capture-pane failed with status: {}", output.status);
-        }
+/// tmux sessions named `<prefix>-...`, oldest first. An absent tmux server means no
+/// sessions.
+pub fn list_sessions(prefix: &str) -> Result<Vec<SessionInfo>> {
+    if !tmux::succeeds(&["list-sessions"]) {
+        return Ok(Vec::new());
+    }
+    let format = format!(
+        "#{{session_name}}\t#{{session_created}}\t#{{pane_current_path}}\t\
+         #{{{CONVERSATION_OPTION}}}\t#{{{FINGERPRINT_OPTION}}}\t#{{{LABEL_OPTION}}}"
+    );
+    let output = tmux::run(&["list-sessions", "-F", &format])?;
+    let clients = tmux::run(&[
+        "list-clients",
+        "-F",
+        "#{client_session}\t#{client_control_mode}",
+    ])?;
+    let attached: Vec<&str> = clients
+        .lines()
+        .filter_map(|line| line.strip_suffix("\t0"))
+        .collect();
+    let name_prefix = format!("{prefix}-");
+    let mut sessions: Vec<SessionInfo> = output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.splitn(6, '\t').collect();
+            let [name, created, cwd, conversation, fingerprint, label] = fields[..] else {
+                return None;
+            };
+            let non_empty = |value: &str| Some(value.to_string()).filter(|v| !v.is_empty());
+            Some(SessionInfo {
+                name: name.to_string(),
+                created: created.parse().unwrap_or(0),
+                attached: attached.contains(&name),
+                cwd: PathBuf::from(cwd),
+                conversation: non_empty(conversation),
+                label: non_empty(label),
+                fingerprint: non_empty(fingerprint),
+            })
+        })
+        .filter(|session| session.name.starts_with(&name_prefix))
+        .collect();
+    sessions.sort_by_key(|session| session.created);
+    Ok(sessions)
+}
 
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+pub fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@".contains(c))
+    {
+        return arg.to_string();
     }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Last `lines` non-empty lines of a captured pane.
+pub fn pane_tail(pane: &str, lines: usize) -> String {
+    let all: Vec<&str> = pane.lines().filter(|l| !l.trim().is_empty()).collect();
+    all[all.len().saturating_sub(lines)..].join("\n")
 }
 
 pub fn timestamp_session_name(prefix: &str) -> Result<String> {
@@ -107,16 +450,21 @@ pub fn timestamp_session_name(prefix: &str) -> Result<String> {
 }
 
 pub fn ensure_tmux_available() -> Result<()> {
-    let status = Command::new("tmux")
-        .arg("-V")
-        .status()
-        .context("failed to run tmux -V")?;
-    if !status.success() {
-        bail!("tmux -V failed with status: {status}");
-    }
+    tmux::run(&["-V"])?;
     Ok(())
 }
 
+pub fn claude_version(command: &str) -> Result<String> {
+    let output = Command::new("sh")
+        .args(["-lc", &format!("{} --version", command)])
+        .output()
+        .context("failed to run claude --version")?;
+    if !output.status.success() {
+        bail!("claude --version failed with status: {}", output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
 pub fn ensure_claude_available(command: &str) -> Result<()> {
     let status = Command::new("sh")
         .args(["-lc", &format!("command -v {}", command)])
@@ -154,7 +502,7 @@ pub fn wait_for_prompt(
     let start = std::time::Instant::now();
     loop {
         let pane = manager.capture_pane(session_name, 200)?;
-        if prompt_ready(&pane) {
+        if prompt_ready(&pane, manager.profile()) {
             std::thread::sleep(Duration::from_millis(1000));
             return Ok(());
         }
@@ -165,7 +513,60 @@ pub fn wait_for_prompt(
     }
 }
 
-fn prompt_ready(pane: &str) -> bool {
+/// Non-blocking `wait_for_prompt` for the coordinator, timed by `clock`. With a
+/// control-mode client on the session, the pane is captured again only after it printed
+/// something (or every `CONTROL_RECHECK`), instead of every `poll`.
+pub async fn wait_for_prompt_async(
+    manager: &TmuxSessionManager,
+    session_name: &str,
+    timeout: Duration,
+    poll: Duration,
+    clock: &dyn Clock,
+) -> Result<()> {
+    let start = clock.now();
+    let mut updates = manager.output_updates(session_name);
+    loop {
+        let pane = manager.capture_pane(session_name, 200)?;
+        if prompt_ready(&pane, manager.profile()) {
+            clock.sleep(Duration::from_millis(1000)).await;
+            return Ok(());
+        }
+        if clock.now().duration_since(start) > timeout {
+            bail!("timed out waiting for input prompt");
+        }
+        if let Some(rx) = updates.as_mut() {
+            tokio::select! {
+                changed = rx.changed() => {
+                    if changed.is_err() {
+                        updates = None;
+                    }
+                }
+                _ = clock.sleep(CONTROL_RECHECK) => {}
+            }
+        }
+        clock.sleep(poll).await;
+    }
+}
+
+/// Whether the last `lines` of the pane show Claude's permission dialog.
+pub fn permission_dialog_open(pane: &str, profile: &PromptProfile, lines: usize) -> bool {
+    let tail = pane_tail(pane, lines);
+    profile
+        .permission_markers
+        .iter()
+        .any(|marker| tail.contains(marker.as_str()))
+}
+
+/// Whether the last `lines` of the pane show Claude's plan approval prompt.
+pub fn plan_prompt_open(pane: &str, profile: &PromptProfile, lines: usize) -> bool {
+    let tail = pane_tail(pane, lines);
+    profile
+        .plan_markers
+        .iter()
+        .any(|marker| tail.contains(marker.as_str()))
+}
+
+pub fn prompt_ready(pane: &str, profile: &PromptProfile) -> bool {
     let lines: Vec<String> = pane
         .lines()
         .map(|line| line.replace('\u{00A0}', " "))
@@ -173,17 +574,13 @@ fn prompt_ready(pane: &str) -> bool {
 
     for line in lines.iter().rev().take(20) {
         let trimmed = line.trim_start();
-        if let Some(rest) = trimmed.strip_prefix('❯') {
-            if rest.contains("esc to interrupt") {
-                return false;
-            }
-            return true;
-        }
-        if let Some(rest) = trimmed.strip_prefix('>') {
-            if rest.contains("esc to interrupt") {
-                return false;
+        for marker in &profile.prompt_markers {
+            if let Some(rest) = trimmed.strip_prefix(marker.as_str()) {
+                return !profile
+                    .busy_markers
+                    .iter()
+                    .any(|busy| rest.contains(busy.as_str()));
             }
-            return true;
         }
     }
     false
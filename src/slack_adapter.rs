@@ -1,34 +1,158 @@
+use crate::chat::ChatAdapter;
 use crate::config::SlackConfig;
-use crate::types::{IncomingMessage, OutgoingMessage};
-use anyhow::{Context, Result};
+use crate::context;
+use crate::outgoing::{split_text, MESSAGE_MAX_CHARS};
+use crate::url_fetch;
+use crate::types::{
+    ActionEvent, Attachment, ChatIdentity, HomeView, IncomingEvent, IncomingMessage,
+    OutgoingMessage, ReactionEvent, ReplyBlock, Role, ShortcutEvent, ThreadMarker,
+    TranscriptMessage,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use http_body_util::{BodyExt, Empty, Limited};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use slack_morphism::errors::SlackClientError;
 use slack_morphism::prelude::*;
 use slack_morphism::prelude::SlackClientHyperHttpsConnector;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::{mpsc, RwLock};
+use tracing::{debug, info, warn};
+
+/// Redirects followed when downloading a shared file.
+const DOWNLOAD_MAX_REDIRECTS: usize = 3;
+/// A file download, redirects included, that takes longer than this is abandoned.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+/// Slack rejects messages with more than 50 blocks.
+const MESSAGE_MAX_BLOCKS: usize = 50;
+/// `chat.postMessage` errors for a `thread_ts` whose parent message no longer exists.
+const MISSING_THREAD_ERRORS: [&str; 2] = ["thread_not_found", "invalid_thread_ts"];
+const DELETED_THREAD_NOTE: &str =
+    "_Posted here because the thread this reply belongs to was deleted._";
+const USER_REFUSED: &str = "Sorry, you are not on the list of people who can use ccterm \
+    here. Ask whoever runs it to add you to `slack.allowed_users`.";
+const CHANNEL_REFUSED: &str = "Sorry, ccterm does not take requests in this channel. Ask \
+    whoever runs it to add the channel to `slack.allowed_channels`, or send me a direct \
+    message.";
 
 #[derive(Clone)]
 struct SlackBridge {
-    tx: mpsc::UnboundedSender<IncomingMessage>,
-    bot_token: SlackApiToken,
+    tx: mpsc::UnboundedSender<IncomingEvent>,
+    tokens: BotTokens,
     user_cache: Arc<RwLock<HashMap<String, String>>>,
+    /// Take prompts from every message in channels the bot is in, not just mentions.
+    respond_without_mention: bool,
+    access: Arc<Access>,
+}
+
+/// `slack.allowed_users` and `slack.allowed_channels`, where an empty list allows all.
+#[derive(Default)]
+struct Access {
+    users: Vec<String>,
+    channels: Vec<String>,
+}
+
+impl Access {
+    /// Why `user` may not use ccterm in `channel`, or `None` if they may. `channel` is
+    /// empty for the App Home tab; direct messages are only checked against users.
+    fn refusal(&self, user: &str, channel: &str) -> Option<&'static str> {
+        if !self.users.is_empty() && !self.users.iter().any(|allowed| allowed == user) {
+            return Some(USER_REFUSED);
+        }
+        if !channel.is_empty()
+            && !is_direct_channel(channel)
+            && !self.channels.is_empty()
+            && !self.channels.iter().any(|allowed| allowed == channel)
+        {
+            return Some(CHANNEL_REFUSED);
+        }
+        None
+    }
+}
+
+/// Bot tokens by workspace. `slack.bot_token` is used for channels whose workspace has
+/// no token of its own (installed through OAuth), or when it is not known yet.
+#[derive(Clone, Default)]
+pub struct BotTokens {
+    default: Option<SlackApiToken>,
+    by_team: Arc<RwLock<HashMap<String, SlackApiToken>>>,
+    /// Learned from incoming events.
+    team_by_channel: Arc<RwLock<HashMap<String, String>>>,
+    /// Learned from App Home events, to publish a user's home view.
+    team_by_user: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl BotTokens {
+    pub async fn insert(&self, team_id: &str, token: &str) {
+        self.by_team.write().await.insert(
+            team_id.to_string(),
+            SlackApiToken::new(SlackApiTokenValue(token.to_string())),
+        );
+    }
+
+    async fn note_channel(&self, channel: &str, team_id: &str) {
+        self.team_by_channel
+            .write()
+            .await
+            .insert(channel.to_string(), team_id.to_string());
+    }
+
+    async fn note_user(&self, user_id: &str, team_id: &str) {
+        self.team_by_user
+            .write()
+            .await
+            .insert(user_id.to_string(), team_id.to_string());
+    }
+
+    async fn for_team(&self, team_id: &str) -> Option<SlackApiToken> {
+        let token = self.by_team.read().await.get(team_id).cloned();
+        token.or_else(|| self.default.clone())
+    }
+
+    async fn for_channel(&self, channel: &str) -> Result<SlackApiToken> {
+        let team_id = self.team_by_channel.read().await.get(channel).cloned();
+        let token = match team_id {
+            Some(team_id) => self.for_team(&team_id).await,
+            None => self.default.clone(),
+        };
+        token.with_context(|| format!("no slack bot token for channel {channel}"))
+    }
+
+    async fn for_user(&self, user_id: &str) -> Result<SlackApiToken> {
+        let team_id = self.team_by_user.read().await.get(user_id).cloned();
+        let token = match team_id {
+            Some(team_id) => self.for_team(&team_id).await,
+            None => self.default.clone(),
+        };
+        token.with_context(|| format!("no slack bot token for user {user_id}"))
+    }
 }
 
 pub struct SlackAdapter {
     client: Arc<SlackClient<SlackClientHyperHttpsConnector>>,
-    bot_token: SlackApiToken,
-    rx: mpsc::UnboundedReceiver<IncomingMessage>,
+    tokens: BotTokens,
+    user_cache: Arc<RwLock<HashMap<String, String>>>,
+    /// `auth.test` results by channel, for `identity` and canvas links.
+    identities: RwLock<HashMap<String, ChatIdentity>>,
+    rx: mpsc::UnboundedReceiver<IncomingEvent>,
 }
 
 impl SlackAdapter {
     pub async fn connect(cfg: &SlackConfig) -> Result<Self> {
-        eprintln!("slack: connecting (socket mode)");
+        info!("connecting (socket mode)");
         let (tx, rx) = mpsc::unbounded_channel();
         let connector = SlackClientHyperHttpsConnector::new()
             .context("failed to create slack hyper connector")?;
         let client: Arc<SlackClient<SlackClientHyperHttpsConnector>> =
             Arc::new(SlackClient::new(connector));
-        let bot_token = SlackApiToken::new(SlackApiTokenValue(cfg.bot_token.clone()));
+        let tokens = BotTokens {
+            default: Some(cfg.bot_token.trim())
+                .filter(|token| !token.is_empty())
+                .map(|token| SlackApiToken::new(SlackApiTokenValue(token.to_string()))),
+            ..BotTokens::default()
+        };
         let app_token = SlackApiToken::new(SlackApiTokenValue(cfg.app_token.clone()));
 
         let user_cache = Arc::new(RwLock::new(HashMap::new()));
@@ -36,16 +160,22 @@ impl SlackAdapter {
             SlackClientEventsListenerEnvironment::new(client.clone())
                 .with_user_state(SlackBridge {
                     tx,
-                    bot_token: bot_token.clone(),
-                    user_cache,
+                    tokens: tokens.clone(),
+                    user_cache: user_cache.clone(),
+                    respond_without_mention: cfg.respond_without_mention,
+                    access: Arc::new(Access {
+                        users: cfg.allowed_users.clone(),
+                        channels: cfg.allowed_channels.clone(),
+                    }),
                 }),
         );
 
         let callbacks = SlackSocketModeListenerCallbacks::new()
             .with_hello_events(|event, _client, _state| async move {
-                eprintln!("slack: hello {:?}", event);
+                debug!("hello {:?}", event);
             })
-            .with_push_events(push_events_callback::<SlackClientHyperHttpsConnector>);
+            .with_push_events(push_events_callback::<SlackClientHyperHttpsConnector>)
+            .with_interaction_events(interaction_events_callback::<SlackClientHyperHttpsConnector>);
 
         let socket_mode_config = SlackClientSocketModeConfig::new();
         let socket_mode_listener =
@@ -55,43 +185,41 @@ impl SlackAdapter {
             .listen_for(&app_token)
             .await
             .context("failed to register socket mode listener")?;
-        eprintln!("slack: socket mode listener registered");
+        info!("socket mode listener registered");
 
         tokio::spawn(async move {
-            eprintln!("slack: socket mode listener starting");
+            info!("socket mode listener starting");
             socket_mode_listener.serve().await;
-            eprintln!("slack: socket mode listener stopped");
+            info!("socket mode listener stopped");
         });
 
         Ok(SlackAdapter {
             client,
-            bot_token,
+            tokens,
+            user_cache,
+            identities: RwLock::new(HashMap::new()),
             rx,
         })
     }
 
-    pub fn incoming(&mut self) -> &mut mpsc::UnboundedReceiver<IncomingMessage> {
-        &mut self.rx
+    pub fn client(&self) -> Arc<SlackClient<SlackClientHyperHttpsConnector>> {
+        self.client.clone()
+    }
+
+    pub fn tokens(&self) -> BotTokens {
+        self.tokens.clone()
     }
 
-    pub async fn send(&self, message: &OutgoingMessage) -> Result<()> {
-        eprintln!(
-            "slack: sending message channel={} thread={}",
+    async fn post_text(&self, message: &OutgoingMessage, text: String) -> Result<String> {
+        debug!(
+            "sending message channel={} thread={}",
             message.conversation_id,
             message.thread_id.as_deref().unwrap_or("-")
         );
-        let session = self.client.open_session(&self.bot_token);
+        let token = self.tokens.for_channel(&message.conversation_id).await?;
         let mut req = SlackApiChatPostMessageRequest {
             channel: SlackChannelId(message.conversation_id.clone()),
-            content: SlackMessageContent {
-                text: Some(message.text.clone()),
-                blocks: None,
-                attachments: None,
-                upload: None,
-                files: None,
-                reactions: None,
-                metadata: None,
-            },
+            content: SlackMessageContent::new().with_text(text),
             as_user: None,
             icon_emoji: None,
             icon_url: None,
@@ -108,13 +236,720 @@ impl SlackAdapter {
             req.thread_ts = Some(SlackTs(thread_id.clone()));
         }
 
-        session
-            .chat_post_message(&req)
+        let resp = self
+            .chat_post(&token, req)
             .await
             .context("failed to post slack message")?;
-        eprintln!("slack: sent message");
+        debug!("sent message ts={}", resp.ts);
+        Ok(resp.ts.to_string())
+    }
+
+    /// `chat.postMessage`. When the thread's parent message was deleted, the message is
+    /// posted at channel level with a note instead of being dropped.
+    async fn chat_post(
+        &self,
+        token: &SlackApiToken,
+        mut req: SlackApiChatPostMessageRequest,
+    ) -> Result<SlackApiChatPostMessageResponse, SlackClientError> {
+        let session = self.client.open_session(token);
+        match session.chat_post_message(&req).await {
+            Err(err) if req.thread_ts.is_some() && is_missing_thread(&err) => {}
+            result => return result,
+        }
+        info!(
+            "thread {} in {} is gone, posting to the channel",
+            req.thread_ts.take().map(|ts| ts.to_string()).unwrap_or_default(),
+            req.channel
+        );
+        let text = req.content.text.take().unwrap_or_default();
+        req.content.text = Some(format!("{text}\n\n{DELETED_THREAD_NOTE}"));
+        if let Some(blocks) = &mut req.content.blocks {
+            blocks.push(SlackContextBlock::new(vec![md!(DELETED_THREAD_NOTE)]).into());
+        }
+        session.chat_post_message(&req).await
+    }
+
+    /// A fetched message as a transcript entry, or `None` when it has no text or no
+    /// sender.
+    async fn transcript_message(
+        &self,
+        token: &SlackApiToken,
+        message: SlackHistoryMessage,
+    ) -> Option<TranscriptMessage> {
+        let timestamp = context::slack_ts_time(message.origin.ts.as_ref());
+        let thread = match (&message.origin.thread_ts, message.parent.reply_count) {
+            (Some(thread_ts), _) if *thread_ts != message.origin.ts => Some(ThreadMarker::Reply),
+            (_, Some(replies)) if replies > 0 => Some(ThreadMarker::Parent { replies }),
+            _ => None,
+        };
+        let raw_text = message.content.text.unwrap_or_default();
+        if raw_text.trim().is_empty() {
+            return None;
+        }
+        if message.sender.bot_id.is_some() {
+            return Some(TranscriptMessage {
+                role: Role::Assistant,
+                text: raw_text,
+                timestamp,
+                speaker: None,
+                thread,
+            });
+        }
+        let user = message.sender.user?;
+        let display_name =
+            resolve_user_display_name(self.client.clone(), token, &self.user_cache, &user)
+                .await
+                .unwrap_or_else(|| user.to_string());
+        Some(TranscriptMessage {
+            role: Role::User,
+            text: clean_incoming_text(&raw_text),
+            timestamp,
+            speaker: Some(display_name),
+            thread,
+        })
+    }
+}
+
+impl ChatAdapter for SlackAdapter {
+    fn name(&self) -> &'static str {
+        "slack"
+    }
+
+    fn incoming(&mut self) -> &mut mpsc::UnboundedReceiver<IncomingEvent> {
+        &mut self.rx
+    }
+
+    /// From `auth.test` with the channel's bot token, cached per channel.
+    async fn identity(&self, conversation_id: &str) -> Result<ChatIdentity> {
+        if let Some(identity) = self.identities.read().await.get(conversation_id) {
+            return Ok(identity.clone());
+        }
+        let token = self.tokens.for_channel(conversation_id).await?;
+        let session = self.client.open_session(&token);
+        let resp = session.auth_test().await.context("failed to call auth.test")?;
+        let identity = ChatIdentity {
+            bot_user_id: resp.user_id.to_string(),
+            workspace_id: resp.team_id.to_string(),
+            workspace_name: resp.team,
+            workspace_url: resp.url.0.to_string(),
+        };
+        self.identities
+            .write()
+            .await
+            .insert(conversation_id.to_string(), identity.clone());
+        Ok(identity)
+    }
+
+    async fn send(&self, message: &OutgoingMessage) -> Result<()> {
+        self.post(message).await.map(|_| ())
+    }
+
+    async fn send_ephemeral(&self, user_id: &str, message: &OutgoingMessage) -> Result<()> {
+        let token = self.tokens.for_channel(&message.conversation_id).await?;
+        let session = self.client.open_session(&token);
+        for chunk in split_text(&message.text, MESSAGE_MAX_CHARS) {
+            let req = SlackApiChatPostEphemeralRequest {
+                channel: SlackChannelId(message.conversation_id.clone()),
+                user: SlackUserId(user_id.to_string()),
+                content: SlackMessageContent::new().with_text(chunk),
+                as_user: None,
+                icon_emoji: None,
+                icon_url: None,
+                link_names: None,
+                parse: None,
+                thread_ts: message.thread_id.clone().map(SlackTs),
+                username: None,
+            };
+            session
+                .chat_post_ephemeral(&req)
+                .await
+                .context("failed to post slack ephemeral message")?;
+        }
+        Ok(())
+    }
+
+    /// Post a message and return its `ts`. Text over Slack's message limit is posted as
+    /// several consecutive messages, and the `ts` of the first one is returned.
+    async fn post(&self, message: &OutgoingMessage) -> Result<String> {
+        let chunks = split_text(&message.text, MESSAGE_MAX_CHARS);
+        if chunks.len() > 1 {
+            debug!(
+                "splitting message of {} chars into {} parts",
+                message.text.chars().count(),
+                chunks.len()
+            );
+        }
+        let mut first_ts = None;
+        for chunk in chunks {
+            let ts = self.post_text(message, chunk).await?;
+            first_ts.get_or_insert(ts);
+        }
+        first_ts.context("empty slack message")
+    }
+
+    /// Post reply blocks, at most `MESSAGE_MAX_BLOCKS` per message. The notification text
+    /// of each message is its blocks' text.
+    async fn post_blocks(
+        &self,
+        conversation_id: &str,
+        thread_id: Option<&str>,
+        blocks: &[ReplyBlock],
+    ) -> Result<String> {
+        let token = self.tokens.for_channel(conversation_id).await?;
+        let mut first_ts = None;
+        for batch in blocks.chunks(MESSAGE_MAX_BLOCKS) {
+            let fallback: Vec<&str> = batch
+                .iter()
+                .filter_map(|block| match block {
+                    ReplyBlock::Header(text) | ReplyBlock::Section(text) => Some(text.as_str()),
+                    ReplyBlock::Divider => None,
+                })
+                .collect();
+            let fallback = split_text(&fallback.join("\n"), MESSAGE_MAX_CHARS).swap_remove(0);
+            let slack_blocks: Vec<SlackBlock> = batch
+                .iter()
+                .map(|block| match block {
+                    ReplyBlock::Header(text) => SlackHeaderBlock::new(pt!(text.clone())).into(),
+                    ReplyBlock::Section(text) => {
+                        SlackSectionBlock::new().with_text(md!(text.clone())).into()
+                    }
+                    ReplyBlock::Divider => SlackDividerBlock::new().into(),
+                })
+                .collect();
+            let mut req = SlackApiChatPostMessageRequest::new(
+                SlackChannelId(conversation_id.to_string()),
+                SlackMessageContent::new().with_text(fallback).with_blocks(slack_blocks),
+            );
+            if let Some(thread_id) = thread_id {
+                req = req.with_thread_ts(SlackTs(thread_id.to_string()));
+            }
+            let resp = self
+                .chat_post(&token, req)
+                .await
+                .context("failed to post slack blocks message")?;
+            debug!("sent blocks message ts={}", resp.ts);
+            first_ts.get_or_insert(resp.ts.to_string());
+        }
+        first_ts.context("empty slack message")
+    }
+
+    /// Post a message with Approve/Reject buttons. The buttons carry `value` and use the
+    /// action ids `<action_prefix>_approve` and `<action_prefix>_reject`.
+    async fn post_approval(
+        &self,
+        message: &OutgoingMessage,
+        action_prefix: &str,
+        value: &str,
+    ) -> Result<String> {
+        let token = self.tokens.for_channel(&message.conversation_id).await?;
+        let blocks: Vec<SlackBlock> = vec![
+            SlackSectionBlock::new()
+                .with_text(md!(message.text.clone()))
+                .into(),
+            approval_buttons(action_prefix, value, "Approve", "Reject"),
+        ];
+        let mut req = SlackApiChatPostMessageRequest::new(
+            SlackChannelId(message.conversation_id.clone()),
+            SlackMessageContent::new()
+                .with_text(message.text.clone())
+                .with_blocks(blocks),
+        );
+        if let Some(thread_id) = &message.thread_id {
+            req = req.with_thread_ts(SlackTs(thread_id.clone()));
+        }
+        let resp = self
+            .chat_post(&token, req)
+            .await
+            .context("failed to post slack approval message")?;
+        debug!("sent approval message ts={}", resp.ts);
+        Ok(resp.ts.to_string())
+    }
+
+    /// Post a permission prompt with Approve, Always allow and Deny buttons
+    /// (`<action_prefix>_approve`, `_always`, `_deny`), each carrying `value`.
+    async fn post_permission_prompt(
+        &self,
+        message: &OutgoingMessage,
+        action_prefix: &str,
+        value: &str,
+    ) -> Result<String> {
+        let token = self.tokens.for_channel(&message.conversation_id).await?;
+        let button = |suffix: &str, label: &str| {
+            SlackBlockButtonElement::new(pt!(label.to_string()))
+                .with_action_id(SlackActionId(format!("{action_prefix}_{suffix}")))
+                .with_value(value.to_string())
+        };
+        let blocks: Vec<SlackBlock> = vec![
+            SlackSectionBlock::new()
+                .with_text(md!(message.text.clone()))
+                .into(),
+            SlackActionsBlock::new(vec![
+                button("approve", "Approve")
+                    .with_style(SlackBlockButtonStyle::Primary)
+                    .into(),
+                button("always", "Always allow").into(),
+                button("deny", "Deny")
+                    .with_style(SlackBlockButtonStyle::Danger)
+                    .into(),
+            ])
+            .into(),
+        ];
+        let mut req = SlackApiChatPostMessageRequest::new(
+            SlackChannelId(message.conversation_id.clone()),
+            SlackMessageContent::new()
+                .with_text(message.text.clone())
+                .with_blocks(blocks),
+        );
+        if let Some(thread_id) = &message.thread_id {
+            req = req.with_thread_ts(SlackTs(thread_id.clone()));
+        }
+        let resp = self
+            .chat_post(&token, req)
+            .await
+            .context("failed to post slack permission prompt")?;
+        debug!("sent permission prompt ts={}", resp.ts);
+        Ok(resp.ts.to_string())
+    }
+
+    /// Post a plan as a header and mrkdwn sections followed by "Approve plan" and "Keep
+    /// planning" buttons, with the same action ids as `post_approval`.
+    async fn post_plan(
+        &self,
+        message: &OutgoingMessage,
+        sections: &[String],
+        action_prefix: &str,
+        value: &str,
+    ) -> Result<String> {
+        let token = self.tokens.for_channel(&message.conversation_id).await?;
+        let mut blocks: Vec<SlackBlock> =
+            vec![SlackHeaderBlock::new(pt!(message.text.clone())).into()];
+        for section in sections {
+            blocks.push(SlackSectionBlock::new().with_text(md!(section.clone())).into());
+        }
+        blocks.push(approval_buttons(action_prefix, value, "Approve plan", "Keep planning"));
+        let mut req = SlackApiChatPostMessageRequest::new(
+            SlackChannelId(message.conversation_id.clone()),
+            SlackMessageContent::new()
+                .with_text(message.text.clone())
+                .with_blocks(blocks),
+        );
+        if let Some(thread_id) = &message.thread_id {
+            req = req.with_thread_ts(SlackTs(thread_id.clone()));
+        }
+        let resp = self
+            .chat_post(&token, req)
+            .await
+            .context("failed to post slack plan message")?;
+        debug!("sent plan message ts={}", resp.ts);
+        Ok(resp.ts.to_string())
+    }
+
+    /// Publish the App Home tab of `user_id`: one section per session with New session,
+    /// Stop and Export buttons (`<action_prefix>_new` etc.), then recent replies.
+    async fn publish_home(
+        &self,
+        user_id: &str,
+        view: &HomeView,
+        action_prefix: &str,
+    ) -> Result<()> {
+        let token = self.tokens.for_user(user_id).await?;
+        let session = self.client.open_session(&token);
+        let mut blocks: Vec<SlackBlock> = vec![
+            SlackHeaderBlock::new(pt!("Your sessions")).into(),
+        ];
+        if view.sessions.is_empty() {
+            blocks.push(
+                SlackSectionBlock::new()
+                    .with_text(md!("No active sessions. Mention ccterm in a channel to start one."))
+                    .into(),
+            );
+        }
+        for home_session in &view.sessions {
+            blocks.push(SlackSectionBlock::new().with_text(md!(home_session.text.clone())).into());
+            let button = |label: &str, action: &str| -> SlackActionBlockElement {
+                SlackBlockButtonElement::new(pt!(label.to_string()))
+                    .with_action_id(SlackActionId(format!("{action_prefix}_{action}")))
+                    .with_value(home_session.value.clone())
+                    .into()
+            };
+            blocks.push(
+                SlackActionsBlock::new(vec![
+                    button("New session", "new"),
+                    button("Stop", "stop"),
+                    button("Export", "export"),
+                ])
+                .into(),
+            );
+        }
+        blocks.push(SlackDividerBlock::new().into());
+        blocks.push(SlackHeaderBlock::new(pt!("Recent replies")).into());
+        let recent = if view.recent.is_empty() {
+            "Nothing yet.".to_string()
+        } else {
+            view.recent.join("\n")
+        };
+        blocks.push(SlackSectionBlock::new().with_text(md!(recent)).into());
+        let req = SlackApiViewsPublishRequest::new(
+            SlackUserId(user_id.to_string()),
+            SlackView::Home(SlackHomeView::new(blocks)),
+        );
+        session
+            .views_publish(&req)
+            .await
+            .context("failed to publish slack app home")?;
+        debug!("published app home user={user_id}");
+        Ok(())
+    }
+
+    async fn permalink(&self, channel: &str, ts: &str) -> Result<String> {
+        let token = self.tokens.for_channel(channel).await?;
+        let session = self.client.open_session(&token);
+        let req = SlackApiChatGetPermalinkRequest::new(
+            SlackChannelId(channel.to_string()),
+            SlackTs(ts.to_string()),
+        );
+        let resp = session
+            .chat_get_permalink(&req)
+            .await
+            .context("failed to get slack permalink")?;
+        Ok(resp.permalink.to_string())
+    }
+
+    /// Replace a message with plain text, dropping its blocks (and buttons).
+    async fn update_text(&self, channel: &str, ts: &str, text: &str) -> Result<()> {
+        let token = self.tokens.for_channel(channel).await?;
+        let session = self.client.open_session(&token);
+        let req = SlackApiChatUpdateRequest::new(
+            SlackChannelId(channel.to_string()),
+            SlackMessageContent::new()
+                .with_text(text.to_string())
+                .with_blocks(Vec::new()),
+            SlackTs(ts.to_string()),
+        );
+        session
+            .chat_update(&req)
+            .await
+            .context("failed to update slack message")?;
+        Ok(())
+    }
+
+    async fn add_reaction(&self, channel: &str, ts: &str, name: &str) -> Result<()> {
+        let token = self.tokens.for_channel(channel).await?;
+        let session = self.client.open_session(&token);
+        let req = SlackApiReactionsAddRequest::new(
+            SlackChannelId(channel.to_string()),
+            SlackReactionName(name.to_string()),
+            SlackTs(ts.to_string()),
+        );
+        session
+            .reactions_add(&req)
+            .await
+            .with_context(|| format!("failed to add reaction {name}"))?;
+        Ok(())
+    }
+
+    async fn remove_reaction(&self, channel: &str, ts: &str, name: &str) -> Result<()> {
+        let token = self.tokens.for_channel(channel).await?;
+        let session = self.client.open_session(&token);
+        let req = SlackApiReactionsRemoveRequest::new(SlackReactionName(name.to_string()))
+            .with_channel(SlackChannelId(channel.to_string()))
+            .with_timestamp(SlackTs(ts.to_string()));
+        session
+            .reactions_remove(&req)
+            .await
+            .with_context(|| format!("failed to remove reaction {name}"))?;
+        Ok(())
+    }
+
+    /// Create a canvas readable by the channel's members and return its id.
+    async fn create_canvas(
+        &self,
+        channel: &str,
+        title: &str,
+        markdown: &str,
+    ) -> Result<String> {
+        let token = self.tokens.for_channel(channel).await?;
+        let session = self.client.open_session(&token);
+        let req = SlackApiCanvasesCreateRequest::new()
+            .with_title(title.to_string())
+            .with_document_content(SlackCanvasDocumentContent::new(markdown.to_string()));
+        let resp = session
+            .canvases_create(&req)
+            .await
+            .context("failed to create slack canvas")?;
+        let access = SlackApiCanvasesAccessSetRequest::new(
+            resp.canvas_id.clone(),
+            SlackCanvasAccessLevel::Read,
+        )
+        .with_channel_ids(vec![SlackChannelId(channel.to_string())]);
+        if let Err(err) = session.canvases_access_set(&access).await {
+            warn!("canvas access not granted to {channel}: {err}");
+        }
+        info!("created canvas {}", resp.canvas_id);
+        Ok(resp.canvas_id.to_string())
+    }
+
+    /// Replace the whole content of a canvas.
+    async fn replace_canvas(
+        &self,
+        channel: &str,
+        canvas_id: &str,
+        markdown: &str,
+    ) -> Result<()> {
+        let token = self.tokens.for_channel(channel).await?;
+        let session = self.client.open_session(&token);
+        let change = SlackCanvasChange::new(SlackCanvasOperation::Replace)
+            .with_document_content(SlackCanvasDocumentContent::new(markdown.to_string()));
+        let req =
+            SlackApiCanvasesEditRequest::new(SlackCanvasId(canvas_id.to_string()), vec![change]);
+        session
+            .canvases_edit(&req)
+            .await
+            .context("failed to update slack canvas")?;
+        info!("updated canvas {canvas_id}");
+        Ok(())
+    }
+
+    async fn canvas_url(&self, channel: &str, canvas_id: &str) -> Result<String> {
+        let identity = self.identity(channel).await?;
+        Ok(format!(
+            "{}/docs/{}/{canvas_id}",
+            identity.workspace_url.trim_end_matches('/'),
+            identity.workspace_id
+        ))
+    }
+
+    /// Upload `content` as a text file shared into the conversation.
+    async fn upload_text(
+        &self,
+        conversation_id: &str,
+        thread_id: Option<&str>,
+        filename: &str,
+        content: &str,
+    ) -> Result<()> {
+        self.upload_bytes(
+            conversation_id,
+            thread_id,
+            filename,
+            content.as_bytes().to_vec(),
+            "text/plain",
+        )
+        .await
+    }
+
+    /// Upload a file shared into the conversation. Slack detects the type for display.
+    async fn upload_bytes(
+        &self,
+        conversation_id: &str,
+        thread_id: Option<&str>,
+        filename: &str,
+        content: Vec<u8>,
+        content_type: &str,
+    ) -> Result<()> {
+        debug!(
+            "uploading file channel={} thread={} filename={} bytes={}",
+            conversation_id,
+            thread_id.unwrap_or("-"),
+            filename,
+            content.len()
+        );
+        let token = self.tokens.for_channel(conversation_id).await?;
+        let session = self.client.open_session(&token);
+        let upload = session
+            .get_upload_url_external(&SlackApiFilesGetUploadUrlExternalRequest::new(
+                filename.to_string(),
+                content.len(),
+            ))
+            .await
+            .context("failed to get slack upload url")?;
+        session
+            .files_upload_via_url(&SlackApiFilesUploadViaUrlRequest::new(
+                upload.upload_url,
+                content,
+                content_type.to_string(),
+            ))
+            .await
+            .context("failed to upload file content")?;
+        let mut req = SlackApiFilesCompleteUploadExternalRequest::new(vec![
+            SlackApiFilesComplete::new(upload.file_id).with_title(filename.to_string()),
+        ])
+        .with_channel_id(SlackChannelId(conversation_id.to_string()));
+        if let Some(thread_id) = thread_id {
+            req = req.with_thread_ts(SlackTs(thread_id.to_string()));
+        }
+        session
+            .files_complete_upload_external(&req)
+            .await
+            .context("failed to complete slack file upload")?;
+        debug!("uploaded file");
         Ok(())
     }
+
+    /// Files are fetched with the bot token, which needs `files:read`. Without it Slack
+    /// answers with its HTML sign-in page instead of an error.
+    async fn download(
+        &self,
+        conversation_id: &str,
+        url: &str,
+        max_bytes: usize,
+    ) -> Result<Vec<u8>> {
+        let token = self.tokens.for_channel(conversation_id).await?;
+        let https = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .context("failed to load native TLS roots")?
+            .https_only()
+            .enable_http1()
+            .build();
+        let client = Client::builder(TokioExecutor::new()).build::<_, Empty<&[u8]>>(https);
+        let download = async {
+            let mut url = url.to_string();
+            for _ in 0..=DOWNLOAD_MAX_REDIRECTS {
+                let mut request = hyper::Request::get(&url);
+                if is_slack_host(&url) {
+                    let bearer = format!("Bearer {}", token.token_value.0);
+                    request = request.header("Authorization", bearer);
+                }
+                let request = request
+                    .body(Empty::new())
+                    .context("failed to build file download request")?;
+                let response = client
+                    .request(request)
+                    .await
+                    .context("failed to download slack file")?;
+                if response.status().is_redirection() {
+                    let location = response
+                        .headers()
+                        .get(hyper::header::LOCATION)
+                        .and_then(|value| value.to_str().ok())
+                        .context("slack file redirect without a location")?;
+                    url = url_fetch::resolve_location(&url, location);
+                    continue;
+                }
+                if !response.status().is_success() {
+                    bail!("slack file download failed: {}", response.status());
+                }
+                let is_html = response
+                    .headers()
+                    .get(hyper::header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .is_some_and(|value| value.starts_with("text/html"));
+                if is_html {
+                    bail!(
+                        "slack returned a sign-in page; the bot token needs the files:read scope"
+                    );
+                }
+                let body = Limited::new(response.into_body(), max_bytes)
+                    .collect()
+                    .await
+                    .map_err(|err| anyhow!("failed to read slack file: {err}"))?
+                    .to_bytes();
+                debug!("downloaded file bytes={}", body.len());
+                return Ok(body.to_vec());
+            }
+            bail!("slack file download redirected more than {DOWNLOAD_MAX_REDIRECTS} times")
+        };
+        tokio::time::timeout(DOWNLOAD_TIMEOUT, download)
+            .await
+            .map_err(|_| anyhow!("slack file download timed out after {DOWNLOAD_TIMEOUT:?}"))?
+    }
+
+    /// Fetch up to `limit` channel messages posted before `before_ts`, oldest first.
+    async fn channel_history(
+        &self,
+        channel: &str,
+        before_ts: Option<&str>,
+        limit: u16,
+    ) -> Result<Vec<TranscriptMessage>> {
+        let token = self.tokens.for_channel(channel).await?;
+        let session = self.client.open_session(&token);
+        let mut req = SlackApiConversationsHistoryRequest::new()
+            .with_channel(SlackChannelId(channel.to_string()))
+            .with_limit(limit);
+        if let Some(ts) = before_ts {
+            req = req.with_latest(SlackTs(ts.to_string())).with_inclusive(false);
+        }
+        let resp = session
+            .conversations_history(&req)
+            .await
+            .context("failed to fetch slack channel history")?;
+
+        let mut out = Vec::new();
+        for message in resp.messages.into_iter().rev() {
+            if let Some(message) = self.transcript_message(&token, message).await {
+                out.push(message);
+            }
+        }
+        debug!(
+            "fetched channel history channel={} messages={}",
+            channel,
+            out.len()
+        );
+        Ok(out)
+    }
+
+    /// Look the message up by its `ts`, in the replies of `thread_ts` when it is one.
+    async fn fetch_message(
+        &self,
+        channel: &str,
+        ts: &str,
+        thread_ts: Option<&str>,
+    ) -> Result<Option<TranscriptMessage>> {
+        let token = self.tokens.for_channel(channel).await?;
+        let session = self.client.open_session(&token);
+        let messages = match thread_ts.filter(|thread_ts| *thread_ts != ts) {
+            Some(thread_ts) => {
+                let req = SlackApiConversationsRepliesRequest::new(
+                    SlackChannelId(channel.to_string()),
+                    SlackTs(thread_ts.to_string()),
+                )
+                .with_oldest(SlackTs(ts.to_string()))
+                .with_latest(SlackTs(ts.to_string()))
+                .with_inclusive(true)
+                .with_limit(2);
+                session
+                    .conversations_replies(&req)
+                    .await
+                    .context("failed to fetch slack thread reply")?
+                    .messages
+            }
+            None => {
+                let req = SlackApiConversationsHistoryRequest::new()
+                    .with_channel(SlackChannelId(channel.to_string()))
+                    .with_oldest(SlackTs(ts.to_string()))
+                    .with_latest(SlackTs(ts.to_string()))
+                    .with_inclusive(true)
+                    .with_limit(1);
+                let messages = session
+                    .conversations_history(&req)
+                    .await
+                    .context("failed to fetch slack message")?
+                    .messages;
+                if messages.iter().any(|message| message.origin.ts.0 == ts) {
+                    messages
+                } else {
+                    // Thread replies are not in the history; `conversations.replies`
+                    // also takes the `ts` of a reply and returns its thread.
+                    let req = SlackApiConversationsRepliesRequest::new(
+                        SlackChannelId(channel.to_string()),
+                        SlackTs(ts.to_string()),
+                    )
+                    .with_oldest(SlackTs(ts.to_string()))
+                    .with_latest(SlackTs(ts.to_string()))
+                    .with_inclusive(true)
+                    .with_limit(2);
+                    session
+                        .conversations_replies(&req)
+                        .await
+                        .context("failed to fetch slack thread reply")?
+                        .messages
+                }
+            }
+        };
+        let Some(message) = messages.into_iter().find(|message| message.origin.ts.0 == ts) else {
+            return Ok(None);
+        };
+        Ok(self.transcript_message(&token, message).await)
+    }
 }
 
 async fn push_events_callback<SCHC>(
@@ -130,17 +965,19 @@ where
         guard
             .get_user_state::<SlackBridge>()
             .cloned()
-            .ok_or_else(|| "missing slack bridge")?
+            .ok_or("missing slack bridge")?
     };
 
+    let team_id = event.team_id.to_string();
     match event.event {
         SlackEventCallbackBody::AppMention(app_mention) => {
-            eprintln!("slack: received app_mention event");
+            debug!("received app_mention event");
             let channel_from_event = app_mention.channel.to_string();
             let raw_text = app_mention
                 .content
                 .text
                 .unwrap_or_else(|| "".to_string());
+            let attachments = attachments(app_mention.content.files);
             let channel_from_origin = app_mention
                 .origin
                 .channel
@@ -154,66 +991,400 @@ where
             let thread_id = app_mention.origin.thread_ts.map(|ts| ts.to_string());
             let timestamp = Some(app_mention.origin.ts.to_string());
 
-            eprintln!(
-                "slack: app_mention fields channel(event)={} channel(origin)={} thread={} text_len={}",
+            debug!(
+                "app_mention fields channel(event)={} channel(origin)={} thread={} text_len={}",
                 channel_from_event,
                 channel_from_origin,
                 thread_id.as_deref().unwrap_or("-"),
                 raw_text.len()
             );
 
-            if !raw_text.trim().is_empty() && !channel.is_empty() {
-                let display_name = resolve_user_display_name(
-                    _client.clone(),
-                    &bridge.bot_token,
-                    &bridge.user_cache,
-                    &app_mention.user,
-                )
-                .await
-                .unwrap_or_else(|| app_mention.user.to_string());
-                let text = format_incoming_text(&raw_text, &display_name);
-
-                eprintln!(
-                    "slack: app_mention -> incoming channel={} thread={}",
-                    channel,
-                    thread_id.as_deref().unwrap_or("-")
-                );
-                if bridge
-                    .tx
-                    .send(IncomingMessage {
-                        text,
-                        conversation_id: channel,
-                        thread_id,
-                        timestamp,
-                    })
-                    .is_err()
-                {
-                    eprintln!("slack: failed to enqueue incoming message");
+            if bridge.respond_without_mention {
+                debug!("app_mention ignored (handled as a message event)");
+                return Ok(());
+            }
+            if (!raw_text.trim().is_empty() || !attachments.is_empty()) && !channel.is_empty() {
+                let user = app_mention.user.to_string();
+                if !is_allowed(&bridge, &_client, &team_id, &user, &channel, true).await {
+                    return Ok(());
                 }
+                let message = IncomingMessage {
+                    text: raw_text,
+                    user_id: Some(user),
+                    user_name: None,
+                    conversation_id: channel,
+                    thread_id,
+                    timestamp,
+                    attachments,
+                };
+                forward_message(&bridge, _client, &team_id, "app_mention", message).await;
             } else {
-                eprintln!(
-                    "slack: app_mention ignored (empty text or channel) channel(event)={} channel(origin)={} text_len={}",
+                debug!(
+                    "app_mention ignored (empty text or channel) channel(event)={} channel(origin)={} text_len={}",
                     channel_from_event,
                     channel_from_origin,
                     raw_text.len()
                 );
             }
         }
+        SlackEventCallbackBody::Message(message) => {
+            let is_dm = message
+                .origin
+                .channel_type
+                .as_ref()
+                .is_some_and(|channel_type| channel_type.0 == "im");
+            if !is_dm && !bridge.respond_without_mention {
+                return Ok(());
+            }
+            // Edits, joins and bot posts (including ccterm's own replies) are not prompts.
+            let file_share = message.subtype == Some(SlackMessageEventType::FileShare);
+            if (message.subtype.is_some() && !file_share) || message.sender.bot_id.is_some() {
+                return Ok(());
+            }
+            let (Some(channel), Some(user)) = (message.origin.channel, message.sender.user)
+            else {
+                return Ok(());
+            };
+            let (raw_text, files) = match message.content {
+                Some(content) => (content.text.unwrap_or_default(), content.files),
+                None => (String::new(), None),
+            };
+            let attachments = attachments(files);
+            if raw_text.trim().is_empty() && attachments.is_empty() {
+                return Ok(());
+            }
+            debug!(
+                "received message event channel={channel} dm={is_dm} text_len={}",
+                raw_text.len()
+            );
+            if !is_allowed(&bridge, &_client, &team_id, user.as_ref(), channel.as_ref(), is_dm)
+                .await
+            {
+                return Ok(());
+            }
+            let message = IncomingMessage {
+                text: raw_text,
+                user_id: Some(user.to_string()),
+                user_name: None,
+                conversation_id: channel.to_string(),
+                thread_id: message.origin.thread_ts.map(|ts| ts.to_string()),
+                timestamp: Some(message.origin.ts.to_string()),
+                attachments,
+            };
+            forward_message(&bridge, _client, &team_id, "message", message).await;
+        }
+        SlackEventCallbackBody::AppHomeOpened(home) => {
+            if home.tab.as_deref() != Some("home") {
+                return Ok(());
+            }
+            debug!("received app_home_opened user={}", home.user);
+            bridge.tokens.note_user(home.user.as_ref(), &team_id).await;
+            if !is_allowed(&bridge, &_client, &team_id, home.user.as_ref(), "", false).await {
+                return Ok(());
+            }
+            if bridge
+                .tx
+                .send(IncomingEvent::HomeOpened(home.user.to_string()))
+                .is_err()
+            {
+                warn!("failed to enqueue app_home_opened");
+            }
+        }
+        SlackEventCallbackBody::ReactionAdded(reaction) => {
+            let SlackReactionsItem::Message(item) = reaction.item else {
+                debug!("reaction_added ignored (not a message)");
+                return Ok(());
+            };
+            let Some(channel) = item.origin.channel else {
+                debug!("reaction_added ignored (no channel)");
+                return Ok(());
+            };
+            debug!(
+                "received reaction_added reaction={} channel={} ts={}",
+                reaction.reaction, channel, item.origin.ts
+            );
+            bridge.tokens.note_channel(channel.as_ref(), &team_id).await;
+            let user = reaction.user.as_ref();
+            if !is_allowed(&bridge, &_client, &team_id, user, channel.as_ref(), false).await {
+                return Ok(());
+            }
+            if bridge
+                .tx
+                .send(IncomingEvent::Reaction(ReactionEvent {
+                    reaction: reaction.reaction.to_string(),
+                    user_id: reaction.user.to_string(),
+                    conversation_id: channel.to_string(),
+                    message_ts: item.origin.ts.to_string(),
+                }))
+                .is_err()
+            {
+                warn!("failed to enqueue reaction event");
+            }
+        }
         other => {
-            eprintln!("slack: received event {:?}", other);
+            debug!("received event {:?}", other);
         }
     }
 
     Ok(())
 }
 
-fn format_incoming_text(text: &str, display_name: &str) -> String {
+async fn interaction_events_callback<SCHC>(
+    event: SlackInteractionEvent,
+    _client: Arc<SlackClient<SCHC>>,
+    state: SlackClientEventsUserState,
+) -> UserCallbackResult<()>
+where
+    SCHC: SlackClientHttpConnector + Send + Sync + 'static,
+{
+    let bridge = {
+        let guard = state.read().await;
+        guard
+            .get_user_state::<SlackBridge>()
+            .cloned()
+            .ok_or("missing slack bridge")?
+    };
+    let event = match event {
+        SlackInteractionEvent::BlockActions(event) => event,
+        SlackInteractionEvent::MessageAction(event) => {
+            message_shortcut(event, _client, &bridge).await;
+            return Ok(());
+        }
+        _ => {
+            debug!("interaction ignored (not block_actions or message_action)");
+            return Ok(());
+        }
+    };
+    let Some(user) = event.user else {
+        debug!("block_actions ignored (no user)");
+        return Ok(());
+    };
+    let channel = match (event.channel, &event.view) {
+        (Some(channel), _) => channel.id.to_string(),
+        (None, Some(SlackView::Home(_))) => {
+            bridge.tokens.note_user(user.id.as_ref(), event.team.id.as_ref()).await;
+            String::new()
+        }
+        (None, _) => {
+            debug!("block_actions ignored (no channel)");
+            return Ok(());
+        }
+    };
+    let team_id = event.team.id.to_string();
+    let refuse = !channel.is_empty();
+    if !is_allowed(&bridge, &_client, &team_id, user.id.as_ref(), &channel, refuse).await {
+        return Ok(());
+    }
+    let message_ts = event.message.map(|message| message.origin.ts.to_string());
+    for action in event.actions.unwrap_or_default() {
+        debug!(
+            "received block action {} channel={}",
+            action.action_id,
+            if channel.is_empty() { "home" } else { &channel }
+        );
+        if bridge
+            .tx
+            .send(IncomingEvent::Action(ActionEvent {
+                action_id: action.action_id.to_string(),
+                value: action.value,
+                user_id: user.id.to_string(),
+                conversation_id: channel.clone(),
+                message_ts: message_ts.clone(),
+            }))
+            .is_err()
+        {
+            warn!("failed to enqueue block action");
+        }
+    }
+    Ok(())
+}
+
+async fn message_shortcut<SCHC>(
+    event: SlackInteractionMessageActionEvent,
+    client: Arc<SlackClient<SCHC>>,
+    bridge: &SlackBridge,
+) where
+    SCHC: SlackClientHttpConnector + Send + Sync + 'static,
+{
+    let (Some(channel), Some(message)) = (event.channel, event.message) else {
+        debug!("message_action ignored (no channel or message)");
+        return;
+    };
+    let team_id = event.team.id.to_string();
+    bridge.tokens.note_channel(channel.id.as_ref(), &team_id).await;
+    let user = event.user.id.as_ref();
+    if !is_allowed(bridge, &client, &team_id, user, channel.id.as_ref(), true).await {
+        return;
+    }
+    let (user_name, author_name) = match bridge.tokens.for_team(&team_id).await {
+        Some(token) => {
+            let cache = &bridge.user_cache;
+            let user_name =
+                resolve_user_display_name(client.clone(), &token, cache, &event.user.id).await;
+            let author_name = match &message.sender.user {
+                Some(author) => {
+                    resolve_user_display_name(client.clone(), &token, cache, author).await
+                }
+                None => None,
+            };
+            (user_name, author_name)
+        }
+        None => (None, None),
+    };
+    let ts = message.origin.ts.to_string();
+    let thread_id = message
+        .origin
+        .thread_ts
+        .map(|thread_ts| thread_ts.to_string())
+        .filter(|thread_ts| *thread_ts != ts);
+    debug!(
+        "received message shortcut {} channel={} ts={ts}",
+        event.callback_id, channel.id
+    );
+    if bridge
+        .tx
+        .send(IncomingEvent::Shortcut(ShortcutEvent {
+            callback_id: event.callback_id.to_string(),
+            user_id: event.user.id.to_string(),
+            user_name,
+            conversation_id: channel.id.to_string(),
+            message_ts: ts,
+            thread_id,
+            message_text: message.content.text.unwrap_or_default(),
+            author_name,
+        }))
+        .is_err()
+    {
+        warn!("failed to enqueue message shortcut");
+    }
+}
+
+/// Resolve the sender's display name, strip the leading bot mention, and pass the
+/// message to the coordinator. `message.text` is the raw Slack text.
+async fn forward_message<SCHC>(
+    bridge: &SlackBridge,
+    client: Arc<SlackClient<SCHC>>,
+    team_id: &str,
+    kind: &str,
+    mut message: IncomingMessage,
+) where
+    SCHC: SlackClientHttpConnector + Send + Sync + 'static,
+{
+    bridge
+        .tokens
+        .note_channel(&message.conversation_id, team_id)
+        .await;
+    let user_id = message.user_id.clone().unwrap_or_default();
+    let display_name = match bridge.tokens.for_team(team_id).await {
+        Some(token) => {
+            resolve_user_display_name(
+                client,
+                &token,
+                &bridge.user_cache,
+                &SlackUserId(user_id.clone()),
+            )
+            .await
+        }
+        None => None,
+    }
+    .unwrap_or(user_id);
+    message.user_name = Some(display_name);
+    message.text = clean_incoming_text(&message.text);
+
+    debug!(
+        "{kind} -> incoming channel={} thread={}",
+        message.conversation_id,
+        message.thread_id.as_deref().unwrap_or("-")
+    );
+    if bridge.tx.send(IncomingEvent::Message(message)).is_err() {
+        warn!("failed to enqueue incoming message");
+    }
+}
+
+/// Check `user` in `channel` against the allowlists. A refused request is logged and,
+/// with `reply`, its sender is told why in a message only they can see.
+async fn is_allowed<SCHC>(
+    bridge: &SlackBridge,
+    client: &Arc<SlackClient<SCHC>>,
+    team_id: &str,
+    user: &str,
+    channel: &str,
+    reply: bool,
+) -> bool
+where
+    SCHC: SlackClientHttpConnector + Send + Sync + 'static,
+{
+    let Some(reason) = bridge.access.refusal(user, channel) else {
+        return true;
+    };
+    info!(
+        "refused request from user={user} channel={}",
+        if channel.is_empty() { "home" } else { channel }
+    );
+    if !reply {
+        return false;
+    }
+    let Some(token) = bridge.tokens.for_team(team_id).await else {
+        return false;
+    };
+    let req = SlackApiChatPostEphemeralRequest {
+        channel: SlackChannelId(channel.to_string()),
+        user: SlackUserId(user.to_string()),
+        content: SlackMessageContent::new().with_text(reason.to_string()),
+        as_user: None,
+        icon_emoji: None,
+        icon_url: None,
+        link_names: None,
+        parse: None,
+        thread_ts: None,
+        username: None,
+    };
+    if let Err(err) = client.open_session(&token).chat_post_ephemeral(&req).await {
+        warn!("failed to tell user={user} their request was refused: {err}");
+    }
+    false
+}
+
+/// Downloadable files of a message. Files without a private URL (external or deleted
+/// ones) are left out.
+fn attachments(files: Option<Vec<SlackFile>>) -> Vec<Attachment> {
+    files
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|file| {
+            let url = file.url_private_download.or(file.url_private)?;
+            let name = file
+                .name
+                .or(file.title)
+                .unwrap_or_else(|| file.id.to_string());
+            Some(Attachment {
+                name,
+                url: url.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Whether the bot token may be sent to `url`.
+fn is_slack_host(url: &str) -> bool {
+    url.parse::<hyper::Uri>()
+        .ok()
+        .and_then(|uri| uri.host().map(str::to_string))
+        .is_some_and(|host| host == "slack.com" || host.ends_with(".slack.com"))
+}
+
+/// Direct message conversations have ids starting with `D`.
+fn is_direct_channel(channel: &str) -> bool {
+    channel.starts_with('D')
+}
+
+fn clean_incoming_text(text: &str) -> String {
     let cleaned = strip_leading_mention(text);
     let cleaned = cleaned.trim();
     if cleaned.is_empty() {
         return text.trim().to_string();
     }
-    format!("{display_name}: {cleaned}")
+    cleaned.to_string()
 }
 
 fn strip_leading_mention(text: &str) -> String {
@@ -273,3 +1444,26 @@ fn normalize_name(name: Option<&str>) -> Option<String> {
         Some(name.to_string())
     }
 }
+
+fn is_missing_thread(err: &SlackClientError) -> bool {
+    matches!(
+        err,
+        SlackClientError::ApiError(err) if MISSING_THREAD_ERRORS.contains(&err.code.as_str())
+    )
+}
+
+fn approval_buttons(action_prefix: &str, value: &str, approve: &str, reject: &str) -> SlackBlock {
+    SlackActionsBlock::new(vec![
+        SlackBlockButtonElement::new(pt!(approve.to_string()))
+            .with_action_id(SlackActionId(format!("{action_prefix}_approve")))
+            .with_value(value.to_string())
+            .with_style(SlackBlockButtonStyle::Primary)
+            .into(),
+        SlackBlockButtonElement::new(pt!(reject.to_string()))
+            .with_action_id(SlackActionId(format!("{action_prefix}_reject")))
+            .with_value(value.to_string())
+            .with_style(SlackBlockButtonStyle::Danger)
+            .into(),
+    ])
+    .into()
+}
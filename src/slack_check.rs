@@ -0,0 +1,139 @@
+use crate::config::{Config, GitDiff, ReactionAction};
+use anyhow::{bail, Context, Result};
+use http_body_util::{BodyExt, Empty};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use serde::Deserialize;
+use slack_morphism::prelude::*;
+use tracing::info;
+
+/// Bot scopes without which ccterm cannot receive mentions or reply.
+const BASE_SCOPES: [&str; 2] = ["app_mentions:read", "chat:write"];
+
+/// `BASE_SCOPES` plus the scopes of the features `config` turns on: `reactions:write`
+/// for status reactions, and `files:write` when replies, diffs, transcripts or files may
+/// be uploaded.
+pub fn required_scopes(config: &Config) -> Vec<&'static str> {
+    let mut scopes = BASE_SCOPES.to_vec();
+    let routes = &config.routes;
+    if config.reactions.enabled || routes.iter().any(|route| route.reactions == Some(true)) {
+        scopes.push("reactions:write");
+    }
+    let coordinator = &config.coordinator;
+    let uploads = coordinator.diff_upload_lines > 0
+        || coordinator.reply_truncate_chars > 0
+        || coordinator.turn_artifacts
+        || coordinator.upload_referenced_files
+        || !coordinator.upload_globs.is_empty()
+        || coordinator.git_diff == GitDiff::Patch
+        || routes.iter().any(|route| route.git_diff == Some(GitDiff::Patch))
+        || config.reactions.actions.values().any(|action| *action == ReactionAction::Transcript);
+    if uploads {
+        scopes.push("files:write");
+    }
+    scopes
+}
+
+const AUTH_TEST_URL: &str = "https://slack.com/api/auth.test";
+
+#[derive(Debug, Deserialize)]
+struct AuthTest {
+    ok: bool,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    team: Option<String>,
+}
+
+/// Check both tokens before connecting, so a missing scope is reported at startup
+/// instead of as a failed API call later.
+pub async fn verify(config: &Config) -> Result<()> {
+    verify_app_token(&config.slack.app_token).await?;
+    let bot_token = config.slack.bot_token.trim();
+    if bot_token.is_empty() {
+        // Workspaces installed through OAuth were granted the configured scopes.
+        return Ok(());
+    }
+    let team = verify_bot_token(bot_token, &required_scopes(config)).await?;
+    info!("bot token for {team} has the required scopes");
+    Ok(())
+}
+
+/// Open a Socket Mode connection URL with the app-level token.
+pub async fn verify_app_token(app_token: &str) -> Result<()> {
+    let connector = SlackClientHyperHttpsConnector::new()
+        .context("failed to create slack hyper connector")?;
+    let client = SlackClient::new(connector);
+    let app_token = SlackApiToken::new(SlackApiTokenValue(app_token.to_string()));
+    client
+        .open_session(&app_token)
+        .apps_connections_open(&SlackApiAppsConnectionOpenRequest::new())
+        .await
+        .context(
+            "slack.app_token check failed (apps.connections.open); Socket Mode must be \
+             enabled and the token needs connections:write",
+        )?;
+    Ok(())
+}
+
+/// Check the bot token with `auth.test` and return the workspace name when it has
+/// `required`, from `required_scopes`.
+pub async fn verify_bot_token(bot_token: &str, required: &[&str]) -> Result<String> {
+    let (team, granted) = auth_test(bot_token).await?;
+    let missing: Vec<&str> = required
+        .iter()
+        .copied()
+        .filter(|scope| !granted.iter().any(|granted| granted == scope))
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "slack.bot_token for {team} is missing required scopes: {}. Add them under \
+             OAuth & Permissions and reinstall the app.",
+            missing.join(", ")
+        );
+    }
+    Ok(team)
+}
+
+/// Call `auth.test` and return the workspace name and the scopes Slack reports in the
+/// `x-oauth-scopes` header, which the API client does not expose.
+async fn auth_test(token: &str) -> Result<(String, Vec<String>)> {
+    let https = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .context("failed to load native TLS roots")?
+        .https_only()
+        .enable_http1()
+        .build();
+    let client = Client::builder(TokioExecutor::new()).build::<_, Empty<&[u8]>>(https);
+    let request = hyper::Request::post(AUTH_TEST_URL)
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Empty::new())
+        .context("failed to build auth.test request")?;
+    let response = client
+        .request(request)
+        .await
+        .context("failed to call auth.test")?;
+    let granted: Vec<String> = response
+        .headers()
+        .get("x-oauth-scopes")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .split(',')
+        .map(|scope| scope.trim().to_string())
+        .filter(|scope| !scope.is_empty())
+        .collect();
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .context("failed to read auth.test response")?
+        .to_bytes();
+    let auth: AuthTest = serde_json::from_slice(&body).context("failed to parse auth.test")?;
+    if !auth.ok {
+        bail!(
+            "slack.bot_token was rejected by auth.test: {}",
+            auth.error.as_deref().unwrap_or("unknown error")
+        );
+    }
+    Ok((auth.team.unwrap_or_else(|| "the workspace".to_string()), granted))
+}
@@ -0,0 +1,249 @@
+use crate::coordinator;
+use crate::hooks;
+use crate::paths;
+use crate::sessions::{self, SpawnOptions, TmuxSessionManager};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Snapshot directories live under `paths::state_dir`.
+pub const SNAPSHOTS_SUBDIR: &str = "snapshots";
+const MANIFEST_FILE: &str = "manifest.json";
+const TRANSCRIPT_FILE: &str = "transcript.jsonl";
+const WORKDIR_ARCHIVE: &str = "workdir.tar.gz";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub session_name: String,
+    pub created_at: u64,
+    pub cwd: PathBuf,
+    /// Session cwd relative to the base cwd, used to place the restore on another host.
+    pub relative_cwd: Option<PathBuf>,
+    pub conversation_id: Option<String>,
+    pub thread_id: Option<String>,
+    pub claude_session_id: Option<String>,
+    pub transcript_file: Option<String>,
+    pub workdir_archive: Option<String>,
+}
+
+pub struct SnapshotPaths<'a> {
+    pub base_cwd: &'a Path,
+    pub hook_events_path: &'a Path,
+}
+
+impl SnapshotPaths<'_> {
+    fn hook_path_for_cwd(&self, cwd: &Path) -> PathBuf {
+        if self.hook_events_path.is_absolute() {
+            self.hook_events_path.to_path_buf()
+        } else {
+            cwd.join(self.hook_events_path)
+        }
+    }
+}
+
+/// Copy the transcript, archive the thread directory, and record a manifest.
+pub fn create(
+    manager: &TmuxSessionManager,
+    paths: &SnapshotPaths,
+    session_name: &str,
+    conversation_id: Option<String>,
+) -> Result<PathBuf> {
+    let cwd = manager.pane_cwd(session_name)?;
+    let cwd = cwd.canonicalize().unwrap_or(cwd);
+    let created_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("system time before UNIX_EPOCH")?
+        .as_secs();
+    let dir = crate::paths::state_dir(paths.base_cwd)
+        .join(SNAPSHOTS_SUBDIR)
+        .join(format!("{session_name}-{created_at}"));
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("failed to create snapshot dir: {}", dir.display()))?;
+
+    let relative_cwd = cwd.strip_prefix(paths.base_cwd).ok().map(Path::to_path_buf);
+    let thread_id = thread_id_from_cwd(relative_cwd.as_deref());
+
+    let latest = latest_hook_for_cwd(&paths.hook_path_for_cwd(&cwd), &cwd)?;
+    let mut transcript_file = None;
+    let mut claude_session_id = None;
+    if let Some(event) = latest {
+        claude_session_id = Some(event.session_id.clone());
+        std::fs::copy(&event.transcript_path, dir.join(TRANSCRIPT_FILE)).with_context(|| {
+            format!(
+                "failed to copy transcript: {}",
+                event.transcript_path.display()
+            )
+        })?;
+        transcript_file = Some(TRANSCRIPT_FILE.to_string());
+    } else {
+        eprintln!("snapshot: no hook event for {}, transcript skipped", cwd.display());
+    }
+
+    // The base directory is the project itself; only per-thread directories are archived.
+    let mut workdir_archive = None;
+    if thread_id.is_some() {
+        let status = Command::new("tar")
+            .arg("-czf")
+            .arg(dir.join(WORKDIR_ARCHIVE))
+            .arg("-C")
+            .arg(&cwd)
+            .arg(".")
+            .status()
+            .context("failed to run tar")?;
+        if !status.success() {
+            bail!("tar failed with status: {status}");
+        }
+        workdir_archive = Some(WORKDIR_ARCHIVE.to_string());
+    }
+
+    let manifest = SnapshotManifest {
+        session_name: session_name.to_string(),
+        created_at,
+        cwd,
+        relative_cwd,
+        conversation_id,
+        thread_id,
+        claude_session_id,
+        transcript_file,
+        workdir_archive,
+    };
+    let mut out =
+        serde_json::to_string_pretty(&manifest).context("failed to render snapshot manifest")?;
+    out.push('\n');
+    paths::write_atomic(&dir.join(MANIFEST_FILE), out)
+        .context("failed to write snapshot manifest")?;
+    Ok(dir)
+}
+
+pub struct RestoreOptions<'a> {
+    pub base_cwd: &'a Path,
+    pub settings_template: Option<&'a str>,
+    pub target_cwd: Option<PathBuf>,
+    pub resume: bool,
+    pub session_prefix: &'a str,
+}
+
+/// Unpack a snapshot into a working directory, install its transcript where Claude
+/// looks for resumable sessions, and optionally start a session with `--resume`.
+pub fn restore(
+    manager: &TmuxSessionManager,
+    snapshot_dir: &Path,
+    opts: &RestoreOptions,
+) -> Result<Option<String>> {
+    let manifest_path = snapshot_dir.join(MANIFEST_FILE);
+    let content = std::fs::read_to_string(&manifest_path)
+        .with_context(|| format!("failed to read manifest: {}", manifest_path.display()))?;
+    let manifest: SnapshotManifest =
+        serde_json::from_str(&content).context("failed to parse snapshot manifest")?;
+
+    let target = match (&opts.target_cwd, &manifest.relative_cwd) {
+        (Some(target), _) => target.clone(),
+        (None, Some(relative)) => opts.base_cwd.join(relative),
+        (None, None) => manifest.cwd.clone(),
+    };
+    std::fs::create_dir_all(&target)
+        .with_context(|| format!("failed to create restore dir: {}", target.display()))?;
+    let target = target.canonicalize().unwrap_or(target);
+
+    if let Some(archive) = &manifest.workdir_archive {
+        let status = Command::new("tar")
+            .arg("-xzf")
+            .arg(snapshot_dir.join(archive))
+            .arg("-C")
+            .arg(&target)
+            .status()
+            .context("failed to run tar")?;
+        if !status.success() {
+            bail!("tar failed with status: {status}");
+        }
+        if let Some(template) = opts.settings_template {
+            let exe_path = std::env::current_exe().context("failed to resolve ccterm path")?;
+            let exe_path = exe_path.canonicalize().unwrap_or(exe_path);
+            let settings = coordinator::render_thread_settings(template, &exe_path.to_string_lossy())?;
+            paths::write_atomic(&target.join(".claude/settings.json"), settings)
+                .context("failed to write restored settings.json")?;
+        }
+    }
+
+    if let (Some(file), Some(session_id)) = (&manifest.transcript_file, &manifest.claude_session_id)
+    {
+        let project_dir = claude_project_dir(&target)?;
+        std::fs::create_dir_all(&project_dir)
+            .with_context(|| format!("failed to create {}", project_dir.display()))?;
+        let dest = project_dir.join(format!("{session_id}.jsonl"));
+        std::fs::copy(snapshot_dir.join(file), &dest)
+            .with_context(|| format!("failed to install transcript: {}", dest.display()))?;
+        eprintln!("snapshot: transcript installed at {}", dest.display());
+    }
+
+    if !opts.resume {
+        return Ok(None);
+    }
+    let session_id = manifest
+        .claude_session_id
+        .as_deref()
+        .context("snapshot has no claude session id to resume")?;
+    let session_name = sessions::timestamp_session_name(opts.session_prefix)?;
+    manager
+        .spawn_with(
+            &session_name,
+            &target,
+            &SpawnOptions {
+                args: vec!["--resume".to_string(), session_id.to_string()],
+                env: Vec::new(),
+            },
+        )
+        .with_context(|| format!("failed to spawn resumed session {session_name}"))?;
+    Ok(Some(session_name))
+}
+
+/// Claude stores transcripts under `~/.claude/projects/<cwd with non-alphanumerics as '-'>`.
+pub fn claude_project_dir(cwd: &Path) -> Result<PathBuf> {
+    let config_dir = match std::env::var_os("CLAUDE_CONFIG_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = std::env::var_os("HOME").context("HOME is not set")?;
+            PathBuf::from(home).join(".claude")
+        }
+    };
+    let encoded: String = cwd
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    Ok(config_dir.join("projects").join(encoded))
+}
+
+fn latest_hook_for_cwd(hook_path: &Path, cwd: &Path) -> Result<Option<hooks::HookEvent>> {
+    if !hook_path.exists() {
+        return Ok(None);
+    }
+    let file = std::fs::File::open(hook_path)
+        .with_context(|| format!("failed to open hook events: {}", hook_path.display()))?;
+    let mut latest = None;
+    for line in std::io::BufReader::new(file).lines() {
+        let line = line.context("failed to read hook events")?;
+        let Ok(event) = hooks::parse_hook_line(&line) else {
+            continue;
+        };
+        let event_cwd = event.cwd.canonicalize().unwrap_or(event.cwd.clone());
+        if event_cwd == cwd {
+            latest = Some(event);
+        }
+    }
+    Ok(latest)
+}
+
+fn thread_id_from_cwd(relative_cwd: Option<&Path>) -> Option<String> {
+    let relative = relative_cwd?;
+    let name = relative
+        .strip_prefix(".ccterm/threads")
+        .ok()?
+        .to_string_lossy()
+        .to_string();
+    // Thread dirs are sanitized Slack timestamps (`1700000000.000100` -> `1700000000_000100`).
+    Some(name.replace('_', "."))
+}
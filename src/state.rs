@@ -0,0 +1,317 @@
+use crate::config::{self, BashResultsMode, OutputTarget, ReplyFormat};
+use crate::context::TokenUsage;
+use crate::paths;
+use anyhow::{bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Coordinator state that should survive restarts, stored as JSON files under
+/// `paths::state_dir`.
+pub const STATE_SUBDIR: &str = "state";
+
+pub struct StateStore {
+    dir: PathBuf,
+    /// `<base cwd>/.ccterm/state`, read when a file has not been written to `dir` yet.
+    legacy_dir: PathBuf,
+}
+
+impl StateStore {
+    pub fn new(base_cwd: &Path) -> Self {
+        Self {
+            dir: paths::state_dir(base_cwd).join(STATE_SUBDIR),
+            legacy_dir: base_cwd.join(paths::LEGACY_STATE_DIR).join(STATE_SUBDIR),
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.json"))
+    }
+
+    /// Load `<name>.json`, or the default value when it does not exist yet.
+    pub fn load<T: DeserializeOwned + Default>(&self, name: &str) -> Result<T> {
+        let mut path = self.path(name);
+        if !path.exists() {
+            path = self.legacy_dir.join(format!("{name}.json"));
+        }
+        if !path.exists() {
+            return Ok(T::default());
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read state: {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse state: {}", path.display()))
+    }
+
+    /// Load the lines of `<name>.jsonl` that parse, oldest first, from its last
+    /// `max_bytes` only, so reading a log that only grows stays cheap. The line cut by
+    /// the window is skipped.
+    pub fn load_recent_lines<T: DeserializeOwned>(
+        &self,
+        name: &str,
+        max_bytes: u64,
+    ) -> Result<Vec<T>> {
+        let path = self.dir.join(format!("{name}.jsonl"));
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut file = std::fs::File::open(&path)
+            .with_context(|| format!("failed to open state: {}", path.display()))?;
+        let len = file
+            .metadata()
+            .with_context(|| format!("failed to read state: {}", path.display()))?
+            .len();
+        let start = len.saturating_sub(max_bytes);
+        file.seek(SeekFrom::Start(start))
+            .with_context(|| format!("failed to seek in state: {}", path.display()))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
+            .with_context(|| format!("failed to read state: {}", path.display()))?;
+        let content = String::from_utf8_lossy(&bytes);
+        let mut lines = content.lines();
+        if start > 0 {
+            lines.next();
+        }
+        Ok(lines.filter_map(|line| serde_json::from_str(line).ok()).collect())
+    }
+
+    /// Append one JSON line to `<name>.jsonl`.
+    pub fn append<T: Serialize>(&self, name: &str, value: &T) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create state dir: {}", self.dir.display()))?;
+        let path = self.dir.join(format!("{name}.jsonl"));
+        let mut line = serde_json::to_string(value).context("failed to render state")?;
+        line.push('\n');
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("failed to open state: {}", path.display()))?;
+        file.write_all(line.as_bytes())
+            .with_context(|| format!("failed to write state: {}", path.display()))
+    }
+
+    pub fn save<T: Serialize>(&self, name: &str, value: &T) -> Result<()> {
+        self.write(name, value, paths::write_atomic)
+    }
+
+    /// `save` for state holding credentials, written owner-only.
+    pub fn save_private<T: Serialize>(&self, name: &str, value: &T) -> Result<()> {
+        self.write(name, value, paths::write_private)
+    }
+
+    fn write<T: Serialize>(
+        &self,
+        name: &str,
+        value: &T,
+        write: fn(&Path, String) -> std::io::Result<()>,
+    ) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create state dir: {}", self.dir.display()))?;
+        let path = self.path(name);
+        let mut out = serde_json::to_string_pretty(value).context("failed to render state")?;
+        out.push('\n');
+        write(&path, out).with_context(|| format!("failed to write state: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// A conversation's session, saved so a restarted coordinator reattaches to its tmux
+/// session instead of spawning another one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub conversation_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+    pub session_name: String,
+    pub cwd: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_transcript_path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_sent_message_uuid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub turn_commits: Vec<String>,
+    #[serde(default)]
+    pub fingerprint: SessionFingerprint,
+}
+
+/// What a session was started with, recorded at spawn for reproducibility.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionFingerprint {
+    /// `claude --version` output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claude_version: Option<String>,
+    /// `--model` argument or `ANTHROPIC_MODEL`; Claude's default when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// `--permission-mode` argument, or the permission policy's default action.
+    #[serde(default)]
+    pub permission_mode: String,
+    /// Short SHA of `HEAD` in the session directory, when it is in a git repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_sha: Option<String>,
+}
+
+impl SessionFingerprint {
+    /// `claude 2.0.1 · model default · permissions ask · git abc1234`.
+    pub fn summary(&self) -> String {
+        format!(
+            "claude {} · model {} · permissions {} · git {}",
+            self.claude_version.as_deref().unwrap_or("unknown"),
+            self.model.as_deref().unwrap_or("default"),
+            self.permission_mode,
+            self.git_sha.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+/// A stopped session that a reply can still resume, saved so a restart keeps it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParkedRecord {
+    pub conversation_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+    pub claude_session_id: String,
+    pub token: String,
+    /// RFC 3339, UTC.
+    pub parked_at: String,
+}
+
+/// One finished turn, appended to `turns.jsonl` when the turn ends.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnRecord {
+    pub conversation_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+    pub session_name: String,
+    /// Sender of the message that started the turn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    /// Omitted with `audit.redact_content`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// RFC 3339, UTC.
+    pub started_at: String,
+    pub ended_at: String,
+    /// Claude's `stop_reason`, or `timed_out` for turns interrupted by `max_turn_secs`.
+    pub stop_reason: String,
+    #[serde(default)]
+    pub usage: TokenUsage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_ts: Option<String>,
+}
+
+/// Set with `ccterm ctl maintenance`, kept across restarts so an upgrade does not end it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MaintenanceState {
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Per-channel overrides set with `!set`. They take precedence over routes and the
+/// global config, and apply to sessions spawned after the change.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChannelSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_in_thread: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputTarget>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_format: Option<ReplyFormat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reactions: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_turn_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bash_results: Option<BashResultsMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_hard_limit_mb: Option<u64>,
+}
+
+/// Settings only `permission_approvers` may change, since they lift a safety limit.
+pub const APPROVER_SETTING_KEYS: [&str; 1] = ["disk_hard_limit_mb"];
+
+pub const CHANNEL_SETTING_KEYS: [&str; 8] = [
+    "model",
+    "reply_in_thread",
+    "output",
+    "reply_format",
+    "reactions",
+    "max_turn_secs",
+    "bash_results",
+    "disk_hard_limit_mb",
+];
+
+impl ChannelSettings {
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "model" => self.model = Some(value.to_string()),
+            "reply_in_thread" => self.reply_in_thread = Some(parse_value(key, value)?),
+            "output" => self.output = Some(parse_value(key, value)?),
+            "reply_format" => self.reply_format = Some(parse_value(key, value)?),
+            "reactions" => self.reactions = Some(parse_value(key, value)?),
+            "max_turn_secs" => self.max_turn_secs = Some(parse_value(key, value)?),
+            "bash_results" => self.bash_results = Some(parse_value(key, value)?),
+            "disk_hard_limit_mb" => {
+                let mb = parse_value(key, value)?;
+                config::check_range(key, mb, 1..=100_000_000)?;
+                self.disk_hard_limit_mb = Some(mb);
+            }
+            _ => bail!(
+                "unknown setting `{key}` (available: {})",
+                CHANNEL_SETTING_KEYS.join(", ")
+            ),
+        }
+        Ok(())
+    }
+
+    pub fn unset(&mut self, key: &str) -> Result<()> {
+        match key {
+            "model" => self.model = None,
+            "reply_in_thread" => self.reply_in_thread = None,
+            "output" => self.output = None,
+            "reply_format" => self.reply_format = None,
+            "reactions" => self.reactions = None,
+            "max_turn_secs" => self.max_turn_secs = None,
+            "bash_results" => self.bash_results = None,
+            "disk_hard_limit_mb" => self.disk_hard_limit_mb = None,
+            _ => bail!("unknown setting `{key}`"),
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+
+    /// Set keys with their values rendered as they would be typed in `!set`.
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        let Ok(Value::Object(map)) = serde_json::to_value(self) else {
+            return Vec::new();
+        };
+        CHANNEL_SETTING_KEYS
+            .iter()
+            .filter_map(|key| {
+                let value = map.get(*key)?;
+                let rendered = match value {
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                };
+                Some((*key, rendered))
+            })
+            .collect()
+    }
+}
+
+/// Parse a `!set` value with the same serde rules as the config file.
+fn parse_value<T: DeserializeOwned>(key: &str, value: &str) -> Result<T> {
+    let json = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+    serde_json::from_value(json).with_context(|| format!("invalid value for `{key}`: {value}"))
+}
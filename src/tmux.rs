@@ -0,0 +1,144 @@
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Commands taking longer than this are logged even when they succeed.
+const SLOW_COMMAND: Duration = Duration::from_millis(500);
+
+static CALLS: AtomicU64 = AtomicU64::new(0);
+static FAILURES: AtomicU64 = AtomicU64::new(0);
+static TOTAL_MICROS: AtomicU64 = AtomicU64::new(0);
+static SLOWEST_MICROS: AtomicU64 = AtomicU64::new(0);
+
+/// tmux invocations since startup, for `!status`.
+#[derive(Debug, Clone, Copy)]
+pub struct TmuxStats {
+    pub calls: u64,
+    pub failures: u64,
+    pub average: Duration,
+    pub slowest: Duration,
+}
+
+pub fn stats() -> TmuxStats {
+    let calls = CALLS.load(Ordering::Relaxed);
+    let total = TOTAL_MICROS.load(Ordering::Relaxed);
+    TmuxStats {
+        calls,
+        failures: FAILURES.load(Ordering::Relaxed),
+        average: Duration::from_micros(total.checked_div(calls).unwrap_or(0)),
+        slowest: Duration::from_micros(SLOWEST_MICROS.load(Ordering::Relaxed)),
+    }
+}
+
+/// Run tmux and return its stdout. A failure carries tmux's stderr, and is logged with
+/// the command, duration and exit status.
+pub fn run(args: &[&str]) -> Result<String> {
+    let output = execute(args, true)?;
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Whether tmux succeeds, for probes such as `has-session` where failing is an answer
+/// rather than an error. Failures are counted but not logged.
+pub fn succeeds(args: &[&str]) -> bool {
+    execute(args, false).is_ok()
+}
+
+/// Attach a control-mode client (`tmux -C`) to `session_name` and return a counter that
+/// is bumped whenever one of its panes prints output. The sender is dropped when the
+/// session ends or the client exits, which closes the receiver.
+///
+/// The client is read-only and ignored for window sizing, so it does not disturb the
+/// pane Claude draws into.
+pub fn watch_output(session_name: &str) -> Result<watch::Receiver<u64>> {
+    let command = format!("tmux -C attach-session -t {session_name}");
+    let mut child = Command::new("tmux")
+        .args([
+            "-C",
+            "attach-session",
+            "-f",
+            "ignore-size,read-only",
+            "-t",
+            session_name,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("failed to run {command}"))?;
+    record(Duration::ZERO, false);
+    let stdout = child.stdout.take().context("tmux control stdout unavailable")?;
+    let (tx, rx) = watch::channel(0u64);
+    let session_name = session_name.to_string();
+    std::thread::spawn(move || {
+        // Control mode exits when stdin closes, so it is held until the client ends.
+        let _stdin = child.stdin.take();
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.starts_with("%output ") || line.starts_with("%extended-output ") {
+                tx.send_modify(|count| *count += 1);
+            } else if line.starts_with("%exit") {
+                break;
+            }
+        }
+        drop(tx);
+        let _ = child.kill();
+        let _ = child.wait();
+        info!("control client for {session_name} ended");
+    });
+    Ok(rx)
+}
+
+fn execute(args: &[&str], log_failure: bool) -> Result<Output> {
+    let command = describe(args);
+    let started = Instant::now();
+    let output = Command::new("tmux")
+        .args(args)
+        .stdin(Stdio::null())
+        .output();
+    let elapsed = started.elapsed();
+    record(elapsed, !matches!(&output, Ok(output) if output.status.success()));
+    let output = output.with_context(|| format!("failed to run {command}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        if log_failure {
+            warn!(
+                "{command} failed with {} after {}ms: {stderr}",
+                output.status,
+                elapsed.as_millis()
+            );
+        }
+        bail!("{command} failed with {}: {stderr}", output.status);
+    }
+    if elapsed >= SLOW_COMMAND {
+        warn!("{command} took {}ms", elapsed.as_millis());
+    }
+    Ok(output)
+}
+
+fn record(elapsed: Duration, failed: bool) {
+    let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+    CALLS.fetch_add(1, Ordering::Relaxed);
+    TOTAL_MICROS.fetch_add(micros, Ordering::Relaxed);
+    SLOWEST_MICROS.fetch_max(micros, Ordering::Relaxed);
+    if failed {
+        FAILURES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// `tmux <subcommand> -t <target>` for logs and errors. Other arguments are left out,
+/// since `send-keys` carries message text and `new-session` the session environment.
+fn describe(args: &[&str]) -> String {
+    let mut command = format!("tmux {}", args.first().copied().unwrap_or_default());
+    if let Some(idx) = args.iter().position(|arg| *arg == "-t" || *arg == "-s")
+        && let Some(target) = args.get(idx + 1)
+    {
+        command.push_str(&format!(" {} {target}", args[idx]));
+    }
+    command
+}
@@ -1,3 +1,4 @@
+use jiff::Timestamp;
 use serde::Serialize;
 
 #[derive(Debug, Clone)]
@@ -10,14 +11,132 @@ pub enum Role {
 pub struct TranscriptMessage {
     pub role: Role,
     pub text: String,
+    /// When the message was sent, if the source records it.
+    pub timestamp: Option<Timestamp>,
+    /// Display name of the person who sent it, when the chat backend provides one.
+    pub speaker: Option<String>,
+    pub thread: Option<ThreadMarker>,
+}
+
+/// Where a channel message stands relative to a thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadMarker {
+    /// The message started a thread with this many replies.
+    Parent { replies: usize },
+    /// A thread reply also sent to the channel.
+    Reply,
 }
 
 #[derive(Debug, Clone)]
 pub struct IncomingMessage {
+    /// Message text with the leading bot mention removed.
     pub text: String,
+    pub user_id: Option<String>,
+    pub user_name: Option<String>,
     pub conversation_id: String,
     pub thread_id: Option<String>,
     pub timestamp: Option<String>,
+    /// Files shared with the message.
+    pub attachments: Vec<Attachment>,
+}
+
+/// A file shared with a chat message.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub name: String,
+    /// Download URL; fetching it takes the chat backend's credentials.
+    pub url: String,
+}
+
+impl IncomingMessage {
+    /// Text sent to Claude: `text`, the message text without its directives, prefixed
+    /// with the speaker's name when known.
+    pub fn prompt_text(&self, text: &str) -> String {
+        match &self.user_name {
+            Some(name) => format!("{name}: {text}"),
+            None => text.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReactionEvent {
+    pub reaction: String,
+    pub user_id: String,
+    pub conversation_id: String,
+    pub message_ts: String,
+}
+
+/// A button click on a message posted by ccterm, or on the App Home tab.
+#[derive(Debug, Clone)]
+pub struct ActionEvent {
+    pub action_id: String,
+    pub value: Option<String>,
+    pub user_id: String,
+    /// Empty for buttons on the App Home tab.
+    pub conversation_id: String,
+    pub message_ts: Option<String>,
+}
+
+/// A message shortcut run on a message, e.g. "Ask ccterm about this".
+#[derive(Debug, Clone)]
+pub struct ShortcutEvent {
+    pub callback_id: String,
+    pub user_id: String,
+    pub user_name: Option<String>,
+    pub conversation_id: String,
+    pub message_ts: String,
+    /// Set when the message is a reply in a thread.
+    pub thread_id: Option<String>,
+    pub message_text: String,
+    pub author_name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum IncomingEvent {
+    Message(IncomingMessage),
+    Reaction(ReactionEvent),
+    Action(ActionEvent),
+    Shortcut(ShortcutEvent),
+    /// The user (by id) opened ccterm's App Home tab.
+    HomeOpened(String),
+}
+
+/// Content of a user's App Home tab.
+#[derive(Debug, Clone, Default)]
+pub struct HomeView {
+    pub sessions: Vec<HomeSession>,
+    /// Recent turns as mrkdwn lines, newest first.
+    pub recent: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HomeSession {
+    /// mrkdwn summary of the session.
+    pub text: String,
+    /// Button value identifying the session's conversation.
+    pub value: String,
+}
+
+/// Who the bot is in the workspace of a conversation.
+#[derive(Debug, Clone)]
+pub struct ChatIdentity {
+    /// The bot's own user id, as it appears in mentions.
+    pub bot_user_id: String,
+    pub workspace_id: String,
+    pub workspace_name: String,
+    /// Base URL of the workspace, e.g. `https://example.slack.com/`.
+    pub workspace_url: String,
+}
+
+/// Layout block of a reply posted with `reply_format = "blocks"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplyBlock {
+    /// Plain-text heading.
+    Header(String),
+    /// mrkdwn text.
+    Section(String),
+    Divider,
 }
 
 #[derive(Debug, Clone, Serialize)]
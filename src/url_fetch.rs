@@ -0,0 +1,154 @@
+use crate::config::UrlFetchConfig;
+use anyhow::{anyhow, bail, Context, Result};
+use http_body_util::{BodyExt, Empty, Limited};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use std::time::Duration;
+
+/// Redirects followed when fetching, each checked against the allowlist again.
+const MAX_REDIRECTS: usize = 3;
+
+/// `http(s)` URLs in a Slack message, in order and without duplicates. Slack wraps links
+/// as `<url>` or `<url|label>` and escapes `&` as `&amp;`.
+pub fn find_urls(text: &str) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    for (start, _) in text.match_indices("http") {
+        let rest = &text[start..];
+        if !(rest.starts_with("http://") || rest.starts_with("https://")) {
+            continue;
+        }
+        if start > 0 && text[..start].ends_with(|c: char| c.is_alphanumeric()) {
+            continue;
+        }
+        let end = rest
+            .find(|c: char| c == '>' || c == '|' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let url = rest[..end].replace("&amp;", "&");
+        if !found.contains(&url) {
+            found.push(url);
+        }
+    }
+    found
+}
+
+/// Whether the host of `url` is one of `domains` or a subdomain of one.
+pub fn is_allowed(url: &str, domains: &[String]) -> bool {
+    let Some(host) = host(url) else {
+        return false;
+    };
+    domains.iter().any(|domain| {
+        let domain = domain.trim_start_matches("*.").to_ascii_lowercase();
+        host == domain || host.ends_with(&format!(".{domain}"))
+    })
+}
+
+fn host(url: &str) -> Option<String> {
+    let uri = url.parse::<hyper::Uri>().ok()?;
+    Some(uri.host()?.to_ascii_lowercase())
+}
+
+/// Fetch a text document, following allowed redirects, and return it truncated to
+/// `max_chars`. Bodies over `max_bytes`, non-text content, and error statuses fail.
+pub async fn fetch(url: &str, cfg: &UrlFetchConfig) -> Result<String> {
+    let timeout = Duration::from_secs(cfg.timeout_secs);
+    tokio::time::timeout(timeout, fetch_text(url, cfg))
+        .await
+        .map_err(|_| anyhow!("timed out after {}s", cfg.timeout_secs))?
+}
+
+async fn fetch_text(url: &str, cfg: &UrlFetchConfig) -> Result<String> {
+    let https = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .context("failed to load native TLS roots")?
+        .https_or_http()
+        .enable_http1()
+        .build();
+    let client = Client::builder(TokioExecutor::new()).build::<_, Empty<&[u8]>>(https);
+    let mut url = url.to_string();
+    for _ in 0..=MAX_REDIRECTS {
+        let request = hyper::Request::get(&url)
+            .header(hyper::header::USER_AGENT, "ccterm")
+            .body(Empty::new())
+            .context("failed to build request")?;
+        let response = client.request(request).await.context("request failed")?;
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(hyper::header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .context("redirect without a location")?;
+            url = resolve_location(&url, location);
+            if !is_allowed(&url, &cfg.allowed_domains) {
+                bail!("redirected to {url}, outside url_fetch.allowed_domains");
+            }
+            continue;
+        }
+        if !response.status().is_success() {
+            bail!("status {}", response.status());
+        }
+        let content_type = response
+            .headers()
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if !is_text(&content_type) {
+            bail!("not a text document ({content_type})");
+        }
+        let max_bytes = usize::try_from(cfg.max_bytes).unwrap_or(usize::MAX);
+        let body = Limited::new(response.into_body(), max_bytes)
+            .collect()
+            .await
+            .map_err(|err| anyhow!("failed to read the body: {err}"))?
+            .to_bytes();
+        let text = String::from_utf8_lossy(&body);
+        return Ok(match text.char_indices().nth(cfg.max_chars) {
+            Some((idx, _)) => format!("{}\n… (truncated)", &text[..idx]),
+            None => text.into_owned(),
+        });
+    }
+    bail!("more than {MAX_REDIRECTS} redirects")
+}
+
+/// Absolute form of a `Location` header, which may be relative to the current URL.
+pub fn resolve_location(current: &str, location: &str) -> String {
+    if location.starts_with("http://") || location.starts_with("https://") {
+        return location.to_string();
+    }
+    let Ok(uri) = current.parse::<hyper::Uri>() else {
+        return location.to_string();
+    };
+    let scheme = uri.scheme_str().unwrap_or("https");
+    let authority = uri.authority().map(|a| a.as_str()).unwrap_or_default();
+    if location.starts_with('/') {
+        format!("{scheme}://{authority}{location}")
+    } else {
+        let path = uri.path();
+        let dir = &path[..path.rfind('/').map_or(0, |idx| idx + 1)];
+        format!("{scheme}://{authority}{dir}{location}")
+    }
+}
+
+/// Plain text, JSON, XML and similar documents; untyped bodies are treated as text.
+fn is_text(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    mime.is_empty()
+        || mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime,
+            "application/json" | "application/xml" | "application/x-yaml" | "application/yaml"
+        )
+}
+
+/// A fetched document for the prompt, fenced so its content cannot close the block.
+pub fn format_for_prompt(url: &str, text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("Content of {url} (fetched by ccterm):\n{fence}\n{}\n{fence}", text.trim_end())
+}